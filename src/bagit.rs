@@ -0,0 +1,123 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{exit::Exit, hash::Hasher, pool::Pool, snapshot, ExportEntry};
+
+/// Directory of a bag holding the exported files, mirroring the tracked tree.
+pub const PAYLOAD_DIR: &str = "data";
+
+/// Name BagIt gives the algorithm in manifest file names, none for the algorithms it doesn't know.
+fn algorithm(hasher: Hasher) -> Option<&'static str> {
+    match hasher {
+        Hasher::Sha256 => Some("sha256"),
+        Hasher::Sha512 => Some("sha512"),
+        Hasher::Md5 => Some("md5"),
+        Hasher::Blake3 => None,
+    }
+}
+
+/// Check that a bag can name the entries and hash them with `hasher`, before anything is copied.
+///
+/// Tag files are UTF-8, names which aren't can't be written in the manifest.
+pub fn check(entries: &[ExportEntry], hasher: Hasher) -> anyhow::Result<()> {
+    if algorithm(hasher).is_none() {
+        return Err(anyhow!("bagit manifests can use sha256, sha512 or md5")).context(Exit::Usage);
+    }
+    if let Some(entry) = entries.iter().find(|entry| entry.name.to_str().is_none()) {
+        return Err(anyhow!(
+            "{} isn't valid UTF-8, a bagit manifest can't name it",
+            entry.path.display()
+        ))
+        .context(Exit::Usage);
+    }
+    Ok(())
+}
+
+/// Encode a path of a manifest line, where line breaks and percent signs are percent-encoded.
+fn encode(path: &str) -> String {
+    path.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+fn write_file(path: &Path, content: &str) -> anyhow::Result<()> {
+    let mut output = BufWriter::new(File::create(path).context(format!("could not create {}", path.display()))?);
+    output.write_all(content.as_bytes())?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Write the tag files of a bag whose payload was copied under `bag/data`, as described by RFC 8493.
+///
+/// These are bagit.txt, the payload manifest with a checksum of every copied file, bag-info.txt with
+/// the date and the size of the payload, and the tag manifest with a checksum of the other three.
+/// The copies are hashed rather than the tracked files, so the manifest describes the bag as written,
+/// without the files --file-timeout skipped. Returns the names of the tag files.
+pub fn write_tag_files(
+    bag: &Path,
+    entries: &[ExportEntry],
+    hasher: Hasher,
+    pool: &Pool,
+) -> anyhow::Result<Vec<String>> {
+    let algorithm = algorithm(hasher).expect("bagit algorithm wasn't checked");
+    let payload: Vec<PathBuf> = entries
+        .iter()
+        .map(|entry| Path::new(PAYLOAD_DIR).join(&entry.name))
+        .filter(|name| fs::symlink_metadata(bag.join(name)).is_ok())
+        .collect();
+    let hashes = pool.try_map(&payload, |name| {
+        let path = bag.join(name);
+        hasher
+            .hash_file(&path)
+            .context(format!("could not hash {}", path.display()))
+    })?;
+    let mut manifest = String::new();
+    let mut octets = 0;
+    for (name, hash) in payload.iter().zip(&hashes) {
+        let name = name.to_str().expect("bagit name wasn't checked");
+        manifest.push_str(&format!("{}  {}\n", hash, encode(name)));
+        let path = bag.join(name);
+        octets += fs::metadata(&path)
+            .context(format!("could not read metadata of {}", path.display()))?
+            .len();
+    }
+
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let (year, month, day) = snapshot::civil_from_days(secs.div_euclid(86400));
+    let manifest_name = format!("manifest-{}.txt", algorithm);
+    let tag_files = [
+        (
+            "bagit.txt".to_string(),
+            "BagIt-Version: 1.0\nTag-File-Character-Encoding: UTF-8\n".to_string(),
+        ),
+        (
+            "bag-info.txt".to_string(),
+            format!(
+                "Bag-Software-Agent: track {}\nBagging-Date: {:04}-{:02}-{:02}\nPayload-Oxum: {}.{}\n",
+                env!("CARGO_PKG_VERSION"),
+                year,
+                month,
+                day,
+                octets,
+                payload.len()
+            ),
+        ),
+        (manifest_name, manifest),
+    ];
+    let mut tag_manifest = String::new();
+    for (name, content) in &tag_files {
+        write_file(&bag.join(name), content)?;
+        let mut digest = hasher.digest();
+        digest.update(content.as_bytes());
+        tag_manifest.push_str(&format!("{}  {}\n", digest.finish(), name));
+    }
+    let tag_manifest_name = format!("tagmanifest-{}.txt", algorithm);
+    write_file(&bag.join(&tag_manifest_name), &tag_manifest)?;
+    let mut names: Vec<String> = tag_files.into_iter().map(|(name, _)| name).collect();
+    names.push(tag_manifest_name);
+    Ok(names)
+}
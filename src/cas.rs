@@ -0,0 +1,574 @@
+use std::{
+    fs::{self, DirBuilder, File},
+    io::{self, BufWriter, Read, Write},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{MetadataExt, PermissionsExt},
+    },
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Context};
+use filetime::FileTime;
+
+use crate::{
+    exit::Exit, flags, hash::Hasher, home_dir, interrupt, json::Value, log, pool::Pool, progress::Progress,
+    read_manifest, restored_path, write_checksum_line, ExportEntry, RestoreArgs,
+};
+
+/// Directory of a store holding the content of every exported file once, named after its hash.
+pub const OBJECTS_DIR: &str = "objects";
+
+/// Directory of a store holding the index of each export, named after its time and hash algorithm.
+pub const INDEX_DIR: &str = "indexes";
+
+/// Type bits of the mode of a symlink, whose object holds its target.
+const S_IFLNK: u32 = 0o120000;
+const S_IFMT: u32 = 0o170000;
+
+/// Last field of the index line of a file stored in chunks, whose object lists them.
+const CHUNKED: &str = "chunked";
+
+/// Size of the chunks of --chunking without --chunk-size.
+pub const DEFAULT_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Numbers the temporary files of the objects being stored, unique within this run.
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// How cas exports split the files larger than the chunk size, so the unchanged parts of a file which
+/// changed are stored once, given with --chunking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chunking {
+    /// Chunks of the same size, which only dedupe changes that don't move the data after them.
+    Fixed,
+    /// Content-defined chunks cut where a rolling hash of the last bytes matches, which also dedupe the
+    /// data after bytes inserted or removed.
+    Cdc,
+}
+
+impl FromStr for Chunking {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "fixed" => Chunking::Fixed,
+            "cdc" => Chunking::Cdc,
+            _ => bail!("Unknown chunking {}", s),
+        })
+    }
+}
+
+/// Random values of the gear rolling hash of content-defined chunking, one per byte value.
+const GEAR: [u64; 256] = {
+    // Generated with splitmix64, the table must never change or chunks would be cut elsewhere.
+    let mut table = [0; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// Call `f` with each chunk of `input`, of `size` bytes with fixed chunking. Content-defined chunks are
+/// between a quarter and four times `size`, cut after about `size` bytes past the minimum.
+fn for_each_chunk(
+    mut input: impl Read,
+    chunking: Chunking,
+    size: usize,
+    mut f: impl FnMut(&[u8]) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let (min, max) = match chunking {
+        Chunking::Fixed => (size, size),
+        Chunking::Cdc => (size / 4, size * 4),
+    };
+    // The top bits of the gear hash depend on the last 64 bytes, being all zeros once every `size` bytes.
+    let shift = 64 - size.next_power_of_two().trailing_zeros();
+    let mut chunk = Vec::with_capacity(max);
+    let mut gear: u64 = 0;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = match input.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        for &b in &buf[..n] {
+            chunk.push(b);
+            gear = (gear << 1).wrapping_add(GEAR[b as usize]);
+            if chunk.len() >= max || (chunking == Chunking::Cdc && chunk.len() >= min && gear >> shift == 0) {
+                f(&chunk)?;
+                chunk.clear();
+                gear = 0;
+            }
+        }
+    }
+    if !chunk.is_empty() {
+        f(&chunk)?;
+    }
+    Ok(())
+}
+
+fn object_path(store: &Path, hash: &str) -> PathBuf {
+    store.join(OBJECTS_DIR).join(&hash[..2]).join(hash)
+}
+
+fn temp_path(store: &Path) -> PathBuf {
+    store.join(OBJECTS_DIR).join(format!(
+        "tmp-{}-{}",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Store `content` hashed as `hash` unless an object has it already, returning whether it's new.
+fn store_bytes(store: &Path, content: &[u8], hash: &str) -> anyhow::Result<bool> {
+    let object = object_path(store, hash);
+    if object.exists() {
+        return Ok(false);
+    }
+    let temp = temp_path(store);
+    fs::write(&temp, content)?;
+    DirBuilder::new()
+        .recursive(true)
+        .create(object.parent().expect("object has no parent"))?;
+    fs::set_permissions(&temp, fs::Permissions::from_mode(0o444))?;
+    fs::rename(&temp, &object).context(format!("could not store {}", object.display()))?;
+    Ok(true)
+}
+
+/// Whether `path` is a store written by a cas export or one of its index files.
+pub fn is_store(path: &Path) -> bool {
+    let store = match path.parent() {
+        Some(parent) if path.is_file() && parent.file_name().is_some_and(|name| name == INDEX_DIR) => {
+            parent.parent().unwrap_or(Path::new("."))
+        }
+        _ => path,
+    };
+    store.join(OBJECTS_DIR).is_dir() && store.join(INDEX_DIR).is_dir()
+}
+
+/// Check that `store` is empty or was written by a previous cas export, a store is never cleaned.
+pub fn check_store(store: &Path) -> anyhow::Result<()> {
+    let mut children = fs::read_dir(store).context(format!("could not read {}", store.display()))?;
+    if !is_store(store) && children.next().is_some() {
+        return Err(anyhow!(
+            "{} is not empty and isn't a cas store, export to an empty directory",
+            store.display()
+        ))
+        .context(Exit::Usage);
+    }
+    Ok(())
+}
+
+/// Move the file `temp` in place as the object of its content, returning its hash and whether no object
+/// had it yet.
+///
+/// The copy is hashed again rather than trusting the hash of the source, which may have changed meanwhile.
+fn commit(store: &Path, temp: &Path, hasher: Hasher) -> anyhow::Result<(String, bool)> {
+    let hash = hasher
+        .hash_file(temp)
+        .context(format!("could not hash {}", temp.display()))?;
+    let object = object_path(store, &hash);
+    if object.exists() {
+        fs::remove_file(temp)?;
+        return Ok((hash, false));
+    }
+    DirBuilder::new()
+        .recursive(true)
+        .create(object.parent().expect("object has no parent"))?;
+    fs::set_permissions(temp, fs::Permissions::from_mode(0o444))?;
+    fs::rename(temp, &object).context(format!("could not store {}", object.display()))?;
+    Ok((hash, true))
+}
+
+/// Store a file in chunks, returning the hash of the object listing them and how many objects are new.
+///
+/// The list starts with a `file HASH` line with the hash of the whole file, then has the hash of each
+/// chunk on a line of its own.
+fn store_chunks(
+    store: &Path,
+    path: &Path,
+    hasher: Hasher,
+    chunking: Chunking,
+    size: usize,
+) -> anyhow::Result<(String, usize)> {
+    let mut file_digest = hasher.digest();
+    let mut chunks = String::new();
+    let mut new = 0;
+    for_each_chunk(File::open(path)?, chunking, size, |chunk| {
+        file_digest.update(chunk);
+        let mut digest = hasher.digest();
+        digest.update(chunk);
+        let hash = digest.finish();
+        new += usize::from(store_bytes(store, chunk, &hash)?);
+        chunks.push_str(&hash);
+        chunks.push('\n');
+        Ok(())
+    })?;
+    let list = format!("file {}\n{}", file_digest.finish(), chunks);
+    let mut digest = hasher.digest();
+    digest.update(list.as_bytes());
+    let hash = digest.finish();
+    new += usize::from(store_bytes(store, list.as_bytes(), &hash)?);
+    Ok((hash, new))
+}
+
+/// Store the content of a file, or the target of a symlink, unless an object has it already, returning
+/// its hash, whether the file was chunked and how many objects are new.
+fn store_entry(
+    store: &Path,
+    entry: &ExportEntry,
+    meta: &fs::Metadata,
+    hasher: Hasher,
+    chunking: Option<(Chunking, usize)>,
+) -> anyhow::Result<(String, bool, usize)> {
+    if meta.file_type().is_symlink() {
+        let target = fs::read_link(&entry.path)?;
+        let target = target.as_os_str().as_bytes();
+        let mut digest = hasher.digest();
+        digest.update(target);
+        let hash = digest.finish();
+        let new = store_bytes(store, target, &hash)?;
+        return Ok((hash, false, usize::from(new)));
+    }
+    if let Some((chunking, size)) = chunking.filter(|&(_, size)| meta.len() > size as u64) {
+        let (hash, new) = store_chunks(store, &entry.path, hasher, chunking, size)?;
+        return Ok((hash, true, new));
+    }
+    let hash = hasher.hash_file(&entry.path)?;
+    if object_path(store, &hash).exists() {
+        return Ok((hash, false, 0));
+    }
+    let temp = temp_path(store);
+    fs::copy(&entry.path, &temp)?;
+    let (hash, new) = commit(store, &temp, hasher)?;
+    Ok((hash, false, usize::from(new)))
+}
+
+/// Name of a new index file of `store`, like `2024-01-31T23:59:59Z.sha256`.
+fn index_path(store: &Path, hasher: Hasher) -> PathBuf {
+    let stamp = log::timestamp(SystemTime::now());
+    let mut path = store.join(INDEX_DIR).join(format!("{}.{}", stamp, hasher.as_str()));
+    let mut n = 2;
+    while path.exists() {
+        path = store
+            .join(INDEX_DIR)
+            .join(format!("{}-{}.{}", stamp, n, hasher.as_str()));
+        n += 1;
+    }
+    path
+}
+
+/// Export the entries to the content-addressed store `store`, adding the objects it doesn't have yet and
+/// an index of this export.
+///
+/// Each object holds the content of a file under `objects/<hash[:2]>/<hash>`, a file already stored by a
+/// previous export is only hashed. With `chunking`, files larger than the chunk size are stored as
+/// chunks instead, along with an object listing them. Index lines are like the checksum lines of a
+/// manifest, with the mode and the modification time of the file after its hash, and `chunked` for
+/// the files whose hash is the one of their list of chunks.
+pub fn export_cas(
+    store: &Path,
+    entries: &[ExportEntry],
+    hasher: Hasher,
+    chunking: Option<(Chunking, usize)>,
+    pool: &Pool,
+    progress: &Progress,
+) -> anyhow::Result<()> {
+    for dir in [OBJECTS_DIR, INDEX_DIR] {
+        let dir = store.join(dir);
+        fs::create_dir_all(&dir).context(format!("could not create {}", dir.display()))?;
+    }
+    let stored = pool.try_map(entries, |entry| -> anyhow::Result<_> {
+        interrupt::check()?;
+        let meta = fs::symlink_metadata(&entry.path)
+            .context(format!("could not read metadata of {}", entry.path.display()))?;
+        let (hash, chunked, new) = store_entry(store, entry, &meta, hasher, chunking)
+            .context(format!("could not store {}", entry.path.display()))?;
+        progress.file(&entry.path);
+        let mut line = format!("{} {:o} {}", hash, meta.mode(), meta.mtime());
+        if chunked {
+            line.push(' ');
+            line.push_str(CHUNKED);
+        }
+        Ok((line, new))
+    })?;
+
+    // Written under a temporary name, so an index only lists files whose objects are all stored.
+    let index = index_path(store, hasher);
+    let temp = index.with_extension("tmp");
+    let mut output = BufWriter::new(File::create(&temp).context(format!("could not create {}", temp.display()))?);
+    for (entry, (line, _)) in entries.iter().zip(&stored) {
+        write_checksum_line(&mut output, line, &entry.name, false)?;
+    }
+    output.flush()?;
+    drop(output);
+    fs::rename(&temp, &index).context(format!("could not create {}", index.display()))?;
+
+    let new: usize = stored.iter().map(|(_, new)| new).sum();
+    let reused = stored.iter().filter(|(_, new)| *new == 0).count();
+    println!(
+        "Stored {} new objects, {} files were stored already, indexed in {}",
+        new,
+        reused,
+        index.display()
+    );
+    log::info(
+        "cas-done",
+        vec![
+            ("new", (new as u64).into()),
+            ("reused", (reused as u64).into()),
+            ("index", Value::path(&index)),
+        ],
+    );
+    Ok(())
+}
+
+/// Store and index file of a cas restore, the latest index of a store unless one is given.
+fn locate(path: &Path) -> anyhow::Result<(PathBuf, PathBuf)> {
+    if path.is_file() {
+        let store = path.parent().and_then(Path::parent).unwrap_or(Path::new("."));
+        return Ok((store.to_path_buf(), path.to_path_buf()));
+    }
+    let dir = path.join(INDEX_DIR);
+    let mut latest: Option<(SystemTime, PathBuf)> = None;
+    for child in fs::read_dir(&dir).context(format!("could not read {}", dir.display()))? {
+        let child = child?;
+        let name = child.path();
+        if name.extension().is_some_and(|ext| ext == "tmp") {
+            continue;
+        }
+        let key = (child.metadata()?.modified()?, name);
+        if latest.as_ref().is_none_or(|latest| key > *latest) {
+            latest = Some(key);
+        }
+    }
+    match latest {
+        Some((_, index)) => Ok((path.to_path_buf(), index)),
+        None => Err(anyhow!("{} has no index, it holds no export", path.display())).context(Exit::Usage),
+    }
+}
+
+fn is_hash(hash: &str) -> bool {
+    hash.len() > 2 && hash.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Write the chunks listed by the object `list` to `dest` in order, returning the hash of the whole file.
+fn restore_chunks(store: &Path, list: &Path, dest: &Path) -> anyhow::Result<String> {
+    let list = fs::read_to_string(list).context(format!("could not read {}", list.display()))?;
+    let mut lines = list.lines();
+    let hash = lines
+        .next()
+        .and_then(|line| line.strip_prefix("file "))
+        .filter(|hash| is_hash(hash))
+        .ok_or_else(|| anyhow!("invalid list of chunks"))?;
+    let mut output = BufWriter::new(File::create(dest)?);
+    for chunk in lines {
+        if !is_hash(chunk) {
+            bail!("invalid list of chunks");
+        }
+        let object = object_path(store, chunk);
+        let mut input = File::open(&object).context(format!("could not open chunk {}", object.display()))?;
+        io::copy(&mut input, &mut output)?;
+    }
+    output.flush()?;
+    Ok(hash.to_string())
+}
+
+/// Rebuild the files listed by an index of a cas store from its objects.
+///
+/// `args.archive` is the store, whose latest index is restored, or one of its index files. Files are
+/// restored with the mode and modification time they were exported with, --verify checks them against
+/// the hashes of the index.
+pub fn restore(args: &RestoreArgs) -> anyhow::Result<()> {
+    for (given, flag) in [
+        (args.xattrs, "--xattrs"),
+        (args.manifest.is_some(), "--manifest"),
+        (args.verify_embedded, "--verify-embedded"),
+    ] {
+        if given {
+            return Err(anyhow!("{} doesn't apply to cas restores", flag)).context(Exit::Usage);
+        }
+    }
+    let (store, index) = locate(&args.archive)?;
+    let hasher: Hasher = index
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(|ext| ext.parse().ok())
+        .ok_or_else(|| {
+            anyhow!(
+                "can't tell the hash algorithm of {} from its extension",
+                index.display()
+            )
+        })?;
+    let content = fs::read(&index).context(format!("could not read {}", index.display()))?;
+    let home = if args.home_relative { Some(home_dir()?) } else { None };
+
+    let mut checked = 0;
+    let mut failed = 0;
+    for (line, name) in read_manifest(&content).context(format!("could not read {}", index.display()))? {
+        interrupt::check()?;
+        let invalid = || anyhow!("invalid index line of {} in {}", name.display(), index.display());
+        let (hash, mode, mtime, chunked) = match line.split(' ').collect::<Vec<_>>()[..] {
+            [hash, mode, mtime] => (hash, mode, mtime, false),
+            [hash, mode, mtime, CHUNKED] => (hash, mode, mtime, true),
+            _ => return Err(invalid()),
+        };
+        if !is_hash(hash) {
+            return Err(invalid());
+        }
+        let mode = u32::from_str_radix(mode, 8).map_err(|_| invalid())?;
+        let mtime: i64 = mtime.parse().map_err(|_| invalid())?;
+        let new_path = match restored_path(args, home.as_deref(), &name)? {
+            Some(new_path) => new_path,
+            None => {
+                if let Some(prefix) = &args.prefix {
+                    log::skipping(&name, &format!("outside of prefix {}", prefix.display()));
+                }
+                continue;
+            }
+        };
+        let object = object_path(&store, hash);
+        if !object.exists() {
+            return Err(anyhow!(
+                "{} has no object {} for {}",
+                store.display(),
+                hash,
+                name.display()
+            ));
+        }
+        DirBuilder::new()
+            .recursive(true)
+            .create(new_path.parent().expect("new path has no parent"))?;
+        if let Ok(meta) = fs::symlink_metadata(&new_path) {
+            if meta.is_file() {
+                let dest_flags = flags::read(&new_path)?;
+                if flags::is_protected(dest_flags) {
+                    log::skipping(
+                        &new_path,
+                        &format!("which is {} and can't be overwritten", flags::protection(dest_flags)),
+                    );
+                    continue;
+                }
+            }
+            if !meta.is_dir() {
+                fs::remove_file(&new_path).context(format!("could not replace {}", new_path.display()))?;
+            }
+        }
+        let mtime = FileTime::from_unix_time(mtime, 0);
+        if mode & S_IFMT == S_IFLNK {
+            let target = fs::read(&object).context(format!("could not read {}", object.display()))?;
+            std::os::unix::fs::symlink(std::ffi::OsStr::from_bytes(&target), &new_path)
+                .context(format!("could not create symlink {}", new_path.display()))?;
+            filetime::set_symlink_file_times(&new_path, mtime, mtime)?;
+            continue;
+        }
+        let expected = if chunked {
+            restore_chunks(&store, &object, &new_path).context(format!("could not restore {}", new_path.display()))?
+        } else {
+            fs::copy(&object, &new_path).context(format!("could not restore {}", new_path.display()))?;
+            hash.to_string()
+        };
+        fs::set_permissions(&new_path, fs::Permissions::from_mode(mode & 0o7777))?;
+        filetime::set_file_mtime(&new_path, mtime)?;
+        if args.verify {
+            checked += 1;
+            match hasher.hash_file(&new_path) {
+                Ok(restored) if restored == expected => {}
+                Ok(_) => {
+                    eprintln!("{}: FAILED", new_path.display());
+                    failed += 1;
+                }
+                Err(err) => {
+                    eprintln!("{}: FAILED open or read: {}", new_path.display(), err);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    if args.verify {
+        if failed > 0 {
+            return Err(anyhow!(
+                "{} of {} restored files don't match the index",
+                failed,
+                checked
+            ))
+            .context(Exit::Mismatch);
+        }
+        eprintln!("Verified {} restored files", checked);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{for_each_chunk, Chunking};
+
+    fn chunks(input: &[u8], chunking: Chunking, size: usize) -> Vec<Vec<u8>> {
+        let mut chunks = Vec::new();
+        for_each_chunk(input, chunking, size, |chunk| {
+            chunks.push(chunk.to_vec());
+            Ok(())
+        })
+        .unwrap();
+        chunks
+    }
+
+    /// Bytes from a linear congruential generator, which no two chunks have in common.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state: u32 = 5;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fixed_chunks_have_the_same_size() {
+        let input = noise(10 * 4096 + 100);
+        let chunks = chunks(&input, Chunking::Fixed, 4096);
+        assert_eq!(chunks.len(), 11);
+        assert!(chunks[..10].iter().all(|chunk| chunk.len() == 4096));
+        assert_eq!(chunks.concat(), input);
+    }
+
+    #[test]
+    fn cdc_chunks_stay_within_bounds() {
+        let input = noise(1024 * 1024);
+        let chunks = chunks(&input, Chunking::Cdc, 4096);
+        let (last, full) = chunks.split_last().unwrap();
+        assert!(full.iter().all(|chunk| (1024..=16384).contains(&chunk.len())));
+        assert!(last.len() <= 16384);
+        assert_eq!(chunks.concat(), input);
+    }
+
+    #[test]
+    fn cdc_cuts_come_back_after_an_insertion() {
+        let input = noise(256 * 1024);
+        let mut inserted = input.clone();
+        inserted.splice(1000..1000, *b"inserted");
+        let (before, after) = (
+            chunks(&input, Chunking::Cdc, 4096),
+            chunks(&inserted, Chunking::Cdc, 4096),
+        );
+        let shared = after.iter().filter(|chunk| before.contains(chunk)).count();
+        assert!(
+            shared + 2 >= before.len(),
+            "{} of {} chunks shared",
+            shared,
+            before.len()
+        );
+    }
+}
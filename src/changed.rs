@@ -0,0 +1,276 @@
+use std::{
+    fs,
+    io::{self, Write},
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use filetime::FileTime;
+use rusqlite::OptionalExtension;
+
+use crate::{
+    output::{self, PathStyle},
+    Filters, PathsDB,
+};
+
+/// Modification time in nanoseconds since the epoch, the resolution the cache compares.
+fn mtime(meta: &fs::Metadata) -> i64 {
+    let time = FileTime::from_last_modification_time(meta);
+    time.unix_seconds() * 1_000_000_000 + i64::from(time.nanoseconds())
+}
+
+fn to_path(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+/// Listing of a directory, its regular files with their modification time and its subdirectories.
+struct Listing {
+    files: Vec<(PathBuf, i64)>,
+    dirs: Vec<PathBuf>,
+}
+
+/// Listings of the directories under the tracked paths, as of the modification time of each directory.
+struct Cache<'a> {
+    paths_db: &'a PathsDB,
+    /// Whether the directories read again are saved, otherwise the cache is only read.
+    update: bool,
+}
+
+impl Cache<'_> {
+    /// Modification time of the directory when its cached listing was made, if there's one.
+    fn listed_at(&self, dir: &Path) -> anyhow::Result<Option<i64>> {
+        let mtime: Option<Option<i64>> = self
+            .paths_db
+            .handle
+            .prepare_cached("SELECT mtime FROM scanned_dirs WHERE path = ?")?
+            .query_row([dir.as_os_str().as_bytes()], |row| row.get(0))
+            .optional()?;
+        Ok(mtime.flatten())
+    }
+
+    fn listing(&self, dir: &Path) -> anyhow::Result<Listing> {
+        let dir_bytes = dir.as_os_str().as_bytes();
+        let mut files = Vec::new();
+        let mut stmt = self
+            .paths_db
+            .handle
+            .prepare_cached("SELECT path, mtime FROM scanned_files WHERE dir = ? ORDER BY path")?;
+        for row in stmt.query_map([dir_bytes], |row| Ok((row.get(0)?, row.get(1)?)))? {
+            let (path, mtime) = row?;
+            files.push((to_path(path), mtime));
+        }
+        let mut dirs = Vec::new();
+        let mut stmt = self
+            .paths_db
+            .handle
+            .prepare_cached("SELECT path FROM scanned_dirs WHERE parent = ? ORDER BY path")?;
+        for row in stmt.query_map([dir_bytes], |row| row.get(0))? {
+            dirs.push(to_path(row?));
+        }
+        Ok(Listing { files, dirs })
+    }
+
+    /// Replace the cached listing of a directory, made when it had the modification time `mtime`.
+    fn store(&self, dir: &Path, parent: Option<&Path>, mtime: i64, listing: &Listing) -> anyhow::Result<()> {
+        let handle = &self.paths_db.handle;
+        let dir_bytes = dir.as_os_str().as_bytes();
+        handle.execute("DELETE FROM scanned_files WHERE dir = ?", [dir_bytes])?;
+        let mut insert_file = handle.prepare_cached("INSERT INTO scanned_files (path, dir, mtime) VALUES (?, ?, ?)")?;
+        for (path, mtime) in &listing.files {
+            insert_file.execute(rusqlite::params![path.as_os_str().as_bytes(), dir_bytes, mtime])?;
+        }
+        // Subdirectories keep their own listing, unless they are gone.
+        let mut stale = Vec::new();
+        for cached in self.listing(dir)?.dirs {
+            if !listing.dirs.contains(&cached) {
+                stale.push(cached);
+            }
+        }
+        while let Some(gone) = stale.pop() {
+            let listing = self.listing(&gone)?;
+            stale.extend(listing.dirs);
+            let gone = gone.as_os_str().as_bytes();
+            handle.execute("DELETE FROM scanned_files WHERE dir = ?", [gone])?;
+            handle.execute("DELETE FROM scanned_dirs WHERE path = ?", [gone])?;
+        }
+        // A nested tracked path may have been listed first, without a parent.
+        let mut insert_dir = handle.prepare_cached(
+            "INSERT INTO scanned_dirs (path, parent, mtime) VALUES (?, ?, NULL)
+             ON CONFLICT (path) DO UPDATE SET parent = excluded.parent",
+        )?;
+        for sub in &listing.dirs {
+            insert_dir.execute(rusqlite::params![sub.as_os_str().as_bytes(), dir_bytes])?;
+        }
+        handle.execute(
+            "INSERT INTO scanned_dirs (path, parent, mtime) VALUES (?, ?, ?)
+             ON CONFLICT (path) DO UPDATE SET mtime = excluded.mtime, parent = COALESCE(excluded.parent, parent)",
+            rusqlite::params![dir_bytes, parent.map(|parent| parent.as_os_str().as_bytes()), mtime],
+        )?;
+        Ok(())
+    }
+}
+
+fn read_listing(dir: &Path) -> anyhow::Result<Listing> {
+    let mut listing = Listing {
+        files: Vec::new(),
+        dirs: Vec::new(),
+    };
+    for entry in fs::read_dir(dir).context(format!("could not read {}", dir.display()))? {
+        let entry = entry.context(format!("could not read {}", dir.display()))?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            listing.dirs.push(entry.path());
+        } else if file_type.is_file() {
+            let meta = entry.metadata()?;
+            listing.files.push((entry.path(), mtime(&meta)));
+        }
+    }
+    listing.files.sort();
+    listing.dirs.sort();
+    Ok(listing)
+}
+
+/// Entry of a directory left to visit by `walk`.
+enum Pending {
+    File(PathBuf, i64),
+    /// A directory along with its parent, none for a tracked path.
+    Dir(PathBuf, Option<PathBuf>),
+}
+
+impl Pending {
+    fn path(&self) -> &Path {
+        match self {
+            Pending::File(path, _) | Pending::Dir(path, _) => path,
+        }
+    }
+}
+
+/// Walk the matched files under the roots, reading again only the directories modified since
+/// they were cached, and call `f` with each file and its modification time.
+fn walk(
+    cache: &Cache,
+    roots: &[PathBuf],
+    filters: &Filters,
+    mut f: impl FnMut(&Path, i64) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    for root in roots {
+        let meta = match fs::metadata(root) {
+            Ok(meta) => meta,
+            Err(_) => continue,
+        };
+        if !meta.is_dir() {
+            if meta.is_file() && filters.exclusion(root, root).is_none() {
+                f(root, mtime(&meta))?;
+            }
+            continue;
+        }
+        // Entries are visited by name within each directory, subdirectories in place, like find_matches.
+        let mut pending = vec![Pending::Dir(root.clone(), None)];
+        while let Some(next) = pending.pop() {
+            let (dir, parent) = match next {
+                Pending::File(file, file_mtime) => {
+                    if filters.exclusion(root, &file).is_none() {
+                        f(&file, file_mtime)?;
+                    }
+                    continue;
+                }
+                Pending::Dir(dir, parent) => (dir, parent),
+            };
+            let dir_mtime = match fs::metadata(&dir) {
+                Ok(meta) => mtime(&meta),
+                // Deleted since its parent was listed, it's forgotten once the parent is listed again.
+                Err(_) => continue,
+            };
+            let listing = if cache.listed_at(&dir)? == Some(dir_mtime) {
+                cache.listing(&dir)?
+            } else {
+                let listing = read_listing(&dir)?;
+                if cache.update {
+                    cache.store(&dir, parent.as_deref(), dir_mtime, &listing)?;
+                }
+                listing
+            };
+            let mut entries: Vec<Pending> = listing
+                .files
+                .into_iter()
+                .map(|(file, file_mtime)| Pending::File(file, file_mtime))
+                .collect();
+            for sub in listing.dirs {
+                if !sub.file_name().is_some_and(|name| filters.skips_dir(name)) && !filters.skips_system_dir(&sub) {
+                    entries.push(Pending::Dir(sub, Some(dir.clone())));
+                }
+            }
+            entries.sort_by(|a, b| a.path().cmp(b.path()));
+            pending.extend(entries.into_iter().rev());
+        }
+    }
+    Ok(())
+}
+
+/// List the matched files modified after `since`, every matched file without it.
+///
+/// Rather than walking every directory, the listing of each directory is cached along with its
+/// modification time, and only the directories whose modification time changed are read again.
+/// This relies on directories being modified when entries are created, deleted or renamed in them,
+/// which editors saving through a temporary file do. A file rewritten in place doesn't modify its
+/// directory, so it's only listed once something else changes the directory. Nothing was cached on
+/// the first run, which walks everything.
+pub fn changed(
+    paths_db: &PathsDB,
+    roots: &[PathBuf],
+    filters: &Filters,
+    since: Option<SystemTime>,
+    style: PathStyle,
+) -> anyhow::Result<()> {
+    let since = match since {
+        Some(since) => since.duration_since(UNIX_EPOCH)?.as_nanos() as i64,
+        None => i64::MIN,
+    };
+    let mut out = io::stdout().lock();
+    let tx = paths_db.handle.unchecked_transaction()?;
+    let cache = Cache { paths_db, update: true };
+    walk(&cache, roots, filters, |file, file_mtime| {
+        if file_mtime > since {
+            output::print_path(&mut out, file, style)?;
+        }
+        Ok(())
+    })?;
+    tx.commit()?;
+    out.flush()?;
+    Ok(())
+}
+
+/// List the matched files like `find_matches`, through the listings cached by `changed`.
+///
+/// Directories whose modification time didn't change aren't read again. Creating, deleting or
+/// renaming an entry modifies its directory, so the listings only go stale when something sets the
+/// modification time of a directory back, like extracting an archive over it, or on filesystems
+/// whose timestamps are too coarse to tell two changes within the same tick apart. Files come in the
+/// same order as with `find_matches`. The listings of the directories read again are only saved with
+/// `update`, so commands which don't otherwise write to the database leave it as it is.
+pub fn cached_matches(
+    paths_db: &PathsDB,
+    roots: &[PathBuf],
+    filters: &Filters,
+    update: bool,
+) -> anyhow::Result<Vec<PathBuf>> {
+    for root in roots {
+        fs::metadata(root).context(format!("error scanning path {}", root.display()))?;
+    }
+    let mut matches = Vec::new();
+    let tx = if update {
+        Some(paths_db.handle.unchecked_transaction()?)
+    } else {
+        None
+    };
+    walk(&Cache { paths_db, update }, roots, filters, |file, _| {
+        matches.push(file.to_path_buf());
+        Ok(())
+    })?;
+    if let Some(tx) = tx {
+        tx.commit()?;
+    }
+    Ok(matches)
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use filetime::FileTime;
+
+use crate::{copy_file, hash::Hasher, pool::Pool, CopyMethod, Reflink};
+
+/// How files whose name is already taken in the collect directory get a name of their own.
+#[derive(Debug, Clone, Copy)]
+pub enum Collision {
+    /// Append -1, -2 and so on to the file stem.
+    Number,
+    /// Append a short hash of the full path to the file stem, which stays stable across runs.
+    Hash,
+}
+
+impl FromStr for Collision {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "number" => Collision::Number,
+            "hash" => Collision::Hash,
+            _ => bail!("Unknown collision mode {}", s),
+        })
+    }
+}
+
+/// Insert `suffix` between the stem and the extension of a file name, `photo.jpg` becomes `photo-1.jpg`.
+fn with_suffix(name: &OsStr, suffix: &str) -> OsString {
+    let path = Path::new(name);
+    let mut renamed = path.file_stem().unwrap_or(name).to_owned();
+    renamed.push("-");
+    renamed.push(suffix);
+    if let Some(extension) = path.extension() {
+        renamed.push(".");
+        renamed.push(extension);
+    }
+    renamed
+}
+
+/// Give every file a distinct name, the first file with a given name keeps it.
+fn assign_names(matches: &[PathBuf], collision: Collision) -> anyhow::Result<Vec<(OsString, bool)>> {
+    let mut counts: HashMap<&OsStr, usize> = HashMap::new();
+    for mat in matches {
+        let name = mat.file_name().expect("matched file has no name");
+        *counts.entry(name).or_default() += 1;
+    }
+
+    let mut taken: HashSet<OsString> = counts.keys().map(|name| name.to_os_string()).collect();
+    let mut seen: HashSet<&OsStr> = HashSet::new();
+    let mut names = Vec::with_capacity(matches.len());
+    for mat in matches {
+        let name = mat.file_name().expect("matched file has no name");
+        if seen.insert(name) {
+            names.push((name.to_owned(), false));
+            continue;
+        }
+        let mut candidate = match collision {
+            Collision::Number => None,
+            Collision::Hash => {
+                let hash = Hasher::Sha256.hash_reader(mat.as_os_str().as_bytes())?;
+                Some(with_suffix(name, &hash[..8]))
+            }
+        };
+        let mut n = 1;
+        while candidate.as_ref().is_none_or(|candidate| taken.contains(candidate)) {
+            candidate = Some(with_suffix(name, &n.to_string()));
+            n += 1;
+        }
+        let candidate = candidate.expect("no name was generated");
+        taken.insert(candidate.clone());
+        names.push((candidate, true));
+    }
+    Ok(names)
+}
+
+/// Copy the matched files directly into `dir`, renaming the ones whose name was already used.
+///
+/// Names are assigned in the order of the matches, so collecting into the same directory again
+/// overwrites the files of the previous run instead of piling up renamed copies.
+pub fn collect(
+    matches: &[PathBuf],
+    dir: &Path,
+    collision: Collision,
+    dry_run: bool,
+    pool: &Pool,
+) -> anyhow::Result<()> {
+    let names = assign_names(matches, collision)?;
+    let renamed = names.iter().filter(|(_, renamed)| *renamed).count();
+    for (mat, (name, renamed)) in matches.iter().zip(&names) {
+        if dry_run {
+            println!("{} -> {}", mat.display(), dir.join(name).display());
+        } else if *renamed {
+            println!(
+                "Collision: {} collected as {}",
+                mat.display(),
+                Path::new(name).display()
+            );
+        }
+    }
+    if dry_run {
+        println!("Would collect {} files, {} renamed", matches.len(), renamed);
+        return Ok(());
+    }
+
+    fs::create_dir_all(dir).context(format!("could not create {}", dir.display()))?;
+    let files: Vec<(&PathBuf, &OsString)> = matches.iter().zip(names.iter().map(|(name, _)| name)).collect();
+    pool.try_for_each(&files, |(mat, name)| -> anyhow::Result<()> {
+        let dst = dir.join(name);
+        // Replacing instead of writing into an existing file, which may be a link to something else.
+        if fs::symlink_metadata(&dst).is_ok() {
+            fs::remove_file(&dst).context(format!("could not replace {}", dst.display()))?;
+        }
+        copy_file(mat, &dst, Reflink::Auto, CopyMethod::Auto).context(format!("could not copy {}", mat.display()))?;
+        let meta = fs::metadata(mat)?;
+        filetime::set_file_mtime(&dst, FileTime::from_last_modification_time(&meta))?;
+        Ok(())
+    })?;
+    println!("Collected {} files, {} renamed", matches.len(), renamed);
+    Ok(())
+}
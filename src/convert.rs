@@ -0,0 +1,300 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Seek, Write},
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Context};
+use filetime::FileTime;
+
+use crate::{
+    create_archive,
+    exit::Exit,
+    gz, interrupt, is_stdout,
+    json::Value,
+    log, write_atomically,
+    zip::{self, Zip64, ZipWriter},
+    TempFile,
+};
+
+/// Format of an archive written by convert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "tar" => ArchiveFormat::Tar,
+            "zip" => ArchiveFormat::Zip,
+            _ => bail!("Unknown archive format {}", s),
+        })
+    }
+}
+
+impl ArchiveFormat {
+    /// Format named by the extension of a path, .tar.gz, .tgz or .zip.
+    fn of(path: &Path) -> Option<ArchiveFormat> {
+        let name = path.file_name()?.as_bytes();
+        if name.ends_with(b".tar.gz") || name.ends_with(b".tgz") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(b".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Destination archive, entries are written in the order they are read.
+enum Writer {
+    Tar(tar::Builder<gz::Compressor<BufWriter<Box<dyn Write>>>>),
+    Zip {
+        archiver: ZipWriter<BufWriter<Box<dyn Write>>>,
+        /// Index of the entry of each name, to copy the content of hard links.
+        indexes: HashMap<Vec<u8>, usize>,
+    },
+}
+
+impl Writer {
+    fn file(&mut self, name: &[u8], mode: u32, mtime: FileTime, len: u64, content: impl Read) -> anyhow::Result<()> {
+        match self {
+            Writer::Tar(archiver) => {
+                let mut header = tar_header(tar::EntryType::Regular, mode, mtime);
+                header.set_size(len);
+                archiver.append_data(&mut header, Path::new(OsStr::from_bytes(name)), content)?;
+            }
+            Writer::Zip { archiver, indexes } => {
+                let index = archiver.append_data(name, mode, mtime, len, content)?;
+                indexes.insert(name.to_vec(), index);
+            }
+        }
+        Ok(())
+    }
+
+    fn symlink(&mut self, name: &[u8], mode: u32, mtime: FileTime, target: &[u8]) -> anyhow::Result<()> {
+        match self {
+            Writer::Tar(archiver) => {
+                let mut header = tar_header(tar::EntryType::Symlink, mode, mtime);
+                archiver.append_link(
+                    &mut header,
+                    Path::new(OsStr::from_bytes(name)),
+                    Path::new(OsStr::from_bytes(target)),
+                )?;
+            }
+            Writer::Zip { archiver, .. } => {
+                archiver.append_symlink_to(name, mode, mtime, target)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a hard link to the entry named `target`, zip has none and gets a copy of its content.
+    fn hard_link(&mut self, dest: &Path, name: &[u8], mode: u32, mtime: FileTime, target: &[u8]) -> anyhow::Result<()> {
+        match self {
+            Writer::Tar(archiver) => {
+                let mut header = tar_header(tar::EntryType::Link, mode, mtime);
+                archiver.append_link(
+                    &mut header,
+                    Path::new(OsStr::from_bytes(name)),
+                    Path::new(OsStr::from_bytes(target)),
+                )?;
+            }
+            Writer::Zip { archiver, indexes } => {
+                let of = *indexes.get(target).ok_or_else(|| {
+                    anyhow!(
+                        "hard link {} points to {}, which wasn't archived before it",
+                        String::from_utf8_lossy(name),
+                        String::from_utf8_lossy(target)
+                    )
+                })?;
+                if is_stdout(dest) {
+                    return Err(anyhow!(
+                        "hard link {} copies content read back from the archive, it can't be written to stdout",
+                        String::from_utf8_lossy(name)
+                    ))
+                    .context(Exit::Usage);
+                }
+                archiver.flush()?;
+                let (offset, _) = archiver.content_range(of);
+                let mut archive = File::open(dest)?;
+                archive.seek(io::SeekFrom::Start(offset))?;
+                let index = archiver.append_copy_of(name, mode, mtime, of, archive)?;
+                indexes.insert(name.to_vec(), index);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        match self {
+            Writer::Tar(archiver) => archiver.into_inner()?.finish()?.flush()?,
+            Writer::Zip { archiver, .. } => archiver.finish()?.flush()?,
+        }
+        Ok(())
+    }
+}
+
+fn tar_header(kind: tar::EntryType, mode: u32, mtime: FileTime) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(kind);
+    header.set_mode(mode & 0o7777);
+    header.set_mtime(mtime.unix_seconds().max(0) as u64);
+    header.set_size(0);
+    header
+}
+
+/// Re-pack the tar.gz or zip archive `source` into `dest`, without reading the tracked files again.
+///
+/// The source format is told from its content, the destination one from `format` or else from its
+/// extension. Names, permissions, modification times, symlinks and hard links are kept. Directory
+/// entries are implied by the names of the files under them and left out, like other special files.
+/// Tar ownership and extended attributes have no zip equivalent and are lost converting to zip.
+pub fn convert(source: &Path, dest: &Path, format: Option<ArchiveFormat>) -> anyhow::Result<()> {
+    let format = match format.or_else(|| ArchiveFormat::of(dest)) {
+        Some(format) => format,
+        None if is_stdout(dest) => {
+            return Err(anyhow!("--to tar or --to zip is needed to write to stdout")).context(Exit::Usage)
+        }
+        None => {
+            return Err(anyhow!(
+                "can't tell the format of {} from its extension, use .tar.gz, .tgz or .zip or give --to",
+                dest.display()
+            ))
+            .context(Exit::Usage)
+        }
+    };
+    if is_stdout(dest) && atty::is(atty::Stream::Stdout) {
+        return Err(anyhow!("refusing to write an archive to a terminal")).context(Exit::Usage);
+    }
+    if !is_stdout(source) && !is_stdout(dest) {
+        if let (Ok(a), Ok(b)) = (fs::canonicalize(source), fs::canonicalize(dest)) {
+            if a == b {
+                return Err(anyhow!("{} can't be converted into itself", source.display())).context(Exit::Usage);
+            }
+        }
+    }
+
+    let mut input: Box<dyn Read> = if is_stdout(source) {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(File::open(source).context(format!("could not open {}", source.display()))?)
+    };
+    let mut magic = [0; 4];
+    input
+        .read_exact(&mut magic)
+        .context(format!("could not read {}", source.display()))?;
+    let input = io::Cursor::new(magic).chain(input);
+    let is_zip = magic == *b"PK\x03\x04" || magic == *b"PK\x05\x06";
+    if !is_zip && magic[..2] != [0x1f, 0x8b] {
+        return Err(anyhow!("{} is not a tar.gz or zip archive", source.display())).context(Exit::Usage);
+    }
+
+    let count = write_atomically(dest, |path| {
+        let output = BufWriter::new(create_archive(path)?);
+        let mut writer = match format {
+            ArchiveFormat::Tar => Writer::Tar(tar::Builder::new(gz::Compressor::new(output, 1))),
+            ArchiveFormat::Zip => Writer::Zip {
+                archiver: ZipWriter::new(output, Zip64::Auto),
+                indexes: HashMap::new(),
+            },
+        };
+        let count = if is_zip {
+            convert_zip(source, input, &mut writer)
+        } else {
+            convert_tar(input, path, &mut writer)
+        }?;
+        writer.finish()?;
+        Ok(count)
+    })?;
+    log::info(
+        "convert-done",
+        vec![("entries", (count as u64).into()), ("dest", Value::path(dest))],
+    );
+    Ok(())
+}
+
+/// Copy the entries of a tar.gz archive, returning how many were written.
+fn convert_tar(input: impl Read, dest: &Path, writer: &mut Writer) -> anyhow::Result<usize> {
+    let mut archive = tar::Archive::new(flate2::read::MultiGzDecoder::new(input));
+    let mut count = 0;
+    for entry in archive.entries()? {
+        interrupt::check()?;
+        let mut entry = entry?;
+        let name = entry.path_bytes().into_owned();
+        let header = entry.header();
+        let mode = header.mode()?;
+        let mtime = FileTime::from_unix_time(header.mtime()? as i64, 0);
+        let kind = header.entry_type();
+        if kind.is_file() || kind.is_contiguous() {
+            let len = entry.size();
+            writer.file(&name, mode, mtime, len, &mut entry)?;
+        } else if kind.is_symlink() || kind.is_hard_link() {
+            let target = entry
+                .link_name_bytes()
+                .ok_or_else(|| anyhow!("link {} has no target", String::from_utf8_lossy(&name)))?
+                .into_owned();
+            if kind.is_symlink() {
+                writer.symlink(&name, mode, mtime, &target)?;
+            } else {
+                writer.hard_link(dest, &name, mode, mtime, &target)?;
+            }
+        } else {
+            if !kind.is_dir() {
+                log::skipping(Path::new(OsStr::from_bytes(&name)), "which is not a file or a symlink");
+            }
+            continue;
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Copy the entries of a zip archive, returning how many were written.
+///
+/// The central directory is at the end of the archive, one read from stdin is spooled to a temporary
+/// file first.
+fn convert_zip(source: &Path, input: impl Read, writer: &mut Writer) -> anyhow::Result<usize> {
+    let spooled;
+    let path = if is_stdout(source) {
+        spooled = TempFile::new("convert.zip");
+        io::copy(&mut { input }, &mut File::create(&spooled.0)?)?;
+        spooled.0.as_path()
+    } else {
+        source
+    };
+    let mut file = File::open(path).context(format!("could not open {}", source.display()))?;
+    let entries = zip::entries(&mut file).context(format!("could not read {}", source.display()))?;
+    let mut count = 0;
+    for entry in &entries {
+        interrupt::check()?;
+        let name = String::from_utf8_lossy(&entry.name).into_owned();
+        // Archives not written on unix have no mode, their files get the usual permissions.
+        let mode = if entry.mode == 0 { 0o644 } else { entry.mode };
+        if entry.is_dir() {
+            continue;
+        }
+        let mut content = zip::open_entry(&mut file, entry)?;
+        if entry.is_symlink() {
+            let mut target = Vec::new();
+            content
+                .read_to_end(&mut target)
+                .context(format!("could not read zip entry {}", name))?;
+            writer.symlink(&entry.name, mode, entry.mtime, &target)?;
+        } else {
+            writer
+                .file(&entry.name, mode, entry.mtime, entry.uncompressed, content)
+                .context(format!("could not convert zip entry {}", name))?;
+        }
+        count += 1;
+    }
+    Ok(count)
+}
@@ -0,0 +1,462 @@
+use std::{
+    ffi::CString,
+    fs,
+    io::{self, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context};
+use path_absolutize::Absolutize;
+use termcolor::{Color, ColorSpec, WriteColor};
+
+use crate::{absolute, exit::Exit, fold_case, json, path_key, resolve_stored, undo, Base, PathsDB, MIGRATIONS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+    /// Failed or warned, and repaired by --fix.
+    Fixed,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Pass => "pass",
+            Status::Warn => "warn",
+            Status::Fail => "fail",
+            Status::Fixed => "fixed",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Status::Pass | Status::Fixed => Color::Green,
+            Status::Warn => Color::Yellow,
+            Status::Fail => Color::Red,
+        }
+    }
+}
+
+struct Check {
+    name: &'static str,
+    status: Status,
+    message: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, message: impl Into<String>) -> Check {
+        Check {
+            name,
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// Whether the process may write to an existing directory, asked to the kernel so nothing is created.
+fn is_writable(dir: &Path) -> bool {
+    match CString::new(dir.as_os_str().as_bytes()) {
+        Ok(dir) => unsafe { libc::access(dir.as_ptr(), libc::W_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
+fn check_database(paths_db: &PathsDB) -> anyhow::Result<Check> {
+    let version = paths_db.schema_version()?;
+    Ok(if version == MIGRATIONS.len() {
+        Check::new("database", Status::Pass, format!("opened, schema version {}", version))
+    } else {
+        Check::new(
+            "database",
+            Status::Fail,
+            format!("schema version {} instead of {}", version, MIGRATIONS.len()),
+        )
+    })
+}
+
+fn check_journal_mode(paths_db: &PathsDB) -> anyhow::Result<Check> {
+    if paths_db.path.is_none() {
+        return Ok(Check::new("journal mode", Status::Pass, "in memory"));
+    }
+    let mode: String = paths_db.handle.query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+    Ok(if mode.eq_ignore_ascii_case("wal") {
+        Check::new("journal mode", Status::Pass, "wal")
+    } else {
+        Check::new(
+            "journal mode",
+            Status::Warn,
+            format!("{}, readers wait on writers without wal", mode),
+        )
+    })
+}
+
+fn check_config_dir() -> Check {
+    match dirs::config_dir() {
+        None => Check::new("config dir", Status::Fail, "neither $XDG_CONFIG_HOME nor $HOME is set"),
+        Some(dir) if !dir.is_dir() => Check::new("config dir", Status::Fail, format!("{} is missing", dir.display())),
+        Some(dir) if !is_writable(&dir) => {
+            Check::new("config dir", Status::Fail, format!("{} is not writable", dir.display()))
+        }
+        Some(dir) => Check::new("config dir", Status::Pass, format!("{} is writable", dir.display())),
+    }
+}
+
+/// Why a tracked path can't be scanned, if it can't.
+fn root_problem(root: &Path) -> Option<String> {
+    let meta = match fs::metadata(root) {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return None,
+        Err(err) => return Some(err.to_string()),
+    };
+    let readable = if meta.is_dir() {
+        fs::read_dir(root).map(drop)
+    } else {
+        fs::File::open(root).map(drop)
+    };
+    readable.err().map(|err| err.to_string())
+}
+
+/// Tracked path among `roots` other than `root` which `root` is under, compared ignoring case with
+/// --case-insensitive.
+fn parent_root<'a>(
+    roots: impl IntoIterator<Item = &'a PathBuf>,
+    root: &Path,
+    case_insensitive: bool,
+) -> Option<&'a PathBuf> {
+    let key = path_key(root, case_insensitive);
+    roots
+        .into_iter()
+        .find(|parent| *parent != root && key.starts_with(path_key(parent, case_insensitive)))
+}
+
+/// Check the tracked paths, removing the missing and the nested ones with `fix`.
+fn check_roots(paths_db: &PathsDB, fix: bool, checks: &mut Vec<Check>) -> anyhow::Result<()> {
+    let roots = paths_db.list()?;
+    let operation = if fix { undo::start(paths_db, "doctor --fix")? } else { 0 };
+    let mut removed: Vec<&PathBuf> = Vec::new();
+    for root in &roots {
+        if fs::symlink_metadata(root).is_err() {
+            if fix {
+                paths_db.rm(root, operation)?;
+                removed.push(root);
+                checks.push(Check::new(
+                    "root",
+                    Status::Fixed,
+                    format!("{} is missing, removed", root.display()),
+                ));
+            } else {
+                checks.push(Check::new(
+                    "root",
+                    Status::Fail,
+                    format!("{} is missing", root.display()),
+                ));
+            }
+        } else if let Some(problem) = root_problem(root) {
+            checks.push(Check::new(
+                "root",
+                Status::Fail,
+                format!("{} is not readable: {}", root.display(), problem),
+            ));
+        } else {
+            checks.push(Check::new(
+                "root",
+                Status::Pass,
+                format!("{} is readable", root.display()),
+            ));
+        }
+    }
+    let case_insensitive = paths_db.case_insensitive()?;
+    for root in &roots {
+        if removed.contains(&root) {
+            continue;
+        }
+        let parent = parent_root(
+            roots.iter().filter(|parent| !removed.contains(parent)),
+            root,
+            case_insensitive,
+        );
+        if let Some(parent) = parent {
+            if fix {
+                paths_db.rm(root, operation)?;
+                removed.push(root);
+                checks.push(Check::new(
+                    "nesting",
+                    Status::Fixed,
+                    format!("{} is under {}, removed", root.display(), parent.display()),
+                ));
+            } else {
+                checks.push(Check::new(
+                    "nesting",
+                    Status::Warn,
+                    format!(
+                        "{} is under {}, which already matches it",
+                        root.display(),
+                        parent.display()
+                    ),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check the health of the database and the tracked paths, printing a report and failing if any check fails.
+///
+/// Nothing is changed unless `fix` is set, which removes the missing tracked paths and the ones nested
+/// under another tracked path, along with their tags and excludes.
+pub fn doctor(paths_db: &PathsDB, fix: bool, json: bool, out: &mut impl WriteColor) -> anyhow::Result<()> {
+    let mut checks = vec![
+        check_database(paths_db)?,
+        check_journal_mode(paths_db)?,
+        check_config_dir(),
+    ];
+    let tx = paths_db.handle.unchecked_transaction()?;
+    check_roots(paths_db, fix, &mut checks)?;
+    tx.commit()?;
+
+    print_checks(&checks, "doctor", json, out)?;
+    let failed = checks.iter().filter(|check| check.status == Status::Fail).count();
+    if failed > 0 {
+        bail!("{} of {} checks failed", failed, checks.len());
+    }
+    Ok(())
+}
+
+/// Path as stored with `.`, `..` and trailing slashes resolved, relative ones staying relative.
+///
+/// Paths compare equal whatever their `.` and trailing slashes are, rebuilding the path from its
+/// components is what drops them from the bytes.
+fn normalized(stored: &Path, base: Option<Base>) -> anyhow::Result<PathBuf> {
+    let normal = match base {
+        Some(_) => {
+            let root = Path::new("/");
+            let absolute = root.join(stored).absolutize_from(root)?.into_owned();
+            absolute.strip_prefix(root)?.to_path_buf()
+        }
+        None => absolute(stored)?.into_owned(),
+    };
+    Ok(normal.components().collect())
+}
+
+/// Move the tags and excludes of the row stored as `from` to the one stored as `to`, created with the
+/// other columns of `from` when there's none, then delete `from`.
+fn merge_row(paths_db: &PathsDB, from: &Path, to: &Path) -> anyhow::Result<()> {
+    let handle = &paths_db.handle;
+    let (from, folded, to) = (from.as_os_str().as_bytes(), fold_case(to), to.as_os_str().as_bytes());
+    handle.execute(
+        "INSERT OR IGNORE INTO paths (path, base, added_at, kind, folded, last_scanned_at, last_match_count)
+         SELECT ?, base, added_at, kind, ?, last_scanned_at, last_match_count FROM paths WHERE path = ?",
+        rusqlite::params![to, folded.as_os_str().as_bytes(), from],
+    )?;
+    handle.execute(
+        "INSERT OR IGNORE INTO tags (path, tag) SELECT ?, tag FROM tags WHERE path = ?",
+        rusqlite::params![to, from],
+    )?;
+    handle.execute(
+        "INSERT OR IGNORE INTO excludes (path, pattern) SELECT ?, pattern FROM excludes WHERE path = ?",
+        rusqlite::params![to, from],
+    )?;
+    handle.execute("DELETE FROM paths WHERE path = ?", [from])?;
+    Ok(())
+}
+
+/// Rewrite the tracked paths as add would store them today, in a single transaction.
+///
+/// Paths get `.`, `..` and trailing slashes resolved, the ones which are then the same path as another,
+/// also ignoring case with --case-insensitive, are merged into it with their tags and excludes, and the
+/// ones nested under another tracked path are removed, which undo reverts. With `dry_run` the changes
+/// are only printed.
+pub fn normalize(paths_db: &PathsDB, dry_run: bool, out: &mut impl Write) -> anyhow::Result<()> {
+    let case_insensitive = paths_db.case_insensitive()?;
+    let tx = paths_db.handle.unchecked_transaction()?;
+    let verb = |done: &'static str, planned: &'static str| if dry_run { planned } else { done };
+    let mut changes = 0;
+    // Rows left after merging, as stored then resolved.
+    let mut kept: Vec<(PathBuf, Option<Base>, PathBuf)> = Vec::new();
+    for (stored, base) in paths_db.stored()? {
+        let normal = normalized(&stored, base)?;
+        let resolved = resolve_stored(&normal, base)?;
+        let key = path_key(&resolved, case_insensitive);
+        match kept
+            .iter()
+            .find(|(_, _, other)| path_key(other, case_insensitive) == key)
+        {
+            // Already merged into the row normalized before it.
+            Some((into, _, _)) if into.as_os_str() == stored.as_os_str() => {}
+            Some((into, _, other)) => {
+                merge_row(paths_db, &stored, into)?;
+                writeln!(
+                    out,
+                    "{} {} into {}, the same path",
+                    verb("Merged", "Would merge"),
+                    resolve_stored(&stored, base)?.display(),
+                    other.display()
+                )?;
+                changes += 1;
+            }
+            None => {
+                if normal.as_os_str() != stored.as_os_str() {
+                    merge_row(paths_db, &stored, &normal)?;
+                    writeln!(
+                        out,
+                        "{} {} to {}",
+                        verb("Normalized", "Would normalize"),
+                        resolve_stored(&stored, base)?.display(),
+                        resolved.display()
+                    )?;
+                    changes += 1;
+                }
+                kept.push((normal, base, resolved));
+            }
+        }
+    }
+    // Rows edited by hand may lack the folded case the lookups of --case-insensitive use.
+    for (stored, _, _) in &kept {
+        paths_db.handle.execute(
+            "UPDATE paths SET folded = ? WHERE path = ?",
+            rusqlite::params![fold_case(stored).as_os_str().as_bytes(), stored.as_os_str().as_bytes()],
+        )?;
+    }
+    let operation = undo::start(paths_db, "db-normalize")?;
+    let roots: Vec<PathBuf> = kept.iter().map(|(_, _, resolved)| resolved.clone()).collect();
+    for (stored, base, resolved) in &kept {
+        if let Some(parent) = parent_root(&roots, resolved, case_insensitive) {
+            let path = stored.as_os_str().as_bytes();
+            let base = base.map(Base::as_str);
+            undo::record(paths_db, operation, path, base)?;
+            paths_db.handle.execute(
+                "DELETE FROM paths WHERE path = ? AND base IS ?",
+                rusqlite::params![path, base],
+            )?;
+            writeln!(
+                out,
+                "{} {}, nested under {}",
+                verb("Removed", "Would remove"),
+                resolved.display(),
+                parent.display()
+            )?;
+            changes += 1;
+        }
+    }
+    if dry_run {
+        tx.rollback()?;
+        writeln!(out, "Would make {} changes", changes)?;
+    } else {
+        tx.commit()?;
+        if changes == 0 {
+            writeln!(out, "The database is normalized already")?;
+        } else {
+            writeln!(out, "Made {} changes", changes)?;
+        }
+    }
+    Ok(())
+}
+
+fn print_checks(checks: &[Check], command: &str, json: bool, out: &mut impl WriteColor) -> anyhow::Result<()> {
+    if json {
+        let checks = checks
+            .iter()
+            .map(|check| {
+                json::object([
+                    ("check", check.name.into()),
+                    ("status", check.status.as_str().into()),
+                    ("message", check.message.as_str().into()),
+                ])
+            })
+            .collect();
+        writeln!(out, "{}", json::envelope(command, json::Value::Array(checks)))?;
+    } else {
+        for check in checks {
+            out.set_color(ColorSpec::new().set_fg(Some(check.status.color())))?;
+            write!(out, "{:5}", check.status.as_str())?;
+            out.reset()?;
+            writeln!(out, "  {}: {}", check.name, check.message)?;
+        }
+    }
+    Ok(())
+}
+
+/// Problems reported by a pragma listing one per row, at most this many are printed.
+const MAX_PROBLEMS: usize = 10;
+
+fn check_integrity(paths_db: &PathsDB) -> anyhow::Result<Vec<Check>> {
+    let mut stmt = paths_db.handle.prepare("PRAGMA integrity_check")?;
+    let rows = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    if rows == ["ok"] {
+        return Ok(vec![Check::new("integrity", Status::Pass, "ok")]);
+    }
+    Ok(rows
+        .into_iter()
+        .take(MAX_PROBLEMS)
+        .map(|problem| Check::new("integrity", Status::Fail, problem))
+        .collect())
+}
+
+fn check_foreign_keys(paths_db: &PathsDB) -> anyhow::Result<Vec<Check>> {
+    let mut stmt = paths_db.handle.prepare("PRAGMA foreign_key_check")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(format!(
+                "row {} of {} references a missing row of {}",
+                row.get::<_, Option<i64>>(1)?
+                    .map_or("?".to_string(), |id| id.to_string()),
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(2)?
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    if rows.is_empty() {
+        return Ok(vec![Check::new(
+            "foreign keys",
+            Status::Pass,
+            "every reference resolves",
+        )]);
+    }
+    let mut checks: Vec<Check> = rows
+        .iter()
+        .take(MAX_PROBLEMS)
+        .map(|problem| Check::new("foreign keys", Status::Fail, problem.as_str()))
+        .collect();
+    if rows.len() > MAX_PROBLEMS {
+        checks.push(Check::new(
+            "foreign keys",
+            Status::Fail,
+            format!("and {} more", rows.len() - MAX_PROBLEMS),
+        ));
+    }
+    Ok(checks)
+}
+
+fn check_foreign_key_enforcement(paths_db: &PathsDB) -> anyhow::Result<Check> {
+    let enforced: bool = paths_db.handle.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+    Ok(if enforced {
+        Check::new("enforcement", Status::Pass, "foreign keys are enforced")
+    } else {
+        Check::new(
+            "enforcement",
+            Status::Fail,
+            "foreign keys aren't enforced, removals leave tags and excludes behind",
+        )
+    })
+}
+
+/// Check the database file itself with SQLite, printing a report and failing if it's damaged.
+///
+/// Runs `PRAGMA integrity_check`, which reads every page, and `PRAGMA foreign_key_check`, and checks
+/// that foreign keys are enforced on the connection, which `PathsDB::open` turns on.
+pub fn verify_db(paths_db: &PathsDB, json: bool, out: &mut impl WriteColor) -> anyhow::Result<()> {
+    let mut checks = check_integrity(paths_db)?;
+    checks.extend(check_foreign_keys(paths_db)?);
+    checks.push(check_foreign_key_enforcement(paths_db)?);
+    print_checks(&checks, "verify-db", json, out)?;
+    let failed = checks.iter().filter(|check| check.status == Status::Fail).count();
+    if failed > 0 {
+        return Err(anyhow!("{} problems found in the database", failed)).context(Exit::Database);
+    }
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use std::fmt::{self, Display};
+
+/// Failure categories with a dedicated process exit code, attached to errors as context.
+///
+/// The exit codes are part of the scripting contract of track:
+///
+/// - 0: success
+/// - 1: any other error
+/// - 2: invalid usage, also returned by the argument parser
+/// - 3: the database couldn't be opened, queried or locked
+/// - 4: an export failed after it started writing, the destination may be partial
+/// - 5: restored files don't match their checksum
+/// - 130: interrupted by SIGINT or SIGTERM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Usage = 2,
+    Database = 3,
+    PartialExport = 4,
+    Mismatch = 5,
+    Interrupted = 130,
+}
+
+impl Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Exit::Usage => "invalid usage",
+            Exit::Database => "database error",
+            Exit::PartialExport => "export did not complete, the destination may be partial",
+            Exit::Mismatch => "checksum mismatch",
+            Exit::Interrupted => "interrupted",
+        })
+    }
+}
+
+impl std::error::Error for Exit {}
+
+/// Exit code the process should return for an error.
+pub fn code(err: &anyhow::Error) -> u8 {
+    // The interruption is why the command failed, whatever error the command stopped with.
+    if crate::interrupt::requested() {
+        Exit::Interrupted as u8
+    } else if let Some(exit) = err.downcast_ref::<Exit>() {
+        *exit as u8
+    } else if err.downcast_ref::<rusqlite::Error>().is_some() {
+        Exit::Database as u8
+    } else {
+        1
+    }
+}
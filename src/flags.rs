@@ -0,0 +1,98 @@
+use std::{io, path::Path};
+
+/// Flags set by chattr, from linux/fs.h.
+const SYNC: u32 = 0x0000_0008;
+const IMMUTABLE: u32 = 0x0000_0010;
+const APPEND: u32 = 0x0000_0020;
+const NODUMP: u32 = 0x0000_0040;
+const NOATIME: u32 = 0x0000_0080;
+const COMPRESS: u32 = 0x0000_0004;
+const NOCOW: u32 = 0x0080_0000;
+
+/// Letters lsattr and chattr use for the flags worth keeping, in the order lsattr prints them.
+const LETTERS: &[(u32, char)] = &[
+    (SYNC, 'S'),
+    (IMMUTABLE, 'i'),
+    (APPEND, 'a'),
+    (NODUMP, 'd'),
+    (NOATIME, 'A'),
+    (COMPRESS, 'c'),
+    (NOCOW, 'C'),
+];
+
+/// Flags of a file as returned by `FS_IOC_GETFLAGS`, none on filesystems and platforms without them.
+#[cfg(target_os = "linux")]
+pub fn read(path: &Path) -> io::Result<u32> {
+    use std::os::unix::{fs::OpenOptionsExt, io::AsRawFd};
+
+    // _IOR('f', 1, long) from linux/fs.h, not exposed by the libc crate. The kernel reads an int regardless.
+    #[cfg(target_pointer_width = "64")]
+    const FS_IOC_GETFLAGS: u32 = 0x8008_6601;
+    #[cfg(target_pointer_width = "32")]
+    const FS_IOC_GETFLAGS: u32 = 0x8004_6601;
+
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)?;
+    let mut flags: libc::c_int = 0;
+    let ret = unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_GETFLAGS as _, &mut flags) };
+    if ret == -1 {
+        let err = io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::ENOTTY | libc::ENOTSUP | libc::EINVAL | libc::ENOSYS) => Ok(0),
+            _ => Err(err),
+        };
+    }
+    Ok(flags as u32)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read(_path: &Path) -> io::Result<u32> {
+    Ok(0)
+}
+
+/// Whether the flags prevent overwriting the file, which immutable and append only files can't be.
+pub fn is_protected(flags: u32) -> bool {
+    flags & (IMMUTABLE | APPEND) != 0
+}
+
+/// The flags kept as chattr letters, like `ia` for an immutable and append only file.
+pub fn encode(flags: u32) -> String {
+    LETTERS
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|&(_, letter)| letter)
+        .collect()
+}
+
+/// Describe how a file is protected, for warnings.
+pub fn protection(flags: u32) -> &'static str {
+    if flags & IMMUTABLE != 0 {
+        "immutable"
+    } else {
+        "append only"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_are_encoded_in_the_order_of_lsattr() {
+        assert_eq!(encode(0), "");
+        assert_eq!(encode(APPEND | IMMUTABLE | NOCOW), "iaC");
+        // Flags without a letter are left out.
+        assert_eq!(encode(NODUMP | 0x0008_0000), "d");
+    }
+
+    #[test]
+    fn only_immutable_and_append_only_files_are_protected() {
+        assert!(is_protected(IMMUTABLE));
+        assert!(is_protected(APPEND | NODUMP));
+        assert!(!is_protected(NODUMP | NOATIME | SYNC));
+        assert_eq!(protection(IMMUTABLE | APPEND), "immutable");
+        assert_eq!(protection(APPEND), "append only");
+    }
+}
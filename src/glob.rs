@@ -0,0 +1,247 @@
+use std::{fmt, os::unix::ffi::OsStrExt, path::Path, str::FromStr};
+
+use anyhow::bail;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(u8),
+    /// `?`, any byte but `/`.
+    Any,
+    /// `*`, any run of bytes without `/`.
+    Star,
+    /// `**`, any run of bytes including `/`.
+    AnyPath,
+    /// `**/`, any number of leading directories, none included.
+    AnyDirs,
+    /// `[...]`, `[!...]` when negated.
+    Class {
+        negated: bool,
+        ranges: Vec<(u8, u8)>,
+    },
+}
+
+/// Shell like pattern matched against the files found under the tracked paths.
+///
+/// A pattern without `/` is matched against the file name, like `*.tmp`. A pattern with `/` is
+/// matched against the end of the path, starting at a directory boundary, so `build/*.o` matches
+/// `/home/me/project/build/main.o`; a leading `/` anchors it on the whole path instead.
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    tokens: Vec<Token>,
+    /// Whether the pattern applies to the file name instead of the path.
+    name_only: bool,
+}
+
+impl Glob {
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn matches(&self, path: &Path, ignore_case: bool) -> bool {
+        if self.name_only {
+            let name = path.file_name().map_or(&[][..], |name| name.as_bytes());
+            return match_tokens(&self.tokens, name, ignore_case);
+        }
+        let path = path.as_os_str().as_bytes();
+        if self.pattern.starts_with('/') {
+            return match_tokens(&self.tokens, path, ignore_case);
+        }
+        path.iter()
+            .enumerate()
+            .filter(|&(_, &b)| b == b'/')
+            .any(|(i, _)| match_tokens(&self.tokens, &path[i + 1..], ignore_case))
+            || match_tokens(&self.tokens, path, ignore_case)
+    }
+}
+
+impl FromStr for Glob {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let bytes = s.as_bytes();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'*' if bytes.get(i + 1) == Some(&b'*') => {
+                    let at_boundary = i == 0 || bytes[i - 1] == b'/';
+                    if at_boundary && bytes.get(i + 2) == Some(&b'/') {
+                        tokens.push(Token::AnyDirs);
+                        i += 3;
+                    } else {
+                        tokens.push(Token::AnyPath);
+                        i += 2;
+                    }
+                    continue;
+                }
+                b'*' => tokens.push(Token::Star),
+                b'?' => tokens.push(Token::Any),
+                b'[' => {
+                    let (token, end) = match parse_class(bytes, i + 1) {
+                        Some(class) => class,
+                        None => bail!("Unclosed [ in pattern {}", s),
+                    };
+                    tokens.push(token);
+                    i = end;
+                }
+                b'\\' => match bytes.get(i + 1) {
+                    Some(&b) => {
+                        tokens.push(Token::Literal(b));
+                        i += 1;
+                    }
+                    None => bail!("Dangling \\ at the end of pattern {}", s),
+                },
+                b => tokens.push(Token::Literal(b)),
+            }
+            i += 1;
+        }
+        Ok(Glob {
+            pattern: s.to_string(),
+            tokens,
+            name_only: !s.contains('/'),
+        })
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+/// Escape the special characters of `s`, so it only matches itself in a pattern.
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Parse the class starting after `[` at `start`, returning it with the index of its closing `]`.
+fn parse_class(bytes: &[u8], start: usize) -> Option<(Token, usize)> {
+    let mut i = start;
+    let negated = matches!(bytes.get(i), Some(b'!' | b'^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    // A `]` right after the opening bracket is part of the class.
+    let first = i;
+    loop {
+        let b = *bytes.get(i)?;
+        if b == b']' && i > first {
+            return Some((Token::Class { negated, ranges }, i));
+        }
+        if bytes.get(i + 1) == Some(&b'-') && bytes.get(i + 2).is_some_and(|&end| end != b']') {
+            ranges.push((b, bytes[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((b, b));
+            i += 1;
+        }
+    }
+}
+
+fn class_contains(ranges: &[(u8, u8)], b: u8, ignore_case: bool) -> bool {
+    let within = |b: u8| ranges.iter().any(|&(start, end)| start <= b && b <= end);
+    within(b) || ignore_case && (within(b.to_ascii_lowercase()) || within(b.to_ascii_uppercase()))
+}
+
+fn match_tokens(tokens: &[Token], text: &[u8], ignore_case: bool) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return text.is_empty(),
+    };
+    match token {
+        Token::Literal(b) => match text.split_first() {
+            Some((t, text)) if t == b || ignore_case && t.eq_ignore_ascii_case(b) => {
+                match_tokens(rest, text, ignore_case)
+            }
+            _ => false,
+        },
+        Token::Any => match text.split_first() {
+            Some((&t, text)) if t != b'/' => match_tokens(rest, text, ignore_case),
+            _ => false,
+        },
+        Token::Class { negated, ranges } => match text.split_first() {
+            Some((&t, text)) if t != b'/' && class_contains(ranges, t, ignore_case) != *negated => {
+                match_tokens(rest, text, ignore_case)
+            }
+            _ => false,
+        },
+        Token::Star => {
+            let run = text.iter().position(|&b| b == b'/').unwrap_or(text.len());
+            (0..=run).any(|i| match_tokens(rest, &text[i..], ignore_case))
+        }
+        Token::AnyPath => (0..=text.len()).any(|i| match_tokens(rest, &text[i..], ignore_case)),
+        Token::AnyDirs => {
+            match_tokens(rest, text, ignore_case)
+                || text
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &b)| b == b'/')
+                    .any(|(i, _)| match_tokens(rest, &text[i + 1..], ignore_case))
+        }
+    }
+}
+
+/// A named set of built-in patterns.
+pub struct Preset {
+    pub name: &'static str,
+    pub patterns: &'static [&'static str],
+}
+
+/// Caches and build outputs which are regenerated when missing.
+pub const CACHES_PRESET: &Preset = &Preset {
+    name: "caches",
+    patterns: &[
+        "**/__pycache__/**",
+        "*.pyc",
+        "**/.mypy_cache/**",
+        "**/target/**",
+        "**/node_modules/**",
+        "**/.cargo/registry/**",
+        "*.o",
+        "*.class",
+    ],
+};
+
+pub const PRESETS: &[&Preset] = &[CACHES_PRESET];
+
+pub fn preset_patterns(preset: &Preset) -> impl Iterator<Item = Glob> {
+    preset
+        .patterns
+        .iter()
+        .map(|pattern| pattern.parse().expect("invalid preset pattern"))
+}
+
+/// Patterns a path is tested against, matching when any of them does.
+#[derive(Debug, Default)]
+pub struct GlobSet {
+    globs: Vec<Glob>,
+    /// Compare ASCII letters regardless of their case.
+    ignore_case: bool,
+}
+
+impl GlobSet {
+    pub fn new(globs: Vec<Glob>, ignore_case: bool) -> GlobSet {
+        GlobSet { globs, ignore_case }
+    }
+
+    /// First pattern matching `path`, if any.
+    pub fn matching(&self, path: &Path) -> Option<&Glob> {
+        self.globs.iter().find(|glob| glob.matches(path, self.ignore_case))
+    }
+
+    /// Every pattern along with whether it matches `path`, in the order they were given.
+    pub fn each_match<'a>(&'a self, path: &'a Path) -> impl Iterator<Item = (&'a Glob, bool)> + 'a {
+        self.globs
+            .iter()
+            .map(move |glob| (glob, glob.matches(path, self.ignore_case)))
+    }
+}
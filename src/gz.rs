@@ -0,0 +1,178 @@
+use std::{
+    io::{self, Write},
+    thread,
+};
+
+use flate2::{write::GzEncoder, Compression};
+
+/// Input compressed into each gzip member by `ParallelGz`.
+const BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Gzip compressor of tar archives, on the calling thread or spread over several with `--compress-threads`.
+pub enum Compressor<W: Write> {
+    Single(GzEncoder<W>),
+    Parallel(ParallelGz<W>),
+}
+
+impl<W: Write> Compressor<W> {
+    pub fn new(out: W, threads: usize) -> Compressor<W> {
+        if threads > 1 {
+            Compressor::Parallel(ParallelGz::new(out, threads))
+        } else {
+            Compressor::Single(GzEncoder::new(out, Compression::default()))
+        }
+    }
+
+    /// Write the end of the compressed stream, returning the writer it was written to.
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Compressor::Single(encoder) => encoder.finish(),
+            Compressor::Parallel(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Compressor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Compressor::Single(encoder) => encoder.write(buf),
+            Compressor::Parallel(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Compressor::Single(encoder) => encoder.flush(),
+            Compressor::Parallel(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Gzip compressor splitting its input in blocks compressed in parallel, like pigz.
+///
+/// Every block becomes a gzip member of its own, and a file of concatenated members is a valid gzip
+/// file which gunzip and tar extract as a whole. Readers must handle multiple members, like
+/// `flate2::read::MultiGzDecoder` does. Blocks are compressed in batches of one per thread, the
+/// output stays the same whatever the number of threads.
+pub struct ParallelGz<W: Write> {
+    out: W,
+    threads: usize,
+    /// Full blocks waiting to be compressed, then the block being filled.
+    blocks: Vec<Vec<u8>>,
+    /// Whether a member was written, an empty input still needs one.
+    written: bool,
+}
+
+impl<W: Write> ParallelGz<W> {
+    pub fn new(out: W, threads: usize) -> ParallelGz<W> {
+        ParallelGz {
+            out,
+            threads,
+            blocks: vec![Vec::with_capacity(BLOCK_SIZE)],
+            written: false,
+        }
+    }
+
+    /// Compress the pending blocks in parallel and write the members in order.
+    fn compress_blocks(&mut self) -> io::Result<()> {
+        let blocks: Vec<Vec<u8>> = self.blocks.drain(..).filter(|block| !block.is_empty()).collect();
+        let members = thread::scope(|scope| {
+            let handles: Vec<_> = blocks
+                .iter()
+                .map(|block| {
+                    scope.spawn(move || {
+                        let mut encoder = GzEncoder::new(Vec::with_capacity(block.len() / 2), Compression::default());
+                        encoder.write_all(block)?;
+                        encoder.finish()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("compression thread panicked"))
+                .collect::<io::Result<Vec<Vec<u8>>>>()
+        })?;
+        for member in members {
+            self.out.write_all(&member)?;
+            self.written = true;
+        }
+        self.blocks.push(Vec::with_capacity(BLOCK_SIZE));
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.compress_blocks()?;
+        if !self.written {
+            self.out
+                .write_all(&GzEncoder::new(Vec::new(), Compression::default()).finish()?)?;
+        }
+        Ok(self.out)
+    }
+}
+
+impl<W: Write> Write for ParallelGz<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let block = self.blocks.last_mut().expect("no block being filled");
+        let len = buf.len().min(BLOCK_SIZE - block.len());
+        block.extend_from_slice(&buf[..len]);
+        if block.len() == BLOCK_SIZE {
+            if self.blocks.len() == self.threads {
+                self.compress_blocks()?;
+            } else {
+                self.blocks.push(Vec::with_capacity(BLOCK_SIZE));
+            }
+        }
+        Ok(len)
+    }
+
+    /// Flush the underlying writer, blocks are only compressed once full so the output doesn't
+    /// depend on how the input was written.
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use flate2::read::MultiGzDecoder;
+
+    use super::{Compressor, BLOCK_SIZE};
+
+    fn compress(input: &[u8], threads: usize) -> Vec<u8> {
+        let mut compressor = Compressor::new(Vec::new(), threads);
+        // Uneven writes, which mustn't change the blocks.
+        for piece in input.chunks(BLOCK_SIZE / 3 + 1) {
+            compressor.write_all(piece).unwrap();
+        }
+        compressor.finish().unwrap()
+    }
+
+    fn decompress(compressed: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        MultiGzDecoder::new(compressed).read_to_end(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn parallel_output_decompresses_to_the_input() {
+        for len in [0, 1, BLOCK_SIZE, 3 * BLOCK_SIZE + 5] {
+            let input: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            for threads in [1, 2, 3] {
+                assert!(
+                    decompress(&compress(&input, threads)) == input,
+                    "{} bytes on {} threads",
+                    len,
+                    threads
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_output_doesnt_depend_on_the_threads() {
+        let input: Vec<u8> = (0..5 * BLOCK_SIZE + 5).map(|i| (i % 251) as u8).collect();
+        assert!(compress(&input, 2) == compress(&input, 4));
+    }
+}
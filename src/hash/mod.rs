@@ -0,0 +1,131 @@
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::bail;
+
+mod blake3;
+mod md5;
+mod sha2;
+
+/// Checksum algorithms available wherever files are hashed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hasher {
+    Blake3,
+    Sha256,
+    Sha512,
+    Md5,
+}
+
+impl FromStr for Hasher {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "blake3" => Hasher::Blake3,
+            "sha256" => Hasher::Sha256,
+            "sha512" => Hasher::Sha512,
+            "md5" => Hasher::Md5,
+            _ => bail!("Unknown hash algorithm {}", s),
+        })
+    }
+}
+
+/// Incremental state of one of the checksum algorithms.
+pub enum Digest {
+    Blake3(blake3::Blake3),
+    Sha256(sha2::Sha256),
+    Sha512(sha2::Sha512),
+    Md5(md5::Md5),
+}
+
+impl Digest {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Digest::Blake3(d) => d.update(data),
+            Digest::Sha256(d) => d.update(data),
+            Digest::Sha512(d) => d.update(data),
+            Digest::Md5(d) => d.update(data),
+        }
+    }
+
+    /// Lowercase hexadecimal digest of the data.
+    pub fn finish(self) -> String {
+        let bytes = match self {
+            Digest::Blake3(d) => d.finish(),
+            Digest::Sha256(d) => d.finish(),
+            Digest::Sha512(d) => d.finish(),
+            Digest::Md5(d) => d.finish(),
+        };
+        let mut hex = String::with_capacity(bytes.len() * 2);
+        for b in bytes {
+            write!(hex, "{:02x}", b).unwrap();
+        }
+        hex
+    }
+}
+
+impl Hasher {
+    /// Name of the algorithm, as given to --hash.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Hasher::Blake3 => "blake3",
+            Hasher::Sha256 => "sha256",
+            Hasher::Sha512 => "sha512",
+            Hasher::Md5 => "md5",
+        }
+    }
+
+    pub fn digest(&self) -> Digest {
+        match self {
+            Hasher::Blake3 => Digest::Blake3(blake3::Blake3::new()),
+            Hasher::Sha256 => Digest::Sha256(sha2::Sha256::new()),
+            Hasher::Sha512 => Digest::Sha512(sha2::Sha512::new()),
+            Hasher::Md5 => Digest::Md5(md5::Md5::new()),
+        }
+    }
+
+    pub fn hash_reader(&self, mut reader: impl Read) -> io::Result<String> {
+        let mut digest = self.digest();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => return Ok(digest.finish()),
+                Ok(n) => digest.update(&buf[..n]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    pub fn hash_file(&self, path: &Path) -> io::Result<String> {
+        self.hash_reader(File::open(path)?)
+    }
+}
+
+/// Cheap signature of a file made of its size and a hash of its first and last `edge` bytes.
+///
+/// Files of the same size differing only between these ranges get the same signature, it tells
+/// files which probably didn't change, not files which certainly didn't.
+pub fn quick_signature(path: &Path, edge: u64) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let hash = if len <= 2 * edge {
+        Hasher::Blake3.hash_reader(file)?
+    } else {
+        let mut digest = Hasher::Blake3.digest();
+        let mut buf = vec![0; edge as usize];
+        file.read_exact(&mut buf)?;
+        digest.update(&buf);
+        file.seek(SeekFrom::End(-(edge as i64)))?;
+        file.read_exact(&mut buf)?;
+        digest.update(&buf);
+        digest.finish()
+    };
+    // With the edge size, signatures made with another one never match.
+    Ok(format!("{}:{}:{}", len, edge, hash))
+}
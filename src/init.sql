@@ -3,3 +3,8 @@ CREATE TABLE IF NOT EXISTS `paths` (
 );
 
 CREATE UNIQUE INDEX IF NOT EXISTS idx_paths_path ON paths (path);
+
+CREATE TABLE IF NOT EXISTS `state` (
+    key TEXT PRIMARY KEY NOT NULL,
+    value BLOB
+);
@@ -0,0 +1,363 @@
+use std::{
+    ffi::OsStr,
+    fmt::{self, Display, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail};
+
+/// Minimal JSON document model, used to produce machine readable output and to read serve requests.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Encode a path losslessly, as a string when it's valid UTF-8 and as `{"bytes": [...]}` otherwise.
+    pub fn path(path: &Path) -> Value {
+        match path.to_str() {
+            Some(s) => Value::String(s.to_owned()),
+            None => object([(
+                "bytes",
+                Value::Array(
+                    path.as_os_str()
+                        .as_bytes()
+                        .iter()
+                        .map(|&b| Value::Number(b.into()))
+                        .collect(),
+                ),
+            )]),
+        }
+    }
+
+    /// Decode a path encoded by `Value::path`.
+    pub fn to_path(&self) -> Option<PathBuf> {
+        match self {
+            Value::String(s) => Some(PathBuf::from(s)),
+            Value::Object(_) => match self.get("bytes")? {
+                Value::Array(items) => {
+                    let bytes = items
+                        .iter()
+                        .map(|item| match item {
+                            Value::Number(n) => u8::try_from(*n).ok(),
+                            _ => None,
+                        })
+                        .collect::<Option<Vec<u8>>>()?;
+                    Some(PathBuf::from(OsStr::from_bytes(&bytes)))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Value of a field of an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Value {
+        Value::Number(n.try_into().unwrap_or(i64::MAX))
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::String(s.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::String(s)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(o: Option<T>) -> Value {
+        o.map_or(Value::Null, Into::into)
+    }
+}
+
+/// Version of the JSON documents printed by the commands, increased on any breaking change to one of them.
+pub const VERSION: u64 = 1;
+
+/// Wrap the output of a command in the envelope of every JSON document, `{"version":1,"command":...,"data":...}`.
+///
+/// The data of each command:
+///
+/// - `stats`: an array of `{"path","files","size"}` objects, one per tracked path
+/// - `top`: an array of `{"path","size"}` objects, largest first
+/// - `recent`: an array of `{"path","added_at","scanned_at"}` objects, newest first, times in seconds
+///   since the epoch or null when unknown
+/// - `which`: `{"path","roots","exported"}` where roots are `{"path","exported","reason"}` objects
+/// - `explain`: the same as which, where roots also have `"filters"`, an array of
+///   `{"filter","used","excluded","reason"}` objects
+/// - `config show`: an object of `{"value","source"}` objects keyed by setting name
+/// - `config show --list-presets`: an object of pattern arrays keyed by preset name
+/// - `doctor`: an array of `{"check","status","message"}` objects, status is pass, warn, fail or fixed
+/// - `verify-db`: the same as doctor, without warn and fixed
+///
+/// Streamed outputs, `matched --format ndjson`, `--progress-format json` and `--log-format json`,
+/// print one bare object per line instead.
+pub fn envelope(command: &str, data: Value) -> Value {
+    object([("version", VERSION.into()), ("command", command.into()), ("data", data)])
+}
+
+/// Build an object from key value pairs, keeping their order.
+pub fn object<K: Into<String>, const N: usize>(fields: [(K, Value); N]) -> Value {
+    Value::Object(fields.into_iter().map(|(k, v)| (k.into(), v)).collect())
+}
+
+fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write_str(f, s),
+            Value::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_char(']')
+            }
+            Value::Object(fields) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_str(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+/// Parse a JSON document. Only integers are supported, like the documents written.
+pub fn parse(s: &str) -> anyhow::Result<Value> {
+    let mut parser = Parser { s, pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < s.len() {
+        bail!("trailing characters at {}", parser.pos);
+    }
+    Ok(value)
+}
+
+/// Nesting of arrays and objects beyond which documents are refused, so they can't overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.s.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> anyhow::Result<()> {
+        self.skip_whitespace();
+        if self.peek() != Some(c) {
+            bail!("expected {} at {}", c as char, self.pos);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> anyhow::Result<Value> {
+        if !self.s[self.pos..].starts_with(word) {
+            bail!("unexpected character at {}", self.pos);
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> anyhow::Result<Value> {
+        self.nested(0)
+    }
+
+    fn nested(&mut self, depth: usize) -> anyhow::Result<Value> {
+        if depth > MAX_DEPTH {
+            bail!("nested deeper than {} levels", MAX_DEPTH);
+        }
+        self.skip_whitespace();
+        match self.peek() {
+            None => bail!("unexpected end of document"),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    items.push(self.nested(depth + 1)?);
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Value::Array(items));
+                        }
+                        _ => bail!("expected , or ] at {}", self.pos),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Value::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some(b'"') {
+                        bail!("expected a key at {}", self.pos);
+                    }
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    fields.push((key, self.nested(depth + 1)?));
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Value::Object(fields));
+                        }
+                        _ => bail!("expected , or }} at {}", self.pos),
+                    }
+                }
+            }
+            Some(_) => bail!("unexpected character at {}", self.pos),
+        }
+    }
+
+    fn number(&mut self) -> anyhow::Result<Value> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        if matches!(self.peek(), Some(b'.' | b'e' | b'E')) {
+            bail!("unsupported non-integer number at {}", start);
+        }
+        let number = &self.s[start..self.pos];
+        number
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| anyhow!("invalid number {} at {}", number, start))
+    }
+
+    fn hex4(&mut self) -> anyhow::Result<u32> {
+        let digits = self
+            .s
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| anyhow!("truncated escape at {}", self.pos))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| anyhow!("invalid escape at {}", self.pos))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn string(&mut self) -> anyhow::Result<String> {
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let rest = &self.s[self.pos..];
+            let end = rest
+                .find(['"', '\\'])
+                .ok_or_else(|| anyhow!("unterminated string at {}", self.pos))?;
+            if let Some(c) = rest[..end].chars().find(|&c| (c as u32) < 0x20) {
+                bail!("unescaped control character {:?} in string", c);
+            }
+            out.push_str(&rest[..end]);
+            self.pos += end + 1;
+            if rest.as_bytes()[end] == b'"' {
+                return Ok(out);
+            }
+            let escape = self.peek().ok_or_else(|| anyhow!("truncated escape at {}", self.pos))?;
+            self.pos += 1;
+            match escape {
+                b'"' => out.push('"'),
+                b'\\' => out.push('\\'),
+                b'/' => out.push('/'),
+                b'b' => out.push('\u{8}'),
+                b'f' => out.push('\u{c}'),
+                b'n' => out.push('\n'),
+                b'r' => out.push('\r'),
+                b't' => out.push('\t'),
+                b'u' => {
+                    let mut code = self.hex4()?;
+                    if (0xd800..0xdc00).contains(&code) && self.s[self.pos..].starts_with("\\u") {
+                        self.pos += 2;
+                        let low = self.hex4()?;
+                        if !(0xdc00..0xe000).contains(&low) {
+                            bail!("invalid surrogate pair at {}", self.pos);
+                        }
+                        code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                    }
+                    out.push(char::from_u32(code).ok_or_else(|| anyhow!("invalid escape at {}", self.pos))?);
+                }
+                _ => bail!("invalid escape at {}", self.pos - 1),
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::glob::{self, Glob};
+
+/// Beginning of the pointer files git LFS leaves in the working tree in place of content which wasn't fetched.
+const POINTER_PREFIX: &[u8] = b"version https://git-lfs.github.com/spec/";
+/// Pointers are about 130 bytes, anything bigger is real content.
+const MAX_POINTER_SIZE: u64 = 1024;
+
+/// Patterns of a `.gitattributes` file setting the `filter` attribute, true for the ones routing files through LFS.
+type Rules = Vec<(Glob, bool)>;
+
+/// Finds the files materialized by git LFS, according to the `.gitattributes` at the root of their repository.
+#[derive(Debug, Default)]
+pub struct LfsFilter {
+    /// Rules of the directories looked at so far, none for directories which aren't a repository root.
+    repos: Mutex<HashMap<PathBuf, Option<Arc<Rules>>>>,
+}
+
+impl LfsFilter {
+    /// Whether `file` is tracked with LFS in its repository and holds the content itself rather than a pointer.
+    pub fn is_materialized(&self, file: &Path) -> bool {
+        let rules = match self.repo_rules(file) {
+            Some(rules) => rules,
+            None => return false,
+        };
+        // Like git, the last line setting the attribute wins.
+        let lfs = rules
+            .iter()
+            .rev()
+            .find(|(glob, _)| glob.matches(file, false))
+            .is_some_and(|(_, lfs)| *lfs);
+        lfs && !is_pointer(file)
+    }
+
+    /// Rules of the nearest repository containing `file`.
+    fn repo_rules(&self, file: &Path) -> Option<Arc<Rules>> {
+        let mut repos = self.repos.lock().expect("lfs cache poisoned");
+        for dir in file.ancestors().skip(1) {
+            let rules = repos
+                .entry(dir.to_path_buf())
+                .or_insert_with(|| is_repo_root(dir).then(|| Arc::new(read_rules(dir))));
+            if let Some(rules) = rules {
+                return Some(rules.clone());
+            }
+        }
+        None
+    }
+}
+
+/// Whether a directory is the root of a git repository, which has a `.git` directory, or a `.git`
+/// file for worktrees and submodules.
+pub fn is_repo_root(dir: &Path) -> bool {
+    fs::symlink_metadata(dir.join(".git")).is_ok()
+}
+
+/// Parse the `.gitattributes` at the root of the repository `root`, missing or unreadable ones have no rules.
+fn read_rules(root: &Path) -> Rules {
+    let content = match fs::read_to_string(root.join(".gitattributes")) {
+        Ok(content) => content,
+        Err(_) => return Vec::new(),
+    };
+    let root = match root.to_str() {
+        Some(root) => glob::escape(root.trim_end_matches('/')),
+        None => return Vec::new(),
+    };
+    let mut rules = Vec::new();
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let pattern = match fields.next() {
+            Some(pattern) if !pattern.starts_with('#') => pattern,
+            _ => continue,
+        };
+        let lfs = match fields.rev().find(|attr| {
+            let name = attr.trim_start_matches(['-', '!']);
+            name == "filter" || name.starts_with("filter=")
+        }) {
+            Some(attr) => attr == "filter=lfs",
+            None => continue,
+        };
+        // Patterns without a slash match the name at any depth, the others are relative to the root.
+        let pattern = if pattern.trim_end_matches('/').contains('/') {
+            format!("{}/{}", root, pattern.trim_start_matches('/'))
+        } else {
+            pattern.to_string()
+        };
+        if let Ok(glob) = pattern.parse() {
+            rules.push((glob, lfs));
+        }
+    }
+    rules
+}
+
+fn is_pointer(file: &Path) -> bool {
+    let small = fs::metadata(file).is_ok_and(|meta| meta.len() <= MAX_POINTER_SIZE);
+    let mut start = [0; POINTER_PREFIX.len()];
+    small
+        && File::open(file)
+            .and_then(|mut file| file.read_exact(&mut start))
+            .is_ok()
+        && start == POINTER_PREFIX
+}
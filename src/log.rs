@@ -0,0 +1,134 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context};
+
+use crate::{json::Value, snapshot};
+
+/// How the records of `--log-file` are written, one per line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `2024-01-31T23:59:59Z info export-done files=3 dest="/backup"`, field values are JSON.
+    Text,
+    /// `{"time":"2024-01-31T23:59:59Z","level":"info","event":"export-done","files":3,"dest":"/backup"}`,
+    /// paths are encoded like in the other JSON outputs.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "text" => LogFormat::Text,
+            "json" => LogFormat::Json,
+            _ => bail!("Unknown log format {}", s),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn as_str(self) -> &'static str {
+        match self {
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+struct Log {
+    file: File,
+    format: LogFormat,
+}
+
+static LOG: OnceLock<Mutex<Log>> = OnceLock::new();
+
+/// Append the records of this run to `path`, nothing is recorded until it's opened.
+///
+/// Records only hold the command name, paths, counts and errors, never the command line or the
+/// environment.
+pub fn open(path: &Path, format: LogFormat) -> anyhow::Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context(format!("could not open log file {}", path.display()))?;
+    let _ = LOG.set(Mutex::new(Log { file, format }));
+    Ok(())
+}
+
+/// Time of a record, like `2024-01-31T23:59:59Z` in UTC.
+pub fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs()) as i64;
+    let (year, month, day) = snapshot::civil_from_days(secs.div_euclid(86400));
+    let secs_of_day = secs.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+/// Write a record of `event`, failing to write it doesn't fail the command.
+fn record(level: Level, event: &str, fields: Vec<(&str, Value)>) {
+    let mut log = match LOG.get() {
+        Some(log) => log.lock().expect("log lock poisoned"),
+        None => return,
+    };
+    let time = timestamp(SystemTime::now());
+    let line = match log.format {
+        LogFormat::Text => {
+            let mut line = format!("{} {} {}", time, level.as_str(), event);
+            for (key, value) in &fields {
+                line.push_str(&format!(" {}={}", key, value));
+            }
+            line
+        }
+        LogFormat::Json => {
+            let mut object = vec![
+                ("time".to_string(), Value::from(time)),
+                ("level".to_string(), level.as_str().into()),
+                ("event".to_string(), event.into()),
+            ];
+            object.extend(fields.into_iter().map(|(key, value)| (key.to_string(), value)));
+            Value::Object(object).to_string()
+        }
+    };
+    let _ = writeln!(log.file, "{}", line);
+}
+
+pub fn info(event: &str, fields: Vec<(&str, Value)>) {
+    record(Level::Info, event, fields);
+}
+
+pub fn warn(event: &str, fields: Vec<(&str, Value)>) {
+    record(Level::Warn, event, fields);
+}
+
+pub fn error(event: &str, fields: Vec<(&str, Value)>) {
+    record(Level::Error, event, fields);
+}
+
+/// Report a file left out of an export or a restore, as `Skipping PATH REASON` on stderr and in the log.
+pub fn skipping(path: &Path, reason: &str) {
+    eprintln!("Skipping {} {}", path.display(), reason);
+    warn("skipped", vec![("path", Value::path(path)), ("reason", reason.into())]);
+}
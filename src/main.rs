@@ -1,50 +1,1140 @@
 use std::{
     borrow::Cow,
-    ffi::{OsStr, OsString},
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::{CStr, CString, OsStr, OsString},
     fs::{self, DirBuilder, File},
-    io,
-    os::unix::ffi::{OsStrExt, OsStringExt},
-    path::{Path, PathBuf},
+    io::{self, BufWriter, Read, Seek, Write},
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        fs::MetadataExt,
+    },
+    path::{Component, Path, PathBuf},
+    process::ExitCode,
     str::FromStr,
+    sync::{Mutex, OnceLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{anyhow, bail, Context};
 use clap::*;
+use collect::Collision;
+use exit::Exit;
+use filetime::FileTime;
+use glob::{Glob, GlobSet};
+use hash::Hasher;
+use lfs::LfsFilter;
+use output::{
+    print_matched, print_path, print_paths_only, print_tracked, print_tracked_long, shell_escape, write_match_record,
+    ColorMode, MatchedFormat, OutputArgs, PathStyle,
+};
 use path_absolutize::Absolutize;
+use pool::Pool;
+use progress::{Progress, ProgressFormat, ProgressWhen};
+use report::{Cell, Table};
+use rusqlite::OptionalExtension;
+use settings::Settings;
+use snapshot::Retention;
 use walkdir::WalkDir;
+use zip::Zip64;
+
+mod bagit;
+mod cas;
+mod changed;
+mod collect;
+mod convert;
+mod doctor;
+mod exit;
+mod flags;
+mod glob;
+mod gz;
+mod hash;
+mod interrupt;
+mod json;
+mod lfs;
+mod log;
+mod output;
+mod paths_file;
+mod pipeline;
+mod pool;
+mod progress;
+mod report;
+mod script;
+mod serve;
+mod settings;
+mod snapshot;
+mod undo;
+mod watch;
+mod watchdog;
+mod xattrs;
+mod zip;
 
 #[derive(Debug, Parser)]
 #[clap(name = "track")]
-enum Args {
+struct Args {
+    /// Wait for another track operation to finish instead of failing.
+    #[clap(long, global = true)]
+    wait: bool,
+
+    /// Path of the database, defaults to track.db in the user config directory.
+    #[clap(long, global = true, env = "TRACK_DB")]
+    db: Option<PathBuf>,
+
+    /// Use a throwaway in-memory database instead of the persistent one, for one-shot runs adding
+    /// paths then exporting them, like `track --memory add - export tar out.tar.gz`.
+    #[clap(long, global = true)]
+    memory: bool,
+
+    /// Read the tracked paths, their tags and their excludes from this TOML file instead of the
+    /// database, so they can be kept under version control. Each path is a [[path]] table with a path
+    /// string and optional tags and exclude arrays. Commands changing the tracked paths are refused,
+    /// edit the file instead.
+    #[clap(long, global = true, value_name = "FILE", parse(try_from_os_str = expand_path))]
+    paths_file: Option<PathBuf>,
+
+    /// Number of parallel jobs, defaults to the available parallelism. Use 1 to do everything sequentially.
+    #[clap(short, long, global = true)]
+    jobs: Option<usize>,
+
+    /// Number of files the parallel jobs may have open at once, fewer jobs run when it's low.
+    /// Defaults to half of the soft limit of open files (ulimit -n).
+    #[clap(long, global = true, value_name = "N")]
+    max_open_files: Option<usize>,
+
+    /// Colorize ls and matched output, auto, always or never.
+    #[clap(long, global = true, default_value = "auto")]
+    color: ColorMode,
+
+    /// Append timestamped records of scans, exports, skipped files and errors to this file.
+    #[clap(long, global = true, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+
+    /// Format of the --log-file records, text or json.
+    #[clap(long, global = true, default_value = "text")]
+    log_format: log::LogFormat,
+
+    /// Compare tracked paths ignoring case, for case-insensitive filesystems. Saved in the database,
+    /// which keeps doing so until --case-sensitive is given.
+    #[clap(long, global = true, conflicts_with = "case-sensitive")]
+    case_insensitive: bool,
+
+    /// Compare tracked paths case-sensitively again, the default.
+    #[clap(long, global = true)]
+    case_sensitive: bool,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
     /// Add a new path to tracked paths.
-    Add { paths: Vec<PathBuf> },
+    #[clap(subcommand_precedence_over_arg = true)]
+    Add {
+        /// Paths to track, a leading ~ and $VARIABLES are expanded. - reads more paths from stdin,
+        /// separated by NULs when there is one, by line breaks otherwise, and taken as they are.
+        #[clap(parse(try_from_os_str = expand_path))]
+        paths: Vec<PathBuf>,
+        /// Add the paths of a single string split like a shell splits words, for scripts holding
+        /// several paths in a variable: `--split "$DIRS"` with DIRS="~/notes '/data/my photos'". Quotes
+        /// and backslashes are removed, then each path is expanded like the other ones.
+        #[clap(long, value_name = "LIST", parse(from_os_str))]
+        split: Vec<OsString>,
+        /// Add paths which don't exist or are under /proc, /sys or /dev anyway.
+        #[clap(long)]
+        force: bool,
+        /// Resolve symlinks and store the real path instead of the absolutized one. Paths which resolve
+        /// to the same real path as a tracked one reached through a symlink are skipped.
+        #[clap(long, alias = "resolve")]
+        canonicalize: bool,
+        /// Store the paths relative to the home directory, so the database works for another home.
+        #[clap(long)]
+        relative: bool,
+        /// Print what would happen to each path without changing the database.
+        #[clap(long)]
+        dry_run: bool,
+        /// Tag the paths, to select them by tag later.
+        #[clap(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Leave the files matching this pattern out of the paths, like the --exclude of matched and export.
+        #[clap(long = "exclude", value_name = "PATTERN")]
+        excludes: Vec<Glob>,
+        /// Replace the tags and excludes of paths already tracked instead of adding to them.
+        #[clap(long)]
+        replace: bool,
+        #[clap(subcommand)]
+        then: Option<AddThen>,
+    },
 
     /// List tracked paths.
-    Ls,
+    Ls {
+        /// Print the raw bytes of each path and nothing else, one per line or NUL terminated with -0.
+        /// Unlike the default output, which may gain decorations, this format never changes.
+        #[clap(long, conflicts_with = "escape")]
+        paths_only: bool,
+        /// Also show when matched or export last scanned each path and how many files it matched then.
+        #[clap(short, long, conflicts_with_all = &["paths-only", "null"])]
+        long: bool,
+        #[clap(flatten)]
+        output: OutputArgs,
+    },
 
     /// Remove a path from tracked paths.
     Rm { paths: Vec<PathBuf> },
 
     /// Automatically remove deleted or unaccessible paths.
-    Prune,
+    ///
+    /// A path which became a directory while it was a file, or the other way around, is kept with a
+    /// warning, and its new kind is recorded.
+    Prune {
+        /// Also remove paths which still exist but no longer contain any file, regardless of filters.
+        #[clap(long)]
+        deep: bool,
+        /// Only remove the missing paths which were files, keeping missing directories like the
+        /// mount points of unplugged drives.
+        #[clap(long)]
+        missing_files: bool,
+        #[clap(flatten)]
+        output: OutputArgs,
+    },
 
     /// List all files matched by tracked paths.
-    Matched,
+    Matched {
+        /// Fail without listing anything if a matched path contains a line break.
+        #[clap(long)]
+        assert_no_newline: bool,
+        /// List the files under tracked paths which are left out instead, along with the reason.
+        /// Skipped directories are listed themselves rather than the files in them.
+        #[clap(long, conflicts_with = "assert-no-newline")]
+        show_excluded: bool,
+        /// Output format, text or ndjson to stream one JSON object per file as soon as it's found.
+        #[clap(long, default_value = "text")]
+        format: MatchedFormat,
+        #[clap(flatten)]
+        filter: FilterArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
+    },
 
-    /// Export all the files matched by the tracked paths.
-    Export {
-        /// Kind of export, dir, tar or zip.
+    /// List the matched files modified since the last export, reading again only the directories
+    /// modified since the previous run.
+    ///
+    /// Directories are modified when entries are created, deleted or renamed in them, which editors
+    /// saving through a temporary file do. A file rewritten in place doesn't modify its directory and
+    /// is missed until something else does. The first run walks everything.
+    Changed {
+        /// List the files modified within this long instead, like 30m or 2h.
+        #[clap(long, parse(try_from_str = parse_duration))]
+        since: Option<Duration>,
+        #[clap(flatten)]
+        filter: FilterArgs,
+        #[clap(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Show the number and total size of the files matched by each tracked path.
+    Stats {
+        /// Output format, text, json or csv.
+        #[clap(long, default_value = "text")]
+        format: report::Format,
+        #[clap(flatten)]
+        filter: FilterArgs,
+    },
+
+    /// List the largest files matched by tracked paths.
+    Top {
+        /// Number of files to list.
+        #[clap(short = 'n', long, default_value = "10")]
+        count: usize,
+        /// Output format, text, json or csv.
+        #[clap(long, default_value = "text")]
+        format: report::Format,
+        #[clap(flatten)]
+        filter: FilterArgs,
+    },
+
+    /// List the tracked paths added or scanned most recently, newest first.
+    Recent {
+        /// Number of paths to list.
+        #[clap(short = 'n', long, default_value = "10")]
+        limit: usize,
+        /// List by when the paths were added or by when matched or export last scanned them, added or
+        /// scanned. Paths without that time, like the ones never scanned, are left out.
+        #[clap(long, default_value = "added")]
+        by: RecentBy,
+        /// Print the paths as JSON, with both times in seconds since the epoch.
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Print the checksum of every matched file, sorted by path, in the format of sha256sum and co,
+    /// without exporting anything.
+    Checksum {
+        /// Checksum algorithm, blake3, sha256, sha512 or md5.
+        #[clap(long, default_value = "sha256")]
+        hash: Hasher,
+        /// End lines with NUL bytes instead of line breaks and print names as they are, like sha256sum -z.
+        #[clap(short = '0', long)]
+        null: bool,
+        #[clap(flatten)]
+        filter: FilterArgs,
+    },
+
+    /// Add tags to every tracked path matching a pattern, in a single transaction.
+    Tag(RetagArgs),
+
+    /// Remove tags from every tracked path matching a pattern, in a single transaction.
+    Untag(RetagArgs),
+
+    /// Choose among the largest matched files the ones to leave out of exports from now on.
+    ///
+    /// The chosen files are saved as excludes of the tracked paths they are under, like add --exclude does.
+    Trim {
+        /// Number of files to choose from.
+        #[clap(short = 'n', long, default_value = "10")]
+        count: usize,
+        /// Exclude the N largest files without asking.
+        #[clap(long, value_name = "N")]
+        exclude_top: Option<usize>,
+        #[clap(flatten)]
+        filter: FilterArgs,
+    },
+
+    /// Estimate the size of a tar or zip export by compressing a sample of the matched files.
+    ///
+    /// The ratio of the sample is extrapolated to every file, which is only a rough guide: a few
+    /// large files that compress unlike the others can make the real size very different.
+    Estimate {
+        /// Kind of archive, tar or zip.
         kind: ExportKind,
-        /// Path of directory or archive to export to.
-        path: PathBuf,
+        /// Number of files compressed, spread evenly over the matched files.
+        #[clap(long, default_value = "64")]
+        sample: usize,
+        #[clap(flatten)]
+        filter: FilterArgs,
+    },
+
+    /// Export all the files matched by the tracked paths.
+    Export(ExportArgs),
+
+    /// Show which tracked paths include a file and whether it would be exported.
+    Which {
+        file: PathBuf,
+        /// Print the result as JSON.
+        #[clap(long)]
+        json: bool,
+        #[clap(flatten)]
+        filter: FilterArgs,
+    },
+
+    /// Explain which filters include or exclude a file and why, ending with whether it would be exported.
+    ///
+    /// Every filter is checked under each tracked path containing the file, even after one excluded it.
+    Explain {
+        file: PathBuf,
+        /// Print the result as JSON.
+        #[clap(long)]
+        json: bool,
+        #[clap(flatten)]
+        filter: FilterArgs,
+    },
+
+    /// Run a command again whenever the files matched by the tracked paths change.
+    Watch {
+        /// How long files must stay unchanged before running the command, like 500ms, 2s or 1m.
+        #[clap(long, default_value = "2s", parse(try_from_str = parse_duration))]
+        debounce: Duration,
+        /// How often the tracked paths are scanned for changes. Each scan walks every tracked path and
+        /// reads the metadata of every matched file, so large trees need a longer interval.
+        #[clap(long, default_value = "10s", parse(try_from_str = parse_duration))]
+        interval: Duration,
+        #[clap(subcommand)]
+        command: WatchCommand,
+    },
+
+    /// Restore the files of an archive or a cas store created by export.
+    Restore(RestoreArgs),
+
+    /// Re-pack a tar.gz or zip archive into the other format, or the same one, without scanning again.
+    Convert {
+        /// Archive to read, - for stdin.
+        source: PathBuf,
+        /// Archive to write, - for stdout.
+        dest: PathBuf,
+        /// Format of the destination, tar or zip, told from its extension by default.
+        #[clap(long = "to", value_name = "FORMAT")]
+        format: Option<convert::ArchiveFormat>,
+    },
+
+    /// Copy all matched files directly into one directory, without mirroring their tree.
+    Collect {
+        dir: PathBuf,
+        /// How files with a name already used are renamed, number or hash.
+        #[clap(long, default_value = "number")]
+        on_collision: Collision,
+        /// List where each file would be copied without copying anything.
+        #[clap(long)]
+        dry_run: bool,
+        #[clap(flatten)]
+        filter: FilterArgs,
+    },
+
+    /// Export a timestamped track-*.tar.gz archive of the matched files into a directory.
+    Snapshot {
+        /// Directory of the snapshots, a leading ~ and $VARIABLES are expanded.
+        #[clap(parse(try_from_os_str = expand_path))]
+        dir: PathBuf,
+        /// Then delete older snapshots of the directory, keeping the most recent one of each of the
+        /// last N hours, days, weeks, months or years, like daily:7,weekly:4,monthly:12.
+        #[clap(long, value_name = "RULES")]
+        retain: Option<Retention>,
+        #[clap(flatten)]
+        filter: FilterArgs,
+    },
+
+    /// Inspect the settings track runs with.
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+
+    /// Add the paths tracked by another database, like one copied from another machine.
+    Import {
+        /// Database to read the paths from.
+        #[clap(required_unless_present = "from-archive")]
+        other: Option<PathBuf>,
+        /// Read the paths from the database embedded in a tar or zip export made with --embed-db instead.
+        #[clap(long, value_name = "ARCHIVE", conflicts_with = "other")]
+        from_archive: Option<PathBuf>,
+        /// List the paths which would be imported without changing anything.
+        #[clap(long)]
+        dry_run: bool,
+    },
+
+    /// Track again the paths removed by the last rm, prune or doctor --fix, with their tags and excludes.
+    ///
+    /// Only the tracking is restored, never files deleted from the disk. The last 10 removals can be
+    /// undone, one by one starting from the most recent.
+    Undo(OutputArgs),
+
+    /// Rewrite the tracked paths as add would store them today: resolve `.`, `..` and trailing slashes,
+    /// merge the ones which are then the same path, and remove the ones nested under another.
+    DbNormalize {
+        /// Print the changes without making them.
+        #[clap(long)]
+        dry_run: bool,
+    },
+
+    /// Check the database file for corruption and broken references, failing if there's any.
+    VerifyDb {
+        /// Print the checks as JSON.
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Keep the database open and answer JSON-RPC requests on a Unix socket, for frontends calling
+    /// track often.
+    ///
+    /// Requests are JSON-RPC 2.0 objects, one per line, like {"jsonrpc":"2.0","id":1,"method":"ls"}.
+    /// The methods are add and rm, with {"paths":[...]} and the add options as fields like
+    /// {"tags":[...]}; ls; and matched and export, with their command line arguments in {"args":[...]}.
+    /// Paths must be absolute. One client is served at a time, until SIGINT or SIGTERM.
+    Serve {
+        /// Path of the socket, replaced when it's left over from a server which is gone.
+        #[clap(long, parse(try_from_os_str = expand_path))]
+        socket: PathBuf,
+    },
+
+    /// Check the database and the tracked paths for common problems, failing if any check fails.
+    Doctor {
+        /// Print the checks as JSON.
+        #[clap(long)]
+        json: bool,
+        /// Remove the tracked paths which are missing or nested under another tracked path.
+        #[clap(long)]
+        fix: bool,
     },
 }
 
-#[derive(Debug)]
+#[derive(Debug, clap::Args)]
+struct RetagArgs {
+    /// Pattern selecting the tracked paths, matched like excludes: `proj-*` against the name,
+    /// `work/*` against the end of the path and `/home/me/**` against the whole path.
+    #[clap(long)]
+    glob: Glob,
+    /// Tags to add or remove.
+    #[clap(required = true)]
+    tags: Vec<String>,
+    /// Don't ask for confirmation when the pattern matches many paths.
+    #[clap(long)]
+    yes: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommand {
+    /// Show the database, jobs, color and filter settings in effect and where each comes from.
+    Show {
+        /// Print the settings as a JSON object.
+        #[clap(long)]
+        json: bool,
+        /// Print the patterns of the built-in exclude presets instead, like the one of --skip-caches.
+        #[clap(long)]
+        list_presets: bool,
+        /// Threads tar archives would be compressed on, like export --compress-threads.
+        #[clap(long, value_name = "N")]
+        compress_threads: Option<usize>,
+        #[clap(flatten)]
+        filter: FilterArgs,
+    },
+}
+
+/// Command run by add once the paths are added, for one-shot runs with --memory like
+/// `find ~/notes -name '*.md' | track --memory add - export tar notes.tar.gz`.
+#[derive(Debug, Subcommand)]
+enum AddThen {
+    /// Export the files matched by the tracked paths, including the ones just added, like the export command.
+    Export(ExportArgs),
+}
+
+#[derive(Debug, Subcommand)]
+enum WatchCommand {
+    /// Export all the files matched by the tracked paths, use --resume or --changed to only copy changes.
+    Export(ExportArgs),
+}
+
+#[derive(Debug, clap::Args)]
+struct ExportArgs {
+    /// Kind of export, dir, tar, zip, script, bagit for a BagIt bag: a directory with the files
+    /// under data/ and checksum manifests, for digital preservation tools, or cas for a store keeping
+    /// the content of each file once under objects/ and an index of every export under indexes/.
+    #[clap(value_name = "KIND", required_unless_present = "output")]
+    kind_arg: Option<ExportKind>,
+    /// Path of directory or archive to export to, a leading ~ and $VARIABLES are expanded. Tar and zip
+    /// archives are written to stdout with -.
+    #[clap(
+        value_name = "PATH",
+        parse(try_from_os_str = expand_path),
+        required_unless_present = "output",
+        conflicts_with = "output"
+    )]
+    path_arg: Option<PathBuf>,
+    /// Path to export to, instead of giving KIND and PATH. The kind is told from a .tar.gz, .tgz or
+    /// .zip extension unless --kind or KIND gives it.
+    #[clap(short, long, value_name = "PATH", parse(try_from_os_str = expand_path))]
+    output: Option<PathBuf>,
+    /// Kind of export with --output, like KIND.
+    #[clap(long = "kind", value_name = "KIND", requires = "output", conflicts_with = "kind-arg")]
+    kind_flag: Option<ExportKind>,
+    /// Kind of the main export, from KIND, --kind or the extension of --output.
+    #[clap(skip = ExportKind::Dir)]
+    kind: ExportKind,
+    /// Destination of the main export, from PATH or --output.
+    #[clap(skip)]
+    path: PathBuf,
+    /// Use copy-on-write clones for dir exports, auto, always or never.
+    #[clap(long, default_value = "auto")]
+    reflink: Reflink,
+    /// How dir exports copy files which aren't reflinked: auto lets the standard library pick, which
+    /// copies in the kernel where it can; copy_file_range and sendfile force one of these Linux system
+    /// calls, failing where it isn't supported; buffered reads and writes through a 1 MiB buffer.
+    #[clap(long, value_name = "METHOD", default_value = "auto")]
+    copy_method: CopyMethod,
+    /// How dir exports create files, copy or symlink to point back to the tracked files for a zero-copy view.
+    #[clap(long, default_value = "copy")]
+    link: LinkMode,
+    /// Only export files modified since the previous export. Dir exports keep the files exported
+    /// before rather than cleaning the directory, like with --resume.
+    #[clap(long)]
+    changed: bool,
+    /// With --changed, tell the changed files by a signature of their size and of their first and
+    /// last KIB kibibytes, 64 by default, instead of their modification time. A change in the middle
+    /// of a file which keeps its size is missed, --full-hash catches it.
+    #[clap(
+        long,
+        value_name = "KIB",
+        min_values = 0,
+        max_values = 1,
+        require_equals = true,
+        default_missing_value = "64",
+        requires = "changed"
+    )]
+    quick_hash: Option<u64>,
+    /// With --changed, tell the changed files by a hash of their whole content instead of their
+    /// modification time.
+    #[clap(long, requires = "changed", conflicts_with = "quick-hash")]
+    full_hash: bool,
+    /// Only export files modified since the most recent track-*.tar.gz snapshot in this directory was
+    /// written, everything when there's none yet. Like --changed, dir exports aren't cleaned.
+    #[clap(long, value_name = "DIR", conflicts_with = "changed")]
+    since_last: Option<PathBuf>,
+    /// Order the files are written in: walk for the order they are found in, path, name for the file
+    /// name, size for the smallest first or mtime for the oldest first. Ordering by size or mtime
+    /// reads the metadata of every file before writing anything.
+    #[clap(long, default_value = "walk")]
+    order: ExportOrder,
+    /// Remove the first N leading components from exported paths.
+    #[clap(long, default_value = "0")]
+    strip_components: usize,
+    /// What to do with paths too short to strip, skip or error.
+    #[clap(long, default_value = "error")]
+    strip_mode: StripMode,
+    /// Remove the directories every exported file is under from exported paths, however deep they
+    /// are, so files all under /home/me/project are stored like src/main.rs. Unlike --strip-components
+    /// the number of components removed depends on the files exported.
+    #[clap(long, conflicts_with = "strip-components")]
+    trim_common_prefix: bool,
+    /// Directory to root every exported path under.
+    #[clap(long)]
+    prefix: Option<PathBuf>,
+    /// Store files under the home directory with a placeholder instead of the home path.
+    #[clap(long)]
+    home_relative: bool,
+    /// Placeholder used in place of the home directory with --home-relative.
+    #[clap(long, default_value = "~")]
+    home_placeholder: PathBuf,
+    /// Store files inside a git repository under the name of the repository directory, relative to
+    /// its root, instead of under their full path. Other files are stored as usual.
+    #[clap(long)]
+    repo_relative: bool,
+    /// Continue an interrupted dir export, keeping files already copied instead of cleaning the directory.
+    #[clap(long)]
+    resume: bool,
+    /// Clean the dir or bagit destination without listing what it removes first, nor asking before
+    /// removing entries no previous export wrote.
+    #[clap(long)]
+    yes: bool,
+    /// Write a checksum manifest of the exported files, readable by sha256sum -c and co.
+    #[clap(long)]
+    manifest: Option<PathBuf>,
+    /// Paths written in the manifest: archive for the exported names, to check an extracted copy from
+    /// where it was extracted; absolute for the tracked files, to check them from anywhere; or
+    /// relative-to:DIR for the tracked files relative to DIR, to check them from DIR.
+    #[clap(long, value_name = "MODE", default_value = "archive")]
+    manifest_paths: ManifestPaths,
+    /// Checksum algorithm, blake3, sha256, sha512 or md5, which also names the objects of cas exports.
+    #[clap(long, default_value = "sha256")]
+    hash: Hasher,
+    /// Preserve extended attributes in tar archives and dir exports.
+    #[clap(long)]
+    xattrs: bool,
+    /// Give the directories created by dir exports the mode and modification time of the source
+    /// directories they mirror, once every file is written. Directories added by --prefix, --home-relative
+    /// and the like have no source and are left as created.
+    #[clap(long)]
+    preserve_dirs: bool,
+    /// Record the chattr flags of files in tar archives as track.flags pax records, like i for
+    /// immutable, which restore reminds to set again.
+    #[clap(long)]
+    file_flags: bool,
+    /// Use the zip64 extensions needed by zip archives over 4 GiB or with more than 65535 files, auto,
+    /// always or never. With never, exporting files exceeding these limits fails.
+    #[clap(long, default_value = "auto")]
+    zip64: Zip64,
+    /// Report progress on stderr, text or json for one event per line meant for frontends.
+    #[clap(long)]
+    progress_format: Option<ProgressFormat>,
+    /// When progress is reported: auto on a terminal or in --progress-format; always to also write
+    /// a line like "1234/56789 files, 2.1 GiB" every second when stderr isn't a terminal, for CI logs;
+    /// never to report none.
+    #[clap(long, value_name = "WHEN", default_value = "auto")]
+    progress: ProgressWhen,
+    /// Store the files larger than --chunk-size in chunks in cas exports, so the parts of a changed file
+    /// which didn't change are stored once: fixed for chunks of the same size, or cdc for chunks cut
+    /// depending on the content, which also share the data moved by an insertion.
+    #[clap(long, value_name = "MODE")]
+    chunking: Option<cas::Chunking>,
+    /// Size of the chunks of --chunking, like 1M, 4 MiB by default. cdc chunks are between a quarter
+    /// and four times as large.
+    #[clap(long, value_name = "SIZE", requires = "chunking", parse(try_from_str = parse_size))]
+    chunk_size: Option<u64>,
+    /// Embed the content of the files in script exports, encoded as base64.
+    #[clap(long)]
+    embed: bool,
+    /// Write one archive per tracked path in the PATH directory instead of a single archive.
+    #[clap(long)]
+    per_root: bool,
+    /// With --per-root, write the archives of up to N tracked paths at once, for paths on separate disks.
+    /// Each archive still uses the jobs of --jobs.
+    #[clap(long, value_name = "N", requires = "per-root")]
+    parallel_roots: Option<usize>,
+    /// Store files identical to one already in a tar or zip archive only once, as hard links in tar archives.
+    #[clap(long)]
+    dedupe: bool,
+    /// Add a copy of the database to tar, zip and dir exports as .track/paths.db, which import
+    /// --from-archive reads the tracked paths back from.
+    #[clap(long)]
+    embed_db: bool,
+    /// Add a checksum manifest of the archived files to tar archives as .track/manifest, which restore
+    /// --verify checks the restored files against.
+    #[clap(long)]
+    embed_manifest: bool,
+    /// Record the checksum of each file archived in tar archives as a track.<hash> pax record, like
+    /// track.sha256 with the default --hash, which restore --verify-embedded checks the restored files against.
+    #[clap(long)]
+    embed_hashes: bool,
+    /// Also write the same files to another destination, like tar:backup.tar.gz or zip:~/backup.zip,
+    /// scanning the tracked paths once. Can be repeated.
+    #[clap(long, value_name = "KIND:PATH")]
+    also: Vec<ExportTarget>,
+    /// Only export this tracked path instead of all of them, a leading ~ and $VARIABLES are expanded.
+    /// Can be repeated, and combined with --tag to export the paths either selects.
+    #[clap(long = "root", value_name = "PATH", parse(try_from_os_str = expand_path))]
+    roots: Vec<PathBuf>,
+    /// Only export the tracked paths with this tag instead of all of them. Can be repeated.
+    #[clap(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// Refuse to export when the matched files add up to more than this size, like 500M or 2G, failing
+    /// with the usage exit code before anything is written.
+    #[clap(long, parse(try_from_str = parse_size))]
+    max_total_size: Option<u64>,
+    /// Skip files whose stat, open or copy takes longer than this, like 30s, instead of waiting forever
+    /// on a stuck mount or a fifo. Tar, zip and script exports only time the stat and the open.
+    #[clap(long, value_name = "DUR", parse(try_from_str = parse_duration))]
+    file_timeout: Option<Duration>,
+    /// Compress tar archives on N threads, as independent 1 MiB gzip members any gzip reader
+    /// extracts. Compression is single-threaded by default.
+    #[clap(long, value_name = "N")]
+    compress_threads: Option<usize>,
+    #[clap(flatten)]
+    filter: FilterArgs,
+}
+
+impl ExportArgs {
+    /// Whether a dir export adds to what the destination holds rather than cleaning it first, which
+    /// incremental exports need as they only copy some of the files.
+    fn keeps_dest(&self) -> bool {
+        self.resume || self.changed || self.since_last.is_some()
+    }
+
+    fn change_check(&self) -> ChangeCheck {
+        match self.quick_hash {
+            Some(kib) => ChangeCheck::Quick(kib * 1024),
+            None if self.full_hash => ChangeCheck::Full,
+            None => ChangeCheck::Mtime,
+        }
+    }
+
+    /// Kind and destination of the main export, then of the ones given with --also.
+    fn targets(&self) -> impl Iterator<Item = (&ExportKind, &Path)> {
+        std::iter::once((&self.kind, self.path.as_path()))
+            .chain(self.also.iter().map(|target| (&target.kind, target.path.as_path())))
+    }
+
+    /// Fill in the kind and destination of the main export from the command line, given as KIND PATH
+    /// or with --output.
+    fn resolve_destination(&mut self) -> anyhow::Result<()> {
+        let path = match self.path_arg.take().or_else(|| self.output.take()) {
+            Some(path) => path,
+            None => return Err(anyhow!("PATH or --output is needed")).context(Exit::Usage),
+        };
+        self.kind = match self.kind_flag.take().or_else(|| self.kind_arg.take()) {
+            Some(kind) => kind,
+            None => match ExportKind::of(&path) {
+                Some(kind) => kind,
+                None => {
+                    return Err(anyhow!(
+                        "can't tell the kind of export from {}, use .tar.gz, .tgz or .zip or give --kind",
+                        path.display()
+                    ))
+                    .context(Exit::Usage)
+                }
+            },
+        };
+        self.path = path;
+        Ok(())
+    }
+
+    /// Options of a plain tar export to `path`.
+    fn tar(path: PathBuf, filter: FilterArgs) -> ExportArgs {
+        ExportArgs {
+            kind_arg: None,
+            path_arg: None,
+            output: None,
+            kind_flag: None,
+            kind: ExportKind::Tar,
+            path,
+            reflink: Reflink::Auto,
+            copy_method: CopyMethod::Auto,
+            link: LinkMode::Copy,
+            changed: false,
+            quick_hash: None,
+            full_hash: false,
+            since_last: None,
+            order: ExportOrder::Walk,
+            strip_components: 0,
+            strip_mode: StripMode::Error,
+            trim_common_prefix: false,
+            prefix: None,
+            home_relative: false,
+            home_placeholder: PathBuf::from("~"),
+            repo_relative: false,
+            resume: false,
+            yes: true,
+            manifest: None,
+            manifest_paths: ManifestPaths::Archive,
+            hash: Hasher::Sha256,
+            xattrs: false,
+            preserve_dirs: false,
+            file_flags: false,
+            zip64: Zip64::Auto,
+            progress_format: None,
+            progress: ProgressWhen::Never,
+            chunking: None,
+            chunk_size: None,
+            embed: false,
+            per_root: false,
+            parallel_roots: None,
+            dedupe: false,
+            embed_db: false,
+            embed_manifest: false,
+            embed_hashes: false,
+            also: Vec::new(),
+            roots: Vec::new(),
+            tags: Vec::new(),
+            max_total_size: None,
+            file_timeout: None,
+            compress_threads: None,
+            filter,
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct FilterArgs {
+    /// Match the track database and its lock file when they are under a tracked path.
+    #[clap(long)]
+    include_db: bool,
+    /// Never descend into directories with this name, like .git directories. Unlike excluding
+    /// files, the skipped subtrees aren't scanned at all, which keeps huge ones like node_modules cheap.
+    #[clap(
+        long = "skip-dir",
+        value_name = "NAME",
+        env = "TRACK_SKIP_DIRS",
+        value_delimiter = ','
+    )]
+    skip_dirs: Vec<OsString>,
+    /// Leave out files matching this pattern. Patterns without / match the file name, like '*.tmp',
+    /// others match the end of the path, like 'build/*.o', or the whole path when starting with /.
+    /// * and ? don't match /, ** does.
+    #[clap(long, value_name = "PATTERN")]
+    exclude: Vec<Glob>,
+    /// Leave out the files listed on stdin, like `fd -e tmp | track export tar b.tar.gz --exclude-stdin`.
+    /// Paths are separated by NULs when there is one, by line breaks otherwise, and relative ones are
+    /// resolved against the current directory. They are compared exactly, on top of the patterns: a
+    /// file is left out when either excludes it.
+    #[clap(long)]
+    exclude_stdin: bool,
+    /// Match --exclude patterns regardless of the case of ASCII letters, so *.JPG matches photo.jpg.
+    /// Only the patterns are affected: the paths compared are the ones found on disk, whether or not
+    /// the filesystem itself ignores case.
+    #[clap(long)]
+    ignore_case: bool,
+    /// Leave out the files git LFS materialized in the repositories they belong to, going by the
+    /// .gitattributes at the root of each repository. Pointers to content which wasn't fetched are kept.
+    #[clap(long)]
+    skip_git_lfs: bool,
+    /// Leave out caches and build outputs which can be regenerated, like __pycache__, node_modules or
+    /// target directories and *.o files. config show --list-presets prints the patterns.
+    #[clap(long)]
+    skip_caches: bool,
+    /// What to do with symlinks to directories found under the tracked paths: skip them, follow them
+    /// to scan what they point to, skipping loops, or record them so tar, zip and dir exports store
+    /// the symlink itself. Commands other than exports skip recorded symlinks.
+    #[clap(long, value_name = "MODE", default_value = "skip")]
+    symlinked_dirs: SymlinkedDirs,
+    /// Match a file only once when it's reachable through several paths, like bind mounts or hard
+    /// links, going by its device and inode. The first path found is kept.
+    #[clap(long)]
+    dedupe_inodes: bool,
+    /// Scan /proc, /sys, /dev and /run when they are under a tracked path like /, instead of skipping
+    /// them. They hold virtual files and runtime state rather than data worth exporting.
+    #[clap(long)]
+    no_safe_excludes: bool,
+    /// Read every directory again instead of reusing the listings export and collect cache for
+    /// directories whose modification time didn't change, which matched and checksums only read. The
+    /// cache isn't used with --symlinked-dirs follow or record and --dedupe-inodes.
+    #[clap(long)]
+    no_scan_cache: bool,
+}
+
+/// Paths read from stdin by --exclude-stdin, kept for the filters built again by watch.
+static STDIN_PATHS: OnceLock<HashSet<PathBuf>> = OnceLock::new();
+
+/// Read the paths separated by NULs or line breaks on stdin, only once.
+fn stdin_paths() -> anyhow::Result<&'static HashSet<PathBuf>> {
+    if let Some(paths) = STDIN_PATHS.get() {
+        return Ok(paths);
+    }
+    let mut paths = HashSet::new();
+    for path in read_stdin_list()? {
+        paths.insert(absolute(&path)?.into_owned());
+    }
+    Ok(STDIN_PATHS.get_or_init(|| paths))
+}
+
+/// Read the paths listed on stdin in order, separated by NULs when there is one, by line breaks otherwise.
+fn read_stdin_list() -> anyhow::Result<Vec<PathBuf>> {
+    let mut input = Vec::new();
+    io::stdin()
+        .lock()
+        .read_to_end(&mut input)
+        .context("could not read paths from stdin")?;
+    let separator = if input.contains(&0) { 0 } else { b'\n' };
+    Ok(input
+        .split(|&b| b == separator)
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(OsStr::from_bytes(path)))
+        .collect())
+}
+
+impl FilterArgs {
+    fn filters(&self, paths_db: &PathsDB) -> anyhow::Result<Filters> {
+        let mut filters = self.export_filters(paths_db)?;
+        if filters.symlinked_dirs == SymlinkedDirs::Record {
+            filters.symlinked_dirs = SymlinkedDirs::Skip;
+        }
+        Ok(filters)
+    }
+
+    /// Filters of exports, which are the only ones matching symlinks with --symlinked-dirs record.
+    fn export_filters(&self, paths_db: &PathsDB) -> anyhow::Result<Filters> {
+        let mut filters = Filters {
+            symlinked_dirs: self.symlinked_dirs,
+            dedupe_inodes: self.dedupe_inodes,
+            scan_cache: !self.no_scan_cache,
+            ..Filters::default()
+        };
+        if !self.include_db {
+            filters.excluded.extend(paths_db.artifacts());
+        }
+        filters.skipped_dirs.extend(self.skip_dirs.iter().cloned());
+        if !self.no_safe_excludes {
+            filters.system_dirs.extend(SYSTEM_DIRS.iter().map(PathBuf::from));
+        }
+        if self.exclude_stdin {
+            filters.listed = stdin_paths()?.clone();
+        }
+        let mut patterns = self.exclude.clone();
+        if self.skip_caches {
+            patterns.extend(glob::preset_patterns(glob::CACHES_PRESET));
+        }
+        filters.patterns = GlobSet::new(patterns, self.ignore_case);
+        if self.skip_git_lfs {
+            filters.lfs = Some(LfsFilter::default());
+        }
+        for (root, excludes) in paths_db.excludes()? {
+            filters
+                .root_patterns
+                .insert(root, GlobSet::new(excludes, self.ignore_case));
+        }
+        Ok(filters)
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct RestoreArgs {
+    /// Path of the tar archive to restore, or of a cas export to restore its latest index, or one of
+    /// its index files.
+    archive: PathBuf,
+    /// Directory the archive paths are restored under.
+    #[clap(long, default_value = "/")]
+    to: PathBuf,
+    /// Prefix the archive was exported with, removed from every path.
+    #[clap(long)]
+    prefix: Option<PathBuf>,
+    /// Restore paths starting with the home placeholder under the current home directory.
+    #[clap(long)]
+    home_relative: bool,
+    /// Placeholder the archive was exported with using --home-relative.
+    #[clap(long, default_value = "~")]
+    home_placeholder: PathBuf,
+    /// Restore the extended attributes stored in the archive.
+    #[clap(long)]
+    xattrs: bool,
+    /// Check the restored files against the manifest embedded with export --embed-manifest, or the
+    /// one given with --manifest, failing if any file doesn't match.
+    #[clap(long)]
+    verify: bool,
+    /// Manifest written by export --manifest with the default --manifest-paths archive, to verify
+    /// against instead of the embedded one.
+    #[clap(long, requires = "verify")]
+    manifest: Option<PathBuf>,
+    /// Checksum algorithm the manifest was written with, blake3, sha256, sha512 or md5.
+    #[clap(long, default_value = "sha256")]
+    hash: Hasher,
+    /// Check each restored file against the checksum recorded in the archive by export --embed-hashes,
+    /// failing if any file doesn't match.
+    #[clap(long)]
+    verify_embedded: bool,
+}
+
+/// Parse a duration made of a number and an optional unit among ms, s, m and h, seconds by default.
+fn parse_duration(s: &str) -> anyhow::Result<Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value.parse().context(format!("invalid duration {}", s))?;
+    Ok(match unit {
+        "ms" => Duration::from_millis(value),
+        "" | "s" => Duration::from_secs(value),
+        "m" => Duration::from_secs(value * 60),
+        "h" => Duration::from_secs(value * 3600),
+        _ => bail!("invalid duration unit {}", unit),
+    })
+}
+
+/// Parse a size made of a number and an optional binary unit among K, M, G and T, bytes by default.
+fn parse_size(s: &str) -> anyhow::Result<u64> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value.parse().context(format!("invalid size {}", s))?;
+    let shift = match unit {
+        "" | "B" => 0,
+        "K" | "KiB" => 10,
+        "M" | "MiB" => 20,
+        "G" | "GiB" => 30,
+        "T" | "TiB" => 40,
+        _ => bail!("invalid size unit {}", unit),
+    };
+    value
+        .checked_mul(1 << shift)
+        .ok_or_else(|| anyhow!("size {} is too large", s))
+}
+
+/// Make `path` absolute like `absolutize`, which reads the current directory even when `path` is
+/// absolute already and then fails if it was removed.
+fn absolute(path: &Path) -> io::Result<Cow<'_, Path>> {
+    if path.is_absolute() {
+        path.absolutize_from(Path::new("/"))
+    } else {
+        path.absolutize()
+    }
+}
+
+fn home_dir() -> anyhow::Result<PathBuf> {
+    dirs::home_dir().ok_or_else(|| anyhow!("couldn't get user home dir"))
+}
+
+/// Home directory of a user, from the password database.
+fn user_home(name: &[u8]) -> Option<PathBuf> {
+    let name = CString::new(name).ok()?;
+    let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+    if entry.is_null() {
+        return None;
+    }
+    let dir = unsafe { CStr::from_ptr((*entry).pw_dir) };
+    Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes())))
+}
+
+/// Expand a leading `~` or `~user` and the `$VAR` or `${VAR}` environment variables of a path,
+/// for paths which didn't go through the shell, like quoted ones.
+///
+/// A `$` not followed by a variable name is kept as is, unset variables are an error.
+fn expand_path(path: &OsStr) -> anyhow::Result<PathBuf> {
+    let bytes = path.as_bytes();
+    let mut expanded = Vec::with_capacity(bytes.len());
+    let mut rest = bytes;
+    if let Some(tilde) = bytes.strip_prefix(b"~") {
+        let end = tilde.iter().position(|&b| b == b'/').unwrap_or(tilde.len());
+        let (user, after) = tilde.split_at(end);
+        let home = if user.is_empty() {
+            home_dir()?
+        } else {
+            user_home(user).ok_or_else(|| anyhow!("unknown user {}", String::from_utf8_lossy(user)))?
+        };
+        expanded.extend_from_slice(home.as_os_str().as_bytes());
+        rest = after;
+    }
+
+    let is_name = |b: u8| b == b'_' || b.is_ascii_alphanumeric();
+    let mut i = 0;
+    while i < rest.len() {
+        let (name, end) = match (rest[i], rest.get(i + 1)) {
+            (b'$', Some(b'{')) => match rest[i + 2..].iter().position(|&b| b == b'}') {
+                Some(len) => (&rest[i + 2..i + 2 + len], i + 3 + len),
+                None => bail!("unclosed ${{ in {}", path.to_string_lossy()),
+            },
+            (b'$', Some(&b)) if b == b'_' || b.is_ascii_alphabetic() => {
+                let len = rest[i + 1..]
+                    .iter()
+                    .position(|&b| !is_name(b))
+                    .unwrap_or(rest.len() - i - 1);
+                (&rest[i + 1..i + 1 + len], i + 1 + len)
+            }
+            (b, _) => {
+                expanded.push(b);
+                i += 1;
+                continue;
+            }
+        };
+        let name = OsStr::from_bytes(name);
+        match std::env::var_os(name) {
+            Some(value) => expanded.extend_from_slice(value.as_bytes()),
+            None => bail!("environment variable {} is not set", name.to_string_lossy()),
+        }
+        i = end;
+    }
+    Ok(PathBuf::from(OsString::from_vec(expanded)))
+}
+
+/// Split `list` into words like a POSIX shell without expanding anything: unquoted blanks separate
+/// words, single quotes keep everything as is, and backslashes escape the next byte unquoted and `$`,
+/// `` ` ``, `"`, `\` or a line break in double quotes.
+fn split_words(list: &OsStr) -> anyhow::Result<Vec<OsString>> {
+    let mut words = Vec::new();
+    let mut word: Option<Vec<u8>> = None;
+    let mut bytes = list.as_bytes().iter().copied();
+    while let Some(b) = bytes.next() {
+        match b {
+            b' ' | b'\t' | b'\n' => {
+                if let Some(word) = word.take() {
+                    words.push(OsString::from_vec(word));
+                }
+            }
+            b'\'' => {
+                let word = word.get_or_insert_with(Vec::new);
+                loop {
+                    match bytes.next() {
+                        Some(b'\'') => break,
+                        Some(b) => word.push(b),
+                        None => bail!("unclosed ' quote"),
+                    }
+                }
+            }
+            b'"' => {
+                let word = word.get_or_insert_with(Vec::new);
+                loop {
+                    match bytes.next() {
+                        Some(b'"') => break,
+                        Some(b'\\') => match bytes.next() {
+                            Some(b'\n') => {}
+                            Some(b @ (b'$' | b'`' | b'"' | b'\\')) => word.push(b),
+                            Some(b) => word.extend_from_slice(&[b'\\', b]),
+                            None => bail!("unclosed \" quote"),
+                        },
+                        Some(b) => word.push(b),
+                        None => bail!("unclosed \" quote"),
+                    }
+                }
+            }
+            b'\\' => match bytes.next() {
+                Some(b'\n') => {}
+                Some(b) => word.get_or_insert_with(Vec::new).push(b),
+                None => bail!("nothing to escape after the trailing \\"),
+            },
+            b => word.get_or_insert_with(Vec::new).push(b),
+        }
+    }
+    words.extend(word.map(OsString::from_vec));
+    Ok(words)
+}
+
+#[derive(Debug, Clone, Copy)]
 enum ExportKind {
     Dir,
     Tar,
     Zip,
+    Script,
+    Bagit,
+    Cas,
 }
 
 impl FromStr for ExportKind {
@@ -55,185 +1145,4388 @@ impl FromStr for ExportKind {
             "dir" => ExportKind::Dir,
             "tar" => ExportKind::Tar,
             "zip" => ExportKind::Zip,
+            "script" => ExportKind::Script,
+            "bagit" => ExportKind::Bagit,
+            "cas" => ExportKind::Cas,
             _ => bail!("Unknown export kind {}", s),
         })
     }
 }
 
-pub struct PathsDB {
-    handle: rusqlite::Connection,
-}
-
-impl PathsDB {
-    fn open() -> anyhow::Result<PathsDB> {
-        let mut db_path = dirs::config_dir().ok_or_else(|| anyhow!("couldn't get user config dir"))?;
-        db_path.push("track.db");
-        let handle = rusqlite::Connection::open(db_path)?;
-        handle.execute_batch(include_str!("init.sql"))?;
-        Ok(PathsDB { handle })
+impl ExportKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ExportKind::Dir => "dir",
+            ExportKind::Tar => "tar",
+            ExportKind::Zip => "zip",
+            ExportKind::Script => "script",
+            ExportKind::Bagit => "bagit",
+            ExportKind::Cas => "cas",
+        }
     }
 
-    fn add(&self, path: &Path) -> anyhow::Result<()> {
-        let path_bytes = path.as_os_str().as_bytes();
-        match self.handle.execute("INSERT INTO paths (path) VALUES (?)", [path_bytes]) {
-            Ok(_) => Ok(()),
-            Err(rusqlite::Error::SqliteFailure(err, _)) if err.code == rusqlite::ErrorCode::ConstraintViolation => {
-                eprintln!("Path already in database!");
-                Ok(())
-            }
-            Err(e) => Err(e.into()),
+    /// Kind of archive named by the extension of a path, .tar.gz, .tgz or .zip.
+    fn of(path: &Path) -> Option<ExportKind> {
+        let name = path.file_name()?.as_bytes();
+        if name.ends_with(b".tar.gz") || name.ends_with(b".tgz") {
+            Some(ExportKind::Tar)
+        } else if name.ends_with(b".zip") {
+            Some(ExportKind::Zip)
+        } else {
+            None
         }
     }
+}
 
-    fn list(&self) -> anyhow::Result<Vec<PathBuf>> {
-        let mut stmt = self.handle.prepare("SELECT path FROM paths ORDER BY path ASC")?;
-        let mut rows = stmt.query([])?;
-        let mut paths = Vec::new();
-        while let Some(row) = rows.next()? {
-            let path_bytes: Vec<u8> = row.get(0)?;
-            paths.push(PathBuf::from(OsString::from_vec(path_bytes)));
+/// Another destination of an export, given as `kind:path`.
+#[derive(Debug)]
+struct ExportTarget {
+    kind: ExportKind,
+    path: PathBuf,
+}
+
+impl FromStr for ExportTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.split_once(':') {
+            Some((kind, path)) if !path.is_empty() => Ok(ExportTarget {
+                kind: kind.parse()?,
+                path: expand_path(OsStr::new(path))?,
+            }),
+            _ => bail!("Export destination {} is not like tar:backup.tar.gz", s),
         }
-        Ok(paths)
     }
+}
 
-    fn rm(&self, path: &Path) -> anyhow::Result<()> {
-        let path_bytes = path.as_os_str().as_bytes();
-        self.handle.execute("DELETE FROM paths WHERE path = ?", [path_bytes])?;
-        Ok(())
+/// Time the recent command sorts tracked paths by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecentBy {
+    Added,
+    Scanned,
+}
+
+impl FromStr for RecentBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "added" => RecentBy::Added,
+            "scanned" => RecentBy::Scanned,
+            _ => bail!("Unknown recent order {}", s),
+        })
     }
 }
 
-trait DirEntryAdapter {
-    fn file_name(&self) -> Cow<OsStr>;
-    fn file_type(&self) -> io::Result<fs::FileType>;
+/// Order the entries of an export are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportOrder {
+    Walk,
+    Path,
+    Name,
+    Size,
+    Mtime,
+}
 
-    fn is_git_dir(&self) -> io::Result<bool> {
-        Ok(self.file_name() == OsStr::new(".git") && self.file_type()?.is_dir())
+impl FromStr for ExportOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "walk" => ExportOrder::Walk,
+            "path" => ExportOrder::Path,
+            "name" => ExportOrder::Name,
+            "size" => ExportOrder::Size,
+            "mtime" => ExportOrder::Mtime,
+            _ => bail!("Unknown order {}", s),
+        })
     }
 }
 
-impl DirEntryAdapter for fs::DirEntry {
-    fn file_name(&self) -> Cow<OsStr> {
-        Cow::Owned(self.file_name())
+/// How export --changed tells the files changed since the previous export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChangeCheck {
+    Mtime,
+    /// By `hash::quick_signature` with edges of this many bytes.
+    Quick(u64),
+    Full,
+}
+
+impl ChangeCheck {
+    /// Kind of the signatures saved for the next export, which are kept apart.
+    fn kind(self) -> &'static str {
+        match self {
+            ChangeCheck::Mtime => "mtime",
+            ChangeCheck::Quick(_) => "quick",
+            ChangeCheck::Full => "full",
+        }
     }
 
-    fn file_type(&self) -> io::Result<fs::FileType> {
-        self.file_type()
+    fn signature(self, path: &Path) -> anyhow::Result<String> {
+        let signature = match self {
+            ChangeCheck::Mtime => unreachable!("modification times have no signature"),
+            ChangeCheck::Quick(edge) => hash::quick_signature(path, edge),
+            ChangeCheck::Full => Hasher::Blake3.hash_file(path),
+        };
+        signature.context(format!("could not hash {}", path.display()))
     }
 }
 
-impl DirEntryAdapter for walkdir::DirEntry {
-    fn file_name(&self) -> Cow<OsStr> {
-        Cow::Borrowed(self.file_name())
+#[derive(Debug, Clone, Copy)]
+enum Reflink {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for Reflink {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "auto" => Reflink::Auto,
+            "always" => Reflink::Always,
+            "never" => Reflink::Never,
+            _ => bail!("Unknown reflink mode {}", s),
+        })
     }
+}
 
-    fn file_type(&self) -> io::Result<fs::FileType> {
-        Ok(self.file_type())
+/// How the content of files is copied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyMethod {
+    Auto,
+    CopyFileRange,
+    Sendfile,
+    Buffered,
+}
+
+impl FromStr for CopyMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "auto" => CopyMethod::Auto,
+            "copy_file_range" => CopyMethod::CopyFileRange,
+            "sendfile" => CopyMethod::Sendfile,
+            "buffered" => CopyMethod::Buffered,
+            _ => bail!("Unknown copy method {}", s),
+        })
     }
 }
 
-fn find_matches(paths: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
-    let mut matches = Vec::new();
-    for path in paths {
-        let walker = WalkDir::new(path)
-            .into_iter()
-            .filter_entry(|d| !d.is_git_dir().expect("could not if detect .git directory"));
-        for entry in walker {
-            let entry = entry.context(format!("error scanning path {}", path.display()))?;
-            if entry.file_type().is_file() {
-                matches.push(entry.into_path());
-            }
-        }
+/// How dir exports bring the files into the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkMode {
+    /// Copy the content of the files.
+    Copy,
+    /// Create symlinks pointing back to the tracked files instead of copying them.
+    Symlink,
+}
+
+impl FromStr for LinkMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "copy" => LinkMode::Copy,
+            "symlink" => LinkMode::Symlink,
+            _ => bail!("Unknown link mode {}", s),
+        })
     }
-    Ok(matches)
 }
 
-fn clean_dir(root: &Path) -> anyhow::Result<()> {
-    let root_children = root.read_dir()?;
-    for child in root_children {
-        let child = child?;
-        if !child.is_git_dir()? {
-            fs::remove_dir_all(child.path())?;
-        }
+/// How scans treat the symlinks to directories under the tracked paths.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum SymlinkedDirs {
+    /// Leave them out, along with what they point to.
+    #[default]
+    Skip,
+    /// Descend into them like into directories.
+    Follow,
+    /// Match the symlink itself without descending.
+    Record,
+}
+
+impl FromStr for SymlinkedDirs {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "skip" => SymlinkedDirs::Skip,
+            "follow" => SymlinkedDirs::Follow,
+            "record" => SymlinkedDirs::Record,
+            _ => bail!("Unknown symlinked dirs mode {}", s),
+        })
     }
-    Ok(())
 }
 
-fn export_dir(root: PathBuf, matches: &[PathBuf]) -> anyhow::Result<()> {
-    for mat in matches {
-        let stem = mat.strip_prefix("/")?;
-        let new_path = root.join(stem);
-        DirBuilder::new()
-            .recursive(true)
-            .create(new_path.parent().expect("new path has no parent"))?;
-        fs::copy(mat, new_path)?;
+impl SymlinkedDirs {
+    /// Name of the mode, as given to --symlinked-dirs.
+    fn as_str(self) -> &'static str {
+        match self {
+            SymlinkedDirs::Skip => "skip",
+            SymlinkedDirs::Follow => "follow",
+            SymlinkedDirs::Record => "record",
+        }
     }
-    Ok(())
 }
 
-fn export_tar(root: PathBuf, matches: &[PathBuf]) -> anyhow::Result<()> {
-    let output = File::create(root)?;
-    let compressor = flate2::write::GzEncoder::new(output, flate2::Compression::default());
-    let mut archiver = tar::Builder::new(compressor);
+/// Which path of the exported files manifests record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ManifestPaths {
+    /// The name in the export.
+    Archive,
+    /// The absolute path of the tracked file.
+    Absolute,
+    /// The path of the tracked file relative to a directory.
+    RelativeTo(PathBuf),
+}
+
+impl FromStr for ManifestPaths {
+    type Err = anyhow::Error;
 
-    for mat in matches {
-        archiver
-            .append_path_with_name(mat, mat.strip_prefix("/")?)
-            .context(format!("could not add path {} to archive", mat.display()))?;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "archive" => ManifestPaths::Archive,
+            "absolute" => ManifestPaths::Absolute,
+            _ => match s.strip_prefix("relative-to:") {
+                Some(dir) if !dir.is_empty() => ManifestPaths::RelativeTo(absolute(Path::new(dir))?.into_owned()),
+                _ => bail!("Unknown manifest paths mode {}", s),
+            },
+        })
     }
+}
 
-    Ok(())
+#[derive(Debug, Clone, Copy)]
+enum StripMode {
+    Skip,
+    Error,
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
-    let paths_db = PathsDB::open()?;
-    match args {
-        Args::Add { paths } => {
-            for path in paths {
-                let path = path.absolutize()?;
-                paths_db.add(&path)?;
-            }
-        }
-        Args::Ls => {
-            for path in paths_db.list()? {
-                println!("{}", path.display())
-            }
+impl FromStr for StripMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "skip" => StripMode::Skip,
+            "error" => StripMode::Error,
+            _ => bail!("Unknown strip mode {}", s),
+        })
+    }
+}
+
+/// Schema changes applied in order on top of init.sql, `PRAGMA user_version` counts the ones applied.
+const MIGRATIONS: &[&str] = &[
+    // Relative paths, a NULL base means the path is absolute.
+    "ALTER TABLE paths ADD COLUMN base TEXT;",
+    // Metadata of the tracked paths, keyed by the path as stored.
+    "ALTER TABLE paths ADD COLUMN added_at INTEGER;
+     CREATE TABLE tags (
+         path BLOB NOT NULL REFERENCES paths (path) ON DELETE CASCADE,
+         tag TEXT NOT NULL,
+         PRIMARY KEY (path, tag)
+     );
+     CREATE TABLE excludes (
+         path BLOB NOT NULL REFERENCES paths (path) ON DELETE CASCADE,
+         pattern TEXT NOT NULL,
+         PRIMARY KEY (path, pattern)
+     );",
+    // Listings of the directories under the tracked paths, keyed by the modification time of the
+    // directory they were made at, NULL for directories not listed yet.
+    "CREATE TABLE scanned_dirs (
+         path BLOB PRIMARY KEY NOT NULL,
+         parent BLOB,
+         mtime INTEGER
+     );
+     CREATE INDEX idx_scanned_dirs_parent ON scanned_dirs (parent);
+     CREATE TABLE scanned_files (
+         path BLOB PRIMARY KEY NOT NULL,
+         dir BLOB NOT NULL,
+         mtime INTEGER NOT NULL
+     );
+     CREATE INDEX idx_scanned_files_dir ON scanned_files (dir);",
+    // Paths removed by the most recent rm, prune and doctor --fix, with their tags and excludes, for undo.
+    "CREATE TABLE undo_operations (
+         id INTEGER PRIMARY KEY AUTOINCREMENT,
+         command TEXT NOT NULL,
+         removed_at INTEGER NOT NULL
+     );
+     CREATE TABLE undo_paths (
+         operation INTEGER NOT NULL REFERENCES undo_operations (id) ON DELETE CASCADE,
+         path BLOB NOT NULL,
+         base TEXT,
+         added_at INTEGER
+     );
+     CREATE TABLE undo_tags (
+         operation INTEGER NOT NULL REFERENCES undo_operations (id) ON DELETE CASCADE,
+         path BLOB NOT NULL,
+         tag TEXT NOT NULL
+     );
+     CREATE TABLE undo_excludes (
+         operation INTEGER NOT NULL REFERENCES undo_operations (id) ON DELETE CASCADE,
+         path BLOB NOT NULL,
+         pattern TEXT NOT NULL
+     );",
+    // Signatures of the files matched by the previous export --changed --quick-hash or --full-hash,
+    // each kind kept apart.
+    "CREATE TABLE signatures (
+         path BLOB NOT NULL,
+         kind TEXT NOT NULL,
+         signature TEXT NOT NULL,
+         PRIMARY KEY (path, kind)
+     );",
+    // Whether a tracked path was a file or a directory when it was added, NULL when it didn't exist.
+    "ALTER TABLE paths ADD COLUMN kind TEXT;
+     ALTER TABLE undo_paths ADD COLUMN kind TEXT;",
+    // Path as stored with its case folded, to look paths up ignoring case with --case-insensitive.
+    // Filled by migrate for the paths tracked before.
+    "ALTER TABLE paths ADD COLUMN folded BLOB;
+     CREATE INDEX idx_paths_folded ON paths (folded);",
+    // When matched or export last scanned each tracked path and how many files it matched then.
+    "ALTER TABLE paths ADD COLUMN last_scanned_at INTEGER;
+     ALTER TABLE paths ADD COLUMN last_match_count INTEGER;",
+];
+
+/// Tags and excludes given when adding a path.
+#[derive(Debug, Default)]
+struct Metadata {
+    tags: Vec<String>,
+    excludes: Vec<Glob>,
+    /// Whether they replace those of a path already tracked, rather than being added to them.
+    replace: bool,
+}
+
+impl Metadata {
+    fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.excludes.is_empty()
+    }
+}
+
+/// What a tracked path is, recorded so prune can tell when it changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PathKind {
+    File,
+    Dir,
+}
+
+impl PathKind {
+    /// Kind of what a path points to, none when it doesn't exist or is neither a file nor a directory.
+    fn of(path: &Path) -> Option<PathKind> {
+        match fs::metadata(path) {
+            Ok(meta) if meta.is_dir() => Some(PathKind::Dir),
+            Ok(meta) if meta.is_file() => Some(PathKind::File),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            PathKind::File => "file",
+            PathKind::Dir => "dir",
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            PathKind::File => "a file",
+            PathKind::Dir => "a directory",
+        }
+    }
+}
+
+impl FromStr for PathKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "file" => PathKind::File,
+            "dir" => PathKind::Dir,
+            _ => bail!("Unknown path kind {}", s),
+        })
+    }
+}
+
+/// Directory relative paths are stored against, resolved again on every run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Base {
+    Home,
+}
+
+impl Base {
+    fn as_str(self) -> &'static str {
+        match self {
+            Base::Home => "home",
+        }
+    }
+
+    fn dir(self) -> anyhow::Result<PathBuf> {
+        match self {
+            Base::Home => home_dir(),
+        }
+    }
+}
+
+/// Absolute path of a path as stored, resolved against its base if it has one.
+fn resolve_stored(stored: &Path, base: Option<Base>) -> anyhow::Result<PathBuf> {
+    Ok(match base {
+        Some(base) => base.dir()?.join(stored),
+        None => stored.to_path_buf(),
+    })
+}
+
+impl FromStr for Base {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "home" => Base::Home,
+            _ => bail!("Unknown path base {}", s),
+        })
+    }
+}
+
+pub struct PathsDB {
+    handle: rusqlite::Connection,
+    path: Option<PathBuf>,
+}
+
+impl PathsDB {
+    fn open(db_path: Option<PathBuf>) -> anyhow::Result<PathsDB> {
+        let db_path = match db_path {
+            Some(db_path) => db_path,
+            None => PathsDB::default_path()?,
+        };
+        let db_path = absolute(&db_path)?.into_owned();
+        let handle = rusqlite::Connection::open(&db_path)?;
+        PathsDB::init(handle, Some(db_path))
+    }
+
+    fn default_path() -> anyhow::Result<PathBuf> {
+        PathsDB::default_path_in(dirs::config_dir())
+    }
+
+    /// Database path in the user config directory `config_dir`, a usage error when it's unknown.
+    fn default_path_in(config_dir: Option<PathBuf>) -> anyhow::Result<PathBuf> {
+        match config_dir {
+            Some(config_dir) => Ok(config_dir.join("track.db")),
+            None => Err(anyhow!(
+                "couldn't get user config dir, neither $XDG_CONFIG_HOME nor $HOME is set; \
+                 use --db <PATH> or set TRACK_DB to choose where the database is stored"
+            ))
+            .context(Exit::Usage),
+        }
+    }
+
+    /// Open a database that lives only as long as the process, nothing is persisted.
+    fn open_in_memory() -> anyhow::Result<PathsDB> {
+        let handle = rusqlite::Connection::open_in_memory()?;
+        PathsDB::init(handle, None)
+    }
+
+    /// Open another database without creating or migrating anything, to read the paths it tracks.
+    fn open_read_only(db_path: &Path) -> anyhow::Result<PathsDB> {
+        let handle = rusqlite::Connection::open_with_flags(db_path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("could not open database {}", db_path.display()))?;
+        Ok(PathsDB {
+            handle,
+            path: Some(db_path.to_owned()),
+        })
+    }
+
+    fn init(handle: rusqlite::Connection, path: Option<PathBuf>) -> anyhow::Result<PathsDB> {
+        handle.pragma_update(None, "foreign_keys", true)?;
+        handle.execute_batch(include_str!("init.sql"))?;
+        let paths_db = PathsDB { handle, path };
+        paths_db.migrate()?;
+        Ok(paths_db)
+    }
+
+    /// Version of the schema, the number of migrations applied.
+    fn schema_version(&self) -> anyhow::Result<usize> {
+        let version: i64 = self.handle.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        Ok(version as usize)
+    }
+
+    /// Apply the migrations the database doesn't have yet.
+    fn migrate(&self) -> anyhow::Result<()> {
+        let version = self.schema_version()?;
+        if version >= MIGRATIONS.len() {
+            return Ok(());
+        }
+        let tx = self.handle.unchecked_transaction()?;
+        for migration in &MIGRATIONS[version..] {
+            tx.execute_batch(migration)?;
+        }
+        // SQLite's lower() only folds ASCII, the paths tracked before are folded here instead.
+        let unfolded: Vec<Vec<u8>> = tx
+            .prepare("SELECT path FROM paths WHERE folded IS NULL")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        for path in unfolded {
+            let folded = fold_case(Path::new(OsStr::from_bytes(&path)));
+            tx.execute(
+                "UPDATE paths SET folded = ? WHERE path = ?",
+                rusqlite::params![folded.as_os_str().as_bytes(), path],
+            )?;
+        }
+        tx.pragma_update(None, "user_version", MIGRATIONS.len() as i64)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn lock_path(path: &Path) -> PathBuf {
+        path.with_extension("lock")
+    }
+
+    /// Files track itself writes to, which aren't worth exporting even when tracked.
+    fn artifacts(&self) -> Vec<PathBuf> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Vec::new(),
+        };
+        let mut artifacts = vec![path.clone(), PathsDB::lock_path(path)];
+        for suffix in ["-wal", "-shm", "-journal"] {
+            let mut name = path.as_os_str().to_owned();
+            name.push(suffix);
+            artifacts.push(name.into());
+        }
+        artifacts
+    }
+
+    /// Acquire the advisory lock guarding destructive operations, released when dropped.
+    ///
+    /// In-memory databases are private to the process and don't need a lock.
+    fn lock(&self, wait: bool) -> anyhow::Result<Option<Lock>> {
+        match &self.path {
+            Some(path) => Ok(Some(Lock::acquire(&PathsDB::lock_path(path), wait)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn add(&self, paths: &[(PathBuf, Option<Base>)], metadata: &Metadata) -> anyhow::Result<()> {
+        let tx = self.handle.unchecked_transaction()?;
+        for ((path, base), inserted) in paths.iter().zip(self.add_many(paths)?) {
+            if inserted {
+                self.set_metadata(path, metadata)?;
+            } else if metadata.is_empty() && !metadata.replace {
+                eprintln!("Path already in database!");
+            } else {
+                let resolved = match base {
+                    Some(base) => base.dir()?.join(path),
+                    None => path.clone(),
+                };
+                let stored = self.stored_as(&resolved)?.expect("tracked path is not stored");
+                self.set_metadata(&stored, metadata)?;
+                eprintln!("Updated {}", resolved.display());
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Path as stored of a tracked path, which may be relative.
+    fn stored_as(&self, resolved: &Path) -> anyhow::Result<Option<PathBuf>> {
+        let mut rows = self.rows_of(resolved)?.into_iter();
+        Ok(rows.next().map(|(path, _)| PathBuf::from(OsString::from_vec(path))))
+    }
+
+    /// Rows an absolute path is stored as, the path and the base it's relative to if any, looked up
+    /// through the indexes rather than by resolving every tracked path.
+    ///
+    /// With --case-insensitive the path is stored in any case, it's looked up by its folded case.
+    fn rows_of(&self, path: &Path) -> anyhow::Result<Vec<(Vec<u8>, Option<&'static str>)>> {
+        let case_insensitive = self.case_insensitive()?;
+        let key = path_key(path, case_insensitive);
+        let mut candidates = vec![(key.clone(), None)];
+        for base in [Base::Home] {
+            if let Ok(relative) = key.strip_prefix(path_key(&base.dir()?, case_insensitive)) {
+                candidates.push((relative.to_path_buf(), Some(base.as_str())));
+            }
+        }
+        let mut stmt = self.handle.prepare_cached(if case_insensitive {
+            "SELECT path FROM paths WHERE folded = ? AND base IS ?"
+        } else {
+            "SELECT path FROM paths WHERE path = ? AND base IS ?"
+        })?;
+        let mut rows = Vec::new();
+        for (candidate, base) in candidates {
+            let candidate = candidate.as_os_str().as_bytes();
+            for path_bytes in stmt.query_map(rusqlite::params![candidate, base], |row| row.get(0))? {
+                rows.push((path_bytes?, base));
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Add tags and excludes to a path as stored, replacing the previous ones if asked to.
+    fn set_metadata(&self, stored: &Path, metadata: &Metadata) -> anyhow::Result<()> {
+        let path_bytes = stored.as_os_str().as_bytes();
+        if metadata.replace {
+            self.handle.execute("DELETE FROM tags WHERE path = ?", [path_bytes])?;
+            self.handle
+                .execute("DELETE FROM excludes WHERE path = ?", [path_bytes])?;
+        }
+        for tag in &metadata.tags {
+            self.handle.execute(
+                "INSERT OR IGNORE INTO tags (path, tag) VALUES (?, ?)",
+                rusqlite::params![path_bytes, tag],
+            )?;
+        }
+        for exclude in &metadata.excludes {
+            self.handle.execute(
+                "INSERT OR IGNORE INTO excludes (path, pattern) VALUES (?, ?)",
+                rusqlite::params![path_bytes, exclude.as_str()],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Tags and excludes of a path as stored.
+    fn metadata(&self, stored: &Path) -> anyhow::Result<Metadata> {
+        let mut metadata = Metadata::default();
+        if self.schema_version()? < 2 {
+            return Ok(metadata);
+        }
+        let path_bytes = stored.as_os_str().as_bytes();
+        let mut stmt = self
+            .handle
+            .prepare("SELECT tag FROM tags WHERE path = ? ORDER BY tag")?;
+        for tag in stmt.query_map([path_bytes], |row| row.get(0))? {
+            metadata.tags.push(tag?);
+        }
+        let mut stmt = self
+            .handle
+            .prepare("SELECT pattern FROM excludes WHERE path = ? ORDER BY pattern")?;
+        for pattern in stmt.query_map([path_bytes], |row| row.get::<_, String>(0))? {
+            metadata.excludes.push(pattern?.parse()?);
+        }
+        Ok(metadata)
+    }
+
+    /// Write a consistent copy of the database to `path`, even while it's being written to.
+    fn backup_to(&self, path: &Path) -> anyhow::Result<()> {
+        self.handle
+            .backup(rusqlite::DatabaseName::Main, path, None)
+            .context(format!("could not copy the database to {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Exclude patterns of every tracked path, by resolved path.
+    fn excludes(&self) -> anyhow::Result<HashMap<PathBuf, Vec<Glob>>> {
+        let mut excludes: HashMap<PathBuf, Vec<Glob>> = HashMap::new();
+        if self.schema_version()? < 2 {
+            return Ok(excludes);
+        }
+        let mut stmt = self.handle.prepare(
+            "SELECT paths.path, paths.base, excludes.pattern FROM excludes JOIN paths ON paths.path = excludes.path",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let path = PathBuf::from(OsString::from_vec(row.get(0)?));
+            let base: Option<String> = row.get(1)?;
+            let pattern: String = row.get(2)?;
+            let resolved = match base {
+                Some(base) => base.parse::<Base>()?.dir()?.join(path),
+                None => path,
+            };
+            excludes.entry(resolved).or_default().push(pattern.parse()?);
+        }
+        Ok(excludes)
+    }
+
+    /// Insert a path, relative to `base` if any, returns false if it was already tracked.
+    fn insert(&self, path: &Path, base: Option<Base>) -> anyhow::Result<bool> {
+        Ok(self.add_many(&[(path.to_path_buf(), base)])?[0])
+    }
+
+    /// Insert paths with a single prepared statement in a single transaction, returning whether each
+    /// one was inserted, false for the ones already tracked.
+    ///
+    /// Callers already in a transaction, like dry runs, keep theirs.
+    fn add_many(&self, paths: &[(PathBuf, Option<Base>)]) -> anyhow::Result<Vec<bool>> {
+        let tx = if self.handle.is_autocommit() {
+            Some(self.handle.unchecked_transaction()?)
+        } else {
+            None
+        };
+        // The same path may be tracked both relative and absolute, once resolved they are the same.
+        let case_insensitive = self.case_insensitive()?;
+        let mut tracked: HashSet<PathBuf> = self
+            .list()?
+            .iter()
+            .map(|path| path_key(path, case_insensitive))
+            .collect();
+        let added_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let mut statement = self
+            .handle
+            .prepare("INSERT INTO paths (path, base, added_at, kind, folded) VALUES (?, ?, ?, ?, ?)")?;
+        let mut inserted = Vec::with_capacity(paths.len());
+        for (path, base) in paths {
+            let resolved = match base {
+                Some(base) => base.dir()?.join(path),
+                None => path.clone(),
+            };
+            let kind = PathKind::of(&resolved).map(PathKind::as_str);
+            if !tracked.insert(path_key(&resolved, case_insensitive)) {
+                inserted.push(false);
+                continue;
+            }
+            let path_bytes = path.as_os_str().as_bytes();
+            let folded = fold_case(path);
+            inserted.push(
+                match statement.execute(rusqlite::params![
+                    path_bytes,
+                    base.map(Base::as_str),
+                    added_at,
+                    kind,
+                    folded.as_os_str().as_bytes()
+                ]) {
+                    Ok(_) => true,
+                    Err(rusqlite::Error::SqliteFailure(err, _))
+                        if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+                    {
+                        false
+                    }
+                    Err(e) => return Err(e.into()),
+                },
+            );
+        }
+        drop(statement);
+        if let Some(tx) = tx {
+            tx.commit()?;
+        }
+        Ok(inserted)
+    }
+
+    /// Tracked paths as they are stored, along with the base relative ones are resolved against.
+    ///
+    /// Databases created before relative paths existed are read as is, their paths are all absolute.
+    fn stored(&self) -> anyhow::Result<Vec<(PathBuf, Option<Base>)>> {
+        let query = if self.schema_version()? >= 1 {
+            "SELECT path, base FROM paths ORDER BY path ASC"
+        } else {
+            "SELECT path, NULL FROM paths ORDER BY path ASC"
+        };
+        let mut stmt = self.handle.prepare(query)?;
+        let mut rows = stmt.query([])?;
+        let mut paths = Vec::new();
+        while let Some(row) = rows.next()? {
+            let path_bytes: Vec<u8> = row.get(0)?;
+            let base: Option<String> = row.get(1)?;
+            let base = base.map(|base| base.parse()).transpose()?;
+            paths.push((PathBuf::from(OsString::from_vec(path_bytes)), base));
+        }
+        Ok(paths)
+    }
+
+    /// Tracked paths, the relative ones resolved against their base on this machine.
+    fn list(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for (path, base) in self.stored()? {
+            paths.push(match base {
+                Some(base) => base.dir()?.join(path),
+                None => path,
+            });
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Stop tracking an absolute path, whether it's stored as is or relative to a base, recording it
+    /// under the undo `operation` first.
+    fn rm(&self, path: &Path, operation: i64) -> anyhow::Result<()> {
+        for (path_bytes, base) in self.rows_of(path)? {
+            undo::record(self, operation, &path_bytes, base)?;
+            self.handle.execute(
+                "DELETE FROM paths WHERE path = ? AND base IS ?",
+                rusqlite::params![path_bytes, base],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Kind a path as stored had when it was added or last pruned, if known.
+    fn kind(&self, stored: &Path) -> anyhow::Result<Option<PathKind>> {
+        let kind: Option<String> = self.handle.query_row(
+            "SELECT kind FROM paths WHERE path = ?",
+            [stored.as_os_str().as_bytes()],
+            |row| row.get(0),
+        )?;
+        kind.map(|kind| kind.parse()).transpose()
+    }
+
+    fn set_kind(&self, stored: &Path, kind: PathKind) -> anyhow::Result<()> {
+        self.handle.execute(
+            "UPDATE paths SET kind = ? WHERE path = ?",
+            rusqlite::params![kind.as_str(), stored.as_os_str().as_bytes()],
+        )?;
+        Ok(())
+    }
+
+    /// Whether tracked paths are compared ignoring case, as saved by --case-insensitive.
+    fn case_insensitive(&self) -> anyhow::Result<bool> {
+        let enabled: Option<bool> = self
+            .handle
+            .query_row("SELECT value FROM state WHERE key = 'case_insensitive'", [], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        Ok(enabled.unwrap_or(false))
+    }
+
+    /// Save whether tracked paths are compared ignoring case.
+    ///
+    /// Enabling it is refused while paths differing only by case are tracked, one of them has to be
+    /// removed first.
+    fn set_case_insensitive(&self, enabled: bool) -> anyhow::Result<()> {
+        if enabled && !self.case_insensitive()? {
+            let mut seen: HashMap<PathBuf, PathBuf> = HashMap::new();
+            for path in self.list()? {
+                if let Some(other) = seen.insert(fold_case(&path), path.clone()) {
+                    return Err(anyhow!(
+                        "{} and {} are the same path ignoring case, remove one of them first",
+                        other.display(),
+                        path.display()
+                    ))
+                    .context(Exit::Usage);
+                }
+            }
+        }
+        self.handle.execute(
+            "INSERT OR REPLACE INTO state (key, value) VALUES ('case_insensitive', ?)",
+            [enabled],
+        )?;
+        Ok(())
+    }
+
+    /// Record that the tracked paths were scanned at `at`, matching the given number of files each,
+    /// in a single transaction.
+    fn record_scan(&self, counts: &[(PathBuf, u64)], at: SystemTime) -> anyhow::Result<()> {
+        let secs = at.duration_since(UNIX_EPOCH)?.as_secs();
+        let tx = self.handle.unchecked_transaction()?;
+        for (root, count) in counts {
+            if let Some(stored) = self.stored_as(root)? {
+                tx.execute(
+                    "UPDATE paths SET last_scanned_at = ?, last_match_count = ? WHERE path = ?",
+                    rusqlite::params![secs, count, stored.as_os_str().as_bytes()],
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// When each tracked path was added and last scanned, if known.
+    fn timestamps(&self) -> anyhow::Result<Vec<Timestamps>> {
+        let mut stmt = self
+            .handle
+            .prepare("SELECT path, base, added_at, last_scanned_at FROM paths")?;
+        let mut rows = stmt.query([])?;
+        let mut timestamps = Vec::new();
+        while let Some(row) = rows.next()? {
+            let path = PathBuf::from(OsString::from_vec(row.get(0)?));
+            let base: Option<String> = row.get(1)?;
+            let base = base.map(|base| base.parse()).transpose()?;
+            timestamps.push(Timestamps {
+                path: resolve_stored(&path, base)?,
+                added_at: row.get(2)?,
+                scanned_at: row.get(3)?,
+            });
+        }
+        Ok(timestamps)
+    }
+
+    /// When each tracked path was last scanned and how many files it matched, by resolved path.
+    fn scan_stats(&self) -> anyhow::Result<HashMap<PathBuf, ScanStats>> {
+        let mut stats = HashMap::new();
+        let mut stmt = self.handle.prepare(
+            "SELECT path, base, last_scanned_at, last_match_count FROM paths WHERE last_scanned_at IS NOT NULL",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let path = PathBuf::from(OsString::from_vec(row.get(0)?));
+            let base: Option<String> = row.get(1)?;
+            let scanned_at: u64 = row.get(2)?;
+            let files: Option<u64> = row.get(3)?;
+            let base = base.map(|base| base.parse()).transpose()?;
+            stats.insert(
+                resolve_stored(&path, base)?,
+                ScanStats {
+                    scanned_at: UNIX_EPOCH + Duration::from_secs(scanned_at),
+                    files: files.unwrap_or(0),
+                },
+            );
+        }
+        Ok(stats)
+    }
+
+    fn last_export_at(&self) -> anyhow::Result<Option<SystemTime>> {
+        let secs: Option<u64> = self
+            .handle
+            .query_row("SELECT value FROM state WHERE key = 'last_export_at'", [], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        Ok(secs.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)))
+    }
+
+    /// Signatures of a kind saved by the previous export, by path.
+    fn signatures(&self, kind: &str) -> anyhow::Result<HashMap<PathBuf, String>> {
+        let mut stmt = self
+            .handle
+            .prepare("SELECT path, signature FROM signatures WHERE kind = ?")?;
+        let mut rows = stmt.query([kind])?;
+        let mut signatures = HashMap::new();
+        while let Some(row) = rows.next()? {
+            let path = PathBuf::from(OsString::from_vec(row.get(0)?));
+            signatures.insert(path, row.get(1)?);
+        }
+        Ok(signatures)
+    }
+
+    /// Record that an export started at `time` succeeded, replacing the signatures of a kind with those
+    /// of the files it matched, in a single transaction so the next --changed never sees one without
+    /// the other.
+    fn record_export(&self, time: SystemTime, signatures: Option<(&str, &[(PathBuf, String)])>) -> anyhow::Result<()> {
+        let secs = time.duration_since(UNIX_EPOCH)?.as_secs();
+        let tx = self.handle.unchecked_transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO state (key, value) VALUES ('last_export_at', ?)",
+            [secs],
+        )?;
+        if let Some((kind, signatures)) = signatures {
+            tx.execute("DELETE FROM signatures WHERE kind = ?", [kind])?;
+            let mut stmt = tx.prepare("INSERT INTO signatures (path, kind, signature) VALUES (?, ?, ?)")?;
+            for (path, signature) in signatures {
+                stmt.execute(rusqlite::params![path.as_os_str().as_bytes(), kind, signature])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+pub struct Lock {
+    _file: File,
+}
+
+/// Times of a tracked path in seconds since the epoch, unknown for paths added before they were recorded
+/// and the ones never scanned.
+struct Timestamps {
+    path: PathBuf,
+    added_at: Option<u64>,
+    scanned_at: Option<u64>,
+}
+
+impl Timestamps {
+    fn by(&self, by: RecentBy) -> Option<u64> {
+        match by {
+            RecentBy::Added => self.added_at,
+            RecentBy::Scanned => self.scanned_at,
+        }
+    }
+}
+
+/// Last scan of a tracked path by matched or export.
+pub struct ScanStats {
+    pub scanned_at: SystemTime,
+    /// Files it matched then, before --changed and co left any out.
+    pub files: u64,
+}
+
+/// Number of `matches` under each of the tracked paths `roots`.
+fn match_counts(roots: &[PathBuf], matches: &[PathBuf]) -> Vec<(PathBuf, u64)> {
+    roots
+        .iter()
+        .map(|root| {
+            (
+                root.clone(),
+                matches.iter().filter(|mat| mat.starts_with(root)).count() as u64,
+            )
+        })
+        .collect()
+}
+
+/// Record a successful scan for ls --long, a database which can't be written only gets a warning.
+fn save_scan(paths_db: &PathsDB, counts: &[(PathBuf, u64)], at: SystemTime) {
+    if let Err(err) = paths_db.record_scan(counts, at) {
+        eprintln!("Warning: could not record the scan in the database: {:#}", err);
+    }
+}
+
+/// Path with its case folded, the same for every case of a path on a case-insensitive filesystem.
+///
+/// Only the ASCII letters of paths which aren't UTF-8 are folded.
+fn fold_case(path: &Path) -> PathBuf {
+    match path.to_str() {
+        Some(path) => PathBuf::from(path.to_lowercase()),
+        None => PathBuf::from(OsString::from_vec(path.as_os_str().as_bytes().to_ascii_lowercase())),
+    }
+}
+
+/// Key tracked paths are compared by, their folded case with --case-insensitive.
+fn path_key(path: &Path, case_insensitive: bool) -> PathBuf {
+    if case_insensitive {
+        fold_case(path)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+impl Lock {
+    fn acquire(path: &Path, wait: bool) -> anyhow::Result<Lock> {
+        use std::os::unix::io::AsRawFd;
+
+        let file = File::create(path).context(format!("could not create lock file {}", path.display()))?;
+        let flags = if wait {
+            libc::LOCK_EX
+        } else {
+            libc::LOCK_EX | libc::LOCK_NB
+        };
+        if unsafe { libc::flock(file.as_raw_fd(), flags) } == -1 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+                return Err(anyhow!(
+                    "another track operation is in progress (use --wait to wait for it)"
+                ))
+                .context(Exit::Database);
+            }
+            return Err(err).context(format!("could not lock {}", path.display()));
+        }
+        Ok(Lock { _file: file })
+    }
+}
+
+trait DirEntryAdapter {
+    fn file_name(&self) -> Cow<'_, OsStr>;
+    fn file_type(&self) -> io::Result<fs::FileType>;
+
+    fn is_git_dir(&self) -> io::Result<bool> {
+        Ok(self.file_name() == OsStr::new(".git") && self.file_type()?.is_dir())
+    }
+
+    fn is_skipped_dir(&self, filters: &Filters) -> io::Result<bool> {
+        Ok(filters.skips_dir(&self.file_name()) && self.file_type()?.is_dir())
+    }
+}
+
+impl DirEntryAdapter for fs::DirEntry {
+    fn file_name(&self) -> Cow<'_, OsStr> {
+        Cow::Owned(self.file_name())
+    }
+
+    fn file_type(&self) -> io::Result<fs::FileType> {
+        self.file_type()
+    }
+}
+
+impl DirEntryAdapter for walkdir::DirEntry {
+    fn file_name(&self) -> Cow<'_, OsStr> {
+        Cow::Borrowed(self.file_name())
+    }
+
+    fn file_type(&self) -> io::Result<fs::FileType> {
+        Ok(self.file_type())
+    }
+}
+
+/// Rules deciding which of the files found under the tracked paths are matched.
+#[derive(Debug, Default)]
+struct Filters {
+    /// Exact paths which are never matched.
+    excluded: HashSet<PathBuf>,
+    /// Paths read from stdin with --exclude-stdin, which are never matched.
+    listed: HashSet<PathBuf>,
+    /// Names of directories which are never descended into, on top of .git.
+    skipped_dirs: HashSet<OsString>,
+    /// Paths of directories which aren't descended into when found under a tracked path.
+    system_dirs: HashSet<PathBuf>,
+    /// Patterns of files which are never matched.
+    patterns: GlobSet,
+    /// Set to leave out the files materialized by git LFS.
+    lfs: Option<LfsFilter>,
+    /// Patterns of files never matched under a tracked path, stored along with it.
+    root_patterns: HashMap<PathBuf, GlobSet>,
+    symlinked_dirs: SymlinkedDirs,
+    /// Set to match files reachable through several paths only once.
+    dedupe_inodes: bool,
+    /// Set to list directories through the scan cache when the other filters allow it.
+    scan_cache: bool,
+}
+
+impl Filters {
+    /// Whether a directory with this name is left out of scans, along with everything it contains.
+    fn skips_dir(&self, name: &OsStr) -> bool {
+        name == ".git" || self.skipped_dirs.contains(name)
+    }
+
+    /// Whether a directory found under a tracked path is one of the system directories left out.
+    fn skips_system_dir(&self, dir: &Path) -> bool {
+        self.system_dirs.contains(dir)
+    }
+
+    /// Reason a regular file found while scanning isn't matched, if any.
+    fn exclusion(&self, root: &Path, path: &Path) -> Option<&'static str> {
+        if self.excluded.contains(path) {
+            return Some("track database file");
+        }
+        if self.listed.contains(path) {
+            return Some("listed on stdin by --exclude-stdin");
+        }
+        if self.patterns.matching(path).is_some() {
+            return Some("matches an --exclude or --skip-caches pattern");
+        }
+        if self
+            .root_patterns
+            .get(root)
+            .is_some_and(|patterns| patterns.matching(path).is_some())
+        {
+            return Some("matches an exclude of the tracked path");
+        }
+        if self.lfs.as_ref().is_some_and(|lfs| lfs.is_materialized(path)) {
+            return Some("materialized git LFS file");
+        }
+        None
+    }
+}
+
+fn find_matches(paths: &[PathBuf], filters: &Filters) -> anyhow::Result<Vec<PathBuf>> {
+    let mut matches = Vec::new();
+    for_each_match(paths, filters, |_, entry| {
+        matches.push(entry.into_path());
+        Ok(())
+    })?;
+    Ok(matches)
+}
+
+/// Matched files like `find_matches`, listed through the scan cache of the database when the filters
+/// allow it. The cache only knows regular files and directories, not symlinks or inodes. It is only
+/// brought up to date with `update_cache`, for the commands writing to the database anyway.
+fn scan_matches(
+    paths_db: &PathsDB,
+    paths: &[PathBuf],
+    filters: &Filters,
+    update_cache: bool,
+) -> anyhow::Result<Vec<PathBuf>> {
+    if filters.scan_cache && filters.symlinked_dirs == SymlinkedDirs::Skip && !filters.dedupe_inodes {
+        changed::cached_matches(paths_db, paths, filters, update_cache)
+    } else {
+        find_matches(paths, filters)
+    }
+}
+
+/// Call `f` with the tracked path and the entry of every matched file as soon as it's found.
+fn for_each_match(
+    paths: &[PathBuf],
+    filters: &Filters,
+    mut f: impl FnMut(&Path, walkdir::DirEntry) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    for_each_entry(paths, filters, |root, entry, exclusion| match exclusion {
+        None => f(root, entry),
+        Some(_) => Ok(()),
+    })
+}
+
+/// Call `f` with the tracked path, the entry and the reason it's left out of everything found under
+/// the tracked paths but directories, the reason being none for the matched files. Skipped
+/// directories are reported themselves, the files in them aren't scanned.
+fn for_each_entry(
+    paths: &[PathBuf],
+    filters: &Filters,
+    mut f: impl FnMut(&Path, walkdir::DirEntry, Option<&'static str>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let follow = filters.symlinked_dirs == SymlinkedDirs::Follow;
+    // Device and inode of the files matched so far, across all the tracked paths.
+    let mut seen_inodes = HashSet::new();
+    for path in paths {
+        // Sorted, so the matches come in the same order from one scan to the next and from the scan cache.
+        let mut walker = WalkDir::new(path).follow_links(follow).sort_by_file_name().into_iter();
+        while let Some(entry) = walker.next() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) if follow && err.loop_ancestor().is_some() => {
+                    let link = err.path().unwrap_or(path);
+                    log::skipping(link, "which loops back to a parent directory");
+                    continue;
+                }
+                // Following a dangling symlink fails, they are skipped like when not following.
+                Err(err) if follow && err.path().is_some_and(|p| p.is_symlink() && !p.exists()) => continue,
+                Err(err) => return Err(err).context(format!("error scanning path {}", path.display())),
+            };
+            if entry.file_type().is_dir() {
+                let skipped = if entry.depth() > 0 && filters.skips_system_dir(entry.path()) {
+                    Some("system directory, left out unless --no-safe-excludes is used")
+                } else if entry.is_skipped_dir(filters)? {
+                    Some("skipped directory")
+                } else {
+                    None
+                };
+                if skipped.is_some() {
+                    walker.skip_current_dir();
+                    f(path, entry, skipped)?;
+                }
+                continue;
+            }
+            let mut exclusion = if follow {
+                // Only symlinks to directories are followed, the other symlinks are still left out.
+                if entry.path_is_symlink() {
+                    Some("symlink to something other than a directory")
+                } else if !entry.file_type().is_file() {
+                    Some("not a regular file")
+                } else {
+                    None
+                }
+            } else if entry.file_type().is_symlink() {
+                if filters.symlinked_dirs != SymlinkedDirs::Record {
+                    Some("symlink, only followed or recorded with --symlinked-dirs when it points to a directory")
+                } else if !entry.path().is_dir() {
+                    Some("symlink to something other than a directory")
+                } else {
+                    None
+                }
+            } else if !entry.file_type().is_file() {
+                Some("not a regular file")
+            } else {
+                None
+            };
+            exclusion = exclusion.or_else(|| filters.exclusion(path, entry.path()));
+            if exclusion.is_none() && filters.dedupe_inodes {
+                let meta = entry
+                    .metadata()
+                    .context(format!("could not read metadata of {}", entry.path().display()))?;
+                if !seen_inodes.insert((meta.dev(), meta.ino())) {
+                    exclusion = Some("same file as one matched before, left out by --dedupe-inodes");
+                }
+            }
+            f(path, entry, exclusion)?;
+        }
+    }
+    Ok(())
+}
+
+/// Directories of virtual files and runtime state, skipped under broad tracked paths like /.
+const SYSTEM_DIRS: &[&str] = &["/proc", "/sys", "/dev", "/run"];
+
+/// Resolve a path given to add, returning how it's stored.
+fn addable_path(
+    paths_db: &PathsDB,
+    path: &Path,
+    force: bool,
+    canonicalize: bool,
+    relative: bool,
+) -> anyhow::Result<(PathBuf, Option<Base>)> {
+    let path = if canonicalize {
+        fs::canonicalize(path).context("could not canonicalize it")?
+    } else {
+        absolute(path).context("could not make it absolute")?.into_owned()
+    };
+    check_addable(paths_db, &path, force)?;
+    if !relative {
+        return Ok((path, None));
+    }
+    match path.strip_prefix(home_dir()?) {
+        Ok(rest) => Ok((rest.to_path_buf(), Some(Base::Home))),
+        Err(_) => Err(anyhow!("{} is not under the home directory", path.display())).context(Exit::Usage),
+    }
+}
+
+/// Tracked path other than `canonical` which resolves to it, like a tracked alias reached through a
+/// symlink, so the same tree isn't scanned twice.
+fn tracked_alias(paths_db: &PathsDB, canonical: &Path) -> anyhow::Result<Option<PathBuf>> {
+    Ok(paths_db
+        .list()?
+        .into_iter()
+        .find(|tracked| tracked != canonical && fs::canonicalize(tracked).is_ok_and(|real| real == canonical)))
+}
+
+/// Print what adding `paths` would do, adding them in a transaction which is rolled back.
+fn plan_add(
+    paths_db: &PathsDB,
+    paths: &[PathBuf],
+    force: bool,
+    canonicalize: bool,
+    relative: bool,
+) -> anyhow::Result<()> {
+    let case_insensitive = paths_db.case_insensitive()?;
+    let tracked = paths_db.list()?;
+    let tx = paths_db.handle.unchecked_transaction()?;
+    let mut added = 0;
+    for path in paths {
+        let (stored, base) = match addable_path(paths_db, path, force, canonicalize, relative) {
+            Ok(addable) => addable,
+            Err(err) => {
+                println!("{}: refused ({})", path.display(), err.root_cause());
+                continue;
+            }
+        };
+        if canonicalize {
+            if let Some(alias) = tracked_alias(paths_db, &resolve_stored(&stored, base)?)? {
+                println!("{}: skipped (same as {})", path.display(), alias.display());
+                continue;
+            }
+        }
+        if !paths_db.insert(&stored, base)? {
+            println!("{}: skipped (duplicate)", path.display());
+            continue;
+        }
+        added += 1;
+        let resolved = resolve_stored(&stored, base)?;
+        let mut notes = Vec::new();
+        let key = path_key(&resolved, case_insensitive);
+        if let Some(parent) = tracked
+            .iter()
+            .find(|parent| key.starts_with(path_key(parent, case_insensitive)))
+        {
+            notes.push(format!("nested under {}", parent.display()));
+        }
+        if fs::symlink_metadata(&resolved).is_err() {
+            notes.push("warning: does not exist".to_string());
+        }
+        if notes.is_empty() {
+            println!("{}: added", path.display());
+        } else {
+            println!("{}: added ({})", path.display(), notes.join(", "));
+        }
+    }
+    tx.rollback()?;
+    println!("Would add {} of {} paths", added, paths.len());
+    Ok(())
+}
+
+/// Failures of a command processing several paths, which keeps going with the other paths.
+struct Batch {
+    total: usize,
+    done: &'static str,
+    failures: Vec<anyhow::Error>,
+}
+
+impl Batch {
+    fn new(total: usize, done: &'static str) -> Batch {
+        Batch {
+            total,
+            done,
+            failures: Vec::new(),
+        }
+    }
+
+    fn fail(&mut self, err: anyhow::Error) {
+        // A single path fails with its own error, there's nothing else to keep going with.
+        if self.total > 1 {
+            eprintln!("Error: {:#}", err);
+        }
+        self.failures.push(err);
+    }
+
+    /// Fail if any path did, with a usage error when all of them were.
+    fn finish(mut self) -> anyhow::Result<()> {
+        if self.total == 1 && self.failures.len() == 1 {
+            return Err(self.failures.remove(0));
+        }
+        if self.failures.is_empty() {
+            return Ok(());
+        }
+        let summary = format!(
+            "{} of {} paths could not be {}",
+            self.failures.len(),
+            self.total,
+            self.done
+        );
+        if self.failures.iter().all(|err| exit::code(err) == Exit::Usage as u8) {
+            Err(anyhow!(Exit::Usage)).context(summary)
+        } else {
+            Err(anyhow!(summary))
+        }
+    }
+}
+
+/// Refuse paths which are most likely a mistake unless forced, and warn when the database would be tracked.
+fn check_addable(paths_db: &PathsDB, path: &Path, force: bool) -> anyhow::Result<()> {
+    if !force {
+        if fs::symlink_metadata(path).is_err() {
+            return Err(anyhow!(
+                "{} does not exist, use --force to add it anyway",
+                path.display()
+            ))
+            .context(Exit::Usage);
+        }
+        if let Some(system) = ["/proc", "/sys", "/dev"].iter().find(|dir| path.starts_with(dir)) {
+            return Err(anyhow!(
+                "{} is under {} which only holds virtual files, use --force to add it anyway",
+                path.display(),
+                system
+            ))
+            .context(Exit::Usage);
+        }
+    }
+    let contained: Vec<&str> = SYSTEM_DIRS
+        .iter()
+        .copied()
+        .filter(|dir| Path::new(dir).starts_with(path) && Path::new(dir) != path)
+        .collect();
+    if !contained.is_empty() {
+        eprintln!(
+            "Warning: {} contains {}, which are skipped unless --no-safe-excludes is used",
+            path.display(),
+            contained.join(", ")
+        );
+    }
+    if let Some(db_path) = &paths_db.path {
+        if db_path.starts_with(path) {
+            eprintln!(
+                "Warning: {} contains the track database, which is left out unless --include-db is used",
+                path.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Reason a file found under the tracked path `root` would be left out of exports, if any.
+fn export_exclusion(root: &Path, file: &Path, filters: &Filters) -> Option<&'static str> {
+    match fs::symlink_metadata(file) {
+        Err(_) => return Some("file does not exist"),
+        Ok(meta) if !meta.is_file() => return Some("not a regular file"),
+        Ok(_) => {}
+    }
+    for dir in file.ancestors().skip(1) {
+        if dir.file_name().is_some_and(|name| filters.skips_dir(name)) && dir.is_dir() {
+            return Some("inside a skipped directory");
+        }
+        if dir == root {
+            break;
+        }
+        if filters.skips_system_dir(dir) {
+            return Some("inside a system directory, left out unless --no-safe-excludes is used");
+        }
+    }
+    filters.exclusion(root, file)
+}
+
+fn which(paths: &[PathBuf], file: &Path, filters: &Filters, json: bool) -> anyhow::Result<()> {
+    let roots: Vec<&PathBuf> = paths.iter().filter(|root| file.starts_with(root)).collect();
+    let exclusions: Vec<Option<&str>> = roots.iter().map(|root| export_exclusion(root, file, filters)).collect();
+    let exported = exclusions.iter().any(Option::is_none);
+
+    if json {
+        let roots = roots
+            .iter()
+            .zip(&exclusions)
+            .map(|(root, exclusion)| {
+                json::object([
+                    ("path", json::Value::path(root)),
+                    ("exported", exclusion.is_none().into()),
+                    ("reason", (*exclusion).into()),
+                ])
+            })
+            .collect();
+        let output = json::object([
+            ("path", json::Value::path(file)),
+            ("roots", json::Value::Array(roots)),
+            ("exported", exported.into()),
+        ]);
+        println!("{}", json::envelope("which", output));
+        return Ok(());
+    }
+
+    println!("{}", file.display());
+    if roots.is_empty() {
+        println!("  not under any tracked path");
+    }
+    for (root, exclusion) in roots.iter().zip(&exclusions) {
+        match exclusion {
+            None => println!("  under tracked path {}, exported", root.display()),
+            Some(reason) => println!("  under tracked path {}, not exported: {}", root.display(), reason),
+        }
+    }
+    println!("  would {}be exported", if exported { "" } else { "not " });
+    Ok(())
+}
+
+/// What a filter decides for the file given to explain.
+struct Decision {
+    filter: String,
+    /// Whether the filter is in use, with the options or the database given.
+    used: bool,
+    /// Why the filter leaves the file out, if it does.
+    excluded: Option<String>,
+}
+
+impl Decision {
+    fn new(filter: impl Into<String>, used: bool, excluded: Option<String>) -> Decision {
+        Decision {
+            filter: filter.into(),
+            used,
+            excluded: excluded.filter(|_| used),
+        }
+    }
+}
+
+/// Decision of every filter for a file found under the tracked path `root`, in the order scans apply them.
+fn decisions(root: &Path, file: &Path, filters: &Filters) -> Vec<Decision> {
+    let file_type = match fs::symlink_metadata(file) {
+        Err(_) => Some("it does not exist".to_string()),
+        Ok(meta) if meta.file_type().is_symlink() => Some("it is a symlink".to_string()),
+        Ok(meta) if !meta.is_file() => Some("it is not a regular file".to_string()),
+        Ok(_) => None,
+    };
+    let mut skipped_dir = None;
+    let mut system_dir = None;
+    for dir in file.ancestors().skip(1) {
+        if let Some(name) = dir.file_name().filter(|name| filters.skips_dir(name) && dir.is_dir()) {
+            skipped_dir.get_or_insert_with(|| format!("it is inside {}, a skipped directory", name.to_string_lossy()));
+        }
+        if dir == root {
+            break;
+        }
+        if filters.skips_system_dir(dir) {
+            system_dir.get_or_insert_with(|| format!("it is inside the system directory {}", dir.display()));
+        }
+    }
+    let mut decisions = vec![
+        Decision::new("regular file", true, file_type),
+        Decision::new("skipped directories, .git and --skip-dir", true, skipped_dir),
+        Decision::new("system directories", !filters.system_dirs.is_empty(), system_dir),
+        Decision::new(
+            "track database",
+            !filters.excluded.is_empty(),
+            filters
+                .excluded
+                .contains(file)
+                .then(|| "it is a track database file, left out unless --include-db is used".to_string()),
+        ),
+        Decision::new(
+            "--exclude-stdin",
+            !filters.listed.is_empty(),
+            filters
+                .listed
+                .contains(file)
+                .then(|| "it is listed on stdin".to_string()),
+        ),
+    ];
+    for (glob, matches) in filters.patterns.each_match(file) {
+        decisions.push(Decision::new(
+            format!("pattern {}", glob),
+            true,
+            matches.then(|| "it matches".to_string()),
+        ));
+    }
+    for (glob, matches) in filters
+        .root_patterns
+        .get(root)
+        .into_iter()
+        .flat_map(|set| set.each_match(file))
+    {
+        decisions.push(Decision::new(
+            format!("exclude {} of the tracked path", glob),
+            true,
+            matches.then(|| "it matches".to_string()),
+        ));
+    }
+    decisions.push(Decision::new(
+        "--skip-git-lfs",
+        filters.lfs.is_some(),
+        filters
+            .lfs
+            .as_ref()
+            .is_some_and(|lfs| lfs.is_materialized(file))
+            .then(|| "it is a file materialized by git LFS".to_string()),
+    ));
+    decisions
+}
+
+fn explain(paths: &[PathBuf], file: &Path, filters: &Filters, json: bool) -> anyhow::Result<()> {
+    let roots: Vec<&PathBuf> = paths.iter().filter(|root| file.starts_with(root)).collect();
+    // The verdict is the one of exports, the decisions only explain it.
+    let exclusions: Vec<Option<&str>> = roots.iter().map(|root| export_exclusion(root, file, filters)).collect();
+    let decisions: Vec<Vec<Decision>> = roots.iter().map(|root| decisions(root, file, filters)).collect();
+    let exported = exclusions.iter().any(Option::is_none);
+
+    if json {
+        let roots = roots
+            .iter()
+            .zip(&exclusions)
+            .zip(&decisions)
+            .map(|((root, exclusion), decisions)| {
+                let decisions = decisions
+                    .iter()
+                    .map(|decision| {
+                        json::object([
+                            ("filter", decision.filter.as_str().into()),
+                            ("used", decision.used.into()),
+                            ("excluded", decision.excluded.is_some().into()),
+                            ("reason", decision.excluded.as_deref().into()),
+                        ])
+                    })
+                    .collect();
+                json::object([
+                    ("path", json::Value::path(root)),
+                    ("filters", json::Value::Array(decisions)),
+                    ("exported", exclusion.is_none().into()),
+                    ("reason", (*exclusion).into()),
+                ])
+            })
+            .collect();
+        let output = json::object([
+            ("path", json::Value::path(file)),
+            ("roots", json::Value::Array(roots)),
+            ("exported", exported.into()),
+        ]);
+        println!("{}", json::envelope("explain", output));
+        return Ok(());
+    }
+
+    println!("{}", file.display());
+    if roots.is_empty() {
+        println!("  not under any tracked path");
+    }
+    for ((root, exclusion), decisions) in roots.iter().zip(&exclusions).zip(&decisions) {
+        println!("  under tracked path {}", root.display());
+        for decision in decisions {
+            match (&decision.excluded, decision.used) {
+                (_, false) => println!("    {}: not used", decision.filter),
+                (None, true) => println!("    {}: included", decision.filter),
+                (Some(reason), true) => println!("    {}: excluded, {}", decision.filter, reason),
+            }
+        }
+        if filters.dedupe_inodes {
+            println!("    --dedupe-inodes: left out if the scan matches the same inode through another path first");
+        }
+        match exclusion {
+            None => println!("    exported"),
+            Some(reason) => println!("    not exported: {}", reason),
+        }
+    }
+    println!("  would {}be exported", if exported { "" } else { "not " });
+    Ok(())
+}
+
+/// Print the files left out of the tracked paths with the reason, for matched --show-excluded.
+fn print_excluded(paths: &[PathBuf], filters: &Filters, format: MatchedFormat, style: PathStyle) -> anyhow::Result<()> {
+    if style == PathStyle::Null {
+        return Err(anyhow!(
+            "--show-excluded prints a reason along with each path, it can't use -0"
+        ))
+        .context(Exit::Usage);
+    }
+    let mut stdout = io::stdout().lock();
+    for_each_entry(paths, filters, |root, entry, exclusion| {
+        let reason = match exclusion {
+            Some(reason) => reason,
+            None => return Ok(()),
+        };
+        match format {
+            MatchedFormat::Text => {
+                let path = match style {
+                    PathStyle::Escape => shell_escape(entry.path()),
+                    _ => entry.path().display().to_string(),
+                };
+                writeln!(stdout, "{}: {}", path, reason)?;
+            }
+            MatchedFormat::Ndjson => {
+                let record = json::object([
+                    ("path", json::Value::path(entry.path())),
+                    ("root", json::Value::path(root)),
+                    ("reason", reason.into()),
+                ]);
+                writeln!(stdout, "{}", record)?;
+                stdout.flush()?;
+            }
+        }
+        Ok(())
+    })
+}
+
+fn stats(paths: &[PathBuf], filters: &Filters) -> anyhow::Result<Table> {
+    let mut table = Table::new(&["path", "files", "size"]);
+    for path in paths {
+        let matches = find_matches(std::slice::from_ref(path), filters)?;
+        let mut size = 0;
+        for mat in &matches {
+            size += fs::metadata(mat)?.len();
+        }
+        table.push(vec![
+            Cell::Path(path.clone()),
+            Cell::Count(matches.len() as u64),
+            Cell::Size(size),
+        ]);
+    }
+    Ok(table)
+}
+
+/// The `count` largest matched files with their size, largest first, each listed once even when it's
+/// under nested tracked paths.
+fn largest(paths: &[PathBuf], filters: &Filters, count: usize) -> anyhow::Result<Vec<(u64, PathBuf)>> {
+    let mut sizes = Vec::new();
+    for mat in find_matches(paths, filters)? {
+        sizes.push((fs::metadata(&mat)?.len(), mat));
+    }
+    sizes.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    sizes.dedup();
+    sizes.truncate(count);
+    Ok(sizes)
+}
+
+fn top(paths: &[PathBuf], filters: &Filters, count: usize) -> anyhow::Result<Table> {
+    let mut table = Table::new(&["path", "size"]);
+    for (size, path) in largest(paths, filters, count)? {
+        table.push(vec![Cell::Path(path), Cell::Size(size)]);
+    }
+    Ok(table)
+}
+
+/// Print the `limit` tracked paths added or scanned most recently, newest first.
+fn recent(paths_db: &PathsDB, limit: usize, by: RecentBy, json: bool) -> anyhow::Result<()> {
+    let mut paths: Vec<Timestamps> = paths_db
+        .timestamps()?
+        .into_iter()
+        .filter(|path| path.by(by).is_some())
+        .collect();
+    paths.sort_by(|a, b| b.by(by).cmp(&a.by(by)).then_with(|| a.path.cmp(&b.path)));
+    paths.truncate(limit);
+    let mut stdout = io::stdout().lock();
+    if json {
+        let paths = paths
+            .into_iter()
+            .map(|path| {
+                json::object([
+                    ("path", json::Value::path(&path.path)),
+                    ("added_at", path.added_at.into()),
+                    ("scanned_at", path.scanned_at.into()),
+                ])
+            })
+            .collect();
+        writeln!(stdout, "{}", json::envelope("recent", json::Value::Array(paths)))?;
+    } else {
+        for path in paths {
+            let time = UNIX_EPOCH + Duration::from_secs(path.by(by).expect("path without a time"));
+            writeln!(stdout, "{}  {}", log::timestamp(time), path.path.display())?;
+        }
+    }
+    Ok(())
+}
+
+/// Parse a selection of numbers from 1 to `max`, like `1 3-5,7`.
+fn parse_selection(s: &str, max: usize) -> anyhow::Result<Vec<usize>> {
+    let mut selected = Vec::new();
+    for item in s
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|item| !item.is_empty())
+    {
+        let (start, end) = item.split_once('-').unwrap_or((item, item));
+        let start: usize = start.parse().context(format!("invalid selection {}", item))?;
+        let end: usize = end.parse().context(format!("invalid selection {}", item))?;
+        if start == 0 || end > max || start > end {
+            bail!("Selection {} is not between 1 and {}", item, max);
+        }
+        selected.extend(start..=end);
+    }
+    selected.sort_unstable();
+    selected.dedup();
+    Ok(selected)
+}
+
+/// Offer the largest matched files to leave out of exports, saving the chosen ones as excludes of the
+/// tracked paths they are under. The `exclude_top` largest ones are chosen without asking when given.
+fn trim(paths_db: &PathsDB, filters: &Filters, count: usize, exclude_top: Option<usize>) -> anyhow::Result<()> {
+    let roots = paths_db.list()?;
+    let largest = largest(&roots, filters, exclude_top.unwrap_or(count))?;
+    if largest.is_empty() {
+        println!("No file to trim");
+        return Ok(());
+    }
+    let selected = match exclude_top {
+        Some(_) => (1..=largest.len()).collect(),
+        None => {
+            if !atty::is(atty::Stream::Stdin) {
+                return Err(anyhow!(
+                    "trim asks which files to exclude, use --exclude-top N without a terminal"
+                ))
+                .context(Exit::Usage);
+            }
+            for (i, (size, path)) in largest.iter().enumerate() {
+                println!("{:>3}  {:>10}  {}", i + 1, report::format_size(*size), path.display());
+            }
+            eprint!("Files to exclude, like 1 3-5 (none when empty): ");
+            io::stderr().flush()?;
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer)?;
+            parse_selection(&answer, largest.len()).context(Exit::Usage)?
+        }
+    };
+
+    let tx = paths_db.handle.unchecked_transaction()?;
+    for i in selected {
+        let path = &largest[i - 1].1;
+        let pattern = match path.to_str() {
+            Some(path) => glob::escape(path),
+            None => {
+                eprintln!("Skipping {} which is not valid UTF-8", path.display());
+                continue;
+            }
+        };
+        let metadata = Metadata {
+            excludes: vec![pattern.parse()?],
+            ..Metadata::default()
+        };
+        // Nested tracked paths all match the file, each of them needs the exclude.
+        for root in roots.iter().filter(|root| path.starts_with(root)) {
+            let stored = paths_db.stored_as(root)?.expect("tracked path is not stored");
+            paths_db.set_metadata(&stored, &metadata)?;
+            println!("Excluded {} from {}", path.display(), root.display());
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Number of tracked paths a pattern may select before tag and untag ask for confirmation.
+const RETAG_CONFIRM_ABOVE: usize = 10;
+
+/// Add or remove tags of the tracked paths matching a pattern.
+fn retag(paths_db: &PathsDB, args: &RetagArgs, remove: bool) -> anyhow::Result<()> {
+    let selected: Vec<PathBuf> = paths_db
+        .list()?
+        .into_iter()
+        .filter(|path| args.glob.matches(path, false))
+        .collect();
+    if selected.is_empty() {
+        return Err(anyhow!("no tracked path matches {}", args.glob)).context(Exit::Usage);
+    }
+    let (verb, done) = if remove {
+        ("Untag", "Untagged")
+    } else {
+        ("Tag", "Tagged")
+    };
+    if selected.len() > RETAG_CONFIRM_ABOVE && !args.yes {
+        if !atty::is(atty::Stream::Stdin) {
+            return Err(anyhow!(
+                "{} matches {} tracked paths, use --yes to change them without a terminal",
+                args.glob,
+                selected.len()
+            ))
+            .context(Exit::Usage);
+        }
+        for path in &selected {
+            println!("{}", path.display());
+        }
+        eprint!("{} these {} paths? [y/N] ", verb, selected.len());
+        io::stderr().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            return Err(anyhow!("cancelled")).context(Exit::Usage);
+        }
+    }
+
+    let tx = paths_db.handle.unchecked_transaction()?;
+    let mut changed = 0;
+    for path in &selected {
+        let stored = paths_db.stored_as(path)?.expect("tracked path is not stored");
+        let path_bytes = stored.as_os_str().as_bytes();
+        let mut rows = 0;
+        for tag in &args.tags {
+            rows += if remove {
+                tx.execute(
+                    "DELETE FROM tags WHERE path = ? AND tag = ?",
+                    rusqlite::params![path_bytes, tag],
+                )?
+            } else {
+                tx.execute(
+                    "INSERT OR IGNORE INTO tags (path, tag) VALUES (?, ?)",
+                    rusqlite::params![path_bytes, tag],
+                )?
+            };
+        }
+        if rows > 0 {
+            println!("{} {}", done, path.display());
+            changed += 1;
+        }
+    }
+    tx.commit()?;
+    eprintln!("{} {} of {} matching paths", done, changed, selected.len());
+    Ok(())
+}
+
+/// Bytes read from each sampled file, enough to see how it compresses without reading it all.
+const SAMPLE_READ_LIMIT: u64 = 4 * 1024 * 1024;
+
+fn estimate(paths: &[PathBuf], filters: &Filters, kind: &ExportKind, sample: usize) -> anyhow::Result<()> {
+    if !matches!(kind, ExportKind::Tar | ExportKind::Zip) {
+        return Err(anyhow!("estimate only applies to tar and zip exports")).context(Exit::Usage);
+    }
+    let matches = find_matches(paths, filters)?;
+    let mut total = 0;
+    let mut overhead = 0;
+    for mat in &matches {
+        let size = fs::metadata(mat)?.len();
+        let name_len = mat.as_os_str().len() as u64;
+        total += size;
+        overhead += match kind {
+            // Header and padding to the next block, compressed along with the content.
+            ExportKind::Tar => 512 + (512 - size % 512) % 512,
+            // Local header, data descriptor and central directory entry with their extended
+            // timestamps, which aren't compressed.
+            _ => 30 + 24 + 46 + 2 * (9 + name_len),
+        };
+    }
+
+    let step = (matches.len() / sample.max(1)).max(1);
+    let (mut read, mut compressed, mut sampled) = (0, 0, 0);
+    for mat in matches.iter().step_by(step).take(sample) {
+        let file = File::open(mat).context(format!("could not open {}", mat.display()))?;
+        let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        read += io::copy(&mut io::Read::take(file, SAMPLE_READ_LIMIT), &mut encoder)?;
+        compressed += encoder.finish()?.len() as u64;
+        sampled += 1;
+    }
+    let ratio = if read == 0 {
+        1.0
+    } else {
+        compressed as f64 / read as f64
+    };
+    let estimated = match kind {
+        ExportKind::Tar => ((total + overhead) as f64 * ratio) as u64,
+        _ => (total as f64 * ratio) as u64 + overhead,
+    };
+
+    println!("Matched {} files, {}", matches.len(), report::format_size(total));
+    println!(
+        "Sampled {} files, {} compressed to {}",
+        sampled,
+        report::format_size(read),
+        report::format_size(compressed)
+    );
+    println!(
+        "Estimated archive size: {} ({:.1}% of the files)",
+        report::format_size(estimated),
+        ratio * 100.0
+    );
+    println!("This extrapolates from a sample, the real size can differ when the files compress unevenly.");
+    Ok(())
+}
+
+fn retain_modified_since(matches: &mut Vec<PathBuf>, since: SystemTime) -> anyhow::Result<()> {
+    let mut kept = Vec::with_capacity(matches.len());
+    for mat in matches.drain(..) {
+        let modified = fs::metadata(&mat)
+            .and_then(|m| m.modified())
+            .context(format!("could not read modification time of {}", mat.display()))?;
+        if modified >= since {
+            kept.push(mat);
+        }
+    }
+    *matches = kept;
+    Ok(())
+}
+
+/// File in dir and bagit destinations listing the top-level entries exports wrote, one name per line, to
+/// tell them from entries something else put there.
+const EXPORT_MARKER: &str = ".track-export";
+
+/// Top-level entry of an export destination which cleaning it removes.
+struct Removal {
+    name: PathBuf,
+    is_dir: bool,
+    /// Files and symlinks under it, or 1 for a file.
+    files: u64,
+    size: u64,
+    /// Whether the export marker lists it.
+    managed: bool,
+}
+
+/// Names the export marker of `root` lists, none when there's no marker.
+fn managed_entries(root: &Path) -> anyhow::Result<HashSet<PathBuf>> {
+    let path = root.join(EXPORT_MARKER);
+    let content = match fs::read(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(err) => return Err(err).context(format!("could not read {}", path.display())),
+    };
+    Ok(content
+        .split(|&b| b == b'\n')
+        .filter(|name| !name.is_empty())
+        .map(|name| PathBuf::from(OsStr::from_bytes(name)))
+        .collect())
+}
+
+/// List the top-level entries `names` in the export marker of `dest`, along with those listed before
+/// when resuming.
+fn write_export_marker(dest: &Path, names: impl IntoIterator<Item = PathBuf>, resume: bool) -> anyhow::Result<()> {
+    let mut managed = if resume { managed_entries(dest)? } else { HashSet::new() };
+    managed.extend(names);
+    let mut names: Vec<PathBuf> = managed.into_iter().collect();
+    names.sort();
+    let mut content = Vec::new();
+    for name in names {
+        content.extend_from_slice(name.as_os_str().as_bytes());
+        content.push(b'\n');
+    }
+    let path = dest.join(EXPORT_MARKER);
+    fs::write(&path, content).context(format!("could not write {}", path.display()))
+}
+
+/// What clean_dir would remove from `root`: its top-level entries but .git and the export marker, with
+/// the number of files and bytes under each.
+fn clean_impact(root: &Path) -> anyhow::Result<Vec<Removal>> {
+    let managed = managed_entries(root)?;
+    let mut removals = Vec::new();
+    for child in root.read_dir().context(format!("could not read {}", root.display()))? {
+        let child = child?;
+        if child.is_git_dir()? || child.file_name() == EXPORT_MARKER {
+            continue;
+        }
+        let name = PathBuf::from(child.file_name());
+        let mut removal = Removal {
+            is_dir: child.file_type()?.is_dir(),
+            files: 0,
+            size: 0,
+            managed: managed.contains(&name),
+            name,
+        };
+        for entry in WalkDir::new(child.path()) {
+            let entry = entry.context(format!("could not walk {}", child.path().display()))?;
+            if entry.file_type().is_dir() {
+                continue;
+            }
+            removal.files += 1;
+            removal.size += entry.metadata()?.len();
+        }
+        removals.push(removal);
+    }
+    removals.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(removals)
+}
+
+/// Print on stderr what cleaning `root` removes, asking first on a terminal when it would remove entries
+/// no previous export wrote.
+fn report_clean(root: &Path) -> anyhow::Result<()> {
+    let removals = clean_impact(root)?;
+    if removals.is_empty() {
+        return Ok(());
+    }
+    let plural = |count: u64, one: &str, many: &str| format!("{} {}", count, if count == 1 { one } else { many });
+    let files = removals.iter().map(|removal| removal.files).sum();
+    let size = removals.iter().map(|removal| removal.size).sum();
+    eprintln!(
+        "Cleaning {} removes {}, {}, {}:",
+        root.display(),
+        plural(removals.len() as u64, "entry", "entries"),
+        plural(files, "file", "files"),
+        report::format_size(size)
+    );
+    for removal in &removals {
+        eprintln!(
+            "  {}{}  {}, {}{}",
+            removal.name.display(),
+            if removal.is_dir { "/" } else { "" },
+            plural(removal.files, "file", "files"),
+            report::format_size(removal.size),
+            if removal.managed {
+                ""
+            } else {
+                "  (not written by track)"
+            }
+        );
+    }
+    let foreign: Vec<String> = removals
+        .iter()
+        .filter(|removal| !removal.managed)
+        .map(|removal| removal.name.display().to_string())
+        .collect();
+    if foreign.is_empty() {
+        return Ok(());
+    }
+    eprintln!(
+        "Warning: no previous export wrote {}, check {} is the right destination",
+        foreign.join(", "),
+        root.display()
+    );
+    if atty::is(atty::Stream::Stdin) {
+        eprint!("Remove them? [y/N] ");
+        io::stderr().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            return Err(anyhow!("cancelled")).context(Exit::Usage);
+        }
+    }
+    Ok(())
+}
+
+fn clean_dir(root: &Path) -> anyhow::Result<()> {
+    let root_children = root.read_dir()?;
+    for child in root_children {
+        let child = child?;
+        if child.is_git_dir()? {
+            continue;
+        }
+        if child.file_type()?.is_dir() {
+            fs::remove_dir_all(child.path())?;
+        } else {
+            fs::remove_file(child.path())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn reflink_file(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // _IOW(0x94, 9, int) from linux/fs.h, not exposed by the libc crate.
+    const FICLONE: u32 = 0x40049409;
+
+    let src_file = File::open(src)?;
+    let dst_file = File::create(dst)?;
+    let ret = unsafe { libc::ioctl(dst_file.as_raw_fd(), FICLONE as _, src_file.as_raw_fd()) };
+    if ret == -1 {
+        let err = io::Error::last_os_error();
+        drop(dst_file);
+        let _ = fs::remove_file(dst);
+        return Err(err);
+    }
+    dst_file.set_permissions(src_file.metadata()?.permissions())?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink_file(_src: &Path, _dst: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reflinks are not supported on this platform",
+    ))
+}
+
+/// Size of the buffer of buffered copies, large enough for few system calls on big files.
+const COPY_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Copy the content of `src` to `dst` with a given method, giving `dst` the permissions of `src`.
+fn copy_contents(src: &Path, dst: &Path, method: CopyMethod) -> io::Result<()> {
+    if method == CopyMethod::Auto {
+        // The standard library already copies in the kernel where it can, and falls back to a buffer.
+        return fs::copy(src, dst).map(drop);
+    }
+    let mut src_file = File::open(src)?;
+    let meta = src_file.metadata()?;
+    let mut dst_file = File::create(dst)?;
+    match method {
+        CopyMethod::Auto => unreachable!("auto copies are done by the standard library"),
+        CopyMethod::Buffered => {
+            let mut reader = io::BufReader::with_capacity(COPY_BUFFER_SIZE, &mut src_file);
+            io::copy(&mut reader, &mut dst_file)?;
+        }
+        CopyMethod::CopyFileRange | CopyMethod::Sendfile => kernel_copy(&src_file, &dst_file, meta.len(), method)?,
+    }
+    // Like fs::copy, the source is read until its end even when it grew since its size was read.
+    if method != CopyMethod::Buffered {
+        io::copy(&mut src_file, &mut dst_file)?;
+    }
+    dst_file.set_permissions(meta.permissions())
+}
+
+/// Copy `len` bytes from the start of `src` to `dst` without going through user space.
+#[cfg(target_os = "linux")]
+fn kernel_copy(src: &File, dst: &File, len: u64, method: CopyMethod) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut left = len;
+    while left > 0 {
+        let chunk = left.min(1 << 30) as usize;
+        let copied = unsafe {
+            match method {
+                CopyMethod::CopyFileRange => libc::copy_file_range(
+                    src.as_raw_fd(),
+                    std::ptr::null_mut(),
+                    dst.as_raw_fd(),
+                    std::ptr::null_mut(),
+                    chunk,
+                    0,
+                ),
+                _ => libc::sendfile(dst.as_raw_fd(), src.as_raw_fd(), std::ptr::null_mut(), chunk),
+            }
+        };
+        match copied {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            // The file shrank.
+            0 => break,
+            n => left -= n as u64,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn kernel_copy(_src: &File, _dst: &File, _len: u64, _method: CopyMethod) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "in-kernel copies are not supported on this platform",
+    ))
+}
+
+fn copy_file(src: &Path, dst: &Path, reflink: Reflink, method: CopyMethod) -> anyhow::Result<()> {
+    match reflink {
+        Reflink::Auto => {
+            if reflink_file(src, dst).is_err() {
+                copy_contents(src, dst, method).context(format!("could not copy {}", src.display()))?;
+            }
+        }
+        Reflink::Always => {
+            reflink_file(src, dst).context(format!("could not reflink {}", src.display()))?;
+        }
+        Reflink::Never => {
+            copy_contents(src, dst, method).context(format!("could not copy {}", src.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// A matched file along with the relative name it is exported under.
+struct ExportEntry {
+    path: PathBuf,
+    name: PathBuf,
+}
+
+/// Sort the matched files in the order of --order, ties are broken by path.
+fn sort_matches(matches: &mut Vec<PathBuf>, order: ExportOrder) -> anyhow::Result<()> {
+    let metadata =
+        |path: &Path| fs::symlink_metadata(path).context(format!("could not read metadata of {}", path.display()));
+    match order {
+        ExportOrder::Walk => {}
+        ExportOrder::Path => matches.sort(),
+        ExportOrder::Name => matches.sort_by(|a, b| a.file_name().cmp(&b.file_name()).then_with(|| a.cmp(b))),
+        ExportOrder::Size | ExportOrder::Mtime => {
+            let mut keyed = Vec::with_capacity(matches.len());
+            for path in matches.drain(..) {
+                let meta = metadata(&path)?;
+                let key = if order == ExportOrder::Size {
+                    i128::from(meta.len())
+                } else {
+                    i128::from(meta.mtime()) * 1_000_000_000 + i128::from(meta.mtime_nsec())
+                };
+                keyed.push((key, path));
+            }
+            keyed.sort();
+            matches.extend(keyed.into_iter().map(|(_, path)| path));
+        }
+    }
+    Ok(())
+}
+
+/// Root of the nearest git repository containing `file`, remembering which directories are roots.
+fn repo_root<'a>(file: &'a Path, roots: &mut HashMap<PathBuf, bool>) -> Option<&'a Path> {
+    file.ancestors()
+        .skip(1)
+        .find(|dir| *roots.entry(dir.to_path_buf()).or_insert_with(|| lfs::is_repo_root(dir)))
+}
+
+fn export_entries(matches: Vec<PathBuf>, args: &ExportArgs) -> anyhow::Result<Vec<ExportEntry>> {
+    let home = if args.home_relative { Some(home_dir()?) } else { None };
+    let mut repo_roots = HashMap::new();
+    // Repository roots by the name their files are stored under, which must be unique.
+    let mut repo_names: HashMap<OsString, PathBuf> = HashMap::new();
+    let mut bases = Vec::with_capacity(matches.len());
+    for path in matches {
+        let repo = if args.repo_relative {
+            repo_root(&path, &mut repo_roots).and_then(|root| Some((root, root.file_name()?)))
+        } else {
+            None
+        };
+        let base = if let Some((root, name)) = repo {
+            let claimed = repo_names.entry(name.to_owned()).or_insert_with(|| root.to_path_buf());
+            if claimed != root {
+                return Err(anyhow!(
+                    "the repositories {} and {} would both be stored as {}",
+                    claimed.display(),
+                    root.display(),
+                    Path::new(name).display()
+                ))
+                .context(Exit::Usage);
+            }
+            Path::new(name).join(path.strip_prefix(root)?)
+        } else {
+            match home.as_ref().and_then(|home| path.strip_prefix(home).ok()) {
+                Some(rest) => args.home_placeholder.join(rest),
+                None => path.strip_prefix("/")?.to_path_buf(),
+            }
+        };
+        bases.push((path, base));
+    }
+    let common = if args.trim_common_prefix {
+        common_dir(bases.iter().map(|(_, base)| base.as_path()))
+    } else {
+        PathBuf::new()
+    };
+    let mut entries = Vec::with_capacity(bases.len());
+    for (path, base) in bases {
+        let base = base.strip_prefix(&common).expect("path is not under the common prefix");
+        let mut components = base.components();
+        if components.clone().count() <= args.strip_components {
+            match args.strip_mode {
+                StripMode::Skip => continue,
+                StripMode::Error => bail!(
+                    "cannot strip {} components from {}",
+                    args.strip_components,
+                    path.display()
+                ),
+            }
+        }
+        for _ in 0..args.strip_components {
+            components.next();
+        }
+        let name = match &args.prefix {
+            Some(prefix) => prefix.join(components.as_path()),
+            None => components.as_path().to_path_buf(),
+        };
+        entries.push(ExportEntry { path, name });
+    }
+    Ok(entries)
+}
+
+/// Deepest directory every one of the paths is under, empty when they have none in common.
+fn common_dir<'a>(mut paths: impl Iterator<Item = &'a Path>) -> PathBuf {
+    let mut common = match paths.next().and_then(Path::parent) {
+        Some(parent) => parent.to_path_buf(),
+        None => return PathBuf::new(),
+    };
+    for path in paths {
+        while !path.parent().is_some_and(|parent| parent.starts_with(&common)) {
+            if !common.pop() {
+                return PathBuf::new();
+            }
+        }
+    }
+    common
+}
+
+/// Whether a previous dir export already copied `src` to `dst` completely.
+///
+/// Copies get the modification time of their source once they are complete, so a file left partially
+/// written by an interrupted export has a different modification time (or size) and is copied again.
+fn is_copied(src: &fs::Metadata, dst: &Path) -> bool {
+    match fs::symlink_metadata(dst) {
+        Ok(dst) => {
+            dst.is_file()
+                && dst.len() == src.len()
+                && FileTime::from_last_modification_time(&dst) == FileTime::from_last_modification_time(src)
+        }
+        Err(_) => false,
+    }
+}
+
+/// Link an exported file back to the tracked one, skipping it with a warning if it no longer exists.
+fn symlink_entry(entry: &ExportEntry, new_path: &Path, resume: bool) -> anyhow::Result<()> {
+    if !entry.path.exists() {
+        log::skipping(&entry.path, "which no longer exists");
+        return Ok(());
+    }
+    if resume {
+        match fs::read_link(new_path) {
+            Ok(target) if target == entry.path => return Ok(()),
+            Ok(_) => fs::remove_file(new_path)?,
+            Err(_) if fs::symlink_metadata(new_path).is_ok() => fs::remove_file(new_path)?,
+            Err(_) => {}
+        }
+    }
+    DirBuilder::new()
+        .recursive(true)
+        .create(new_path.parent().expect("new path has no parent"))?;
+    std::os::unix::fs::symlink(&entry.path, new_path)
+        .context(format!("could not create symlink {}", new_path.display()))
+}
+
+/// Create a symlink with the same target as a symlink recorded with --symlinked-dirs record.
+fn copy_symlink(entry: &ExportEntry, new_path: &Path, resume: bool) -> anyhow::Result<()> {
+    let target = fs::read_link(&entry.path).context(format!("could not read symlink {}", entry.path.display()))?;
+    if resume {
+        match fs::read_link(new_path) {
+            Ok(existing) if existing == target => return Ok(()),
+            _ if fs::symlink_metadata(new_path).is_ok() => fs::remove_file(new_path)?,
+            _ => {}
+        }
+    }
+    DirBuilder::new()
+        .recursive(true)
+        .create(new_path.parent().expect("new path has no parent"))?;
+    std::os::unix::fs::symlink(&target, new_path).context(format!("could not create symlink {}", new_path.display()))
+}
+
+fn export_dir(
+    args: &ExportArgs,
+    dest: &Path,
+    entries: &[ExportEntry],
+    pool: &Pool,
+    progress: &Progress,
+) -> anyhow::Result<()> {
+    pool.try_for_each(entries, |entry| {
+        interrupt::check()?;
+        if !watchdog::skipped(export_dir_entry(args, dest, entry))? {
+            progress.file(&entry.path);
+        }
+        Ok(())
+    })
+}
+
+fn export_dir_entry(args: &ExportArgs, dest: &Path, entry: &ExportEntry) -> anyhow::Result<()> {
+    let new_path = dest.join(&entry.name);
+    if args.link == LinkMode::Symlink {
+        return symlink_entry(entry, &new_path, args.resume);
+    }
+    let meta = watchdog::run(args.file_timeout, &entry.path, |path| Ok(fs::symlink_metadata(path)?))?;
+    if meta.file_type().is_symlink() {
+        return copy_symlink(entry, &new_path, args.resume);
+    }
+    let source_flags = flags::read(&entry.path)?;
+    if flags::is_protected(source_flags) {
+        eprintln!(
+            "Warning: {} is {}, its copy isn't",
+            entry.path.display(),
+            flags::protection(source_flags)
+        );
+    }
+    if args.resume {
+        if is_copied(&meta, &new_path) {
+            return Ok(());
+        }
+        if fs::symlink_metadata(&new_path).is_ok_and(|m| m.is_file()) {
+            let dest_flags = flags::read(&new_path)?;
+            if flags::is_protected(dest_flags) {
+                log::skipping(
+                    &new_path,
+                    &format!("which is {} and can't be overwritten", flags::protection(dest_flags)),
+                );
+                return Ok(());
+            }
+        }
+        // Copying through a symlink left by a previous --link symlink export would overwrite the tracked file.
+        if fs::symlink_metadata(&new_path).is_ok_and(|m| m.file_type().is_symlink()) {
+            fs::remove_file(&new_path)?;
+        }
+    }
+    DirBuilder::new()
+        .recursive(true)
+        .create(new_path.parent().expect("new path has no parent"))?;
+    let (dst, reflink, method) = (new_path.clone(), args.reflink, args.copy_method);
+    watchdog::run(args.file_timeout, &entry.path, move |path| {
+        copy_file(path, &dst, reflink, method)
+    })
+    .inspect_err(|err| {
+        // The copy left behind may still be written to, it's unlinked before it's taken for a complete one.
+        if err.is::<watchdog::TimedOut>() {
+            let _ = fs::remove_file(&new_path);
+        }
+    })?;
+    filetime::set_file_mtime(&new_path, FileTime::from_last_modification_time(&meta))?;
+    if args.xattrs {
+        xattrs::apply(&new_path, &xattrs::read(&entry.path)?).context(format!(
+            "could not copy extended attributes of {}",
+            entry.path.display()
+        ))?;
+    }
+    Ok(())
+}
+
+/// Copy the mode and modification time of the source directories onto the directories of a dir export.
+///
+/// Walking up from each file, a directory of the export mirrors the source directory at the same
+/// depth as long as their names are the same. This runs after the files are written, since creating
+/// them modifies their directories.
+fn preserve_dirs(dest: &Path, entries: &[ExportEntry]) -> anyhow::Result<()> {
+    let mut dirs = HashMap::new();
+    for entry in entries {
+        let sources = entry.path.ancestors().skip(1);
+        let names = entry
+            .name
+            .ancestors()
+            .skip(1)
+            .take_while(|name| !name.as_os_str().is_empty());
+        for (source, name) in sources.zip(names) {
+            if source.file_name() != name.file_name() {
+                break;
+            }
+            if dirs.insert(dest.join(name), source.to_path_buf()).is_some() {
+                break;
+            }
+        }
+    }
+    // Deepest first, in case a directory isn't writable once its mode is set.
+    let mut dirs: Vec<(PathBuf, PathBuf)> = dirs.into_iter().collect();
+    dirs.sort_by(|(a, _), (b, _)| b.components().count().cmp(&a.components().count()).then(a.cmp(b)));
+    for (dir, source) in dirs {
+        let meta = fs::metadata(&source).context(format!("could not read metadata of {}", source.display()))?;
+        fs::set_permissions(&dir, meta.permissions())
+            .context(format!("could not set the mode of {}", dir.display()))?;
+        filetime::set_file_mtime(&dir, FileTime::from_last_modification_time(&meta))
+            .context(format!("could not set the modification time of {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+/// Encode a record of a pax extended header, which is prefixed by its own length.
+fn pax_record(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let rest = key.len() + value.len() + 3;
+    let mut len = rest + 1;
+    while rest + len.to_string().len() != len {
+        len = rest + len.to_string().len();
+    }
+    let mut record = format!("{} ", len).into_bytes();
+    record.extend_from_slice(key);
+    record.push(b'=');
+    record.extend_from_slice(value);
+    record.push(b'\n');
+    record
+}
+
+/// Append a pax extended header applying to the next entry of the archive.
+fn append_pax_extensions<W: io::Write>(archiver: &mut tar::Builder<W>, records: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_ustar();
+    header.set_entry_type(tar::EntryType::XHeader);
+    header.set_path("././@PaxHeader")?;
+    header.set_mode(0o644);
+    header.set_size(records.len() as u64);
+    header.set_cksum();
+    archiver.append(&header, records)
+}
+
+/// Duplicates found by hashing the content of every entry.
+struct Duplicates {
+    /// For every entry, the first entry with the same content if it's not the first itself.
+    originals: Vec<Option<usize>>,
+    hasher: Hasher,
+    hashes: Vec<String>,
+}
+
+impl Duplicates {
+    fn find(entries: &[ExportEntry], hasher: Hasher, pool: &Pool) -> anyhow::Result<Duplicates> {
+        // MD5 collisions can be crafted, that's fine for a manifest but not to decide what to leave out.
+        let hasher = if hasher == Hasher::Md5 { Hasher::Sha256 } else { hasher };
+        let hashes = hash_entries(entries, hasher, pool)?;
+        let mut first = HashMap::new();
+        let mut originals = Vec::with_capacity(entries.len());
+        for (index, (entry, hash)) in entries.iter().zip(&hashes).enumerate() {
+            let size = fs::metadata(&entry.path)?.len();
+            let original = *first.entry((size, hash)).or_insert(index);
+            originals.push(if original == index { None } else { Some(original) });
+        }
+        Ok(Duplicates {
+            originals,
+            hasher,
+            hashes,
+        })
+    }
+
+    /// Hashes usable for the manifest, when they were computed with the algorithm it uses.
+    fn manifest_hashes(self, hasher: Hasher) -> Option<Vec<String>> {
+        if self.hasher == hasher {
+            Some(self.hashes)
+        } else {
+            None
+        }
+    }
+}
+
+/// Write a tar archive of the entries.
+///
+/// When a manifest is requested and several jobs are allowed, each file is read once and hashed
+/// while it's compressed, and the hashes are returned. With --dedupe, files identical to one
+/// already archived are stored as hard links to it.
+fn export_tar(
+    args: &ExportArgs,
+    dest: &Path,
+    entries: &[ExportEntry],
+    pool: &Pool,
+    progress: &Progress,
+) -> anyhow::Result<Option<Vec<String>>> {
+    let duplicates = if args.dedupe {
+        Some(Duplicates::find(entries, args.hash, pool)?)
+    } else {
+        None
+    };
+    let pipelined = args.manifest.is_some() && pool.is_parallel() && duplicates.is_none();
+    let mut hashes = Vec::with_capacity(if pipelined { entries.len() } else { 0 });
+    let output = BufWriter::new(create_archive(dest)?);
+    let compressor = gz::Compressor::new(output, args.compress_threads.unwrap_or(1));
+    let mut archiver = tar::Builder::new(compressor);
+    // Only symlinks recorded with --symlinked-dirs record are archived, as symlinks.
+    archiver.follow_symlinks(false);
+
+    for (index, entry) in entries.iter().enumerate() {
+        interrupt::check()?;
+        if watchdog::skipped(watchdog::probe(args.file_timeout, &entry.path))? {
+            continue;
+        }
+        if let Some(original) = duplicates.as_ref().and_then(|d| d.originals[index]) {
+            let mut header = tar::Header::new_gnu();
+            header.set_metadata_in_mode(&fs::metadata(&entry.path)?, tar::HeaderMode::Complete);
+            header.set_entry_type(tar::EntryType::Link);
+            header.set_size(0);
+            archiver
+                .append_link(&mut header, &entry.name, &entries[original].name)
+                .context(format!("could not add path {} to archive", entry.path.display()))?;
+            progress.file(&entry.path);
+            continue;
+        }
+        let mut records = Vec::new();
+        if args.xattrs {
+            for (name, value) in xattrs::read(&entry.path)? {
+                records.extend(pax_record(&[b"SCHILY.xattr.", name.as_bytes()].concat(), &value));
+            }
+        }
+        if args.embed_hashes && !entry.path.is_symlink() {
+            let hash = args
+                .hash
+                .hash_file(&entry.path)
+                .context(format!("could not hash {}", entry.path.display()))?;
+            records.extend(pax_record(
+                format!("{}{}", EMBEDDED_HASH_PREFIX, args.hash.as_str()).as_bytes(),
+                hash.as_bytes(),
+            ));
+        }
+        if args.file_flags && !entry.path.is_symlink() {
+            let letters = flags::encode(flags::read(&entry.path)?);
+            if !letters.is_empty() {
+                records.extend(pax_record(b"track.flags", letters.as_bytes()));
+            }
+        }
+        if !records.is_empty() {
+            append_pax_extensions(&mut archiver, &records)?;
+        }
+        if pipelined {
+            let (_, hash) = append_hashed(&mut archiver, entry, args.hash)
+                .context(format!("could not add path {} to archive", entry.path.display()))?;
+            hashes.push(hash);
+        } else {
+            archiver
+                .append_path_with_name(&entry.path, &entry.name)
+                .context(format!("could not add path {} to archive", entry.path.display()))?;
+        }
+        progress.file(&entry.path);
+    }
+    let mut hashes = match duplicates {
+        Some(duplicates) => duplicates.manifest_hashes(args.hash),
+        None if pipelined => Some(hashes),
+        None => None,
+    };
+    if args.embed_manifest {
+        let manifest_hashes = match hashes.take() {
+            Some(hashes) => hashes,
+            None => hash_entries(entries, args.hash, pool)?,
+        };
+        let mut manifest = Vec::new();
+        write_manifest_lines(&mut manifest, entries, &manifest_hashes, &ManifestPaths::Archive, false)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs());
+        archiver.append_data(&mut header, EMBEDDED_MANIFEST, manifest.as_slice())?;
+        // Kept for an export --manifest, so the files aren't hashed again.
+        hashes = Some(manifest_hashes);
+    }
+    archiver.into_inner()?.finish()?.flush()?;
+    Ok(hashes)
+}
+
+/// Append a regular file like `append_path_with_name` does, hashing its content along the way.
+fn append_hashed<W: io::Write>(
+    archiver: &mut tar::Builder<W>,
+    entry: &ExportEntry,
+    hasher: Hasher,
+) -> io::Result<((), String)> {
+    let file = File::open(&entry.path)?;
+    let meta = file.metadata()?;
+    let mut header = tar::Header::new_gnu();
+    header.set_metadata_in_mode(&meta, tar::HeaderMode::Complete);
+    pipeline::hash_while_reading(file, meta.len(), hasher, |reader| {
+        archiver.append_data(&mut header, &entry.name, reader)
+    })
+}
+
+/// Name of the copy of the database in exports made with --embed-db.
+const EMBEDDED_DB: &str = ".track/paths.db";
+
+/// Name of the manifest in tar exports made with --embed-manifest.
+const EMBEDDED_MANIFEST: &str = ".track/manifest";
+
+/// Start of the key of the pax records holding the checksum of a file in tar exports made with
+/// --embed-hashes, followed by the name of the algorithm like `track.sha256`. The value is the
+/// lowercase hexadecimal checksum of the content of the file.
+const EMBEDDED_HASH_PREFIX: &str = "track.";
+
+/// File in the temporary directory, removed when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str) -> TempFile {
+        TempFile(std::env::temp_dir().join(format!("track-{}-{}", std::process::id(), name)))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Extract the database embedded in a tar or zip export made with --embed-db.
+fn extract_embedded_db(archive: &Path) -> anyhow::Result<TempFile> {
+    let mut magic = [0; 4];
+    File::open(archive)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .context(format!("could not read {}", archive.display()))?;
+    let copy = TempFile::new("imported.db");
+    let found = if magic == *b"PK\x03\x04" {
+        match zip::read_entry(archive, EMBEDDED_DB.as_bytes())
+            .context(format!("could not read {}", archive.display()))?
+        {
+            Some(content) => {
+                fs::write(&copy.0, content)?;
+                true
+            }
+            None => false,
+        }
+    } else {
+        let input = File::open(archive)?;
+        let mut tar = tar::Archive::new(flate2::read::MultiGzDecoder::new(input));
+        let mut found = false;
+        for entry in tar.entries().context(format!("could not read {}", archive.display()))? {
+            let mut entry = entry?;
+            if entry.path()? == Path::new(EMBEDDED_DB) {
+                io::copy(&mut entry, &mut File::create(&copy.0)?)?;
+                found = true;
+                break;
+            }
+        }
+        found
+    };
+    if !found {
+        return Err(anyhow!(
+            "{} has no embedded database, export with --embed-db to add one",
+            archive.display()
+        ))
+        .context(Exit::Usage);
+    }
+    Ok(copy)
+}
+
+/// Whether an archive is written to stdout, which `-` stands for.
+fn is_stdout(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Refuse the exports which can't be streamed to stdout.
+fn check_stdout_export(export: &ExportArgs) -> anyhow::Result<()> {
+    if !matches!(export.kind, ExportKind::Tar | ExportKind::Zip) {
+        return Err(anyhow!("only tar and zip exports can be written to stdout")).context(Exit::Usage);
+    }
+    if export.per_root {
+        return Err(anyhow!("--per-root writes several archives, it can't write to stdout")).context(Exit::Usage);
+    }
+    if export.dedupe && matches!(export.kind, ExportKind::Zip) {
+        return Err(anyhow!(
+            "--dedupe reads zip archives back, it can't write them to stdout"
+        ))
+        .context(Exit::Usage);
+    }
+    if atty::is(atty::Stream::Stdout) {
+        return Err(anyhow!("refusing to write an archive to a terminal")).context(Exit::Usage);
+    }
+    Ok(())
+}
+
+/// Sibling of `dest` an archive or a script is written to until it is complete.
+fn partial_path(dest: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(dest.file_name().unwrap_or_default());
+    name.push(".partial");
+    dest.with_file_name(name)
+}
+
+/// Write the file `dest` with `write` under its partial path, renamed to `dest` once `write` succeeds,
+/// so a failed export leaves neither a truncated `dest` nor the partial file. Stdout is written as is.
+fn write_atomically<T>(dest: &Path, write: impl FnOnce(&Path) -> anyhow::Result<T>) -> anyhow::Result<T> {
+    if is_stdout(dest) {
+        return write(dest);
+    }
+    let partial = partial_path(dest);
+    let written = write(&partial).and_then(|value| {
+        fs::rename(&partial, dest).context(format!("could not rename {}", partial.display()))?;
+        Ok(value)
+    });
+    if written.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    written
+}
+
+/// Create the file an archive is written to, or lock stdout for `-`.
+fn create_archive(dest: &Path) -> anyhow::Result<Box<dyn Write>> {
+    if is_stdout(dest) {
+        return Ok(Box::new(io::stdout().lock()));
+    }
+    let file = File::create(dest).context(format!("could not create {}", dest.display()))?;
+    Ok(Box::new(file))
+}
+
+/// Write a zip archive of the entries.
+///
+/// Zip has no hard links, with --dedupe the compressed content of files identical to one already
+/// archived is copied from the archive instead of being compressed again.
+fn export_zip(
+    args: &ExportArgs,
+    dest: &Path,
+    entries: &[ExportEntry],
+    pool: &Pool,
+    progress: &Progress,
+) -> anyhow::Result<Option<Vec<String>>> {
+    let duplicates = if args.dedupe {
+        Some(Duplicates::find(entries, args.hash, pool)?)
+    } else {
+        None
+    };
+    let output = BufWriter::new(create_archive(dest)?);
+    let mut archiver = zip::ZipWriter::new(output, args.zip64);
+    let mut indexes = Vec::with_capacity(entries.len());
+    for (index, entry) in entries.iter().enumerate() {
+        interrupt::check()?;
+        if watchdog::skipped(watchdog::probe(args.file_timeout, &entry.path))? {
+            continue;
+        }
+        let name = entry.name.as_os_str().as_bytes();
+        let zip_index = match duplicates.as_ref().and_then(|d| d.originals[index]) {
+            Some(original) => {
+                archiver.flush()?;
+                let (offset, _) = archiver.content_range(indexes[original]);
+                let mut archive = File::open(dest)?;
+                archive.seek(io::SeekFrom::Start(offset))?;
+                archiver.append_copy(&entry.path, name, indexes[original], archive)
+            }
+            None if entry.path.is_symlink() => archiver.append_symlink(&entry.path, name),
+            None => archiver.append_file(&entry.path, name),
+        }
+        .context(format!("could not add path {} to archive", entry.path.display()))?;
+        indexes.push(zip_index);
+        progress.file(&entry.path);
+    }
+    archiver.finish()?.flush()?;
+    Ok(duplicates.and_then(|duplicates| duplicates.manifest_hashes(args.hash)))
+}
+
+/// Where an archived name is restored, None for names outside of the prefix.
+fn restored_path(args: &RestoreArgs, home: Option<&Path>, name: &Path) -> anyhow::Result<Option<PathBuf>> {
+    let name = match &args.prefix {
+        Some(prefix) => match name.strip_prefix(prefix) {
+            Ok(name) => name,
+            Err(_) => return Ok(None),
+        },
+        None => name,
+    };
+    if name.as_os_str().is_empty() {
+        return Ok(None);
+    }
+    if !name.components().all(|c| matches!(c, Component::Normal(_))) {
+        bail!("refusing to restore unsafe path {}", name.display());
+    }
+    Ok(Some(match home.zip(name.strip_prefix(&args.home_placeholder).ok()) {
+        Some((home, rest)) => home.join(rest),
+        None => args.to.join(name),
+    }))
+}
+
+fn restore_tar(args: &RestoreArgs) -> anyhow::Result<()> {
+    let input = File::open(&args.archive).context(format!("could not open {}", args.archive.display()))?;
+    let mut archive = tar::Archive::new(flate2::read::MultiGzDecoder::new(input));
+    let home = if args.home_relative { Some(home_dir()?) } else { None };
+    let restored_path = |name: &Path| restored_path(args, home.as_deref(), name);
+
+    let mut manifest = match &args.manifest {
+        Some(path) => Some(fs::read(path).context(format!("could not read {}", path.display()))?),
+        None => None,
+    };
+    let mut embedded_checked = 0;
+    let mut embedded_failed = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.into_owned();
+        if name == Path::new(EMBEDDED_MANIFEST) {
+            if manifest.is_none() {
+                let mut content = Vec::new();
+                entry.read_to_end(&mut content)?;
+                manifest = Some(content);
+            }
+            continue;
+        }
+        let new_path = match restored_path(&name)? {
+            Some(new_path) => new_path,
+            None => {
+                if let Some(prefix) = &args.prefix {
+                    log::skipping(&name, &format!("outside of prefix {}", prefix.display()));
+                }
+                continue;
+            }
+        };
+        DirBuilder::new()
+            .recursive(true)
+            .create(new_path.parent().expect("new path has no parent"))?;
+        if entry.header().entry_type().is_hard_link() {
+            // Written by --dedupe, the target is an archived name which was restored before.
+            let target = entry
+                .link_name()?
+                .ok_or_else(|| anyhow!("hard link {} has no target", name.display()))?;
+            let target = restored_path(&target)?
+                .ok_or_else(|| anyhow!("hard link {} points outside of the prefix", name.display()))?;
+            if fs::symlink_metadata(&new_path).is_ok() {
+                fs::remove_file(&new_path)?;
+            }
+            if fs::hard_link(&target, &new_path).is_err() {
+                fs::copy(&target, &new_path).context(format!("could not restore {}", new_path.display()))?;
+            }
+            continue;
         }
-        Args::Rm { paths } => {
-            for path in paths {
-                let path = path.absolutize()?;
-                paths_db.rm(&path)?;
+        if fs::symlink_metadata(&new_path).is_ok_and(|m| m.is_file()) {
+            let dest_flags = flags::read(&new_path)?;
+            if flags::is_protected(dest_flags) {
+                log::skipping(
+                    &new_path,
+                    &format!("which is {} and can't be overwritten", flags::protection(dest_flags)),
+                );
+                continue;
+            }
+        }
+        let mut archived_flags = None;
+        let mut embedded_hash = None;
+        if let Some(extensions) = entry.pax_extensions()? {
+            for extension in extensions {
+                let extension = extension?;
+                if extension.key_bytes() == b"track.flags" {
+                    archived_flags = Some(String::from_utf8_lossy(extension.value_bytes()).into_owned());
+                } else if let Some(hasher) = extension
+                    .key()
+                    .ok()
+                    .and_then(|key| key.strip_prefix(EMBEDDED_HASH_PREFIX))
+                    .and_then(|name| name.parse::<Hasher>().ok())
+                {
+                    embedded_hash = Some((hasher, String::from_utf8_lossy(extension.value_bytes()).into_owned()));
+                }
+            }
+        }
+        entry
+            .unpack(&new_path)
+            .context(format!("could not restore {}", new_path.display()))?;
+        if let (true, Some((hasher, expected))) = (args.verify_embedded, embedded_hash) {
+            embedded_checked += 1;
+            match hasher.hash_file(&new_path) {
+                Ok(hash) if hash == expected => {}
+                Ok(_) => {
+                    eprintln!("{}: FAILED", new_path.display());
+                    embedded_failed += 1;
+                }
+                Err(err) => {
+                    eprintln!("{}: FAILED open or read: {}", new_path.display(), err);
+                    embedded_failed += 1;
+                }
+            }
+        }
+        if let Some(letters) = archived_flags {
+            eprintln!(
+                "{} had the chattr flags {} when exported, chattr +{} sets them again",
+                new_path.display(),
+                letters,
+                letters
+            );
+        }
+        if args.xattrs {
+            let mut attrs = Vec::new();
+            if let Some(extensions) = entry.pax_extensions()? {
+                for extension in extensions {
+                    let extension = extension?;
+                    if let Some(name) = extension.key_bytes().strip_prefix(b"SCHILY.xattr.") {
+                        attrs.push((OsStr::from_bytes(name).to_owned(), extension.value_bytes().to_vec()));
+                    }
+                }
+            }
+            xattrs::apply(&new_path, &attrs).context(format!(
+                "could not restore extended attributes of {}",
+                new_path.display()
+            ))?;
+        }
+    }
+
+    if args.verify_embedded {
+        if embedded_checked == 0 {
+            return Err(anyhow!(
+                "{} has no embedded hashes, export with --embed-hashes to add them",
+                args.archive.display()
+            ))
+            .context(Exit::Usage);
+        }
+        if embedded_failed > 0 {
+            return Err(anyhow!(
+                "{} of {} restored files don't match their embedded hashes",
+                embedded_failed,
+                embedded_checked
+            ))
+            .context(Exit::Mismatch);
+        }
+        eprintln!(
+            "Verified {} restored files against their embedded hashes",
+            embedded_checked
+        );
+    }
+    if args.verify {
+        let manifest = match manifest {
+            Some(manifest) => read_manifest(&manifest)?,
+            None => {
+                return Err(anyhow!(
+                    "{} has no embedded manifest, export with --embed-manifest or give one with --manifest",
+                    args.archive.display()
+                ))
+                .context(Exit::Usage)
+            }
+        };
+        let mut checked = 0;
+        let mut failed = 0;
+        for (expected, name) in &manifest {
+            let path = match restored_path(name)? {
+                Some(path) => path,
+                None => continue,
+            };
+            checked += 1;
+            match args.hash.hash_file(&path) {
+                Ok(hash) if hash == *expected => {}
+                Ok(_) => {
+                    eprintln!("{}: FAILED", path.display());
+                    failed += 1;
+                }
+                Err(err) => {
+                    eprintln!("{}: FAILED open or read: {}", path.display(), err);
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            return Err(anyhow!(
+                "{} of {} restored files don't match the manifest",
+                failed,
+                checked
+            ))
+            .context(Exit::Mismatch);
+        }
+        eprintln!("Verified {} restored files", checked);
+    }
+
+    Ok(())
+}
+
+/// Hash the exported files in parallel, in the order of the entries.
+fn hash_entries(entries: &[ExportEntry], hasher: Hasher, pool: &Pool) -> anyhow::Result<Vec<String>> {
+    pool.try_map(entries, |entry| {
+        hasher
+            .hash_file(&entry.path)
+            .context(format!("could not hash {}", entry.path.display()))
+    })
+}
+
+/// Write the checksum of every exported file along with its exported name, one per line.
+///
+/// Names containing a backslash or a line break are escaped and their line starts with a backslash, like
+/// the coreutils checksum tools do.
+fn write_manifest(
+    path: &Path,
+    entries: &[ExportEntry],
+    hashes: Vec<String>,
+    paths: &ManifestPaths,
+    embed_db: bool,
+) -> anyhow::Result<()> {
+    let mut output = BufWriter::new(File::create(path).context(format!("could not create {}", path.display()))?);
+    write_manifest_lines(&mut output, entries, &hashes, paths, embed_db)?;
+    output.flush()?;
+    Ok(())
+}
+
+fn write_manifest_lines(
+    output: &mut impl Write,
+    entries: &[ExportEntry],
+    hashes: &[String],
+    paths: &ManifestPaths,
+    embed_db: bool,
+) -> anyhow::Result<()> {
+    for (entry, hash) in entries.iter().zip(hashes) {
+        // The embedded database is a temporary copy, only the archive has it for good.
+        if embed_db && *paths != ManifestPaths::Archive && entry.name == Path::new(EMBEDDED_DB) {
+            continue;
+        }
+        let name = match paths {
+            ManifestPaths::Archive => &entry.name,
+            ManifestPaths::Absolute => &entry.path,
+            ManifestPaths::RelativeTo(dir) => match entry.path.strip_prefix(dir) {
+                Ok(relative) => relative,
+                Err(_) => bail!("{} is not under {}", entry.path.display(), dir.display()),
+            },
+        };
+        write_checksum_line(output, hash, name, false)?;
+    }
+    Ok(())
+}
+
+/// Write a checksum line of sha256sum and co, or one ending with a NUL byte like they write with -z.
+fn write_checksum_line(output: &mut impl Write, hash: &str, name: &Path, null: bool) -> io::Result<()> {
+    let name = name.as_os_str().as_bytes();
+    if null {
+        write!(output, "{}  ", hash)?;
+        output.write_all(name)?;
+        return output.write_all(b"\0");
+    }
+    if name.contains(&b'\\') || name.contains(&b'\n') {
+        let mut escaped = Vec::with_capacity(name.len());
+        for &b in name {
+            match b {
+                b'\\' => escaped.extend_from_slice(b"\\\\"),
+                b'\n' => escaped.extend_from_slice(b"\\n"),
+                b => escaped.push(b),
+            }
+        }
+        write!(output, "\\{}  ", hash)?;
+        output.write_all(&escaped)?;
+    } else {
+        write!(output, "{}  ", hash)?;
+        output.write_all(name)?;
+    }
+    writeln!(output)
+}
+
+/// Files hashed by the pool before their lines are printed by `print_checksums`.
+const CHECKSUM_BATCH: usize = 256;
+
+/// Print the checksum of every matched file sorted by path, a batch at a time so lines come as the files
+/// are hashed while keeping the order whatever the number of jobs.
+fn print_checksums(
+    paths_db: &PathsDB,
+    filters: &Filters,
+    hasher: Hasher,
+    null: bool,
+    pool: &Pool,
+) -> anyhow::Result<()> {
+    let mut matches = scan_matches(paths_db, &paths_db.list()?, filters, false)?;
+    matches.sort();
+    let mut stdout = io::stdout().lock();
+    for batch in matches.chunks(CHECKSUM_BATCH) {
+        interrupt::check()?;
+        let hashes = pool.try_map(batch, |path| {
+            hasher
+                .hash_file(path)
+                .context(format!("could not hash {}", path.display()))
+        })?;
+        for (path, hash) in batch.iter().zip(&hashes) {
+            write_checksum_line(&mut stdout, hash, path, null)?;
+        }
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+/// Read the lines written by `write_manifest`, unescaping the names.
+fn read_manifest(content: &[u8]) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    let mut lines = Vec::new();
+    for line in content.split(|&b| b == b'\n').filter(|line| !line.is_empty()) {
+        let (escaped, line) = match line.strip_prefix(b"\\") {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let split = line
+            .windows(2)
+            .position(|w| w == b"  ")
+            .ok_or_else(|| anyhow!("invalid manifest line {}", String::from_utf8_lossy(line)))?;
+        let hash = String::from_utf8(line[..split].to_vec())?;
+        let mut name = Vec::with_capacity(line.len() - split - 2);
+        let mut bytes = line[split + 2..].iter();
+        while let Some(&b) = bytes.next() {
+            match b {
+                b'\\' if escaped => match bytes.next() {
+                    Some(b'n') => name.push(b'\n'),
+                    Some(&b) => name.push(b),
+                    None => bail!("invalid manifest line {}", String::from_utf8_lossy(line)),
+                },
+                b => name.push(b),
+            }
+        }
+        lines.push((hash, PathBuf::from(OsString::from_vec(name))));
+    }
+    Ok(lines)
+}
+
+/// File names for the archives of each tracked path with --per-root, `/home/me/proj` becomes
+/// `home-me-proj`. Characters which aren't safe in file names are replaced and a number is
+/// appended to names already taken.
+fn per_root_names(paths: &[PathBuf]) -> Vec<String> {
+    let mut taken = HashSet::new();
+    let mut names = Vec::with_capacity(paths.len());
+    for path in paths {
+        let mut name: String = path
+            .to_string_lossy()
+            .trim_matches('/')
+            .chars()
+            .map(|c| match c {
+                '/' => '-',
+                c if c.is_ascii_alphanumeric() || "._-".contains(c) => c,
+                _ => '_',
+            })
+            .collect();
+        if name.is_empty() || name.starts_with('.') {
+            name.insert_str(0, "root");
+        }
+        let mut unique = name.clone();
+        let mut n = 2;
+        while !taken.insert(unique.clone()) {
+            unique = format!("{}-{}", name, n);
+            n += 1;
+        }
+        names.push(unique);
+    }
+    names
+}
+
+/// Fail before anything is written when the files to export are larger than the limit in total.
+fn check_total_size(entries: &[ExportEntry], max_total_size: u64) -> anyhow::Result<()> {
+    let mut total: u64 = 0;
+    for entry in entries {
+        let metadata = fs::metadata(&entry.path).context(format!("could not read {}", entry.path.display()))?;
+        total += metadata.len();
+        if total > max_total_size {
+            return Err(anyhow!(
+                "the files to export add up to more than --max-total-size {}, nothing was exported",
+                report::format_size(max_total_size)
+            ))
+            .context(Exit::Usage);
+        }
+    }
+    Ok(())
+}
+
+/// Modification time of the most recent `track-*.tar.gz` snapshot in `dir`, if any.
+fn last_snapshot_at(dir: &Path) -> anyhow::Result<Option<SystemTime>> {
+    let read_dir = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).context(format!("could not read {}", dir.display())),
+    };
+    let mut latest = None;
+    for entry in read_dir {
+        let entry = entry.context(format!("could not read {}", dir.display()))?;
+        let name = entry.file_name();
+        let name = name.as_bytes();
+        if !name.starts_with(b"track-") || !name.ends_with(b".tar.gz") {
+            continue;
+        }
+        let meta = entry.metadata()?;
+        if !meta.is_file() {
+            continue;
+        }
+        let modified = meta.modified()?;
+        if latest.is_none_or(|latest| modified > latest) {
+            latest = Some(modified);
+        }
+    }
+    Ok(latest)
+}
+
+/// Bounds of --chunk-size, each export job holds a chunk in memory and cdc ones reach four times the size.
+const MIN_CHUNK_SIZE: u64 = 4 * 1024;
+const MAX_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Export the files matched by `paths`, returning how many were exported.
+///
+/// The database lock is only taken, waiting for it with `wait`, to clean destinations and to record the
+/// export, so other commands can run while the files are scanned and written.
+fn export_matches(
+    paths_db: &PathsDB,
+    paths: &[PathBuf],
+    export: &ExportArgs,
+    pool: &Pool,
+    wait: bool,
+) -> anyhow::Result<usize> {
+    if export.link == LinkMode::Symlink && !matches!(export.kind, ExportKind::Dir) {
+        return Err(anyhow!("--link symlink only applies to dir exports")).context(Exit::Usage);
+    }
+    if export.per_root && !matches!(export.kind, ExportKind::Tar | ExportKind::Zip) {
+        return Err(anyhow!("--per-root only applies to tar and zip exports")).context(Exit::Usage);
+    }
+    if is_stdout(&export.path) {
+        check_stdout_export(export)?;
+    }
+    if !export.also.is_empty() && export.per_root {
+        return Err(anyhow!("--also doesn't apply with --per-root")).context(Exit::Usage);
+    }
+    if export.also.iter().any(|target| is_stdout(&target.path)) {
+        return Err(anyhow!("only the main destination of an export can be stdout")).context(Exit::Usage);
+    }
+    let has_script = export.targets().any(|(kind, _)| matches!(kind, ExportKind::Script));
+    let has_bagit = export.targets().any(|(kind, _)| matches!(kind, ExportKind::Bagit));
+    if export.embed_db && (export.per_root || has_script || export.link == LinkMode::Symlink) {
+        return Err(anyhow!(
+            "--embed-db only applies to single tar, zip and copied dir exports"
+        ))
+        .context(Exit::Usage);
+    }
+    if export.filter.symlinked_dirs == SymlinkedDirs::Record
+        && (has_script || has_bagit || export.manifest.is_some() || export.embed_manifest || export.dedupe)
+    {
+        return Err(anyhow!(
+            "--symlinked-dirs record doesn't apply to script and bagit exports, --manifest, --embed-manifest and --dedupe, which read file contents"
+        ))
+        .context(Exit::Usage);
+    }
+    if export.preserve_dirs
+        && !export
+            .targets()
+            .any(|(kind, _)| matches!(kind, ExportKind::Dir | ExportKind::Bagit))
+    {
+        return Err(anyhow!("--preserve-dirs only applies to dir and bagit exports")).context(Exit::Usage);
+    }
+    if export.embed_manifest && !export.targets().any(|(kind, _)| matches!(kind, ExportKind::Tar)) {
+        return Err(anyhow!("--embed-manifest only applies to tar exports")).context(Exit::Usage);
+    }
+    if export.embed_hashes && !export.targets().any(|(kind, _)| matches!(kind, ExportKind::Tar)) {
+        return Err(anyhow!("--embed-hashes only applies to tar exports")).context(Exit::Usage);
+    }
+    if export.chunking.is_some() && !export.targets().any(|(kind, _)| matches!(kind, ExportKind::Cas)) {
+        return Err(anyhow!("--chunking only applies to cas exports")).context(Exit::Usage);
+    }
+    if export
+        .chunk_size
+        .is_some_and(|size| !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&size))
+    {
+        return Err(anyhow!("--chunk-size must be between 4K and 64M")).context(Exit::Usage);
+    }
+    if export.file_timeout.is_some() && export.targets().any(|(kind, _)| matches!(kind, ExportKind::Cas)) {
+        return Err(anyhow!("--file-timeout doesn't apply to cas exports")).context(Exit::Usage);
+    }
+    if export.file_timeout.is_some()
+        && (export.manifest.is_some() || export.dedupe || export.embed_manifest || export.embed_hashes)
+    {
+        return Err(anyhow!(
+            "--file-timeout doesn't apply with --manifest, --embed-manifest, --embed-hashes and --dedupe, which read every file outside of it"
+        ))
+        .context(Exit::Usage);
+    }
+    let incremental = export.changed || export.since_last.is_some();
+    if incremental && has_bagit {
+        return Err(anyhow!(
+            "--changed and --since-last don't apply to bagit exports, whose manifest must list every file"
+        ))
+        .context(Exit::Usage);
+    }
+    if export.parallel_roots == Some(0) {
+        return Err(anyhow!("--parallel-roots needs at least 1 root at a time")).context(Exit::Usage);
+    }
+    if export.compress_threads == Some(0) {
+        return Err(anyhow!("--compress-threads needs at least 1 thread")).context(Exit::Usage);
+    }
+    check_destinations(export)?;
+    let paths = &export_roots(paths_db, paths, export)?;
+    let started_at = SystemTime::now();
+    let filters = export.filter.export_filters(paths_db)?;
+    let since = if let Some(dir) = &export.since_last {
+        last_snapshot_at(dir)?
+    } else if export.changed {
+        paths_db.last_export_at()?
+    } else {
+        None
+    };
+    let check = if export.changed {
+        export.change_check()
+    } else {
+        ChangeCheck::Mtime
+    };
+    let previous = match check {
+        ChangeCheck::Mtime => HashMap::new(),
+        check => paths_db.signatures(check.kind())?,
+    };
+    // Signatures of every matched file, saved for the next export once this one is done.
+    let mut signatures = Vec::new();
+    let mut counts = Vec::new();
+    let mut scan = |roots: &[PathBuf]| -> anyhow::Result<Vec<ExportEntry>> {
+        log::info("scan-start", vec![("roots", (roots.len() as u64).into())]);
+        let mut matches = scan_matches(paths_db, roots, &filters, true)?;
+        let root_counts = match_counts(roots, &matches);
+        for (root, files) in &root_counts {
+            log::info(
+                "scan-root",
+                vec![("root", json::Value::path(root)), ("files", (*files).into())],
+            );
+        }
+        counts.extend(root_counts);
+        if check != ChangeCheck::Mtime {
+            let current = pool.try_map(&matches, |mat| check.signature(mat))?;
+            let mut changed = Vec::new();
+            for (mat, signature) in matches.into_iter().zip(current) {
+                if previous.get(&mat) != Some(&signature) {
+                    changed.push(mat.clone());
+                }
+                signatures.push((mat, signature));
+            }
+            matches = changed;
+        } else if let Some(since) = since {
+            retain_modified_since(&mut matches, since)?;
+        }
+        sort_matches(&mut matches, export.order)?;
+        log::info("scan-end", vec![("files", (matches.len() as u64).into())]);
+        export_entries(matches, export)
+    };
+
+    // Destination of each range of entries, there's one per tracked path with --per-root.
+    let mut groups = Vec::new();
+    let mut entries = Vec::new();
+    if export.per_root {
+        let extension = if matches!(export.kind, ExportKind::Zip) {
+            "zip"
+        } else {
+            "tar.gz"
+        };
+        for (root, name) in paths.iter().zip(per_root_names(paths)) {
+            let start = entries.len();
+            entries.extend(scan(std::slice::from_ref(root))?);
+            let dest = export.path.join(format!("{}.{}", name, extension));
+            groups.push((dest, start..entries.len()));
+        }
+    } else {
+        entries = scan(paths)?;
+    }
+    // Kept until the export is done, the copy is removed when dropped.
+    let embedded_db = if export.embed_db {
+        let copy = TempFile::new("paths.db");
+        paths_db.backup_to(&copy.0)?;
+        entries.push(ExportEntry {
+            path: copy.0.clone(),
+            name: PathBuf::from(EMBEDDED_DB),
+        });
+        Some(copy)
+    } else {
+        None
+    };
+    if !export.per_root {
+        groups.push((export.path.clone(), 0..entries.len()));
+    }
+
+    if has_bagit {
+        bagit::check(&entries, export.hash)?;
+    }
+    if let Some(max_total_size) = export.max_total_size {
+        check_total_size(&entries, max_total_size)?;
+    }
+    if let (Some(_), ManifestPaths::RelativeTo(dir)) = (&export.manifest, &export.manifest_paths) {
+        if let Some(outside) = entries
+            .iter()
+            .find(|entry| !entry.path.starts_with(dir) && entry.name != Path::new(EMBEDDED_DB))
+        {
+            return Err(anyhow!(
+                "{} is not under {}, it can't be written in the manifest relative to it",
+                outside.path.display(),
+                dir.display()
+            ))
+            .context(Exit::Usage);
+        }
+    }
+    create_destinations(export)?;
+    let progress = Progress::new(
+        export.progress_format,
+        export.progress,
+        entries.len() * (1 + export.also.len()),
+    );
+    let lock = || paths_db.lock(wait);
+    let written = write_export(export, &export.kind, &groups, &entries, pool, &progress, &lock).and_then(|hashes| {
+        for target in &export.also {
+            let groups = [(target.path.clone(), 0..entries.len())];
+            write_export(export, &target.kind, &groups, &entries, pool, &progress, &lock)?;
+        }
+        Ok(hashes)
+    });
+    let hashes = match written {
+        Ok(hashes) => hashes,
+        Err(err) if interrupt::requested() => {
+            progress.interrupted();
+            return Err(err);
+        }
+        Err(err) => return Err(err),
+    };
+    progress.finish();
+    if let Some(manifest) = &export.manifest {
+        let hashes = match hashes {
+            Some(hashes) => hashes,
+            None => hash_entries(&entries, export.hash, pool)?,
+        };
+        write_manifest(manifest, &entries, hashes, &export.manifest_paths, export.embed_db)?;
+    }
+    log::info(
+        "export-done",
+        vec![
+            ("files", (entries.len() as u64).into()),
+            ("dest", json::Value::path(&export.path)),
+        ],
+    );
+    let _lock = lock()?;
+    // Exporting some of the tracked paths doesn't make the others up to date for the next --changed.
+    if export.roots.is_empty() && export.tags.is_empty() {
+        let signatures = (check != ChangeCheck::Mtime).then(|| (check.kind(), signatures.as_slice()));
+        paths_db.record_export(started_at, signatures)?;
+    }
+    save_scan(paths_db, &counts, started_at);
+    drop(embedded_db);
+    Ok(entries.len())
+}
+
+/// Check that each destination of an export is a directory or a file as its kind needs, before anything
+/// is scanned. The missing directories are only created by `create_destinations`.
+fn check_destinations(export: &ExportArgs) -> anyhow::Result<()> {
+    for (kind, dest) in export.targets() {
+        if is_stdout(dest) {
+            continue;
+        }
+        let meta = fs::metadata(dest).ok();
+        let wants_dir = matches!(kind, ExportKind::Dir | ExportKind::Bagit | ExportKind::Cas);
+        if wants_dir || export.per_root {
+            match meta {
+                Some(meta) if !meta.is_dir() => {
+                    return Err(anyhow!(
+                        "{} is a file, a {} export{} needs a directory to write to",
+                        dest.display(),
+                        kind.as_str(),
+                        if export.per_root { " with --per-root" } else { "" }
+                    ))
+                    .context(Exit::Usage)
+                }
+                _ => {}
+            }
+        } else if meta.is_some_and(|meta| meta.is_dir()) {
+            return Err(anyhow!(
+                "{} is a directory, a {} export writes a single file, give a file name like {}",
+                dest.display(),
+                kind.as_str(),
+                dest.join(match kind {
+                    ExportKind::Zip => "backup.zip",
+                    ExportKind::Script => "restore.sh",
+                    _ => "backup.tar.gz",
+                })
+                .display()
+            ))
+            .context(Exit::Usage);
+        }
+    }
+    Ok(())
+}
+
+/// Create the missing directories dir, bagit and cas exports write to, and the one --per-root writes
+/// its archives in, once nothing can refuse the export anymore.
+fn create_destinations(export: &ExportArgs) -> anyhow::Result<()> {
+    for (kind, dest) in export.targets() {
+        let wants_dir = matches!(kind, ExportKind::Dir | ExportKind::Bagit | ExportKind::Cas);
+        if (wants_dir || export.per_root) && !is_stdout(dest) {
+            fs::create_dir_all(dest).context(format!("could not create {}", dest.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Tracked paths an export is restricted to by --root and --tag, all of them without either.
+fn export_roots(paths_db: &PathsDB, paths: &[PathBuf], export: &ExportArgs) -> anyhow::Result<Vec<PathBuf>> {
+    if export.roots.is_empty() && export.tags.is_empty() {
+        return Ok(paths.to_vec());
+    }
+    let case_insensitive = paths_db.case_insensitive()?;
+    let mut selected = HashSet::new();
+    for root in &export.roots {
+        let root = absolute(root)?;
+        let key = path_key(&root, case_insensitive);
+        match paths.iter().find(|path| path_key(path, case_insensitive) == key) {
+            Some(path) => selected.insert(path),
+            None => return Err(anyhow!("{} is not a tracked path", root.display())).context(Exit::Usage),
+        };
+    }
+    for tag in &export.tags {
+        let mut tagged = false;
+        for path in paths {
+            let stored = paths_db.stored_as(path)?.expect("tracked path is not stored");
+            if paths_db.metadata(&stored)?.tags.contains(tag) {
+                selected.insert(path);
+                tagged = true;
+            }
+        }
+        if !tagged {
+            return Err(anyhow!("no tracked path is tagged {}", tag)).context(Exit::Usage);
+        }
+    }
+    Ok(paths.iter().filter(|path| selected.contains(path)).cloned().collect())
+}
+
+/// Write the entries of an export of `kind`, `groups` gives the destination of each range of entries,
+/// there's a single one but for --per-root archives.
+///
+/// Archives and scripts are renamed into place once complete, a directory is left as it is. Directories
+/// are cleaned holding the database lock taken by `lock`.
+fn write_export(
+    export: &ExportArgs,
+    kind: &ExportKind,
+    groups: &[(PathBuf, std::ops::Range<usize>)],
+    entries: &[ExportEntry],
+    pool: &Pool,
+    progress: &Progress,
+    lock: &dyn Fn() -> anyhow::Result<Option<Lock>>,
+) -> anyhow::Result<Option<Vec<String>>> {
+    let failed = |err: anyhow::Error| -> anyhow::Error {
+        if interrupt::requested() {
+            err
+        } else {
+            err.context(Exit::PartialExport)
+        }
+    };
+    let mut hashes = None;
+    match kind {
+        ExportKind::Dir => {
+            let dest = &groups[0].0;
+            if !export.keeps_dest() {
+                let _lock = lock()?;
+                if !export.yes {
+                    report_clean(dest)?;
+                }
+                clean_dir(dest)?;
+            }
+            export_dir(export, dest, entries, pool, progress).context(Exit::PartialExport)?;
+            if export.preserve_dirs {
+                preserve_dirs(dest, entries)?;
+            }
+            let names = entries
+                .iter()
+                .filter_map(|entry| entry.name.components().next())
+                .map(|name| PathBuf::from(name.as_os_str()));
+            write_export_marker(dest, names, export.keeps_dest())?;
+        }
+        ExportKind::Tar | ExportKind::Zip => {
+            let roots = Pool::new(export.parallel_roots.unwrap_or(1));
+            // Archives written with --parallel-roots are reported in the order of the tracked paths,
+            // like they are one after the other: the next one to report and the ones done before it.
+            let reported = Mutex::new((0, BTreeMap::new()));
+            let groups: Vec<_> = groups.iter().enumerate().collect();
+            let group_hashes = roots.try_map(&groups, |&(index, (dest, range))| -> anyhow::Result<_> {
+                let group = &entries[range.clone()];
+                let group_hashes = write_atomically(dest, |path| {
+                    if matches!(kind, ExportKind::Zip) {
+                        export_zip(export, path, group, pool, progress)
+                    } else {
+                        export_tar(export, path, group, pool, progress)
+                    }
+                })
+                .map_err(failed)?;
+                if export.per_root {
+                    let mut reported = reported.lock().unwrap();
+                    let (next, done) = &mut *reported;
+                    done.insert(index, (group.len(), dest));
+                    while let Some((files, dest)) = done.remove(next) {
+                        println!("Exported {} files to {}", files, dest.display());
+                        log::info(
+                            "archive-done",
+                            vec![("files", (files as u64).into()), ("dest", json::Value::path(dest))],
+                        );
+                        *next += 1;
+                    }
+                }
+                Ok(group_hashes)
+            })?;
+            for group_hashes in group_hashes.into_iter().flatten() {
+                hashes.get_or_insert_with(Vec::new).extend(group_hashes);
+            }
+        }
+        ExportKind::Script => {
+            let dest = &groups[0].0;
+            write_atomically(dest, |path| {
+                script::export_script(path, entries, export.embed, export.file_timeout, progress)
+            })
+            .map_err(failed)?
+        }
+        ExportKind::Bagit => {
+            let dest = &groups[0].0;
+            if !export.resume {
+                let _lock = lock()?;
+                if !export.yes {
+                    report_clean(dest)?;
+                }
+                clean_dir(dest)?;
+            }
+            let payload = dest.join(bagit::PAYLOAD_DIR);
+            fs::create_dir_all(&payload).context(format!("could not create {}", payload.display()))?;
+            export_dir(export, &payload, entries, pool, progress).context(Exit::PartialExport)?;
+            if export.preserve_dirs {
+                preserve_dirs(&payload, entries)?;
+            }
+            let tag_files = bagit::write_tag_files(dest, entries, export.hash, pool)?;
+            let names = tag_files.into_iter().chain([bagit::PAYLOAD_DIR.to_string()]);
+            write_export_marker(dest, names.map(PathBuf::from), export.resume)?;
+        }
+        ExportKind::Cas => {
+            let dest = &groups[0].0;
+            cas::check_store(dest)?;
+            let chunking = export.chunking.map(|chunking| {
+                (
+                    chunking,
+                    export.chunk_size.unwrap_or(cas::DEFAULT_CHUNK_SIZE as u64) as usize,
+                )
+            });
+            cas::export_cas(dest, entries, export.hash, chunking, pool, progress).context(Exit::PartialExport)?;
+        }
+    }
+    Ok(hashes)
+}
+
+fn main() -> ExitCode {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    match run(args, &matches) {
+        Ok(()) => {
+            log::info("done", Vec::new());
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            log::error("failed", vec![("error", format!("{:#}", err).into())]);
+            eprintln!("Error: {:?}", err);
+            ExitCode::from(exit::code(&err))
+        }
+    }
+}
+
+/// Refuse the commands changing the tracked paths with --paths-file, whose in-memory database would
+/// forget the change.
+fn check_paths_file_command(args: &Args, matches: &ArgMatches) -> anyhow::Result<()> {
+    let changes = matches!(
+        args.command,
+        Command::Add { .. }
+            | Command::Rm { .. }
+            | Command::Prune { .. }
+            | Command::Tag(_)
+            | Command::Untag(_)
+            | Command::Trim { .. }
+            | Command::Import { .. }
+            | Command::Undo(_)
+            | Command::DbNormalize { .. }
+    ) || args.case_insensitive
+        || args.case_sensitive;
+    if let Command::Export(export) = &args.command {
+        if export.changed {
+            return Err(anyhow!(
+                "--changed compares with the last export, which --paths-file doesn't keep: use --since-last instead"
+            ))
+            .context(Exit::Usage);
+        }
+    }
+    if changes {
+        let file = args.paths_file.as_ref().expect("no paths file");
+        let command = if args.case_insensitive || args.case_sensitive {
+            "--case-insensitive and --case-sensitive"
+        } else {
+            matches.subcommand_name().unwrap_or_default()
+        };
+        return Err(anyhow!(
+            "{} changes the tracked paths, which --paths-file reads from {}: edit it instead",
+            command,
+            file.display()
+        ))
+        .context(Exit::Usage);
+    }
+    Ok(())
+}
+
+fn run(args: Args, matches: &ArgMatches) -> anyhow::Result<()> {
+    let settings = Settings::resolve(&args, matches)?;
+    if let Some(path) = &args.log_file {
+        log::open(path, args.log_format)?;
+        log::info(
+            "start",
+            vec![("command", matches.subcommand_name().unwrap_or_default().into())],
+        );
+    }
+    let pool = Pool::new(settings.jobs.value);
+    pool::limit_open_files(settings.max_open_files.value);
+    let paths_db = match (&args.paths_file, &settings.db.value) {
+        (Some(file), _) => {
+            check_paths_file_command(&args, matches)?;
+            let paths_db = PathsDB::open_in_memory()?;
+            paths_file::load(&paths_db, file)?;
+            paths_db
+        }
+        (None, Some(path)) => PathsDB::open(Some(path.clone()))?,
+        (None, None) => PathsDB::open_in_memory()?,
+    };
+    if args.case_insensitive || args.case_sensitive {
+        paths_db.set_case_insensitive(args.case_insensitive)?;
+    }
+    match args.command {
+        Command::Add {
+            mut paths,
+            split,
+            force,
+            canonicalize,
+            relative,
+            dry_run,
+            tags,
+            excludes,
+            replace,
+            then,
+        } => {
+            for list in &split {
+                let words = split_words(list)
+                    .context(format!("could not split --split {}", list.to_string_lossy()))
+                    .context(Exit::Usage)?;
+                for word in words {
+                    paths.push(expand_path(&word)?);
+                }
+            }
+            if let Some(AddThen::Export(export)) = &then {
+                if dry_run {
+                    return Err(anyhow!("--dry-run doesn't add the paths to export")).context(Exit::Usage);
+                }
+                if export.filter.exclude_stdin && paths.iter().any(|path| path.as_os_str() == "-") {
+                    return Err(anyhow!("add - and --exclude-stdin can't both read stdin")).context(Exit::Usage);
+                }
+            }
+            if let Some(index) = paths.iter().position(|path| path.as_os_str() == "-") {
+                let stdin = read_stdin_list()?;
+                paths.remove(index);
+                paths.retain(|path| path.as_os_str() != "-");
+                paths.splice(index..index, stdin);
+            }
+            let lock = paths_db.lock(args.wait)?;
+            if dry_run {
+                return plan_add(&paths_db, &paths, force, canonicalize, relative);
+            }
+            let metadata = Metadata {
+                tags,
+                excludes,
+                replace,
+            };
+            let mut batch = Batch::new(paths.len(), "added");
+            let mut addable = Vec::with_capacity(paths.len());
+            for path in &paths {
+                match addable_path(&paths_db, path, force, canonicalize, relative) {
+                    Ok((stored, base)) if canonicalize => {
+                        match tracked_alias(&paths_db, &resolve_stored(&stored, base)?)? {
+                            Some(alias) => eprintln!(
+                                "Skipping {}, it resolves to the same path as the tracked {}",
+                                path.display(),
+                                alias.display()
+                            ),
+                            None => addable.push((stored, base)),
+                        }
+                    }
+                    Ok(stored) => addable.push(stored),
+                    Err(err) => batch.fail(err.context(format!("could not add {}", path.display()))),
+                }
+            }
+            paths_db.add(&addable, &metadata)?;
+            batch.finish()?;
+            // The export locks the database again while it records itself.
+            drop(lock);
+            if let Some(AddThen::Export(mut export)) = then {
+                export.resolve_destination()?;
+                interrupt::install();
+                export_matches(&paths_db, &paths_db.list()?, &export, &pool, args.wait)?;
+            }
+        }
+        Command::Ls {
+            paths_only: true,
+            output,
+            ..
+        } => {
+            print_paths_only(&mut io::stdout().lock(), &paths_db.list()?, output.style())?;
+        }
+        Command::Ls { long: true, output, .. } => {
+            let stats = paths_db.scan_stats()?;
+            let rows: Vec<(PathBuf, Option<&ScanStats>)> = paths_db
+                .list()?
+                .into_iter()
+                .map(|path| {
+                    let stats = stats.get(&path);
+                    (path, stats)
+                })
+                .collect();
+            print_tracked_long(&mut args.color.stdout().lock(), &rows, output.style())?;
+        }
+        Command::Ls { output, .. } => {
+            print_tracked(&mut args.color.stdout().lock(), &paths_db.list()?, output.style())?;
+        }
+        Command::Rm { paths } => {
+            let _lock = paths_db.lock(args.wait)?;
+            let tx = paths_db.handle.unchecked_transaction()?;
+            let operation = undo::start(&paths_db, "rm")?;
+            let mut batch = Batch::new(paths.len(), "removed");
+            for path in &paths {
+                match absolute(path) {
+                    Ok(absolute) => paths_db.rm(&absolute, operation)?,
+                    Err(err) => batch.fail(anyhow!(err).context(format!("could not remove {}", path.display()))),
+                }
             }
+            tx.commit()?;
+            batch.finish()?;
         }
-        Args::Prune => {
+        Command::Undo(output) => {
+            let _lock = paths_db.lock(args.wait)?;
+            undo::undo(&paths_db, output.style())?;
+        }
+        Command::DbNormalize { dry_run } => {
+            let _lock = paths_db.lock(args.wait)?;
+            doctor::normalize(&paths_db, dry_run, &mut io::stdout().lock())?;
+        }
+        Command::VerifyDb { json } => doctor::verify_db(&paths_db, json, &mut args.color.stdout().lock())?,
+        Command::Serve { socket } => serve::serve(&paths_db, &socket, &pool, args.wait)?,
+        Command::Doctor { json, fix } => {
+            let _lock = if fix { paths_db.lock(args.wait)? } else { None };
+            doctor::doctor(&paths_db, fix, json, &mut args.color.stdout().lock())?;
+        }
+        Command::Prune {
+            deep,
+            missing_files,
+            output,
+        } => {
+            let _lock = paths_db.lock(args.wait)?;
+            let tx = paths_db.handle.unchecked_transaction()?;
+            let operation = undo::start(&paths_db, "prune")?;
             let paths = paths_db.list()?;
+            let style = output.style();
+            let mut stdout = io::stdout().lock();
             for path in paths {
-                if !path.exists() {
-                    println!("Pruned {}", path.display());
-                    paths_db.rm(&path)?;
+                let stored = paths_db.stored_as(&path)?.expect("tracked path is not stored");
+                let was = paths_db.kind(&stored)?;
+                let now = PathKind::of(&path);
+                match (was, now) {
+                    (Some(was), Some(now)) if was != now => {
+                        eprintln!(
+                            "Warning: {} was {} and is now {}, keeping it",
+                            path.display(),
+                            was.describe(),
+                            now.describe()
+                        );
+                        paths_db.set_kind(&stored, now)?;
+                    }
+                    (None, Some(now)) => paths_db.set_kind(&stored, now)?,
+                    _ => {}
+                }
+                let gone = !path.exists();
+                let label = if gone && missing_files {
+                    if was != Some(PathKind::File) {
+                        continue;
+                    }
+                    "Pruned (missing file)"
+                } else if gone {
+                    "Pruned"
+                } else if deep && find_matches(std::slice::from_ref(&path), &Filters::default())?.is_empty() {
+                    "Pruned (empty)"
+                } else {
+                    continue;
+                };
+                if style != PathStyle::Null {
+                    write!(stdout, "{} ", label)?;
                 }
+                print_path(&mut stdout, &path, style)?;
+                paths_db.rm(&path, operation)?;
             }
+            tx.commit()?;
         }
-        Args::Matched => {
+        Command::Matched {
+            show_excluded: true,
+            format,
+            filter,
+            output,
+            ..
+        } => print_excluded(&paths_db.list()?, &filter.filters(&paths_db)?, format, output.style())?,
+        Command::Matched {
+            assert_no_newline,
+            format: MatchedFormat::Ndjson,
+            filter,
+            ..
+        } if !assert_no_newline => {
+            let started_at = SystemTime::now();
             let paths = paths_db.list()?;
-            let matches = find_matches(&paths)?;
-            for path in matches {
-                println!("{}", path.display());
-            }
+            let mut counts: Vec<(PathBuf, u64)> = paths.iter().map(|path| (path.clone(), 0)).collect();
+            let mut stdout = io::stdout().lock();
+            for_each_match(&paths, &filter.filters(&paths_db)?, |root, entry| {
+                write_match_record(&mut stdout, root, entry.path(), &entry.metadata()?)?;
+                if let Some((_, count)) = counts.iter_mut().find(|(path, _)| path == root) {
+                    *count += 1;
+                }
+                Ok(())
+            })?;
+            save_scan(&paths_db, &counts, started_at);
         }
-        Args::Export { kind, path } => {
+        Command::Matched {
+            assert_no_newline,
+            format,
+            filter,
+            output,
+            ..
+        } => {
+            let started_at = SystemTime::now();
             let paths = paths_db.list()?;
-            let matches = find_matches(&paths)?;
-            match kind {
-                ExportKind::Dir => {
-                    clean_dir(&path)?;
-                    export_dir(path, &matches)?;
+            let matches = scan_matches(&paths_db, &paths, &filter.filters(&paths_db)?, false)?;
+            save_scan(&paths_db, &match_counts(&paths, &matches), started_at);
+            if assert_no_newline {
+                let offenders: Vec<&PathBuf> = matches
+                    .iter()
+                    .filter(|mat| mat.as_os_str().as_bytes().iter().any(|&b| b == b'\n' || b == b'\r'))
+                    .collect();
+                if !offenders.is_empty() {
+                    for offender in &offenders {
+                        eprintln!("{}", shell_escape(offender));
+                    }
+                    bail!("{} matched paths contain a line break", offenders.len());
+                }
+            }
+            match format {
+                MatchedFormat::Text => {
+                    print_matched(&mut args.color.stdout().lock(), &paths, &matches, output.style())?
+                }
+                MatchedFormat::Ndjson => {
+                    let mut stdout = io::stdout().lock();
+                    for mat in &matches {
+                        let root = paths
+                            .iter()
+                            .find(|path| mat.starts_with(path))
+                            .expect("match has no root");
+                        write_match_record(&mut stdout, root, mat, &fs::metadata(mat)?)?;
+                    }
+                }
+            }
+        }
+        Command::Changed { since, filter, output } => {
+            let _lock = paths_db.lock(args.wait)?;
+            let since = match since {
+                Some(within) => Some(SystemTime::now() - within),
+                None => paths_db.last_export_at()?,
+            };
+            changed::changed(
+                &paths_db,
+                &paths_db.list()?,
+                &filter.filters(&paths_db)?,
+                since,
+                output.style(),
+            )?;
+        }
+        Command::Stats { format, filter } => {
+            let table = stats(&paths_db.list()?, &filter.filters(&paths_db)?)?;
+            table.write(&mut io::stdout().lock(), format, "stats")?;
+        }
+        Command::Top { count, format, filter } => {
+            let table = top(&paths_db.list()?, &filter.filters(&paths_db)?, count)?;
+            table.write(&mut io::stdout().lock(), format, "top")?;
+        }
+        Command::Recent { limit, by, json } => recent(&paths_db, limit, by, json)?,
+        Command::Checksum { hash, null, filter } => {
+            print_checksums(&paths_db, &filter.filters(&paths_db)?, hash, null, &pool)?;
+        }
+        Command::Tag(retag_args) => {
+            let _lock = paths_db.lock(args.wait)?;
+            retag(&paths_db, &retag_args, false)?;
+        }
+        Command::Untag(retag_args) => {
+            let _lock = paths_db.lock(args.wait)?;
+            retag(&paths_db, &retag_args, true)?;
+        }
+        Command::Trim {
+            count,
+            exclude_top,
+            filter,
+        } => {
+            let _lock = paths_db.lock(args.wait)?;
+            trim(&paths_db, &filter.filters(&paths_db)?, count, exclude_top)?;
+        }
+        Command::Estimate { kind, sample, filter } => {
+            estimate(&paths_db.list()?, &filter.filters(&paths_db)?, &kind, sample)?;
+        }
+        Command::Export(mut export) => {
+            export.resolve_destination()?;
+            interrupt::install();
+            export_matches(&paths_db, &paths_db.list()?, &export, &pool, args.wait)?;
+        }
+        Command::Watch {
+            debounce,
+            interval,
+            command: WatchCommand::Export(mut export),
+        } => {
+            export.resolve_destination()?;
+            watch::watch_export(&paths_db, &export, &pool, args.wait, debounce, interval)?
+        }
+        Command::Which { file, json, filter } => {
+            let file = absolute(&file)?;
+            which(&paths_db.list()?, &file, &filter.filters(&paths_db)?, json)?;
+        }
+        Command::Explain { file, json, filter } => {
+            let file = absolute(&file)?;
+            explain(&paths_db.list()?, &file, &filter.filters(&paths_db)?, json)?;
+        }
+        Command::Restore(restore) if cas::is_store(&restore.archive) => cas::restore(&restore)?,
+        Command::Restore(restore) => restore_tar(&restore)?,
+        Command::Convert { source, dest, format } => {
+            interrupt::install();
+            convert::convert(&source, &dest, format)?;
+        }
+        Command::Collect {
+            dir,
+            on_collision,
+            dry_run,
+            filter,
+        } => {
+            let matches = scan_matches(&paths_db, &paths_db.list()?, &filter.filters(&paths_db)?, true)?;
+            collect::collect(&matches, &dir, on_collision, dry_run, &pool)?;
+        }
+        Command::Snapshot { dir, retain, filter } => {
+            interrupt::install();
+            fs::create_dir_all(&dir).context(format!("could not create {}", dir.display()))?;
+            // Tar exports are written under a partial name, an interrupted snapshot isn't taken for a complete one.
+            let path = dir.join(snapshot::file_name(SystemTime::now()));
+            let export = ExportArgs::tar(path.clone(), filter);
+            let count = export_matches(&paths_db, &paths_db.list()?, &export, &pool, args.wait)?;
+            println!("Exported {} files to {}", count, path.display());
+            if let Some(retain) = retain {
+                let _lock = paths_db.lock(args.wait)?;
+                snapshot::prune(&dir, &retain)?;
+            }
+        }
+        Command::Config {
+            command:
+                ConfigCommand::Show {
+                    json,
+                    list_presets: true,
+                    ..
+                },
+        } => settings::show_presets(json),
+        Command::Config {
+            command:
+                ConfigCommand::Show {
+                    json,
+                    compress_threads,
+                    filter,
+                    ..
+                },
+        } => {
+            let matches = matches
+                .subcommand_matches("config")
+                .and_then(|matches| matches.subcommand_matches("show"))
+                .expect("config show was parsed without its matches");
+            settings::show(&settings, &filter, compress_threads, matches, &paths_db, json);
+        }
+        Command::Import {
+            other,
+            from_archive,
+            dry_run,
+        } => {
+            let embedded = match &from_archive {
+                Some(archive) => Some(extract_embedded_db(archive)?),
+                None => None,
+            };
+            let other = match (&other, &embedded) {
+                (_, Some(embedded)) => PathsDB::open_read_only(&embedded.0)?,
+                (Some(other), None) => PathsDB::open_read_only(other)?,
+                (None, None) => unreachable!("clap requires a database or an archive"),
+            };
+            let _lock = paths_db.lock(args.wait)?;
+            let tx = paths_db.handle.unchecked_transaction()?;
+            let (mut imported, mut skipped) = (0, 0);
+            let stored = other.stored()?;
+            for ((path, _), inserted) in stored.iter().zip(paths_db.add_many(&stored)?) {
+                if inserted {
+                    paths_db.set_metadata(path, &other.metadata(path)?)?;
+                    imported += 1;
+                    if dry_run {
+                        println!("Would import {}", path.display());
+                    }
+                } else {
+                    skipped += 1;
                 }
-                ExportKind::Tar => export_tar(path, &matches)?,
-                ExportKind::Zip => todo!(),
+            }
+            if dry_run {
+                tx.rollback()?;
+                println!("Would import {} paths, {} already tracked", imported, skipped);
+            } else {
+                tx.commit()?;
+                println!("Imported {} paths, {} already tracked", imported, skipped);
             }
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_database_is_in_the_config_directory() {
+        let path = PathsDB::default_path_in(Some(PathBuf::from("/home/me/.config"))).unwrap();
+        assert_eq!(path, Path::new("/home/me/.config/track.db"));
+    }
+
+    #[test]
+    fn an_unknown_config_directory_is_a_usage_error() {
+        let err = PathsDB::default_path_in(None).unwrap_err();
+        assert_eq!(exit::code(&err), Exit::Usage as u8);
+        let message = format!("{:#}", err);
+        assert!(message.contains("$XDG_CONFIG_HOME nor $HOME"), "{}", message);
+        assert!(message.contains("use --db <PATH> or set TRACK_DB"), "{}", message);
+    }
+
+    fn words(list: &str) -> Vec<String> {
+        split_words(OsStr::new(list))
+            .unwrap()
+            .into_iter()
+            .map(|word| word.into_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn split_words_follow_shell_quoting() {
+        assert_eq!(words(" a\tb \t c\n"), ["a", "b", "c"]);
+        assert_eq!(words("'my dir' \"x y\" a\\ b"), ["my dir", "x y", "a b"]);
+        // Quotes join the parts of a word, and an empty quoted word is kept.
+        assert_eq!(words("pre'fix'\"ed\" ''"), ["prefixed", ""]);
+        // Single quotes keep backslashes, double quotes only take them before $ ` " \ and line breaks.
+        assert_eq!(words("'a\\b' \"c\\\"d\\e\\\\\""), ["a\\b", "c\"d\\e\\"]);
+        assert_eq!(words("a\\\nb \"c\\\nd\""), ["ab", "cd"]);
+    }
+
+    #[test]
+    fn unbalanced_quotes_fail_to_split() {
+        for (list, message) in [
+            ("a 'b", "unclosed ' quote"),
+            ("a \"b\\\"", "unclosed \" quote"),
+            ("a\\", "nothing to escape after the trailing \\"),
+        ] {
+            let err = split_words(OsStr::new(list)).unwrap_err();
+            assert_eq!(err.to_string(), message, "{}", list);
+        }
+    }
+
+    /// Options of an export command line, with its destination resolved.
+    fn export_args(args: &[&str]) -> anyhow::Result<ExportArgs> {
+        let args = Args::try_parse_from(["track", "export"].iter().chain(args))?;
+        match args.command {
+            Command::Export(mut export) => {
+                export.resolve_destination()?;
+                Ok(export)
+            }
+            command => panic!("parsed as {:?}", command),
+        }
+    }
+
+    #[test]
+    fn output_and_kind_flags_parse_like_kind_and_path() {
+        for (flags, positional) in [
+            (&["--kind", "tar", "--output", "b.tar.gz"][..], &["tar", "b.tar.gz"][..]),
+            (&["-o", "b.zip"], &["zip", "b.zip"]),
+            (&["--output", "b.tgz", "--changed"], &["tar", "b.tgz", "--changed"]),
+            (&["--kind", "dir", "-o", "out.zip"], &["dir", "out.zip"]),
+            (&["tar", "--output", "b.bin"], &["tar", "b.bin"]),
+        ] {
+            let flags = export_args(flags).unwrap();
+            let positional = export_args(positional).unwrap();
+            assert_eq!(format!("{:?}", flags), format!("{:?}", positional));
+        }
+    }
+
+    #[test]
+    fn conflicting_destinations_are_usage_errors() {
+        use clap::ErrorKind;
+        for (args, kind) in [
+            (
+                &["tar", "a.tar.gz", "--output", "b.tar.gz"][..],
+                ErrorKind::ArgumentConflict,
+            ),
+            (
+                &["tar", "--kind", "zip", "--output", "b.zip"],
+                ErrorKind::ArgumentConflict,
+            ),
+            (&["--kind", "tar"], ErrorKind::MissingRequiredArgument),
+        ] {
+            let err = export_args(args).unwrap_err();
+            assert_eq!(err.downcast_ref::<clap::Error>().unwrap().kind(), kind, "{:?}", args);
+        }
+        let err = export_args(&["--output", "b.bin"]).unwrap_err();
+        assert_eq!(exit::code(&err), Exit::Usage as u8);
+        let message = format!("{:#}", err);
+        assert!(
+            message.contains("can't tell the kind of export from b.bin"),
+            "{}",
+            message
+        );
+    }
+
+    /// A tree standing in for /, removed when dropped.
+    struct FakeRoot(PathBuf);
+
+    impl FakeRoot {
+        fn new(name: &str) -> FakeRoot {
+            let root = std::env::temp_dir().join(format!("track-unit-{}-{}", std::process::id(), name));
+            for file in ["proc/1/status", "sys/kernel/x", "home/me/notes"] {
+                let path = root.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, "x").unwrap();
+            }
+            FakeRoot(root)
+        }
+
+        /// Filters leaving out the system directories of the fake root.
+        fn filters(&self) -> Filters {
+            Filters {
+                system_dirs: ["proc", "sys"].iter().map(|dir| self.0.join(dir)).collect(),
+                ..Filters::default()
+            }
+        }
+    }
+
+    impl Drop for FakeRoot {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn system_dirs_under_a_tracked_path_are_skipped() {
+        let root = FakeRoot::new("system-dirs");
+        let filters = root.filters();
+        let mut skipped = Vec::new();
+        let mut matched = Vec::new();
+        for_each_entry(std::slice::from_ref(&root.0), &filters, |_, entry, exclusion| {
+            match exclusion {
+                Some(reason) => skipped.push((entry.into_path(), reason)),
+                None => matched.push(entry.into_path()),
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(matched, [root.0.join("home/me/notes")]);
+        let reason = "system directory, left out unless --no-safe-excludes is used";
+        assert_eq!(skipped, [(root.0.join("proc"), reason), (root.0.join("sys"), reason)]);
+        assert_eq!(
+            export_exclusion(&root.0, &root.0.join("proc/1/status"), &filters),
+            Some("inside a system directory, left out unless --no-safe-excludes is used")
+        );
+    }
+
+    #[test]
+    fn system_dirs_tracked_themselves_are_scanned() {
+        let root = FakeRoot::new("system-dirs-tracked");
+        let filters = root.filters();
+        let proc = root.0.join("proc");
+        assert_eq!(
+            find_matches(std::slice::from_ref(&proc), &filters).unwrap(),
+            [proc.join("1/status")]
+        );
+        assert_eq!(export_exclusion(&proc, &proc.join("1/status"), &filters), None);
+        // Without the safe excludes, nothing is skipped.
+        assert_eq!(
+            find_matches(std::slice::from_ref(&root.0), &Filters::default())
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    fs,
+    io::{self, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf, MAIN_SEPARATOR},
+    str::FromStr,
+    time::UNIX_EPOCH,
+};
+
+use crate::{json, log, ScanStats};
+use anyhow::bail;
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+#[derive(Debug, Clone, Copy)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "auto" => ColorMode::Auto,
+            "always" => ColorMode::Always,
+            "never" => ColorMode::Never,
+            _ => bail!("Unknown color mode {}", s),
+        })
+    }
+}
+
+impl ColorMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ColorMode::Auto => "auto",
+            ColorMode::Always => "always",
+            ColorMode::Never => "never",
+        }
+    }
+
+    /// Open stdout with coloring enabled only if this mode allows it.
+    ///
+    /// In auto mode colors are used when stdout is a terminal, `NO_COLOR` is unset and `TERM` isn't dumb.
+    pub fn stdout(self) -> StandardStream {
+        let choice = match self {
+            ColorMode::Auto if atty::is(atty::Stream::Stdout) => ColorChoice::Auto,
+            ColorMode::Auto | ColorMode::Never => ColorChoice::Never,
+            ColorMode::Always => ColorChoice::Always,
+        };
+        StandardStream::stdout(choice)
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct OutputArgs {
+    /// Separate paths with NUL bytes and print them as raw bytes, for xargs -0 and co.
+    #[clap(short = '0', long)]
+    null: bool,
+    /// Quote paths which aren't plain text like a shell would, making them unambiguous.
+    #[clap(long, conflicts_with = "null")]
+    escape: bool,
+}
+
+impl OutputArgs {
+    pub fn style(&self) -> PathStyle {
+        if self.null {
+            PathStyle::Null
+        } else if self.escape {
+            PathStyle::Escape
+        } else {
+            PathStyle::Display
+        }
+    }
+}
+
+/// Output format of the matched command.
+#[derive(Debug, Clone, Copy)]
+pub enum MatchedFormat {
+    Text,
+    /// One JSON object per line, `{"path":PATH,"root":PATH,"size":N,"mtime":SECS}`, or
+    /// `{"path":PATH,"root":PATH,"reason":REASON}` with --show-excluded.
+    Ndjson,
+}
+
+impl FromStr for MatchedFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "text" => MatchedFormat::Text,
+            "ndjson" => MatchedFormat::Ndjson,
+            _ => bail!("Unknown format {}", s),
+        })
+    }
+}
+
+/// Write the JSON record of a matched file on its own line, flushed so consumers see it right away.
+pub fn write_match_record(out: &mut impl Write, root: &Path, path: &Path, meta: &fs::Metadata) -> io::Result<()> {
+    let mtime = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let record = json::object([
+        ("path", json::Value::path(path)),
+        ("root", json::Value::path(root)),
+        ("size", meta.len().into()),
+        ("mtime", mtime.into()),
+    ]);
+    writeln!(out, "{}", record)?;
+    out.flush()
+}
+
+/// How paths are printed by the commands listing them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathStyle {
+    /// One path per line, non UTF-8 bytes are replaced so the output is lossy.
+    Display,
+    /// One path per line, quoted like `ls --quoting-style=shell-escape` when needed.
+    Escape,
+    /// Raw path bytes terminated by a NUL byte.
+    Null,
+}
+
+fn is_shell_safe(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"/._-+,:@%=".contains(&b)
+}
+
+/// Quote a path for a POSIX shell, using `$'...'` quoting when it contains control or non UTF-8 bytes.
+pub fn shell_escape(path: &Path) -> String {
+    let bytes = path.as_os_str().as_bytes();
+    if !bytes.is_empty() && bytes.iter().all(|&b| is_shell_safe(b)) {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(s) if !s.chars().any(char::is_control) => format!("'{}'", s.replace('\'', "'\\''")),
+        _ => {
+            let mut escaped = String::from("$'");
+            for chunk in bytes.utf8_chunks() {
+                for c in chunk.valid().chars() {
+                    match c {
+                        '\n' => escaped.push_str("\\n"),
+                        '\r' => escaped.push_str("\\r"),
+                        '\t' => escaped.push_str("\\t"),
+                        '\'' => escaped.push_str("\\'"),
+                        '\\' => escaped.push_str("\\\\"),
+                        c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+                        c => escaped.push(c),
+                    }
+                }
+                for b in chunk.invalid() {
+                    escaped.push_str(&format!("\\x{:02x}", b));
+                }
+            }
+            escaped.push('\'');
+            escaped
+        }
+    }
+}
+
+/// Print a path in the given style, followed by its terminator.
+pub fn print_path(out: &mut impl Write, path: &Path, style: PathStyle) -> io::Result<()> {
+    match style {
+        PathStyle::Display => writeln!(out, "{}", path.display()),
+        PathStyle::Escape => writeln!(out, "{}", shell_escape(path)),
+        PathStyle::Null => {
+            out.write_all(path.as_os_str().as_bytes())?;
+            out.write_all(b"\0")
+        }
+    }
+}
+
+/// Color used to display a tracked path depending of what it currently points to.
+pub fn path_color(path: &Path) -> ColorSpec {
+    let mut spec = ColorSpec::new();
+    match fs::symlink_metadata(path) {
+        Err(_) => spec.set_fg(Some(Color::Red)),
+        Ok(meta) if meta.file_type().is_symlink() => spec.set_fg(Some(Color::Cyan)),
+        Ok(meta) if meta.is_dir() => spec.set_fg(Some(Color::Blue)).set_bold(true),
+        Ok(_) => &mut spec,
+    };
+    spec
+}
+
+/// Print the raw bytes of each path followed by a line break, or a NUL byte in the null style.
+///
+/// This is the stable scripting format of `ls --paths-only`: no color, quoting or decoration is
+/// ever added to it, whatever the default output of `ls` becomes.
+pub fn print_paths_only(out: &mut impl Write, paths: &[PathBuf], style: PathStyle) -> io::Result<()> {
+    let terminator = if style == PathStyle::Null { b"\0" } else { b"\n" };
+    for path in paths {
+        out.write_all(path.as_os_str().as_bytes())?;
+        out.write_all(terminator)?;
+    }
+    out.flush()
+}
+
+pub fn print_tracked(out: &mut impl WriteColor, paths: &[PathBuf], style: PathStyle) -> io::Result<()> {
+    for path in paths {
+        out.set_color(&path_color(path))?;
+        print_path(out, path, style)?;
+        out.reset()?;
+    }
+    Ok(())
+}
+
+/// Print tracked paths along with when they were last scanned and how many files they matched then.
+pub fn print_tracked_long(
+    out: &mut impl WriteColor,
+    rows: &[(PathBuf, Option<&ScanStats>)],
+    style: PathStyle,
+) -> io::Result<()> {
+    let names: Vec<String> = rows
+        .iter()
+        .map(|(path, _)| match style {
+            PathStyle::Escape => shell_escape(path),
+            _ => path.display().to_string(),
+        })
+        .collect();
+    let width = names.iter().map(|name| name.chars().count()).max().unwrap_or(0);
+    for ((path, stats), name) in rows.iter().zip(&names) {
+        out.set_color(&path_color(path))?;
+        write!(out, "{}", name)?;
+        out.reset()?;
+        let pad = " ".repeat(width - name.chars().count());
+        match stats {
+            Some(stats) => writeln!(
+                out,
+                "{}  scanned {}  {} {}",
+                pad,
+                log::timestamp(stats.scanned_at),
+                stats.files,
+                if stats.files == 1 { "file" } else { "files" }
+            )?,
+            None => writeln!(out, "{}  never scanned", pad)?,
+        }
+    }
+    Ok(())
+}
+
+/// Print matched files, displayed ones get the tracked path they were found under highlighted.
+pub fn print_matched(
+    out: &mut impl WriteColor,
+    paths: &[PathBuf],
+    matches: &[PathBuf],
+    style: PathStyle,
+) -> io::Result<()> {
+    if style != PathStyle::Display {
+        for mat in matches {
+            print_path(out, mat, style)?;
+        }
+        return Ok(());
+    }
+    let mut root_color = ColorSpec::new();
+    root_color.set_fg(Some(Color::Blue));
+    for mat in matches {
+        match paths.iter().find_map(|root| Some((root, mat.strip_prefix(root).ok()?))) {
+            Some((root, rest)) if !rest.as_os_str().is_empty() => {
+                out.set_color(&root_color)?;
+                write!(out, "{}", root.display())?;
+                out.reset()?;
+                writeln!(out, "{}{}", MAIN_SEPARATOR, rest.display())?;
+            }
+            _ => writeln!(out, "{}", mat.display())?,
+        }
+    }
+    Ok(())
+}
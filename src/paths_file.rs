@@ -0,0 +1,279 @@
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::{absolute, exit::Exit, expand_path, glob::Glob, Metadata, PathsDB};
+
+/// A tracked path read from a paths file, with its tags and excludes.
+struct Entry {
+    line: usize,
+    path: Option<PathBuf>,
+    tags: Vec<String>,
+    excludes: Vec<Glob>,
+}
+
+/// Value of a key, the subset of TOML paths files use.
+enum Value {
+    String(String),
+    Array(Vec<String>),
+}
+
+/// Read the string starting at the quote opening `input`, returning it and the rest of the input.
+///
+/// Double quotes allow the \\, \", \n and \t escapes, single quotes are taken literally like in TOML.
+fn parse_string(input: &str) -> anyhow::Result<(String, &str)> {
+    let quote = input.chars().next().expect("string without a quote");
+    let mut string = String::new();
+    let mut chars = input.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c == quote => return Ok((string, &input[i + 1..])),
+            '\\' if quote == '"' => match chars.next().map(|(_, c)| c) {
+                Some('\\') => string.push('\\'),
+                Some('"') => string.push('"'),
+                Some('n') => string.push('\n'),
+                Some('t') => string.push('\t'),
+                Some(c) => bail!("unknown escape \\{}", c),
+                None => bail!("unclosed {} quote", quote),
+            },
+            c => string.push(c),
+        }
+    }
+    bail!("unclosed {} quote", quote)
+}
+
+/// Parse the value of a key, a string or an array of strings, on a single line.
+fn parse_value(input: &str) -> anyhow::Result<Value> {
+    let (value, rest) = if input.starts_with(['"', '\'']) {
+        let (string, rest) = parse_string(input)?;
+        (Value::String(string), rest)
+    } else if let Some(mut rest) = input.strip_prefix('[') {
+        let mut strings = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                break (Value::Array(strings), after);
+            }
+            if !rest.starts_with(['"', '\'']) {
+                bail!("expected a string or ] in the array");
+            }
+            let (string, after) = parse_string(rest)?;
+            strings.push(string);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                bail!("expected , or ] after a string of the array");
+            }
+        }
+    } else {
+        bail!("expected a quoted string or an array of strings");
+    };
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        bail!("unexpected {} after the value", rest);
+    }
+    Ok(value)
+}
+
+/// Parse the `[[path]]` tables of a paths file, relative paths being relative to `dir`.
+fn parse(content: &str, dir: &Path) -> anyhow::Result<Vec<Entry>> {
+    let mut entries: Vec<Entry> = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line_number = index + 1;
+        let at_line = || format!("line {}", line_number);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            if line.split('#').next().map(str::trim_end) != Some("[[path]]") {
+                return Err(anyhow!("only [[path]] tables are supported")).context(at_line());
+            }
+            entries.push(Entry {
+                line: line_number,
+                path: None,
+                tags: Vec::new(),
+                excludes: Vec::new(),
+            });
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected key = value"))
+            .context(at_line())?;
+        let key = key.trim();
+        let entry = entries
+            .last_mut()
+            .ok_or_else(|| anyhow!("{} is outside of a [[path]] table", key))
+            .context(at_line())?;
+        let value = parse_value(value.trim_start()).context(at_line())?;
+        match (key, value) {
+            ("path", Value::String(path)) if entry.path.is_none() => {
+                let path = expand_path(OsStr::new(&path)).context(at_line())?;
+                entry.path = Some(absolute(&dir.join(path))?.into_owned());
+            }
+            ("tags", Value::Array(tags)) if entry.tags.is_empty() => entry.tags = tags,
+            ("exclude", Value::Array(patterns)) if entry.excludes.is_empty() => {
+                for pattern in patterns {
+                    let glob = pattern
+                        .parse()
+                        .context(format!("invalid exclude {}", pattern))
+                        .context(at_line())?;
+                    entry.excludes.push(glob);
+                }
+            }
+            ("path", Value::Array(_)) => return Err(anyhow!("path must be a string")).context(at_line()),
+            ("tags" | "exclude", Value::String(_)) => {
+                return Err(anyhow!("{} must be an array of strings", key)).context(at_line())
+            }
+            ("path" | "tags" | "exclude", _) => return Err(anyhow!("{} is given twice", key)).context(at_line()),
+            _ => return Err(anyhow!("unknown key {}, expected path, tags or exclude", key)).context(at_line()),
+        }
+    }
+    if let Some(entry) = entries.iter().find(|entry| entry.path.is_none()) {
+        bail!("the [[path]] table of line {} has no path", entry.line);
+    }
+    Ok(entries)
+}
+
+/// Track the paths listed by the paths file `file` in `paths_db`, an in-memory database standing in
+/// for the persistent one with --paths-file.
+///
+/// Each path is a `[[path]]` table with a `path` string, a leading ~ and $VARIABLES being expanded and
+/// relative paths resolved against the directory of the file, and optional `tags` and `exclude` arrays
+/// of strings, like:
+///
+/// ```toml
+/// [[path]]
+/// path = "~/Documents"
+/// tags = ["docs"]
+/// exclude = ["*.tmp", "build/**"]
+/// ```
+pub fn load(paths_db: &PathsDB, file: &Path) -> anyhow::Result<()> {
+    let content = fs::read_to_string(file).context(format!("could not read paths file {}", file.display()))?;
+    let dir = absolute(file)?;
+    let dir = dir.parent().unwrap_or(Path::new("/"));
+    let entries = parse(&content, dir)
+        .context(format!("invalid paths file {}", file.display()))
+        .context(Exit::Usage)?;
+    let paths: Vec<_> = entries
+        .iter()
+        .map(|entry| (entry.path.clone().expect("paths file entry has no path"), None))
+        .collect();
+    for ((path, _), (entry, inserted)) in paths.iter().zip(entries.iter().zip(paths_db.add_many(&paths)?)) {
+        if !inserted {
+            return Err(anyhow!(
+                "{} is listed twice in {}, the second time at line {}",
+                path.display(),
+                file.display(),
+                entry.line
+            ))
+            .context(Exit::Usage);
+        }
+        let metadata = Metadata {
+            tags: entry.tags.clone(),
+            excludes: entry.excludes.clone(),
+            replace: false,
+        };
+        paths_db.set_metadata(path, &metadata)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{parse, parse_value, Value};
+
+    fn errors(content: &str) -> String {
+        format!("{:#}", parse(content, Path::new("/base")).err().expect("parsed"))
+    }
+
+    #[test]
+    fn strings_and_arrays() {
+        match parse_value(r#""a \"quoted\"\tpath\\" # comment"#).unwrap() {
+            Value::String(s) => assert_eq!(s, "a \"quoted\"\tpath\\"),
+            Value::Array(_) => panic!("not a string"),
+        }
+        match parse_value(r"'C:\literal'").unwrap() {
+            Value::String(s) => assert_eq!(s, r"C:\literal"),
+            Value::Array(_) => panic!("not a string"),
+        }
+        match parse_value(r#"[ "a", 'b',"c" , ]"#).unwrap() {
+            Value::Array(strings) => assert_eq!(strings, ["a", "b", "c"]),
+            Value::String(_) => panic!("not an array"),
+        }
+        match parse_value("[]").unwrap() {
+            Value::Array(strings) => assert!(strings.is_empty()),
+            Value::String(_) => panic!("not an array"),
+        }
+    }
+
+    #[test]
+    fn invalid_values() {
+        for (value, error) in [
+            (r#""unclosed"#, "unclosed \" quote"),
+            (r#""bad \x""#, "unknown escape \\x"),
+            ("bare", "expected a quoted string or an array of strings"),
+            (r#"["a" "b"]"#, "expected , or ] after a string of the array"),
+            ("[1]", "expected a string or ] in the array"),
+            (r#""a" "b""#, "unexpected \"b\" after the value"),
+        ] {
+            assert_eq!(parse_value(value).err().expect(value).to_string(), error);
+        }
+    }
+
+    #[test]
+    fn path_tables() {
+        let entries = parse(
+            r#"
+            # Backed up every night.
+            [[path]]
+            path = "docs"
+            tags = ["work", "docs"]
+            exclude = ["*.tmp", "build/**"]
+
+            [[path]] # no tags
+            path = '/abs/../other'
+            "#,
+            Path::new("/base"),
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].line, 3);
+        assert_eq!(entries[0].path, Some(PathBuf::from("/base/docs")));
+        assert_eq!(entries[0].tags, ["work", "docs"]);
+        assert_eq!(entries[0].excludes.len(), 2);
+        assert_eq!(entries[1].path, Some(PathBuf::from("/other")));
+        assert!(entries[1].tags.is_empty());
+        assert!(entries[1].excludes.is_empty());
+    }
+
+    #[test]
+    fn invalid_tables() {
+        for (content, error) in [
+            ("path = \"a\"", "line 1: path is outside of a [[path]] table"),
+            ("[paths]", "line 1: only [[path]] tables are supported"),
+            ("[[path]]\npath", "line 2: expected key = value"),
+            ("[[path]]\npath = \"a\"\npath = \"b\"", "line 3: path is given twice"),
+            ("[[path]]\npath = [\"a\"]", "line 2: path must be a string"),
+            ("[[path]]\ntags = \"a\"", "line 2: tags must be an array of strings"),
+            (
+                "[[path]]\nname = \"a\"",
+                "line 2: unknown key name, expected path, tags or exclude",
+            ),
+            (
+                "[[path]]\ntags = []\n[[path]]\npath = \"a\"",
+                "the [[path]] table of line 1 has no path",
+            ),
+        ] {
+            assert_eq!(errors(content), error, "{}", content);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc,
+    },
+    thread,
+};
+
+use crate::hash::Hasher;
+
+const CHUNK_SIZE: usize = 256 * 1024;
+/// Chunks read ahead of the slowest of the hasher and the consumer, bounding memory use.
+const CHUNKS_IN_FLIGHT: usize = 8;
+
+type Chunk = Arc<Vec<u8>>;
+
+/// Reader over the chunks sent by the thread reading the file.
+struct ChunkReader {
+    chunks: Receiver<io::Result<Chunk>>,
+    current: Chunk,
+    pos: usize,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.current.len() {
+            match self.chunks.recv() {
+                Ok(chunk) => {
+                    self.current = chunk?;
+                    self.pos = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.current.len() - self.pos);
+        buf[..n].copy_from_slice(&self.current[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Read the first `len` bytes of `file`, stopping early if the consumer or the hasher went away.
+fn read_chunks(file: File, len: u64, to_hasher: SyncSender<Chunk>, to_consumer: SyncSender<io::Result<Chunk>>) {
+    let mut input = file.take(len);
+    loop {
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let n = match input.read(&mut chunk) {
+            Ok(0) => return,
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                let _ = to_consumer.send(Err(err));
+                return;
+            }
+        };
+        chunk.truncate(n);
+        let chunk = Arc::new(chunk);
+        if to_hasher.send(chunk.clone()).is_err() || to_consumer.send(Ok(chunk)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Let `consume` read the first `len` bytes of `file` while they are hashed, returning its result
+/// along with the hash.
+///
+/// The file is read on its own thread and hashed on another so reading, hashing and whatever
+/// `consume` does with the content, typically compressing it, overlap. `consume` sees exactly the
+/// bytes which are hashed.
+pub fn hash_while_reading<R>(
+    file: File,
+    len: u64,
+    hasher: Hasher,
+    consume: impl FnOnce(&mut dyn Read) -> io::Result<R>,
+) -> io::Result<(R, String)> {
+    let (to_hasher, hasher_chunks) = mpsc::sync_channel::<Chunk>(CHUNKS_IN_FLIGHT);
+    let (to_consumer, consumer_chunks) = mpsc::sync_channel(CHUNKS_IN_FLIGHT);
+    thread::scope(|scope| {
+        scope.spawn(move || read_chunks(file, len, to_hasher, to_consumer));
+        let hashing = scope.spawn(move || {
+            let mut digest = hasher.digest();
+            for chunk in hasher_chunks {
+                digest.update(&chunk);
+            }
+            digest.finish()
+        });
+        let mut reader = ChunkReader {
+            chunks: consumer_chunks,
+            current: Arc::new(Vec::new()),
+            pos: 0,
+        };
+        let result = consume(&mut reader);
+        // Unblock the reading thread if the consumer stopped before the end.
+        drop(reader);
+        let hash = hashing.join().expect("hashing thread panicked");
+        Ok((result?, hash))
+    })
+}
@@ -0,0 +1,128 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+/// Files a worker has open at once at most, like the source and the destination of a copy.
+const FILES_PER_WORKER: usize = 2;
+
+/// Workers which may run on top of the calling threads, within --max-open-files.
+static SPARE_WORKERS: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Bound the files open at once by the workers of every pool to `max`, from `--max-open-files`.
+///
+/// Each worker opens up to `FILES_PER_WORKER` files at a time. The first worker of a call stands in
+/// for the calling thread, which waits meanwhile, the others each need a share of the limit and
+/// are only started while some is left. Pools nested in workers never wait on the limit, they use
+/// fewer workers instead, down to running sequentially.
+pub fn limit_open_files(max: usize) {
+    SPARE_WORKERS.store((max / FILES_PER_WORKER).saturating_sub(1), Ordering::Relaxed);
+}
+
+/// Default of `--max-open-files`: half of the soft RLIMIT_NOFILE, leaving the other half to the
+/// database, the archives being written and the files left open by timed out reads.
+pub fn default_max_open_files() -> usize {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 || limit.rlim_cur == libc::RLIM_INFINITY {
+        return usize::MAX;
+    }
+    usize::try_from(limit.rlim_cur / 2).unwrap_or(usize::MAX)
+}
+
+/// Take a spare worker if there's one left.
+fn take_spare_worker() -> bool {
+    SPARE_WORKERS
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |spare| spare.checked_sub(1))
+        .is_ok()
+}
+
+/// Concurrency shared by the commands doing work in parallel, configured once from `--jobs`.
+///
+/// Work is spread over scoped worker threads for the duration of each call. With a single job
+/// everything runs sequentially on the calling thread, in order.
+#[derive(Debug, Clone, Copy)]
+pub struct Pool {
+    jobs: usize,
+}
+
+impl Pool {
+    pub fn new(jobs: usize) -> Pool {
+        Pool { jobs }
+    }
+
+    /// Whether work may run on several threads at once.
+    pub fn is_parallel(&self) -> bool {
+        self.jobs > 1
+    }
+
+    /// Number of jobs the available parallelism allows.
+    pub fn default_jobs() -> usize {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    }
+
+    /// Call `f` on every item, stopping at the first error.
+    pub fn try_for_each<T, E, F>(&self, items: &[T], f: F) -> Result<(), E>
+    where
+        T: Sync,
+        E: Send,
+        F: Fn(&T) -> Result<(), E> + Sync,
+    {
+        self.try_map(items, f).map(drop)
+    }
+
+    /// Call `f` on every item and collect the results in the order of the items, stopping at the first error.
+    pub fn try_map<T, R, E, F>(&self, items: &[T], f: F) -> Result<Vec<R>, E>
+    where
+        T: Sync,
+        R: Send,
+        E: Send,
+        F: Fn(&T) -> Result<R, E> + Sync,
+    {
+        let mut workers = 1;
+        while workers < self.jobs.min(items.len()) && take_spare_worker() {
+            workers += 1;
+        }
+        if workers <= 1 {
+            return items.iter().map(f).collect();
+        }
+
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let results = Mutex::new(Vec::with_capacity(items.len()));
+        let error = Mutex::new(None);
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    while !failed.load(Ordering::Relaxed) {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let item = match items.get(index) {
+                            Some(item) => item,
+                            None => break,
+                        };
+                        match f(item) {
+                            Ok(result) => results.lock().unwrap().push((index, result)),
+                            Err(err) => {
+                                failed.store(true, Ordering::Relaxed);
+                                error.lock().unwrap().get_or_insert(err);
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        SPARE_WORKERS.fetch_add(workers - 1, Ordering::Relaxed);
+
+        if let Some(err) = error.into_inner().unwrap() {
+            return Err(err);
+        }
+        let mut results = results.into_inner().unwrap();
+        results.sort_unstable_by_key(|(index, _)| *index);
+        Ok(results.into_iter().map(|(_, result)| result).collect())
+    }
+}
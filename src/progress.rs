@@ -0,0 +1,187 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    str::FromStr,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+
+use crate::{
+    json::{self, Value},
+    report::format_size,
+};
+
+/// Time between two lines of periodic progress.
+const PERIOD: Duration = Duration::from_secs(1);
+
+/// How the progress of an export is reported on stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressFormat {
+    /// A line counting the exported files, rewritten as the export goes.
+    Text,
+    /// One JSON object per line, for frontends:
+    ///
+    /// - `{"event":"file","path":PATH,"done":N,"total":M}` once a file is exported, paths are
+    ///   encoded like in the other JSON outputs
+    /// - `{"event":"done","files":N,"bytes":B}` when the export is complete
+    /// - `{"event":"interrupted","files":N}` when the export is interrupted instead
+    Json,
+}
+
+impl FromStr for ProgressFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "text" => ProgressFormat::Text,
+            "json" => ProgressFormat::Json,
+            _ => bail!("Unknown progress format {}", s),
+        })
+    }
+}
+
+/// When the progress of an export is reported, given with --progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressWhen {
+    /// In the format of --progress-format if given, else as a line rewritten when stderr is a terminal.
+    Auto,
+    /// Like auto, but with plain periodic lines when stderr isn't a terminal and no format is given.
+    Always,
+    /// Never, whatever --progress-format says.
+    Never,
+}
+
+impl FromStr for ProgressWhen {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "auto" => ProgressWhen::Auto,
+            "always" => ProgressWhen::Always,
+            "never" => ProgressWhen::Never,
+            _ => bail!("Unknown progress mode {}", s),
+        })
+    }
+}
+
+/// How progress is written on stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Report {
+    /// The line of the text format, rewritten after each file.
+    Line,
+    /// A line like `1234/56789 files, 2.1 GiB` at most once per `PERIOD`, for logs.
+    Periodic,
+    Json,
+}
+
+/// Progress of an export, shared by the threads exporting files.
+pub struct Progress {
+    report: Option<Report>,
+    total: usize,
+    done: AtomicUsize,
+    bytes: AtomicU64,
+    started: Instant,
+    /// Milliseconds after `started` the last periodic line was written.
+    reported_at: AtomicU64,
+}
+
+impl Progress {
+    pub fn new(format: Option<ProgressFormat>, when: ProgressWhen, total: usize) -> Progress {
+        let report = match (when, format) {
+            (ProgressWhen::Never, _) => None,
+            (_, Some(ProgressFormat::Json)) => Some(Report::Json),
+            (_, Some(ProgressFormat::Text)) => Some(Report::Line),
+            _ if atty::is(atty::Stream::Stderr) => Some(Report::Line),
+            (ProgressWhen::Always, None) => Some(Report::Periodic),
+            (_, None) => None,
+        };
+        Progress {
+            report,
+            total,
+            done: AtomicUsize::new(0),
+            bytes: AtomicU64::new(0),
+            started: Instant::now(),
+            reported_at: AtomicU64::new(0),
+        }
+    }
+
+    /// Write a periodic line, in a single write so it isn't mixed with the warnings of other threads.
+    fn write_periodic(&self, done: usize, bytes: u64) -> io::Result<()> {
+        let line = format!("{}/{} files, {}\n", done, self.total, format_size(bytes));
+        io::stderr().lock().write_all(line.as_bytes())
+    }
+
+    /// Record that a file was exported.
+    pub fn file(&self, path: &Path) {
+        let report = match self.report {
+            Some(report) => report,
+            None => return,
+        };
+        let size = fs::metadata(path).map_or(0, |meta| meta.len());
+        let bytes = self.bytes.fetch_add(size, Ordering::Relaxed) + size;
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        // Progress is best effort, failing to report it shouldn't fail the export.
+        let _ = match report {
+            Report::Line => write!(io::stderr(), "\rExported {}/{} files", done, self.total),
+            Report::Periodic => {
+                let now = self.started.elapsed().as_millis() as u64;
+                let last = self.reported_at.load(Ordering::Relaxed);
+                // The thread which moves the time of the last line forward writes the next one.
+                if now - last >= PERIOD.as_millis() as u64
+                    && self
+                        .reported_at
+                        .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                        .is_ok()
+                {
+                    self.write_periodic(done, bytes)
+                } else {
+                    Ok(())
+                }
+            }
+            Report::Json => {
+                let event = json::object([
+                    ("event", "file".into()),
+                    ("path", Value::path(path)),
+                    ("done", (done as u64).into()),
+                    ("total", (self.total as u64).into()),
+                ]);
+                writeln!(io::stderr(), "{}", event)
+            }
+        };
+    }
+
+    /// Report the end of the export.
+    pub fn finish(&self) {
+        let done = self.done.load(Ordering::Relaxed) as u64;
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let _ = match self.report {
+            None => Ok(()),
+            Some(Report::Line) => writeln!(io::stderr()),
+            Some(Report::Periodic) => self.write_periodic(done as usize, bytes),
+            Some(Report::Json) => {
+                let event = json::object([
+                    ("event", "done".into()),
+                    ("files", done.into()),
+                    ("bytes", bytes.into()),
+                ]);
+                writeln!(io::stderr(), "{}", event)
+            }
+        };
+    }
+
+    /// Report that the export was interrupted, ending the progress line.
+    pub fn interrupted(&self) {
+        let done = self.done.load(Ordering::Relaxed) as u64;
+        let _ = match self.report {
+            None | Some(Report::Periodic) => Ok(()),
+            Some(Report::Line) => writeln!(io::stderr()),
+            Some(Report::Json) => {
+                let event = json::object([("event", "interrupted".into()), ("files", done.into())]);
+                writeln!(io::stderr(), "{}", event)
+            }
+        };
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use anyhow::bail;
+
+use crate::json;
+
+/// Output format of the reporting commands.
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    Text,
+    Json,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "text" => Format::Text,
+            "json" => Format::Json,
+            "csv" => Format::Csv,
+            _ => bail!("Unknown format {}", s),
+        })
+    }
+}
+
+pub enum Cell {
+    Path(PathBuf),
+    Count(u64),
+    /// A size in bytes, humanized in text output.
+    Size(u64),
+}
+
+impl Cell {
+    fn text(&self) -> String {
+        match self {
+            Cell::Path(path) => path.display().to_string(),
+            Cell::Count(n) => n.to_string(),
+            Cell::Size(n) => format_size(*n),
+        }
+    }
+
+    fn raw(&self) -> String {
+        match self {
+            Cell::Path(path) => path.display().to_string(),
+            Cell::Count(n) | Cell::Size(n) => n.to_string(),
+        }
+    }
+
+    fn json(&self) -> json::Value {
+        match self {
+            Cell::Path(path) => json::Value::path(path),
+            Cell::Count(n) | Cell::Size(n) => (*n).into(),
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        !matches!(self, Cell::Path(_))
+    }
+}
+
+/// Rows of a report, written in any of the output formats.
+pub struct Table {
+    columns: &'static [&'static str],
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new(columns: &'static [&'static str]) -> Table {
+        Table {
+            columns,
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<Cell>) {
+        debug_assert_eq!(row.len(), self.columns.len());
+        self.rows.push(row);
+    }
+
+    /// Write the table, `command` names the command it's the output of in JSON documents.
+    pub fn write(&self, out: &mut impl Write, format: Format, command: &str) -> io::Result<()> {
+        match format {
+            Format::Text => self.write_text(out),
+            Format::Json => self.write_json(out, command),
+            Format::Csv => self.write_csv(out),
+        }
+    }
+
+    /// Aligned columns meant for humans, numbers are right aligned.
+    fn write_text(&self, out: &mut impl Write) -> io::Result<()> {
+        let rows: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| row.iter().map(Cell::text).collect())
+            .collect();
+        let mut widths = vec![0; self.columns.len()];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        for (row, cells) in rows.iter().zip(&self.rows) {
+            let mut line = String::new();
+            for (i, (text, cell)) in row.iter().zip(cells).enumerate() {
+                if i > 0 {
+                    line.push_str("  ");
+                }
+                let pad = " ".repeat(widths[i] - text.chars().count());
+                if cell.is_numeric() {
+                    line.push_str(&pad);
+                    line.push_str(text);
+                } else if i + 1 < row.len() {
+                    line.push_str(text);
+                    line.push_str(&pad);
+                } else {
+                    line.push_str(text);
+                }
+            }
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    fn write_json(&self, out: &mut impl Write, command: &str) -> io::Result<()> {
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| {
+                json::Value::Object(
+                    self.columns
+                        .iter()
+                        .zip(row)
+                        .map(|(column, cell)| (column.to_string(), cell.json()))
+                        .collect(),
+                )
+            })
+            .collect();
+        writeln!(out, "{}", json::envelope(command, json::Value::Array(rows)))
+    }
+
+    fn write_csv(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "{}", self.columns.join(","))?;
+        for row in &self.rows {
+            let fields: Vec<String> = row.iter().map(|cell| csv_field(&cell.raw())).collect();
+            writeln!(out, "{}", fields.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Quote a CSV field when it contains a separator, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Format a size in bytes with binary units, like `4.2 MiB`.
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
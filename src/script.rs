@@ -0,0 +1,186 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Read, Write},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{OpenOptionsExt, PermissionsExt},
+    },
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::Context;
+
+use crate::{interrupt, progress::Progress, watchdog, ExportEntry};
+
+const PREAMBLE: &str = r#"#!/bin/sh
+# Generated by track export script.
+#
+# Usage: sh restore.sh [--force] [DEST]
+#
+# Creates the directories of the exported files under DEST, / by default. Running it again is safe,
+# existing files are kept unless --force is given.
+set -eu
+
+force=0
+dest=/
+for arg; do
+    case $arg in
+        --force) force=1 ;;
+        -*) echo "unknown option $arg" >&2; exit 2 ;;
+        *) dest=$arg ;;
+    esac
+done
+
+"#;
+
+const RESTORE_FN: &str = r#"# restore PATH MODE, the base64 content of the file is read from stdin.
+restore() {
+    if [ -e "$1" ] && [ "$force" = 0 ]; then
+        echo "skipping existing $1, use --force to overwrite" >&2
+        cat >/dev/null
+        return 0
+    fi
+    base64 -d >"$1.track-tmp"
+    chmod "$2" "$1.track-tmp"
+    mv -f "$1.track-tmp" "$1"
+}
+
+"#;
+
+const CHECK_FN: &str = r#"# check PATH, reports files which still have to be restored by other means.
+check() {
+    if [ ! -e "$1" ]; then
+        echo "missing $1" >&2
+    fi
+}
+
+"#;
+
+const HEREDOC_END: &str = "TRACK_EOF";
+
+/// Quote bytes for a POSIX shell, single quotes preserve everything but single quotes themselves.
+fn sh_quote(bytes: &[u8]) -> Vec<u8> {
+    let mut quoted = Vec::with_capacity(bytes.len() + 2);
+    quoted.push(b'\'');
+    for &b in bytes {
+        if b == b'\'' {
+            quoted.extend_from_slice(b"'\\''");
+        } else {
+            quoted.push(b);
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+/// Write `"$dest"/'name'`, a path under the destination chosen when running the script.
+fn write_dest_path(out: &mut impl Write, name: &Path) -> io::Result<()> {
+    out.write_all(b"\"$dest\"/")?;
+    out.write_all(&sh_quote(name.as_os_str().as_bytes()))
+}
+
+/// Fill `buf` as much as possible, only returning less at the end of the input.
+fn read_full(input: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match input.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(len)
+}
+
+/// Encode the input as base64 in lines of 76 characters, like `base64` does.
+fn write_base64(out: &mut impl Write, input: &mut impl Read) -> io::Result<()> {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    const LINE: usize = 57;
+
+    let mut buf = vec![0; LINE * 1024];
+    loop {
+        let len = read_full(input, &mut buf)?;
+        for line in buf[..len].chunks(LINE) {
+            let mut encoded = Vec::with_capacity(77);
+            for chunk in line.chunks(3) {
+                let n = (chunk[0] as u32) << 16
+                    | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+                    | *chunk.get(2).unwrap_or(&0) as u32;
+                for i in 0..4 {
+                    if i <= chunk.len() {
+                        encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize]);
+                    } else {
+                        encoded.push(b'=');
+                    }
+                }
+            }
+            encoded.push(b'\n');
+            out.write_all(&encoded)?;
+        }
+        if len < buf.len() {
+            return Ok(());
+        }
+    }
+}
+
+/// Write a shell script recreating the exported files, for systems without track to restore them.
+///
+/// Without `embed` the script only creates the directories and lists the files it doesn't contain.
+pub fn export_script(
+    path: &Path,
+    entries: &[ExportEntry],
+    embed: bool,
+    file_timeout: Option<Duration>,
+    progress: &Progress,
+) -> anyhow::Result<()> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o755)
+        .open(path)
+        .context(format!("could not create {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+
+    out.write_all(PREAMBLE.as_bytes())?;
+    out.write_all(if embed { RESTORE_FN } else { CHECK_FN }.as_bytes())?;
+
+    let dirs: BTreeSet<&Path> = entries
+        .iter()
+        .filter_map(|entry| entry.name.parent())
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .collect();
+    for dir in dirs {
+        out.write_all(b"mkdir -p ")?;
+        write_dest_path(&mut out, dir)?;
+        out.write_all(b"\n")?;
+    }
+    out.write_all(b"\n")?;
+
+    for entry in entries {
+        interrupt::check()?;
+        if embed {
+            if watchdog::skipped(watchdog::probe(file_timeout, &entry.path))? {
+                continue;
+            }
+            let mut input = File::open(&entry.path).context(format!("could not open {}", entry.path.display()))?;
+            let mode = fs::metadata(&entry.path)?.permissions().mode() & 0o7777;
+            out.write_all(b"restore ")?;
+            write_dest_path(&mut out, &entry.name)?;
+            writeln!(out, " {:o} <<'{}'", mode, HEREDOC_END)?;
+            write_base64(&mut out, &mut input).context(format!("could not read {}", entry.path.display()))?;
+            writeln!(out, "{}", HEREDOC_END)?;
+        } else {
+            out.write_all(b"check ")?;
+            write_dest_path(&mut out, &entry.name)?;
+            out.write_all(b"\n")?;
+        }
+        progress.file(&entry.path);
+    }
+
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,366 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use clap::Parser;
+
+use crate::{
+    addable_path,
+    exit::{self, Exit},
+    export_matches, interrupt,
+    json::{self, Value},
+    pool::Pool,
+    scan_matches, undo, ExportArgs, FilterArgs, Glob, Metadata, PathsDB,
+};
+
+/// How often the socket and the client are checked for a shutdown signal while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Error codes defined by JSON-RPC 2.0.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Code of the failures of the methods themselves, like a path which can't be added.
+const SERVER_ERROR: i64 = -32000;
+
+/// Arguments of the matched method, the filter options of the matched command.
+#[derive(Debug, Parser)]
+#[clap(name = "matched", no_binary_name = true)]
+struct MatchedParams {
+    #[clap(flatten)]
+    filter: FilterArgs,
+}
+
+/// Arguments of the export method, the same as the export command.
+#[derive(Debug, Parser)]
+#[clap(name = "export", no_binary_name = true)]
+struct ExportParams {
+    #[clap(flatten)]
+    export: ExportArgs,
+}
+
+/// Error of a request, sent back as the error member of the response.
+struct RpcError {
+    code: i64,
+    message: String,
+    /// Exit code the command would have failed with.
+    exit: Option<u8>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> RpcError {
+        RpcError {
+            code,
+            message: message.into(),
+            exit: None,
+        }
+    }
+
+    fn invalid_params(message: impl Into<String>) -> RpcError {
+        RpcError::new(INVALID_PARAMS, message)
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(err: anyhow::Error) -> RpcError {
+        RpcError {
+            code: SERVER_ERROR,
+            message: format!("{:#}", err),
+            exit: Some(exit::code(&err)),
+        }
+    }
+}
+
+/// Answer requests on a Unix socket until SIGINT or SIGTERM, keeping the database open between them.
+///
+/// Requests are JSON-RPC 2.0 objects, one per line, answered with one response per line. Clients
+/// are served one at a time, the next one waits until the current one disconnects.
+pub fn serve(paths_db: &PathsDB, socket: &Path, pool: &Pool, wait: bool) -> anyhow::Result<()> {
+    let listener = bind(socket)?;
+    interrupt::install();
+    let result = accept_clients(&listener, paths_db, pool, wait);
+    fs::remove_file(socket).context(format!("could not remove {}", socket.display()))?;
+    result
+}
+
+/// Listen on the socket, replacing the one left behind by a server which didn't shut down.
+fn bind(socket: &Path) -> anyhow::Result<UnixListener> {
+    if let Ok(meta) = fs::symlink_metadata(socket) {
+        if !meta.file_type().is_socket() {
+            return Err(anyhow!("{} exists and isn't a socket", socket.display())).context(Exit::Usage);
+        }
+        if UnixStream::connect(socket).is_ok() {
+            return Err(anyhow!("another server is listening on {}", socket.display())).context(Exit::Usage);
+        }
+        fs::remove_file(socket).context(format!("could not remove {}", socket.display()))?;
+    }
+    let listener = UnixListener::bind(socket).context(format!("could not listen on {}", socket.display()))?;
+    // Accepting doesn't return when a signal is caught, the listener is polled instead.
+    listener.set_nonblocking(true)?;
+    eprintln!("Listening on {}", socket.display());
+    Ok(listener)
+}
+
+fn accept_clients(listener: &UnixListener, paths_db: &PathsDB, pool: &Pool, wait: bool) -> anyhow::Result<()> {
+    while !interrupt::requested() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if let Err(err) = session(stream, paths_db, pool, wait) {
+                    eprintln!("Warning: client dropped: {:#}", err);
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err).context("could not accept a client"),
+        }
+    }
+    eprintln!("Shutting down");
+    Ok(())
+}
+
+/// Answer the requests of a client until it disconnects or a signal is caught.
+fn session(stream: UnixStream, paths_db: &PathsDB, pool: &Pool, wait: bool) -> anyhow::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut line = Vec::new();
+    while !interrupt::requested() {
+        // A read timing out keeps what it read so far in the line.
+        let at_end = match reader.read_until(b'\n', &mut line) {
+            Ok(_) => !line.ends_with(b"\n"),
+            Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        if !line.trim_ascii().is_empty() {
+            if let Some(response) = respond(&line, paths_db, pool, wait) {
+                writeln!(writer, "{}", response)?;
+            }
+        }
+        if at_end {
+            break;
+        }
+        line.clear();
+    }
+    Ok(())
+}
+
+/// Response to a request, none for notifications which don't have an id.
+fn respond(line: &[u8], paths_db: &PathsDB, pool: &Pool, wait: bool) -> Option<Value> {
+    let request = match std::str::from_utf8(line)
+        .map_err(anyhow::Error::from)
+        .and_then(json::parse)
+    {
+        Ok(request) => request,
+        Err(err) => return Some(response(Value::Null, Err(RpcError::new(PARSE_ERROR, err.to_string())))),
+    };
+    let id = request.get("id").cloned();
+    let result = match (request.get("method"), request.get("params")) {
+        (Some(Value::String(method)), params) => {
+            let params = params.cloned().unwrap_or(Value::Object(Vec::new()));
+            call(method, &params, paths_db, pool, wait)
+        }
+        _ => Err(RpcError::new(INVALID_REQUEST, "the request has no method")),
+    };
+    id.map(|id| response(id, result))
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json::object([("jsonrpc", "2.0".into()), ("id", id), ("result", result)]),
+        Err(err) => {
+            let mut error = vec![
+                ("code".to_string(), Value::Number(err.code)),
+                ("message".to_string(), err.message.into()),
+            ];
+            if let Some(exit) = err.exit {
+                error.push(("data".to_string(), json::object([("exit", u64::from(exit).into())])));
+            }
+            json::object([("jsonrpc", "2.0".into()), ("id", id), ("error", Value::Object(error))])
+        }
+    }
+}
+
+fn call(method: &str, params: &Value, paths_db: &PathsDB, pool: &Pool, wait: bool) -> Result<Value, RpcError> {
+    if !matches!(params, Value::Object(_)) {
+        return Err(RpcError::invalid_params("params must be an object"));
+    }
+    match method {
+        "add" => add(params, paths_db, wait),
+        "rm" => rm(params, paths_db, wait),
+        "ls" => Ok(Value::Array(
+            paths_db.list()?.iter().map(|path| Value::path(path)).collect(),
+        )),
+        "matched" => {
+            let filter = parse_args::<MatchedParams>(params)?.filter;
+            if filter.exclude_stdin {
+                return Err(RpcError::invalid_params(
+                    "--exclude-stdin would read the stdin of the server",
+                ));
+            }
+            let matches = scan_matches(paths_db, &paths_db.list()?, &filter.filters(paths_db)?, false)?;
+            Ok(Value::Array(matches.iter().map(|path| Value::path(path)).collect()))
+        }
+        "export" => export(params, paths_db, pool, wait),
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("unknown method {}", method))),
+    }
+}
+
+/// Add the paths, with `{"paths": [...]}` and the options of the add command as fields, like
+/// `{"tags": ["work"], "relative": true}`. Returns the paths newly tracked, those tracked already and
+/// the ones which couldn't be added with their error.
+fn add(params: &Value, paths_db: &PathsDB, wait: bool) -> Result<Value, RpcError> {
+    let paths = path_list(params)?;
+    let (force, canonicalize, relative) = (
+        flag(params, "force")?,
+        flag(params, "canonicalize")?,
+        flag(params, "relative")?,
+    );
+    let metadata = Metadata {
+        tags: strings(params, "tags")?,
+        excludes: strings(params, "excludes")?
+            .iter()
+            .map(|pattern| pattern.parse::<Glob>())
+            .collect::<anyhow::Result<_>>()
+            .map_err(|err| RpcError::invalid_params(err.to_string()))?,
+        replace: flag(params, "replace")?,
+    };
+    let _lock = paths_db.lock(wait)?;
+    let tracked: HashSet<PathBuf> = paths_db.list()?.into_iter().collect();
+    let (mut added, mut already, mut failed) = (Vec::new(), Vec::new(), Vec::new());
+    let mut addable = Vec::with_capacity(paths.len());
+    for path in &paths {
+        let stored = match addable_path(paths_db, path, force, canonicalize, relative) {
+            Ok(stored) => stored,
+            Err(err) => {
+                failed.push(json::object([
+                    ("path", Value::path(path)),
+                    ("error", format!("{:#}", err).into()),
+                ]));
+                continue;
+            }
+        };
+        let resolved = match stored.1 {
+            Some(base) => base.dir()?.join(&stored.0),
+            None => stored.0.clone(),
+        };
+        if tracked.contains(&resolved) {
+            already.push(Value::path(&resolved));
+        } else {
+            added.push(Value::path(&resolved));
+        }
+        addable.push(stored);
+    }
+    paths_db.add(&addable, &metadata)?;
+    Ok(json::object([
+        ("added", Value::Array(added)),
+        ("already_tracked", Value::Array(already)),
+        ("failed", Value::Array(failed)),
+    ]))
+}
+
+/// Stop tracking the paths of `{"paths": [...]}`, which undo can track again, returning the ones
+/// which were tracked.
+fn rm(params: &Value, paths_db: &PathsDB, wait: bool) -> Result<Value, RpcError> {
+    let paths = path_list(params)?;
+    let _lock = paths_db.lock(wait)?;
+    let tracked: HashSet<PathBuf> = paths_db.list()?.into_iter().collect();
+    let tx = paths_db.handle.unchecked_transaction().map_err(anyhow::Error::from)?;
+    let operation = undo::start(paths_db, "rm")?;
+    let mut removed = Vec::new();
+    for path in &paths {
+        paths_db.rm(path, operation)?;
+        if tracked.contains(path) {
+            removed.push(Value::path(path));
+        }
+    }
+    tx.commit().map_err(anyhow::Error::from)?;
+    Ok(json::object([("removed", Value::Array(removed))]))
+}
+
+/// Export like the export command, with its arguments in `{"args": [...]}`, like
+/// `["tar", "/backups/home.tar.gz", "--changed"]`. Returns the number of files exported.
+fn export(params: &Value, paths_db: &PathsDB, pool: &Pool, wait: bool) -> Result<Value, RpcError> {
+    let mut export = parse_args::<ExportParams>(params)?.export;
+    export.resolve_destination()?;
+    if export.filter.exclude_stdin {
+        return Err(RpcError::invalid_params(
+            "--exclude-stdin would read the stdin of the server",
+        ));
+    }
+    if export.targets().any(|(_, path)| !path.is_absolute()) {
+        return Err(RpcError::invalid_params(
+            "export destinations must be absolute, the server doesn't write to its stdout",
+        ));
+    }
+    let count = export_matches(paths_db, &paths_db.list()?, &export, pool, wait)?;
+    Ok(json::object([("exported", (count as u64).into())]))
+}
+
+/// Parse the `args` field, an array of command line arguments.
+fn parse_args<P: Parser>(params: &Value) -> Result<P, RpcError> {
+    let args: Vec<OsString> = strings(params, "args")?.into_iter().map(OsString::from).collect();
+    P::try_parse_from(args).map_err(|err| RpcError::invalid_params(err.to_string().trim_end()))
+}
+
+/// The `paths` field, an array of absolute paths as strings or `{"bytes": [...]}` objects.
+///
+/// The current directory of the server has nothing to do with the client's, relative paths are refused.
+fn path_list(params: &Value) -> Result<Vec<PathBuf>, RpcError> {
+    let items = match params.get("paths") {
+        Some(Value::Array(items)) => items,
+        _ => return Err(RpcError::invalid_params("paths must be an array of paths")),
+    };
+    let mut paths = Vec::with_capacity(items.len());
+    for item in items {
+        let path = item
+            .to_path()
+            .ok_or_else(|| RpcError::invalid_params("paths must be strings or {\"bytes\": [...]} objects"))?;
+        if !path.is_absolute() {
+            return Err(RpcError::invalid_params(format!("{} isn't absolute", path.display())));
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+fn flag(params: &Value, name: &str) -> Result<bool, RpcError> {
+    match params.get(name) {
+        None => Ok(false),
+        Some(Value::Bool(b)) => Ok(*b),
+        Some(_) => Err(RpcError::invalid_params(format!("{} must be a boolean", name))),
+    }
+}
+
+fn strings(params: &Value, name: &str) -> Result<Vec<String>, RpcError> {
+    match params.get(name) {
+        None => Ok(Vec::new()),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| match item {
+                Value::String(s) => Ok(s.clone()),
+                _ => Err(RpcError::invalid_params(format!(
+                    "{} must be an array of strings",
+                    name
+                ))),
+            })
+            .collect(),
+        Some(_) => Err(RpcError::invalid_params(format!(
+            "{} must be an array of strings",
+            name
+        ))),
+    }
+}
@@ -0,0 +1,298 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::{ArgMatches, ValueSource};
+use flate2::Compression;
+
+use crate::{
+    absolute,
+    exit::Exit,
+    glob,
+    json::{self, Value},
+    output::ColorMode,
+    pool::{self, Pool},
+    Args, FilterArgs, PathsDB,
+};
+
+/// Where the value of a setting comes from, the command line wins over the environment.
+#[derive(Debug, Clone, Copy)]
+pub enum Source {
+    CommandLine,
+    Environment,
+    Default,
+}
+
+impl Source {
+    /// Source of the argument `id`.
+    fn of(matches: &ArgMatches, id: &str) -> Source {
+        match matches.value_source(id) {
+            Some(ValueSource::CommandLine) => Source::CommandLine,
+            Some(ValueSource::EnvVariable) => Source::Environment,
+            _ => Source::Default,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Source::CommandLine => "command line",
+            Source::Environment => "environment",
+            Source::Default => "default",
+        }
+    }
+}
+
+/// A setting along with where its value comes from.
+#[derive(Debug, Clone)]
+pub struct Sourced<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+/// The global settings, resolved from the arguments and the environment before running any command.
+#[derive(Debug)]
+pub struct Settings {
+    /// Absolute path of the database, none when it's in memory.
+    pub db: Sourced<Option<PathBuf>>,
+    pub wait: Sourced<bool>,
+    pub jobs: Sourced<usize>,
+    pub max_open_files: Sourced<usize>,
+    pub color: Sourced<ColorMode>,
+}
+
+impl Settings {
+    pub fn resolve(args: &Args, matches: &ArgMatches) -> anyhow::Result<Settings> {
+        // The tracked paths of --paths-file are loaded in memory, the persistent database isn't needed.
+        let db = if args.memory || args.paths_file.is_some() {
+            Sourced {
+                value: None,
+                source: Source::of(matches, if args.memory { "memory" } else { "paths-file" }),
+            }
+        } else {
+            let path = match &args.db {
+                Some(path) => path.clone(),
+                None => PathsDB::default_path()?,
+            };
+            Sourced {
+                value: Some(absolute(&path)?.into_owned()),
+                source: Source::of(matches, "db"),
+            }
+        };
+        let jobs = match args.jobs {
+            Some(0) => return Err(anyhow::anyhow!("--jobs must be at least 1")).context(Exit::Usage),
+            Some(jobs) => jobs,
+            None => Pool::default_jobs(),
+        };
+        let max_open_files = match args.max_open_files {
+            Some(0) => return Err(anyhow::anyhow!("--max-open-files must be at least 1")).context(Exit::Usage),
+            Some(max) => max,
+            None => pool::default_max_open_files(),
+        };
+        Ok(Settings {
+            db,
+            wait: Sourced {
+                value: args.wait,
+                source: Source::of(matches, "wait"),
+            },
+            jobs: Sourced {
+                value: jobs,
+                source: Source::of(matches, "jobs"),
+            },
+            max_open_files: Sourced {
+                value: max_open_files,
+                source: Source::of(matches, "max-open-files"),
+            },
+            color: Sourced {
+                value: args.color,
+                source: Source::of(matches, "color"),
+            },
+        })
+    }
+}
+
+/// One line of `config show`.
+struct Row {
+    name: &'static str,
+    text: String,
+    value: Value,
+    source: Source,
+}
+
+/// Print the resolved settings and the filters given along, with the source of each.
+pub fn show(
+    settings: &Settings,
+    filter: &FilterArgs,
+    compress_threads: Option<usize>,
+    matches: &ArgMatches,
+    paths_db: &PathsDB,
+    json: bool,
+) {
+    let mut rows = vec![
+        match &settings.db.value {
+            Some(path) => Row {
+                name: "database",
+                text: path.display().to_string(),
+                value: Value::path(path),
+                source: settings.db.source,
+            },
+            None => Row {
+                name: "database",
+                text: "in memory".to_string(),
+                value: Value::Null,
+                source: settings.db.source,
+            },
+        },
+        Row {
+            name: "wait",
+            text: settings.wait.value.to_string(),
+            value: settings.wait.value.into(),
+            source: settings.wait.source,
+        },
+        Row {
+            name: "jobs",
+            text: settings.jobs.value.to_string(),
+            value: (settings.jobs.value as u64).into(),
+            source: settings.jobs.source,
+        },
+        Row {
+            name: "max open files",
+            text: match settings.max_open_files.value {
+                usize::MAX => "unlimited".to_string(),
+                max => max.to_string(),
+            },
+            value: match settings.max_open_files.value {
+                usize::MAX => Value::Null,
+                max => (max as u64).into(),
+            },
+            source: settings.max_open_files.source,
+        },
+        Row {
+            name: "color",
+            text: settings.color.value.as_str().to_string(),
+            value: settings.color.value.as_str().into(),
+            source: settings.color.source,
+        },
+    ];
+
+    let mut skipped_dirs = vec![OsString::from(".git")];
+    skipped_dirs.extend(filter.skip_dirs.iter().cloned());
+    rows.push(list_row(
+        "skipped dirs",
+        skipped_dirs.iter().map(Path::new),
+        Source::of(matches, "skip-dirs"),
+    ));
+    rows.push(list_row(
+        "excludes",
+        filter.exclude.iter().map(|glob| Path::new(glob.as_str())),
+        Source::of(matches, "exclude"),
+    ));
+    rows.push(Row {
+        name: "skip caches",
+        text: filter.skip_caches.to_string(),
+        value: filter.skip_caches.into(),
+        source: Source::of(matches, "skip-caches"),
+    });
+    rows.push(Row {
+        name: "ignore case",
+        text: filter.ignore_case.to_string(),
+        value: filter.ignore_case.into(),
+        source: Source::of(matches, "ignore-case"),
+    });
+    rows.push(Row {
+        name: "skip git lfs",
+        text: filter.skip_git_lfs.to_string(),
+        value: filter.skip_git_lfs.into(),
+        source: Source::of(matches, "skip-git-lfs"),
+    });
+    let db_files = if filter.include_db {
+        Vec::new()
+    } else {
+        paths_db.artifacts()
+    };
+    rows.push(list_row(
+        "excluded db files",
+        db_files.iter().map(PathBuf::as_path),
+        Source::of(matches, "include-db"),
+    ));
+    rows.push(Row {
+        name: "symlinked dirs",
+        text: filter.symlinked_dirs.as_str().to_string(),
+        value: filter.symlinked_dirs.as_str().into(),
+        source: Source::of(matches, "symlinked-dirs"),
+    });
+    // The gzip level isn't configurable, only the number of threads compressing.
+    let compression = match compress_threads.unwrap_or(1) {
+        1 => format!("gzip level {}", Compression::default().level()),
+        threads => format!("gzip level {} on {} threads", Compression::default().level(), threads),
+    };
+    rows.push(Row {
+        name: "compression",
+        text: compression.clone(),
+        value: compression.into(),
+        source: Source::of(matches, "compress-threads"),
+    });
+
+    if json {
+        let fields = rows
+            .into_iter()
+            .map(|row| {
+                let setting = json::object([("value", row.value), ("source", row.source.as_str().into())]);
+                (row.name.to_string(), setting)
+            })
+            .collect();
+        println!("{}", json::envelope("config show", Value::Object(fields)));
+        return;
+    }
+    let width = rows.iter().map(|row| row.name.len()).max().unwrap_or(0);
+    for row in rows {
+        println!(
+            "{:width$}  {} ({})",
+            row.name,
+            row.text,
+            row.source.as_str(),
+            width = width
+        );
+    }
+}
+
+/// Print the patterns of the built-in presets.
+pub fn show_presets(json: bool) {
+    if json {
+        let presets = glob::PRESETS
+            .iter()
+            .map(|preset| {
+                let patterns = preset.patterns.iter().map(|&pattern| pattern.into()).collect();
+                (preset.name.to_string(), Value::Array(patterns))
+            })
+            .collect();
+        println!(
+            "{}",
+            json::envelope("config show --list-presets", Value::Object(presets))
+        );
+        return;
+    }
+    for preset in glob::PRESETS {
+        println!("{}: {}", preset.name, preset.patterns.join(" "));
+    }
+}
+
+fn list_row<'a>(name: &'static str, items: impl Iterator<Item = &'a Path>, source: Source) -> Row {
+    let items: Vec<&Path> = items.collect();
+    let text = if items.is_empty() {
+        "none".to_string()
+    } else {
+        items
+            .iter()
+            .map(|item| item.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    Row {
+        name,
+        text,
+        value: Value::Array(items.into_iter().map(Value::path).collect()),
+        source,
+    }
+}
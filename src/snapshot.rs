@@ -0,0 +1,286 @@
+use std::{
+    fs,
+    path::Path,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context};
+
+const PREFIX: &str = "track-";
+const SUFFIX: &str = ".tar.gz";
+
+/// Days since the epoch of a date of the proleptic Gregorian calendar.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Date of the proleptic Gregorian calendar of a number of days since the epoch.
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// File name of a snapshot taken at `time`, like `track-20240131T235959Z.tar.gz` in UTC.
+pub fn file_name(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs()) as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let secs_of_day = secs.rem_euclid(86400);
+    format!(
+        "{}{:04}{:02}{:02}T{:02}{:02}{:02}Z{}",
+        PREFIX,
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        SUFFIX
+    )
+}
+
+/// Seconds since the epoch embedded in the name of a snapshot, if it is one.
+fn parse_file_name(name: &str) -> Option<i64> {
+    let stamp = name.strip_prefix(PREFIX)?.strip_suffix(SUFFIX)?.as_bytes();
+    if stamp.len() != 16 || stamp[8] != b'T' || stamp[15] != b'Z' {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = &stamp[range];
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        std::str::from_utf8(digits).ok()?.parse().ok()
+    };
+    let (year, month, day) = (number(0..4)?, number(4..6)?, number(6..8)?);
+    let (hour, minute, second) = (number(9..11)?, number(11..13)?, number(13..15)?);
+    let days = days_from_civil(year, month, day);
+    // Reject dates like February 30th, which don't survive the round trip.
+    if civil_from_days(days) != (year, month, day) || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Length of the periods snapshots are kept for, weeks start on monday.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Period {
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Period {
+    /// Identifier of the period containing a time, in seconds since the epoch.
+    fn key(self, secs: i64) -> i64 {
+        let days = secs.div_euclid(86400);
+        match self {
+            Period::Hourly => secs.div_euclid(3600),
+            Period::Daily => days,
+            // The epoch was a thursday.
+            Period::Weekly => (days + 3).div_euclid(7),
+            Period::Monthly => {
+                let (year, month, _) = civil_from_days(days);
+                year * 12 + month
+            }
+            Period::Yearly => civil_from_days(days).0,
+        }
+    }
+}
+
+impl FromStr for Period {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "hourly" => Period::Hourly,
+            "daily" => Period::Daily,
+            "weekly" => Period::Weekly,
+            "monthly" => Period::Monthly,
+            "yearly" => Period::Yearly,
+            _ => bail!("Unknown retention period {}", s),
+        })
+    }
+}
+
+/// Grandfather-father-son retention, like `daily:7,weekly:4,monthly:12`.
+///
+/// For every period, the most recent snapshot of each of the last N periods having one is kept. A
+/// snapshot is deleted only when no period keeps it.
+#[derive(Debug, Clone)]
+pub struct Retention {
+    periods: Vec<(Period, usize)>,
+}
+
+impl Retention {
+    /// Which of the snapshots taken at `times`, in seconds since the epoch, are kept.
+    fn retained(&self, times: &[i64]) -> Vec<bool> {
+        let mut newest_first: Vec<usize> = (0..times.len()).collect();
+        newest_first.sort_by_key(|&i| std::cmp::Reverse(times[i]));
+        let mut kept = vec![false; times.len()];
+        for &(period, count) in &self.periods {
+            let mut last_key = None;
+            let mut periods = 0;
+            for &i in &newest_first {
+                let key = period.key(times[i]);
+                if last_key == Some(key) {
+                    continue;
+                }
+                if periods == count {
+                    break;
+                }
+                kept[i] = true;
+                periods += 1;
+                last_key = Some(key);
+            }
+        }
+        kept
+    }
+}
+
+impl FromStr for Retention {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut periods = Vec::new();
+        for rule in s.split(',') {
+            let (period, count) = match rule.split_once(':') {
+                Some(rule) => rule,
+                None => bail!("Retention rule {} is not like daily:7", rule),
+            };
+            let count = count
+                .parse()
+                .context(format!("invalid count in retention rule {}", rule))?;
+            periods.push((period.parse()?, count));
+        }
+        Ok(Retention { periods })
+    }
+}
+
+/// Delete the snapshots of `dir` which `retention` doesn't keep, files not named like snapshots are left alone.
+pub fn prune(dir: &Path, retention: &Retention) -> anyhow::Result<()> {
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(dir).context(format!("could not read {}", dir.display()))? {
+        let entry = entry.context(format!("could not read {}", dir.display()))?;
+        let time = entry.file_name().to_str().and_then(parse_file_name);
+        if let Some(time) = time {
+            if entry.file_type()?.is_file() {
+                snapshots.push((entry.path(), time));
+            }
+        }
+    }
+    snapshots.sort_by_key(|(_, time)| *time);
+    let times: Vec<i64> = snapshots.iter().map(|(_, time)| *time).collect();
+    for ((path, _), kept) in snapshots.iter().zip(retention.retained(&times)) {
+        if !kept {
+            fs::remove_file(path).context(format!("could not delete {}", path.display()))?;
+            println!("Deleted {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{days_from_civil, file_name, parse_file_name, Retention};
+
+    /// Seconds since the epoch of a time in UTC.
+    fn at(year: i64, month: i64, day: i64, hour: i64, minute: i64) -> i64 {
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60
+    }
+
+    fn retained(retention: &str, times: &[i64]) -> Vec<bool> {
+        retention.parse::<Retention>().unwrap().retained(times)
+    }
+
+    #[test]
+    fn file_names_round_trip() {
+        let time = at(2024, 2, 29, 23, 59) + 58;
+        let name = file_name(UNIX_EPOCH + Duration::from_secs(time as u64));
+        assert_eq!(name, "track-20240229T235958Z.tar.gz");
+        assert_eq!(parse_file_name(&name), Some(time));
+        for name in [
+            "track-20230229T000000Z.tar.gz",
+            "track-20240101T240000Z.tar.gz",
+            "track-2024010T1000000Z.tar.gz",
+            "other-20240101T000000Z.tar.gz",
+            ".track-20240101T000000Z.tar.gz.partial",
+        ] {
+            assert_eq!(parse_file_name(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn the_newest_snapshot_of_each_period_is_kept() {
+        let times = [
+            at(2024, 1, 1, 9, 0),
+            at(2024, 1, 1, 18, 0),
+            at(2024, 1, 2, 9, 0),
+            at(2024, 1, 3, 9, 0),
+            at(2024, 1, 3, 18, 0),
+        ];
+        assert_eq!(retained("daily:2", &times), [false, false, true, false, true]);
+        assert_eq!(retained("daily:7", &times), [false, true, true, false, true]);
+        assert_eq!(retained("hourly:1", &times), [false, false, false, false, true]);
+        // Given in any order.
+        let mut reversed = times;
+        reversed.reverse();
+        assert_eq!(retained("daily:2", &reversed), [true, false, true, false, false]);
+    }
+
+    #[test]
+    fn periods_keeping_the_same_snapshot_dont_keep_more() {
+        // Sunday, then twice on the monday after, 2024-01-01 being a monday.
+        let times = [at(2024, 1, 7, 12, 0), at(2024, 1, 8, 10, 0), at(2024, 1, 8, 12, 0)];
+        // The last snapshot is both the daily and the newest weekly one, the next week keeps sunday's.
+        assert_eq!(retained("daily:1,weekly:2", &times), [true, false, true]);
+        assert_eq!(retained("daily:1,weekly:1", &times), [false, false, true]);
+        assert_eq!(retained("weekly:2,monthly:1", &times), [true, false, true]);
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        let sunday_night = at(2024, 1, 14, 23, 59);
+        let monday_midnight = at(2024, 1, 15, 0, 0);
+        assert_eq!(retained("weekly:1", &[sunday_night, monday_midnight]), [false, true]);
+        assert_eq!(retained("weekly:2", &[sunday_night, monday_midnight]), [true, true]);
+        // Monday to sunday is a single week.
+        let monday = at(2024, 1, 8, 0, 0);
+        assert_eq!(retained("weekly:2", &[monday, sunday_night]), [false, true]);
+    }
+
+    #[test]
+    fn months_and_years_follow_the_calendar() {
+        let times = [at(2023, 12, 31, 23, 0), at(2024, 1, 31, 12, 0), at(2024, 2, 1, 0, 0)];
+        assert_eq!(retained("monthly:2", &times), [false, true, true]);
+        assert_eq!(retained("yearly:5", &times), [true, false, true]);
+    }
+
+    #[test]
+    fn invalid_retentions() {
+        for (retention, error) in [
+            ("daily", "Retention rule daily is not like daily:7"),
+            ("fortnightly:2", "Unknown retention period fortnightly"),
+            ("daily:x", "invalid count in retention rule daily:x"),
+        ] {
+            assert_eq!(retention.parse::<Retention>().unwrap_err().to_string(), error);
+        }
+    }
+}
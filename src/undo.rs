@@ -0,0 +1,138 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    io::{self, Write},
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context};
+use rusqlite::OptionalExtension;
+
+use crate::{
+    exit::Exit,
+    fold_case,
+    output::{self, PathStyle},
+    path_key, Base, PathsDB,
+};
+
+/// Number of removals kept, the older ones can't be undone.
+const HISTORY: i64 = 10;
+
+/// Start recording a command removing tracked paths, returning the id its removals are recorded under.
+///
+/// Operations which removed nothing are forgotten, so they don't use up the history.
+pub fn start(paths_db: &PathsDB, command: &str) -> anyhow::Result<i64> {
+    let handle = &paths_db.handle;
+    handle.execute(
+        "DELETE FROM undo_operations WHERE id NOT IN (SELECT operation FROM undo_paths)",
+        [],
+    )?;
+    let removed_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    handle.execute(
+        "INSERT INTO undo_operations (command, removed_at) VALUES (?, ?)",
+        rusqlite::params![command, removed_at],
+    )?;
+    let operation = handle.last_insert_rowid();
+    handle.execute("DELETE FROM undo_operations WHERE id <= ?", [operation - HISTORY])?;
+    Ok(operation)
+}
+
+/// Record the row of a path as stored along with its tags and excludes, before it's deleted.
+pub fn record(paths_db: &PathsDB, operation: i64, path: &[u8], base: Option<&str>) -> anyhow::Result<()> {
+    let handle = &paths_db.handle;
+    let recorded = handle.execute(
+        "INSERT INTO undo_paths (operation, path, base, added_at, kind)
+         SELECT ?, path, base, added_at, kind FROM paths WHERE path = ? AND base IS ?",
+        rusqlite::params![operation, path, base],
+    )?;
+    if recorded == 0 {
+        return Ok(());
+    }
+    handle.execute(
+        "INSERT INTO undo_tags (operation, path, tag) SELECT ?, path, tag FROM tags WHERE path = ?",
+        rusqlite::params![operation, path],
+    )?;
+    handle.execute(
+        "INSERT INTO undo_excludes (operation, path, pattern) SELECT ?, path, pattern FROM excludes WHERE path = ?",
+        rusqlite::params![operation, path],
+    )?;
+    Ok(())
+}
+
+/// Track again the paths removed by the most recent operation, with their tags and excludes.
+///
+/// Paths tracked again since they were removed are left as they are.
+pub fn undo(paths_db: &PathsDB, style: PathStyle) -> anyhow::Result<()> {
+    let tx = paths_db.handle.unchecked_transaction()?;
+    let last: Option<(i64, String)> = tx
+        .query_row(
+            "SELECT id, command FROM undo_operations WHERE id IN (SELECT operation FROM undo_paths)
+             ORDER BY id DESC LIMIT 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let (operation, command) = match last {
+        Some(last) => last,
+        None => return Err(anyhow!("nothing to undo")).context(Exit::Usage),
+    };
+
+    // The same path may have been tracked again relative or absolute, once resolved they are the same.
+    let case_insensitive = paths_db.case_insensitive()?;
+    let tracked: HashSet<PathBuf> = paths_db
+        .list()?
+        .iter()
+        .map(|path| path_key(path, case_insensitive))
+        .collect();
+    let mut restored = Vec::new();
+    {
+        let mut stmt =
+            tx.prepare("SELECT path, base, added_at, kind FROM undo_paths WHERE operation = ? ORDER BY path")?;
+        let mut rows = stmt.query([operation])?;
+        while let Some(row) = rows.next()? {
+            let path: Vec<u8> = row.get(0)?;
+            let base: Option<String> = row.get(1)?;
+            let added_at: Option<i64> = row.get(2)?;
+            let kind: Option<String> = row.get(3)?;
+            let stored = PathBuf::from(OsString::from_vec(path.clone()));
+            let folded = fold_case(&stored);
+            let resolved = match &base {
+                Some(base) => base.parse::<Base>()?.dir()?.join(stored),
+                None => stored,
+            };
+            if tracked.contains(&path_key(&resolved, case_insensitive))
+                || tx.execute(
+                    "INSERT OR IGNORE INTO paths (path, base, added_at, kind, folded) VALUES (?, ?, ?, ?, ?)",
+                    rusqlite::params![path, base, added_at, kind, folded.as_os_str().as_bytes()],
+                )? == 0
+            {
+                eprintln!("{} is tracked again already, left as it is", resolved.display());
+                continue;
+            }
+            tx.execute(
+                "INSERT OR IGNORE INTO tags (path, tag) SELECT path, tag FROM undo_tags WHERE operation = ? AND path = ?",
+                rusqlite::params![operation, path],
+            )?;
+            tx.execute(
+                "INSERT OR IGNORE INTO excludes (path, pattern)
+                 SELECT path, pattern FROM undo_excludes WHERE operation = ? AND path = ?",
+                rusqlite::params![operation, path],
+            )?;
+            restored.push(resolved);
+        }
+    }
+    tx.execute("DELETE FROM undo_operations WHERE id = ?", [operation])?;
+    tx.commit()?;
+
+    let mut stdout = io::stdout().lock();
+    for path in &restored {
+        if style != PathStyle::Null {
+            write!(stdout, "Restored ")?;
+        }
+        output::print_path(&mut stdout, path, style)?;
+    }
+    eprintln!("Undid {}, {} paths tracked again", command, restored.len());
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+use filetime::FileTime;
+
+use crate::{export_matches, find_matches, log, pool::Pool, ExportArgs, PathsDB};
+
+/// Size and modification time of every matched file, compared between scans to detect changes.
+type Snapshot = HashMap<PathBuf, (u64, FileTime)>;
+
+/// Tracked paths which currently exist, the others are skipped until they are created again.
+fn existing_paths(paths_db: &PathsDB) -> anyhow::Result<Vec<PathBuf>> {
+    Ok(paths_db.list()?.into_iter().filter(|path| path.exists()).collect())
+}
+
+fn snapshot(paths_db: &PathsDB, export: &ExportArgs) -> anyhow::Result<Snapshot> {
+    let paths = existing_paths(paths_db)?;
+    let mut snapshot = Snapshot::new();
+    for mat in find_matches(&paths, &export.filter.filters(paths_db)?)? {
+        // Files can disappear between the scan and the stat, the next scan will notice.
+        if let Ok(meta) = fs::metadata(&mat) {
+            snapshot.insert(mat, (meta.len(), FileTime::from_last_modification_time(&meta)));
+        }
+    }
+    Ok(snapshot)
+}
+
+fn count_changes(old: &Snapshot, new: &Snapshot) -> usize {
+    let changed = new.iter().filter(|(path, stat)| old.get(*path) != Some(stat)).count();
+    let removed = old.keys().filter(|path| !new.contains_key(*path)).count();
+    changed + removed
+}
+
+/// Poll the tracked paths and export again once files changed and then stayed unchanged for `debounce`.
+///
+/// Every poll walks all the tracked paths and reads the metadata of each matched file, so its cost grows
+/// with the tree and `interval` should be kept high enough for large trees.
+///
+/// Scanning errors, like a tracked path being removed in the middle of a scan, and failed exports, like
+/// the database being locked by another command, are reported and retried on the next poll instead of
+/// stopping the watch.
+pub fn watch_export(
+    paths_db: &PathsDB,
+    export: &ExportArgs,
+    pool: &Pool,
+    wait: bool,
+    debounce: Duration,
+    interval: Duration,
+) -> anyhow::Result<()> {
+    let mut current = snapshot(paths_db, export)?;
+    // None until the first export succeeds.
+    let mut exported = run_export(paths_db, export, pool, wait, None).then(|| current.clone());
+    let mut changed_at = None;
+    loop {
+        thread::sleep(interval);
+        let next = match snapshot(paths_db, export) {
+            Ok(next) => next,
+            Err(err) => {
+                log::error("failed", vec![("error", format!("{:#}", err).into())]);
+                eprintln!("Error: {:?}", err);
+                continue;
+            }
+        };
+        if next != current {
+            changed_at = Some(Instant::now());
+            current = next;
+        }
+        let done = match &exported {
+            None => run_export(paths_db, export, pool, wait, None),
+            Some(exported) if changed_at.is_some_and(|at| at.elapsed() >= debounce) => {
+                let changes = count_changes(exported, &current);
+                changes == 0 || run_export(paths_db, export, pool, wait, Some(changes))
+            }
+            Some(_) => false,
+        };
+        if done {
+            changed_at = None;
+            exported = Some(current.clone());
+        }
+    }
+}
+
+/// Export the matched files, returning whether it succeeded. Errors are reported for the caller to
+/// retry on the next poll.
+fn run_export(paths_db: &PathsDB, export: &ExportArgs, pool: &Pool, wait: bool, changes: Option<usize>) -> bool {
+    let result = existing_paths(paths_db).and_then(|paths| export_matches(paths_db, &paths, export, pool, wait));
+    match result {
+        Ok(count) => {
+            match changes {
+                Some(changes) => println!("Exported {} files after {} changes", count, changes),
+                None => println!("Exported {} files", count),
+            }
+            true
+        }
+        Err(err) => {
+            log::error("failed", vec![("error", format!("{:#}", err).into())]);
+            eprintln!("Error: {:?}", err);
+            false
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+/// Error of IO on a file which didn't complete in time.
+#[derive(Debug)]
+pub struct TimedOut {
+    pub path: PathBuf,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} didn't answer within {:?}", self.path.display(), self.timeout)
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Turn the timeout of a file into a warning, returning whether the file was skipped.
+pub fn skipped(result: anyhow::Result<()>) -> anyhow::Result<bool> {
+    match result {
+        Ok(()) => Ok(false),
+        Err(err) => match err.downcast_ref::<TimedOut>() {
+            Some(timed_out) => {
+                crate::log::skipping(
+                    &timed_out.path,
+                    &format!("which didn't answer within {:?}", timed_out.timeout),
+                );
+                Ok(true)
+            }
+            None => Err(err),
+        },
+    }
+}
+
+/// Call `f` with `path`, failing with `TimedOut` when it takes longer than `timeout`.
+///
+/// Without a timeout `f` runs on the calling thread. With one, it runs on a thread of its own while
+/// the calling thread waits for its result. IO blocked in the kernel can't be cancelled, a thread
+/// which times out is left behind, blocked until the IO completes or the process exits, and
+/// whatever it returns is dropped. The side effects of `f` may still happen after it timed out.
+pub fn run<T, F>(timeout: Option<Duration>, path: &Path, f: F) -> anyhow::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&Path) -> anyhow::Result<T> + Send + 'static,
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return f(path),
+    };
+    let (sender, receiver) = mpsc::channel();
+    let owned = path.to_path_buf();
+    thread::Builder::new()
+        .name("track-watchdog".to_string())
+        .spawn(move || {
+            // The receiver is gone when the wait timed out.
+            let _ = sender.send(f(&owned));
+        })?;
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(_) => Err(TimedOut {
+            path: path.to_path_buf(),
+            timeout,
+        }
+        .into()),
+    }
+}
+
+/// Check that a file can be stat-ed and opened in time, before it's read without a watchdog.
+///
+/// Opening a fifo blocks until it has a writer, and a dead network mount blocks on the first stat.
+pub fn probe(timeout: Option<Duration>, path: &Path) -> anyhow::Result<()> {
+    if timeout.is_none() {
+        return Ok(());
+    }
+    run(timeout, path, |path| {
+        if !fs::symlink_metadata(path)?.file_type().is_symlink() {
+            fs::File::open(path)?;
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,35 @@
+use std::{ffi::OsString, io, path::Path};
+
+/// Extended attributes of a file, as name and value pairs.
+pub type XAttrs = Vec<(OsString, Vec<u8>)>;
+
+fn is_unsupported(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::ENOTSUP) || err.kind() == io::ErrorKind::Unsupported
+}
+
+/// Read the extended attributes of a file, a filesystem without xattrs support has none.
+pub fn read(path: &Path) -> io::Result<XAttrs> {
+    let names = match xattr::list(path) {
+        Ok(names) => names,
+        Err(err) if is_unsupported(&err) => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut attrs = Vec::new();
+    for name in names {
+        if let Some(value) = xattr::get(path, &name)? {
+            attrs.push((name, value));
+        }
+    }
+    Ok(attrs)
+}
+
+/// Set extended attributes on a file, silently skipped if the filesystem doesn't support them.
+pub fn apply(path: &Path, attrs: &XAttrs) -> io::Result<()> {
+    for (name, value) in attrs {
+        match xattr::set(path, name, value) {
+            Err(err) if is_unsupported(&err) => return Ok(()),
+            res => res?,
+        }
+    }
+    Ok(())
+}
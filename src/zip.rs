@@ -0,0 +1,704 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, Write},
+    os::unix::{ffi::OsStrExt, fs::PermissionsExt},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use filetime::FileTime;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+
+use crate::snapshot;
+
+/// When the zip64 extensions are written, they lift the 4 GiB and 65535 entries limits of zip archives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zip64 {
+    /// Only for the entries and archives which need them.
+    Auto,
+    /// For every entry and the archive itself.
+    Always,
+    /// Never, an archive exceeding the limits fails to export instead of being corrupted.
+    Never,
+}
+
+impl FromStr for Zip64 {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "auto" => Zip64::Auto,
+            "always" => Zip64::Always,
+            "never" => Zip64::Never,
+            _ => bail!("Unknown zip64 mode {}", s),
+        })
+    }
+}
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const DATA_DESCRIPTOR: u32 = 0x08074b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const ZIP64_END: u32 = 0x06064b50;
+const ZIP64_LOCATOR: u32 = 0x07064b50;
+const END: u32 = 0x06054b50;
+
+const ZIP64_EXTRA: u16 = 0x0001;
+/// Extended timestamp extra field, with the modification time to the second like unix has it.
+const TIMESTAMP_EXTRA: u16 = 0x5455;
+const TIMESTAMP_MTIME: u8 = 1;
+/// Sizes are written in the data descriptor following the content.
+const FLAG_DESCRIPTOR: u16 = 1 << 3;
+const FLAG_UTF8: u16 = 1 << 11;
+const METHOD_DEFLATE: u16 = 8;
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+/// Version made by, the high byte tells the external attributes hold unix permissions.
+const VERSION_MADE_BY: u16 = 3 << 8 | VERSION_ZIP64;
+
+const MAX_U16: u64 = u16::MAX as u64;
+const MAX_U32: u64 = u32::MAX as u64;
+
+/// Content written to the archive can exceed the input when it doesn't compress, keep some margin
+/// before deciding an entry fits without zip64.
+fn may_exceed_u32(size: u64) -> bool {
+    size + size / 1024 + 65536 >= MAX_U32
+}
+
+/// Little endian encoding of the zip structures.
+#[derive(Default)]
+struct Record(Vec<u8>);
+
+impl Record {
+    fn u16(&mut self, n: u16) -> &mut Self {
+        self.0.extend_from_slice(&n.to_le_bytes());
+        self
+    }
+
+    fn u32(&mut self, n: u32) -> &mut Self {
+        self.0.extend_from_slice(&n.to_le_bytes());
+        self
+    }
+
+    fn u64(&mut self, n: u64) -> &mut Self {
+        self.0.extend_from_slice(&n.to_le_bytes());
+        self
+    }
+
+    fn bytes(&mut self, b: &[u8]) -> &mut Self {
+        self.0.extend_from_slice(b);
+        self
+    }
+}
+
+/// Writer keeping track of the offset in the archive.
+struct Counter<W> {
+    inner: W,
+    offset: u64,
+}
+
+impl<W: Write> Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// What the central directory needs to know about an entry written earlier.
+struct CentralEntry {
+    name: Vec<u8>,
+    flags: u16,
+    zip64: bool,
+    dos_time: u16,
+    dos_date: u16,
+    /// Modification time for the extended timestamp, none when it doesn't fit in its 32 bits.
+    unix_mtime: Option<i32>,
+    crc: u32,
+    compressed: u64,
+    uncompressed: u64,
+    offset: u64,
+    data_offset: u64,
+    mode: u32,
+}
+
+/// Convert a unix timestamp to the DOS date and time of zip headers, clamped to the 1980 to 2107 range.
+///
+/// Zip times have no time zone, UTC is used.
+fn dos_datetime(time: FileTime) -> (u16, u16) {
+    const MIN: i64 = 315532800; // 1980-01-01
+    const MAX: i64 = 4354819199; // 2107-12-31 23:59:59
+    let secs = time.unix_seconds().clamp(MIN, MAX);
+    let days = secs.div_euclid(86400);
+    let rem = secs.rem_euclid(86400);
+
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let time = (rem / 3600) << 11 | (rem % 3600 / 60) << 5 | (rem % 60 / 2);
+    let date = (year - 1980) << 9 | month << 5 | day;
+    (time as u16, date as u16)
+}
+
+/// Streaming zip archive writer, the content of the files is deflated and followed by a data descriptor.
+///
+/// Nothing is written back, so the output can be a pipe. Unlike archives written by seeking back,
+/// the local headers have no sizes or checksums: readers take them from the data descriptors or the
+/// central directory, like the common ones do.
+pub struct ZipWriter<W: Write> {
+    out: Counter<W>,
+    entries: Vec<CentralEntry>,
+    zip64: Zip64,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(out: W, zip64: Zip64) -> ZipWriter<W> {
+        ZipWriter {
+            out: Counter { inner: out, offset: 0 },
+            entries: Vec::new(),
+            zip64,
+        }
+    }
+
+    fn check_limits(&self, len: u64, name: &[u8]) -> anyhow::Result<()> {
+        if self.zip64 == Zip64::Never && self.entries.len() as u64 >= MAX_U16 {
+            bail!("more than {} files can't be archived with --zip64 never", MAX_U16);
+        }
+        if self.zip64 == Zip64::Never && len > MAX_U32 {
+            bail!("files larger than 4 GiB can't be archived with --zip64 never");
+        }
+        if name.len() > MAX_U16 as usize {
+            bail!("name is too long for a zip archive");
+        }
+        Ok(())
+    }
+
+    /// Start an entry, returning it with its local header written.
+    fn start_entry(&mut self, mode: u32, mtime: FileTime, name: &[u8], zip64: bool) -> io::Result<CentralEntry> {
+        let mut flags = FLAG_DESCRIPTOR;
+        if std::str::from_utf8(name).is_ok() {
+            flags |= FLAG_UTF8;
+        }
+        let (dos_time, dos_date) = dos_datetime(mtime);
+        // DOS times only have a precision of two seconds.
+        let unix_mtime = i32::try_from(mtime.unix_seconds()).ok();
+        let offset = self.out.offset;
+
+        let mut extra = Record::default();
+        if zip64 {
+            extra.u16(ZIP64_EXTRA).u16(16).u64(0).u64(0);
+        }
+        if let Some(unix_mtime) = unix_mtime {
+            extra
+                .u16(TIMESTAMP_EXTRA)
+                .u16(5)
+                .bytes(&[TIMESTAMP_MTIME])
+                .u32(unix_mtime as u32);
+        }
+
+        let mut header = Record::default();
+        header
+            .u32(LOCAL_HEADER)
+            .u16(if zip64 { VERSION_ZIP64 } else { VERSION_DEFAULT })
+            .u16(flags)
+            .u16(METHOD_DEFLATE)
+            .u16(dos_time)
+            .u16(dos_date)
+            .u32(0);
+        let size = if zip64 { u32::MAX } else { 0 };
+        header
+            .u32(size)
+            .u32(size)
+            .u16(name.len() as u16)
+            .u16(extra.0.len() as u16)
+            .bytes(name)
+            .bytes(&extra.0);
+        self.out.write_all(&header.0)?;
+
+        Ok(CentralEntry {
+            name: name.to_vec(),
+            flags,
+            zip64,
+            dos_time,
+            dos_date,
+            unix_mtime,
+            crc: 0,
+            compressed: 0,
+            uncompressed: 0,
+            offset,
+            data_offset: self.out.offset,
+            mode,
+        })
+    }
+
+    /// Write the data descriptor of an entry whose content was written, returning its index.
+    fn end_entry(&mut self, entry: CentralEntry) -> anyhow::Result<usize> {
+        if !entry.zip64 && (entry.compressed > MAX_U32 || entry.uncompressed > MAX_U32) {
+            match self.zip64 {
+                Zip64::Never => bail!("files larger than 4 GiB can't be archived with --zip64 never"),
+                _ => bail!("file grew past 4 GiB while it was archived"),
+            }
+        }
+        let mut descriptor = Record::default();
+        descriptor.u32(DATA_DESCRIPTOR).u32(entry.crc);
+        if entry.zip64 {
+            descriptor.u64(entry.compressed).u64(entry.uncompressed);
+        } else {
+            descriptor.u32(entry.compressed as u32).u32(entry.uncompressed as u32);
+        }
+        self.out.write_all(&descriptor.0)?;
+        self.entries.push(entry);
+        Ok(self.entries.len() - 1)
+    }
+
+    fn zip64_for(&self, len: u64) -> bool {
+        match self.zip64 {
+            Zip64::Always => true,
+            Zip64::Auto => may_exceed_u32(len),
+            Zip64::Never => false,
+        }
+    }
+
+    /// Add the regular file at `path` to the archive, as `name`, returning the index of its entry.
+    pub fn append_file(&mut self, path: &Path, name: &[u8]) -> anyhow::Result<usize> {
+        let input = File::open(path)?;
+        let meta = input.metadata()?;
+        let mtime = FileTime::from_last_modification_time(&meta);
+        self.append_data(name, meta.permissions().mode(), mtime, meta.len(), input)
+    }
+
+    /// Add a regular file of `len` bytes read from `input`, as `name` with the permissions of `mode`.
+    pub fn append_data(
+        &mut self,
+        name: &[u8],
+        mode: u32,
+        mtime: FileTime,
+        len: u64,
+        mut input: impl Read,
+    ) -> anyhow::Result<usize> {
+        self.check_limits(len, name)?;
+        let mut entry = self.start_entry(0o100000 | (mode & 0o7777), mtime, name, self.zip64_for(len))?;
+
+        let mut crc = crc32fast::Hasher::new();
+        let mut encoder = DeflateEncoder::new(&mut self.out, Compression::default());
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = match input.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+            crc.update(&buf[..n]);
+            encoder.write_all(&buf[..n])?;
+            entry.uncompressed += n as u64;
+        }
+        encoder.finish()?;
+        entry.crc = crc.finalize();
+        entry.compressed = self.out.offset - entry.data_offset;
+        self.end_entry(entry)
+    }
+
+    /// Add the symlink at `path` to the archive, as `name`, storing its target as the content like
+    /// Info-ZIP does.
+    pub fn append_symlink(&mut self, path: &Path, name: &[u8]) -> anyhow::Result<usize> {
+        let meta = fs::symlink_metadata(path)?;
+        let target = fs::read_link(path)?;
+        let mtime = FileTime::from_last_modification_time(&meta);
+        self.append_symlink_to(name, meta.permissions().mode(), mtime, target.as_os_str().as_bytes())
+    }
+
+    /// Add a symlink to `target`, as `name` with the permissions of `mode`.
+    pub fn append_symlink_to(
+        &mut self,
+        name: &[u8],
+        mode: u32,
+        mtime: FileTime,
+        target: &[u8],
+    ) -> anyhow::Result<usize> {
+        self.check_limits(target.len() as u64, name)?;
+        let mut entry = self.start_entry(0o120000 | (mode & 0o7777), mtime, name, false)?;
+
+        let mut encoder = DeflateEncoder::new(&mut self.out, Compression::default());
+        encoder.write_all(target)?;
+        encoder.finish()?;
+        entry.crc = crc32fast::hash(target);
+        entry.uncompressed = target.len() as u64;
+        entry.compressed = self.out.offset - entry.data_offset;
+        self.end_entry(entry)
+    }
+
+    /// Position and length of the compressed content of an entry in the archive.
+    pub fn content_range(&self, index: usize) -> (u64, u64) {
+        let entry = &self.entries[index];
+        (entry.data_offset, entry.compressed)
+    }
+
+    /// Add the file at `path`, whose content is identical to the entry `of`, by copying the already
+    /// compressed content from `compressed` instead of compressing it again.
+    pub fn append_copy(&mut self, path: &Path, name: &[u8], of: usize, compressed: impl Read) -> anyhow::Result<usize> {
+        let meta = fs::metadata(path)?;
+        let mtime = FileTime::from_last_modification_time(&meta);
+        self.append_copy_of(name, meta.permissions().mode(), mtime, of, compressed)
+    }
+
+    /// Add a regular file identical to the entry `of` like `append_copy`, as `name` with the
+    /// permissions of `mode`.
+    pub fn append_copy_of(
+        &mut self,
+        name: &[u8],
+        mode: u32,
+        mtime: FileTime,
+        of: usize,
+        compressed: impl Read,
+    ) -> anyhow::Result<usize> {
+        let (crc, uncompressed, zip64) = {
+            let original = &self.entries[of];
+            (original.crc, original.uncompressed, original.zip64)
+        };
+        self.check_limits(uncompressed, name)?;
+        let mut entry = self.start_entry(0o100000 | (mode & 0o7777), mtime, name, zip64)?;
+        entry.compressed = io::copy(&mut compressed.take(self.entries[of].compressed), &mut self.out)?;
+        entry.crc = crc;
+        entry.uncompressed = uncompressed;
+        self.end_entry(entry)
+    }
+
+    /// Flush what was written so far to the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Write the central directory and end records, returning the underlying writer.
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        let cd_offset = self.out.offset;
+        let entries = std::mem::take(&mut self.entries);
+        for entry in &entries {
+            self.write_central_entry(entry)?;
+        }
+        let cd_size = self.out.offset - cd_offset;
+        let count = entries.len() as u64;
+
+        let needs_zip64 = count >= MAX_U16 || cd_offset >= MAX_U32 || cd_size >= MAX_U32;
+        if count >= MAX_U16 && self.zip64 == Zip64::Never {
+            bail!("{} files can't be archived with --zip64 never", count);
+        }
+        if needs_zip64 && self.zip64 == Zip64::Never {
+            bail!("the archive is larger than 4 GiB, which requires zip64");
+        }
+        if needs_zip64 || self.zip64 == Zip64::Always {
+            let end_offset = self.out.offset;
+            let mut end = Record::default();
+            end.u32(ZIP64_END)
+                .u64(44)
+                .u16(VERSION_MADE_BY)
+                .u16(VERSION_ZIP64)
+                .u32(0)
+                .u32(0)
+                .u64(count)
+                .u64(count)
+                .u64(cd_size)
+                .u64(cd_offset)
+                .u32(ZIP64_LOCATOR)
+                .u32(0)
+                .u64(end_offset)
+                .u32(1);
+            self.out.write_all(&end.0)?;
+        }
+
+        let mut end = Record::default();
+        end.u32(END)
+            .u16(0)
+            .u16(0)
+            .u16(count.min(MAX_U16) as u16)
+            .u16(count.min(MAX_U16) as u16)
+            .u32(cd_size.min(MAX_U32) as u32)
+            .u32(cd_offset.min(MAX_U32) as u32)
+            .u16(0);
+        self.out.write_all(&end.0)?;
+        self.out.flush()?;
+        Ok(self.out.inner)
+    }
+
+    fn write_central_entry(&mut self, entry: &CentralEntry) -> anyhow::Result<()> {
+        let sizes_overflow = entry.zip64 || entry.compressed >= MAX_U32 || entry.uncompressed >= MAX_U32;
+        let offset_overflow = entry.zip64 || entry.offset >= MAX_U32;
+        if offset_overflow && self.zip64 == Zip64::Never {
+            bail!("the archive is larger than 4 GiB, which requires zip64");
+        }
+
+        let mut zip64_extra = Record::default();
+        if sizes_overflow {
+            zip64_extra.u64(entry.uncompressed).u64(entry.compressed);
+        }
+        if offset_overflow {
+            zip64_extra.u64(entry.offset);
+        }
+        let version = if zip64_extra.0.is_empty() {
+            VERSION_DEFAULT
+        } else {
+            VERSION_ZIP64
+        };
+        let mut extra = Record::default();
+        if !zip64_extra.0.is_empty() {
+            extra
+                .u16(ZIP64_EXTRA)
+                .u16(zip64_extra.0.len() as u16)
+                .bytes(&zip64_extra.0);
+        }
+        if let Some(unix_mtime) = entry.unix_mtime {
+            extra
+                .u16(TIMESTAMP_EXTRA)
+                .u16(5)
+                .bytes(&[TIMESTAMP_MTIME])
+                .u32(unix_mtime as u32);
+        }
+
+        let mut header = Record::default();
+        header
+            .u32(CENTRAL_HEADER)
+            .u16(VERSION_MADE_BY)
+            .u16(version)
+            .u16(entry.flags)
+            .u16(METHOD_DEFLATE)
+            .u16(entry.dos_time)
+            .u16(entry.dos_date)
+            .u32(entry.crc)
+            .u32(if sizes_overflow {
+                u32::MAX
+            } else {
+                entry.compressed as u32
+            })
+            .u32(if sizes_overflow {
+                u32::MAX
+            } else {
+                entry.uncompressed as u32
+            })
+            .u16(entry.name.len() as u16)
+            .u16(extra.0.len() as u16)
+            .u16(0)
+            .u16(0)
+            .u16(0)
+            .u32(entry.mode << 16)
+            .u32(if offset_overflow { u32::MAX } else { entry.offset as u32 })
+            .bytes(&entry.name)
+            .bytes(&extra.0);
+        self.out.write_all(&header.0)?;
+        Ok(())
+    }
+}
+
+fn le_u16(b: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([b[at], b[at + 1]])
+}
+
+fn le_u32(b: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(b[at..at + 4].try_into().expect("slice of 4 bytes"))
+}
+
+fn le_u64(b: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(b[at..at + 8].try_into().expect("slice of 8 bytes"))
+}
+
+fn read_at(file: &mut File, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    file.seek(io::SeekFrom::Start(offset))?;
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Offset and size of the central directory of an archive.
+fn central_directory(file: &mut File) -> anyhow::Result<(u64, u64)> {
+    let len = file.metadata()?.len();
+    // The end record is 22 bytes followed by a comment of up to 65535 bytes.
+    let tail_len = len.min(22 + MAX_U16);
+    let tail = read_at(file, len - tail_len, tail_len as usize)?;
+    let end = match (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| le_u32(&tail, i) == END)
+    {
+        Some(end) => end,
+        None => bail!("not a zip archive"),
+    };
+    let (size, offset) = (le_u32(&tail, end + 12), le_u32(&tail, end + 16));
+    if size != u32::MAX && offset != u32::MAX && le_u16(&tail, end + 10) != u16::MAX {
+        return Ok((offset as u64, size as u64));
+    }
+    let locator_at = (len - tail_len) + end as u64 - 20;
+    let locator = read_at(file, locator_at, 20)?;
+    if le_u32(&locator, 0) != ZIP64_LOCATOR {
+        bail!("zip64 archive without its end locator");
+    }
+    let zip64_end = read_at(file, le_u64(&locator, 8), 56)?;
+    if le_u32(&zip64_end, 0) != ZIP64_END {
+        bail!("zip64 archive without its end record");
+    }
+    Ok((le_u64(&zip64_end, 48), le_u64(&zip64_end, 40)))
+}
+
+/// An entry of the central directory of an archive.
+pub struct ZipEntry {
+    pub name: Vec<u8>,
+    /// Unix mode along with the file type, 0 when the archive wasn't written on unix.
+    pub mode: u32,
+    pub mtime: FileTime,
+    pub uncompressed: u64,
+    method: u16,
+    crc: u32,
+    compressed: u64,
+    offset: u64,
+}
+
+impl ZipEntry {
+    pub fn is_dir(&self) -> bool {
+        self.mode & 0o170000 == 0o040000 || self.name.ends_with(b"/")
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & 0o170000 == 0o120000
+    }
+}
+
+/// Convert the DOS date and time of zip headers to a unix timestamp, in UTC like `dos_datetime`.
+fn unix_time(dos_time: u16, dos_date: u16) -> FileTime {
+    let (time, date) = (dos_time as i64, dos_date as i64);
+    let days = snapshot::days_from_civil(1980 + (date >> 9), (date >> 5) & 15, date & 31);
+    FileTime::from_unix_time(
+        days * 86400 + (time >> 11) * 3600 + (time >> 5 & 63) * 60 + (time & 31) * 2,
+        0,
+    )
+}
+
+/// Read the entries of an archive from its central directory, in the order they were archived.
+pub fn entries(file: &mut File) -> anyhow::Result<Vec<ZipEntry>> {
+    let (cd_offset, cd_size) = central_directory(file)?;
+    let cd = read_at(file, cd_offset, cd_size as usize)?;
+    let mut entries = Vec::new();
+    let mut at = 0;
+    while at + 46 <= cd.len() && le_u32(&cd, at) == CENTRAL_HEADER {
+        let made_by = le_u16(&cd, at + 4);
+        let method = le_u16(&cd, at + 10);
+        let mut mtime = unix_time(le_u16(&cd, at + 12), le_u16(&cd, at + 14));
+        let crc = le_u32(&cd, at + 16);
+        let mut compressed = le_u32(&cd, at + 20) as u64;
+        let mut uncompressed = le_u32(&cd, at + 24) as u64;
+        let name_len = le_u16(&cd, at + 28) as usize;
+        let extra_len = le_u16(&cd, at + 30) as usize;
+        let comment_len = le_u16(&cd, at + 32) as usize;
+        let external = le_u32(&cd, at + 38);
+        let mut offset = le_u32(&cd, at + 42) as u64;
+        if at + 46 + name_len + extra_len > cd.len() {
+            bail!("corrupted zip archive, truncated central directory");
+        }
+        let name = cd[at + 46..at + 46 + name_len].to_vec();
+
+        // The zip64 extra field only has the values which overflowed, in this order.
+        let mut extra = &cd[at + 46 + name_len..at + 46 + name_len + extra_len];
+        while extra.len() >= 4 {
+            let (id, len) = (le_u16(extra, 0), le_u16(extra, 2) as usize);
+            if id == ZIP64_EXTRA {
+                let mut field = 4;
+                for value in [&mut uncompressed, &mut compressed, &mut offset] {
+                    if *value == MAX_U32 && field + 8 <= (4 + len).min(extra.len()) {
+                        *value = le_u64(extra, field);
+                        field += 8;
+                    }
+                }
+            }
+            // The central extended timestamp only has the modification time, when its flag is set.
+            if id == TIMESTAMP_EXTRA && len >= 5 && extra.len() >= 9 && extra[4] & TIMESTAMP_MTIME != 0 {
+                mtime = FileTime::from_unix_time(le_u32(extra, 5) as i32 as i64, 0);
+            }
+            extra = &extra[(4 + len).min(extra.len())..];
+        }
+
+        entries.push(ZipEntry {
+            name,
+            mode: if made_by >> 8 == 3 { external >> 16 } else { 0 },
+            mtime,
+            uncompressed,
+            method,
+            crc,
+            compressed,
+            offset,
+        });
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+/// Reader of the content of an entry, failing at the end if it doesn't match the checksum.
+struct Checked<R> {
+    inner: R,
+    crc: crc32fast::Hasher,
+    len: u64,
+    expected_crc: u32,
+    expected_len: u64,
+}
+
+impl<R: Read> Read for Checked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.crc.update(&buf[..n]);
+        self.len += n as u64;
+        if n == 0
+            && !buf.is_empty()
+            && (self.len != self.expected_len || self.crc.clone().finalize() != self.expected_crc)
+        {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "corrupted zip entry"));
+        }
+        Ok(n)
+    }
+}
+
+/// Stream the decompressed content of an entry of the archive `file`.
+pub fn open_entry<'a>(file: &'a mut File, entry: &ZipEntry) -> anyhow::Result<Box<dyn Read + 'a>> {
+    let local = read_at(file, entry.offset, 30)?;
+    if le_u32(&local, 0) != LOCAL_HEADER {
+        bail!(
+            "corrupted zip archive, no local header for {}",
+            String::from_utf8_lossy(&entry.name)
+        );
+    }
+    let data_at = entry.offset + 30 + le_u16(&local, 26) as u64 + le_u16(&local, 28) as u64;
+    file.seek(io::SeekFrom::Start(data_at))?;
+    let data = file.take(entry.compressed);
+    let inner: Box<dyn Read> = match entry.method {
+        0 => Box::new(data),
+        METHOD_DEFLATE => Box::new(DeflateDecoder::new(data)),
+        method => bail!("unsupported zip compression method {}", method),
+    };
+    Ok(Box::new(Checked {
+        inner,
+        crc: crc32fast::Hasher::new(),
+        len: 0,
+        expected_crc: entry.crc,
+        expected_len: entry.uncompressed,
+    }))
+}
+
+/// Read the content of the entry named `name` of a zip archive, if it has one.
+pub fn read_entry(path: &Path, name: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut file = File::open(path)?;
+    let entry = match entries(&mut file)?.into_iter().find(|entry| entry.name == name) {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    let mut content = Vec::with_capacity(entry.uncompressed as usize);
+    open_entry(&mut file, &entry)?
+        .read_to_end(&mut content)
+        .context(format!("could not read zip entry {}", String::from_utf8_lossy(name)))?;
+    Ok(Some(content))
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use std::fs;
+
+use common::{fails, ok, track, TempDir};
+
+#[test]
+fn dry_run_prints_the_plan_without_adding_anything() {
+    let dir = TempDir::new("add-dry-run");
+    let (a, sub, b, missing) = (dir.join("a"), dir.join("a/sub"), dir.join("b"), dir.join("missing"));
+    fs::create_dir_all(&sub).unwrap();
+    fs::create_dir(&b).unwrap();
+    ok(track(&dir).arg("add").arg(&a));
+    let before = fs::read(dir.join("track.db")).unwrap();
+
+    let output = ok(track(&dir)
+        .args(["add", "--dry-run"])
+        .arg(&a)
+        .arg(&sub)
+        .arg(&b)
+        .arg(&b)
+        .arg(&missing));
+    assert_eq!(
+        output,
+        format!(
+            "{a}: skipped (duplicate)\n\
+             {sub}: added (nested under {a})\n\
+             {b}: added\n\
+             {b}: skipped (duplicate)\n\
+             {missing}: refused ({missing} does not exist, use --force to add it anyway)\n\
+             Would add 2 of 5 paths\n",
+            a = a.display(),
+            sub = sub.display(),
+            b = b.display(),
+            missing = missing.display(),
+        )
+    );
+    let output = ok(track(&dir).args(["add", "--dry-run", "--force"]).arg(&missing));
+    assert_eq!(
+        output,
+        format!(
+            "{}: added (warning: does not exist)\nWould add 1 of 1 paths\n",
+            missing.display()
+        )
+    );
+
+    // Nothing was inserted.
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n", a.display()));
+    assert!(fs::read(dir.join("track.db")).unwrap() == before);
+}
+
+#[test]
+fn dry_run_refuses_to_export() {
+    let dir = TempDir::new("add-dry-run-then");
+    dir.write("src/a", "a");
+    let output = fails(
+        track(&dir)
+            .args(["add", "--dry-run"])
+            .arg(dir.join("src"))
+            .args(["then", "export", "tar"])
+            .arg(dir.join("out.tar.gz")),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--dry-run doesn't add the paths to export"));
+    assert_eq!(ok(track(&dir).arg("ls")), "");
+}
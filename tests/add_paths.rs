@@ -0,0 +1,127 @@
+mod common;
+
+use std::{fs, os::unix::fs::symlink};
+
+use common::{fails, ok, track, TempDir};
+
+#[test]
+fn missing_paths_need_force() {
+    let dir = TempDir::new("add-missing");
+    let missing = dir.join("missing");
+    let output = fails(track(&dir).arg("add").arg(&missing));
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!(
+        "{} does not exist, use --force to add it anyway",
+        missing.display()
+    )));
+    assert_eq!(ok(track(&dir).arg("ls")), "");
+
+    ok(track(&dir).arg("add").arg(&missing).arg("--force"));
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n", missing.display()));
+}
+
+#[test]
+fn virtual_file_systems_need_force() {
+    let dir = TempDir::new("add-virtual");
+    for path in ["/proc/self", "/sys/kernel", "/dev/null"] {
+        let output = fails(track(&dir).arg("add").arg(path));
+        assert_eq!(output.status.code(), Some(2), "{}", path);
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("which only holds virtual files, use --force"),
+            "{}",
+            path
+        );
+    }
+    assert_eq!(ok(track(&dir).arg("ls")), "");
+    ok(track(&dir).args(["add", "/proc/self", "--force"]));
+    assert_eq!(ok(track(&dir).arg("ls")), "/proc/self\n");
+}
+
+#[test]
+fn relative_and_dot_dot_paths_are_made_absolute() {
+    let dir = TempDir::new("add-relative");
+    fs::create_dir_all(dir.join("src/sub")).unwrap();
+    fs::create_dir_all(dir.join("other")).unwrap();
+    ok(track(&dir).current_dir(dir.path()).args(["add", "./src/sub/../sub/"]));
+    ok(track(&dir).current_dir(dir.join("src")).args(["add", "../other/."]));
+    assert_eq!(
+        ok(track(&dir).arg("ls")),
+        format!("{}\n{}\n", dir.join("other").display(), dir.join("src/sub").display())
+    );
+
+    // The same path given another way is already tracked.
+    let output = track(&dir)
+        .current_dir(dir.path())
+        .args(["add", "src/../src/sub"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "Path already in database!\n");
+    assert_eq!(ok(track(&dir).arg("ls")).lines().count(), 2);
+}
+
+#[test]
+fn canonicalize_resolves_symlinks() {
+    let dir = TempDir::new("add-canonicalize");
+    let real = dir.join("real");
+    fs::create_dir(&real).unwrap();
+    let alias = dir.join("alias");
+    symlink("real", &alias).unwrap();
+
+    // Without it the symlink itself is tracked.
+    ok(track(&dir).arg("add").arg(&alias));
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n", alias.display()));
+    ok(track(&dir).arg("rm").arg(&alias));
+
+    ok(track(&dir).arg("add").arg(&alias).arg("--canonicalize"));
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n", real.display()));
+
+    // A path which doesn't exist can't be resolved, even with --force.
+    let missing = dir.join("missing");
+    let output = fails(track(&dir).arg("add").arg(&missing).args(["--canonicalize", "--force"]));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("could not canonicalize it"));
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n", real.display()));
+}
+
+#[test]
+fn split_lists_are_quoted_like_a_shell() {
+    let dir = TempDir::new("add-split");
+    for name in ["my dir", "q\"x", "plain", "tab\there"] {
+        fs::create_dir(dir.join(name)).unwrap();
+    }
+    ok(track(&dir)
+        .current_dir(dir.path())
+        .arg("add")
+        .arg("--split")
+        .arg("'my dir'  \"q\\\"x\"\tplain\n")
+        .args(["--split", "tab\\\there"]));
+    let mut listed: Vec<_> = ok(track(&dir).arg("ls")).lines().map(str::to_owned).collect();
+    listed.sort();
+    assert_eq!(
+        listed,
+        ["my dir", "plain", "q\"x", "tab\there"].map(|name| dir.join(name).display().to_string())
+    );
+}
+
+#[test]
+fn unbalanced_split_lists_are_usage_errors() {
+    let dir = TempDir::new("add-split-unbalanced");
+    fs::create_dir(dir.join("ok")).unwrap();
+    for (list, message) in [
+        ("ok 'oops", "unclosed ' quote"),
+        ("ok \"oops", "unclosed \" quote"),
+        ("ok oops\\", "nothing to escape after the trailing \\"),
+    ] {
+        let output = fails(track(&dir).current_dir(dir.path()).args(["add", "--split", list]));
+        assert_eq!(output.status.code(), Some(2), "{}", list);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains(&format!("could not split --split {}", list)),
+            "{}",
+            stderr
+        );
+        assert!(stderr.contains(message), "{}", stderr);
+    }
+    // Nothing is added when a list can't be split.
+    assert_eq!(ok(track(&dir).arg("ls")), "");
+}
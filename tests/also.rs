@@ -0,0 +1,70 @@
+mod common;
+
+use std::fs;
+
+use common::{exported_files, fails, ok, tar_names, track, tracked_tree, TempDir};
+
+#[test]
+fn one_scan_writes_every_destination() {
+    let dir = TempDir::new("also");
+    let src = tracked_tree(&dir, "src", &[("a", "a"), ("sub/b", "b")]);
+    let (view, tar, zip, log) = (
+        dir.join("view"),
+        dir.join("b.tar.gz"),
+        dir.join("b.zip"),
+        dir.join("track.log"),
+    );
+    let output = track(&dir)
+        .args(["export", "dir"])
+        .arg(&view)
+        .arg("--also")
+        .arg(format!("tar:{}", tar.display()))
+        .arg("--also")
+        .arg(format!("zip:{}", zip.display()))
+        .arg("--log-file")
+        .arg(&log)
+        .args(["--progress", "always", "--progress-format", "json"])
+        .output()
+        .unwrap();
+    let progress = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", progress);
+
+    let name = src.strip_prefix("/").unwrap().display().to_string();
+    let names = [format!("{}/a", name), format!("{}/sub/b", name)];
+    assert_eq!(exported_files(&view), names);
+    assert_eq!(tar_names(&tar), names);
+    let converted = dir.join("converted.tar.gz");
+    ok(track(&dir).arg("convert").arg(&zip).arg(&converted));
+    assert_eq!(tar_names(&converted), names);
+
+    // The tracked paths were scanned once, and the progress counts the files of every destination.
+    let log = fs::read_to_string(&log).unwrap();
+    assert_eq!(log.matches(" info scan-start ").count(), 1, "{}", log);
+    assert!(progress.contains(r#""done":6,"total":6}"#), "{}", progress);
+    assert!(
+        progress.ends_with("{\"event\":\"done\",\"files\":6,\"bytes\":6}\n"),
+        "{}",
+        progress
+    );
+}
+
+#[test]
+fn other_destinations_need_a_kind_and_cant_be_stdout() {
+    let dir = TempDir::new("also-invalid");
+    tracked_tree(&dir, "src", &[("a", "a")]);
+    for (also, error) in [
+        ("foo:x", "Unknown export kind foo"),
+        ("tar", "Export destination tar is not like tar:backup.tar.gz"),
+        ("tar:-", "only the main destination of an export can be stdout"),
+    ] {
+        let output = fails(
+            track(&dir)
+                .args(["export", "dir"])
+                .arg(dir.join("view"))
+                .args(["--also", also]),
+        );
+        assert_eq!(output.status.code(), Some(2), "{}", also);
+        assert!(String::from_utf8_lossy(&output.stderr).contains(error), "{}", also);
+    }
+    assert!(!dir.join("view").exists());
+}
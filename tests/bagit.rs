@@ -0,0 +1,119 @@
+mod common;
+
+use std::{fs, path::Path, process::Command};
+
+use common::{fails, files_under, ok, track, tracked_tree, TempDir};
+
+fn read(bag: &Path, name: &str) -> String {
+    fs::read_to_string(bag.join(name)).unwrap()
+}
+
+/// Check the manifest `name` of `bag` with `program`, from the bag like the paths are written.
+fn assert_checks(program: &str, bag: &Path, name: &str) {
+    let output = Command::new(program)
+        .args(["-c", "--strict", name])
+        .current_dir(bag)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn bags_hold_the_payload_and_checksums_of_every_file() {
+    let dir = TempDir::new("bagit");
+    let src = tracked_tree(&dir, "src", &[("a", "a"), ("sub/b", "b")]);
+    let name = src.strip_prefix("/").unwrap().display().to_string();
+    let bag = dir.join("bag");
+    ok(track(&dir).args(["export", "bagit"]).arg(&bag));
+
+    let mut tag_files: Vec<_> = fs::read_dir(&bag)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    tag_files.sort();
+    assert_eq!(
+        tag_files,
+        [
+            ".track-export",
+            "bag-info.txt",
+            "bagit.txt",
+            "data",
+            "manifest-sha256.txt",
+            "tagmanifest-sha256.txt"
+        ]
+    );
+    assert_eq!(
+        files_under(&bag.join("data")),
+        [format!("{}/a", name), format!("{}/sub/b", name)]
+    );
+    assert_eq!(
+        read(&bag, "bagit.txt"),
+        "BagIt-Version: 1.0\nTag-File-Character-Encoding: UTF-8\n"
+    );
+    let info = read(&bag, "bag-info.txt");
+    assert!(info.starts_with("Bag-Software-Agent: track "), "{}", info);
+    assert!(info.ends_with("Payload-Oxum: 2.2\n"), "{}", info);
+    assert_eq!(
+        read(&bag, "manifest-sha256.txt"),
+        format!(
+            "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb  data/{}/a\n\
+             3e23e8160039594a33894f6564e1b1348bbd7a0088d42c4acb73eeaed59c009d  data/{}/sub/b\n",
+            name, name
+        )
+    );
+    assert_checks("sha256sum", &bag, "manifest-sha256.txt");
+    let tag_manifest = read(&bag, "tagmanifest-sha256.txt");
+    let named: Vec<_> = tag_manifest
+        .lines()
+        .map(|line| line.split_once("  ").unwrap().1)
+        .collect();
+    assert_eq!(named, ["bagit.txt", "bag-info.txt", "manifest-sha256.txt"]);
+    assert_checks("sha256sum", &bag, "tagmanifest-sha256.txt");
+}
+
+#[test]
+fn manifests_use_the_hash_algorithm() {
+    let dir = TempDir::new("bagit-md5");
+    let src = tracked_tree(&dir, "src", &[("a", "a")]);
+    let bag = dir.join("bag");
+    ok(track(&dir).args(["export", "bagit"]).arg(&bag).args(["--hash", "md5"]));
+    assert_eq!(
+        read(&bag, "manifest-md5.txt"),
+        format!(
+            "0cc175b9c0f1b6a831c399e269772661  data/{}/a\n",
+            src.strip_prefix("/").unwrap().display()
+        )
+    );
+    assert!(!bag.join("manifest-sha256.txt").exists());
+    assert_checks("md5sum", &bag, "tagmanifest-md5.txt");
+
+    // BagIt has no name for blake3.
+    let output = fails(
+        track(&dir)
+            .args(["export", "bagit"])
+            .arg(dir.join("other"))
+            .args(["--hash", "blake3"]),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("bagit manifests can use sha256, sha512 or md5"));
+}
+
+#[test]
+fn percent_signs_are_encoded_in_manifest_paths() {
+    let dir = TempDir::new("bagit-encode");
+    let src = tracked_tree(&dir, "src", &[("50%", "a")]);
+    let bag = dir.join("bag");
+    ok(track(&dir).args(["export", "bagit"]).arg(&bag));
+    assert_eq!(
+        read(&bag, "manifest-sha256.txt"),
+        format!(
+            "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb  data/{}/50%25\n",
+            src.strip_prefix("/").unwrap().display()
+        )
+    );
+    assert!(bag
+        .join("data")
+        .join(src.strip_prefix("/").unwrap())
+        .join("50%")
+        .exists());
+}
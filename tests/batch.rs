@@ -0,0 +1,75 @@
+mod common;
+
+use std::{
+    fs,
+    process::{Command, Output},
+};
+
+use common::{fails, ok, track, TempDir};
+
+/// Run track with `args` from a working directory removed once it's entered, so relative paths can't
+/// be resolved.
+fn in_removed_dir(dir: &TempDir, args: &[&str]) -> Output {
+    let gone = dir.join("gone");
+    fs::create_dir(&gone).unwrap();
+    let track = track(dir);
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(r#"cd "$1" && rmdir "$1" && shift && exec "$@""#)
+        .arg("sh")
+        .arg(&gone)
+        .arg(track.get_program())
+        .args(track.get_args())
+        .args(args);
+    for (name, value) in track.get_envs() {
+        match value {
+            Some(value) => command.env(name, value),
+            None => command.env_remove(name),
+        };
+    }
+    command.output().unwrap()
+}
+
+#[test]
+fn add_applies_the_paths_which_dont_fail() {
+    let dir = TempDir::new("batch-add");
+    let (a, missing, b) = (dir.join("a"), dir.join("missing"), dir.join("b"));
+    fs::create_dir(&a).unwrap();
+    fs::create_dir(&b).unwrap();
+    let output = fails(track(&dir).arg("add").arg(&a).arg(&missing).arg(&b));
+    // Every path failed for a usage error.
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("could not add {}", missing.display())),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("1 of 3 paths could not be added"), "{}", stderr);
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n{}\n", a.display(), b.display()));
+
+    let output = in_removed_dir(&dir, &["add", "relative", dir.join("c").to_str().unwrap(), "--force"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("could not add relative: could not make it absolute"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("1 of 2 paths could not be added"), "{}", stderr);
+    assert_eq!(ok(track(&dir).arg("ls")).lines().count(), 3);
+}
+
+#[test]
+fn rm_applies_the_paths_which_dont_fail() {
+    let dir = TempDir::new("batch-rm");
+    let (a, b) = (dir.join("a"), dir.join("b"));
+    ok(track(&dir).arg("add").arg(&a).arg(&b).arg("--force"));
+    let output = in_removed_dir(&dir, &["rm", "relative", a.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("could not remove relative"), "{}", stderr);
+    assert!(stderr.contains("1 of 2 paths could not be removed"), "{}", stderr);
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n", b.display()));
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use common::{ok, track, track_without_db, with_stdin, TempDir};
+
+const PATHS: usize = 10_000;
+
+/// Sorted lines of `output`.
+fn sorted_lines(output: &str) -> Vec<String> {
+    let mut lines: Vec<_> = output.lines().map(str::to_owned).collect();
+    lines.sort();
+    lines
+}
+
+#[test]
+fn large_adds_and_imports_insert_each_path_once() {
+    let dir = TempDir::new("bulk-add");
+    let mut paths: Vec<_> = (0..PATHS).map(|i| format!("{}/p{}", dir.path().display(), i)).collect();
+    // The last thousand paths are given twice.
+    let mut list = paths.join("\n");
+    for path in &paths[PATHS - 1000..] {
+        list.push('\n');
+        list.push_str(path);
+    }
+
+    let output = with_stdin(track(&dir).args(["add", "--force", "-"]), &list);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("Path already in database!").count(), 1000, "{}", stderr);
+    paths.sort();
+    assert_eq!(sorted_lines(&ok(track(&dir).arg("ls"))), paths);
+
+    let fresh = dir.join("fresh.db");
+    let import = || {
+        let mut command = track_without_db(&dir);
+        command.arg("--db").arg(&fresh).arg("import").arg(dir.join("track.db"));
+        command
+    };
+    assert_eq!(
+        ok(&mut import()),
+        format!("Imported {} paths, 0 already tracked\n", PATHS)
+    );
+    assert_eq!(
+        ok(&mut import()),
+        format!("Imported 0 paths, {} already tracked\n", PATHS)
+    );
+    let listed = ok(track_without_db(&dir).arg("--db").arg(&fresh).arg("ls"));
+    assert_eq!(sorted_lines(&listed), paths);
+}
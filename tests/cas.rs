@@ -0,0 +1,129 @@
+mod common;
+
+use std::{
+    fs,
+    io::ErrorKind,
+    os::unix::fs::{symlink, MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use common::{files_under, ok, track, tracked_tree, TempDir};
+
+/// A tracked tree with an executable, a file whose content another file has and a symlink to a
+/// directory, which the exports record.
+fn cas_tree(dir: &TempDir) -> PathBuf {
+    let src = tracked_tree(
+        dir,
+        "src",
+        &[
+            ("a", "same content"),
+            ("sub/copy-of-a", "same content"),
+            ("sub/run.sh", "#!/bin/sh\necho hi\n"),
+            ("empty", ""),
+        ],
+    );
+    fs::set_permissions(src.join("sub/run.sh"), fs::Permissions::from_mode(0o750)).unwrap();
+    symlink("sub", src.join("link")).unwrap();
+    src
+}
+
+fn export(dir: &TempDir, store: &Path) -> String {
+    ok(track(dir)
+        .args(["export", "cas"])
+        .arg(store)
+        .args(["--symlinked-dirs", "record"]))
+}
+
+/// Index files of the store, oldest first.
+fn indexes(store: &Path) -> Vec<PathBuf> {
+    let mut indexes: Vec<_> = fs::read_dir(store.join("indexes"))
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            (entry.metadata().unwrap().modified().unwrap(), entry.path())
+        })
+        .collect();
+    indexes.sort();
+    indexes.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Restore `index`, a store or one of its index files, returning where the tracked tree was restored.
+fn restore(dir: &TempDir, index: &Path, to: &str, src: &Path) -> PathBuf {
+    let to = dir.join(to);
+    ok(track(dir)
+        .arg("restore")
+        .arg(index)
+        .arg("--to")
+        .arg(&to)
+        .arg("--verify"));
+    to.join(src.strip_prefix("/").unwrap())
+}
+
+#[test]
+fn cas_exports_restore_to_the_same_files() {
+    let dir = TempDir::new("cas-round-trip");
+    let src = cas_tree(&dir);
+    let store = dir.join("store");
+    export(&dir, &store);
+
+    let restored = restore(&dir, &store, "restored", &src);
+    assert_eq!(files_under(&restored), files_under(&src));
+    for file in files_under(&src) {
+        let (original, copy) = (src.join(&file), restored.join(&file));
+        let (original_meta, copy_meta) = (
+            fs::symlink_metadata(&original).unwrap(),
+            fs::symlink_metadata(&copy).unwrap(),
+        );
+        assert_eq!(copy_meta.mode(), original_meta.mode(), "mode of {}", file);
+        assert_eq!(copy_meta.mtime(), original_meta.mtime(), "mtime of {}", file);
+        if original_meta.file_type().is_symlink() {
+            assert_eq!(fs::read_link(&copy).unwrap(), fs::read_link(&original).unwrap());
+        } else {
+            assert_eq!(
+                fs::read(&copy).unwrap(),
+                fs::read(&original).unwrap(),
+                "content of {}",
+                file
+            );
+        }
+    }
+}
+
+#[test]
+fn cas_exports_store_each_content_once() {
+    let dir = TempDir::new("cas-dedup");
+    let src = cas_tree(&dir);
+    let store = dir.join("store");
+    // The two files with the same content share an object, the symlink target has one.
+    assert!(export(&dir, &store).starts_with("Stored 4 new objects, 1 files were stored already"));
+    let objects = files_under(&store.join("objects"));
+    assert_eq!(objects.len(), 4, "{:?}", objects);
+
+    assert!(export(&dir, &store).starts_with("Stored 0 new objects, 5 files were stored already"));
+    assert_eq!(files_under(&store.join("objects")), objects);
+
+    dir.write("src/sub/copy-of-a", "changed");
+    assert!(export(&dir, &store).starts_with("Stored 1 new objects, 4 files were stored already"));
+    assert_eq!(files_under(&store.join("objects")).len(), 5);
+    let indexes = indexes(&store);
+    assert_eq!(indexes.len(), 3, "{:?}", indexes);
+
+    // Each index still restores the files as they were when it was written.
+    let first = restore(&dir, &indexes[0], "first", &src);
+    assert_eq!(fs::read_to_string(first.join("sub/copy-of-a")).unwrap(), "same content");
+    let latest = restore(&dir, &store, "latest", &src);
+    assert_eq!(fs::read_to_string(latest.join("sub/copy-of-a")).unwrap(), "changed");
+}
+
+#[test]
+fn cas_exports_refuse_other_directories() {
+    let dir = TempDir::new("cas-not-a-store");
+    cas_tree(&dir);
+    let store = dir.join("store");
+    dir.write("store/unrelated", "unrelated");
+    common::fails(track(&dir).args(["export", "cas"]).arg(&store));
+    assert_eq!(
+        fs::metadata(store.join("objects")).map(|_| ()).unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::{ffi::OsStr, fs, os::unix::ffi::OsStrExt};
+
+use common::{ok, track, TempDir};
+
+fn ls(dir: &TempDir) -> Vec<String> {
+    ok(track(dir).arg("ls")).lines().map(str::to_owned).collect()
+}
+
+/// Directories whose names only differ by case, told apart by this filesystem.
+fn case_variants(dir: &TempDir) {
+    for name in ["Docs", "docs", "DOCS"] {
+        fs::create_dir_all(dir.join(name)).unwrap();
+    }
+}
+
+#[test]
+fn case_variants_are_tracked_once() {
+    let dir = TempDir::new("case-insensitive");
+    case_variants(&dir);
+    ok(track(&dir)
+        .arg("--case-insensitive")
+        .arg("add")
+        .arg(dir.join("Docs"))
+        .arg(dir.join("docs")));
+    let docs = dir.join("Docs").display().to_string();
+    assert_eq!(ls(&dir), [docs.as_str()]);
+
+    // The setting is saved, later commands keep ignoring case.
+    ok(track(&dir).arg("add").arg(dir.join("DOCS")));
+    assert_eq!(ls(&dir), [docs.as_str()]);
+    ok(track(&dir).arg("rm").arg(dir.join("DOCS")));
+    assert!(ls(&dir).is_empty());
+}
+
+#[test]
+fn case_variants_are_distinct_by_default() {
+    let dir = TempDir::new("case-sensitive");
+    case_variants(&dir);
+    ok(track(&dir).arg("add").arg(dir.join("Docs")).arg(dir.join("docs")));
+    assert_eq!(ls(&dir).len(), 2);
+    ok(track(&dir).arg("rm").arg(dir.join("DOCS")));
+    assert_eq!(ls(&dir).len(), 2);
+}
+
+#[test]
+fn case_sensitive_tells_variants_apart_again() {
+    let dir = TempDir::new("case-sensitive-again");
+    case_variants(&dir);
+    ok(track(&dir).arg("--case-insensitive").arg("add").arg(dir.join("Docs")));
+    ok(track(&dir).arg("--case-sensitive").arg("add").arg(dir.join("docs")));
+    assert_eq!(ls(&dir).len(), 2);
+}
+
+#[test]
+fn names_which_arent_utf8_fold_their_ascii_letters() {
+    let dir = TempDir::new("case-insensitive-bytes");
+    let (upper, lower) = (
+        dir.join(OsStr::from_bytes(b"CAF\xe9")),
+        dir.join(OsStr::from_bytes(b"caf\xe9")),
+    );
+    let other = dir.join(OsStr::from_bytes(b"caf\xc9"));
+    for path in [&upper, &lower, &other] {
+        fs::create_dir(path).unwrap();
+    }
+    ok(track(&dir)
+        .arg("--case-insensitive")
+        .arg("add")
+        .arg(&upper)
+        .arg(&lower)
+        .arg(&other));
+    // Only ASCII letters are folded, the other bytes are left as they are.
+    let tracked = ok(track(&dir).args(["ls", "--null"]));
+    let tracked: Vec<_> = tracked.split('\0').filter(|path| !path.is_empty()).collect();
+    assert_eq!(tracked.len(), 2, "{:?}", tracked);
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::{
+    fs,
+    time::{Duration, SystemTime},
+};
+
+use common::{ok, track, TempDir};
+use filetime::FileTime;
+
+/// A tracked tree whose files and directories were all last modified two hours ago.
+fn old_tree(dir: &TempDir) {
+    let past = FileTime::from_system_time(SystemTime::now() - Duration::from_secs(7200));
+    for name in ["a", "sub/b", "sub/c"] {
+        filetime::set_file_mtime(dir.write(&format!("src/{}", name), name), past).unwrap();
+    }
+    for sub in ["src/sub", "src"] {
+        filetime::set_file_mtime(dir.join(sub), past).unwrap();
+    }
+    ok(track(dir).arg("add").arg(dir.join("src")));
+}
+
+/// Files listed by `changed --since 1h`, relative to the tracked path.
+fn changed(dir: &TempDir) -> Vec<String> {
+    let src = dir.join("src");
+    ok(track(dir).args(["changed", "--since", "1h"]))
+        .lines()
+        .map(|line| line.strip_prefix(src.to_str().unwrap()).unwrap().to_owned())
+        .collect()
+}
+
+#[test]
+fn created_files_are_listed() {
+    let dir = TempDir::new("changed-create");
+    old_tree(&dir);
+    // Nothing was cached yet, the first run walks everything.
+    assert_eq!(changed(&dir), Vec::<String>::new());
+
+    dir.write("src/sub/new", "new");
+    assert_eq!(changed(&dir), ["/sub/new"]);
+}
+
+#[test]
+fn files_renamed_over_are_listed() {
+    let dir = TempDir::new("changed-rename");
+    old_tree(&dir);
+    assert_eq!(changed(&dir), Vec::<String>::new());
+
+    // Like an editor saving through a temporary file.
+    dir.write("src/sub/.b.swp", "saved");
+    fs::rename(dir.join("src/sub/.b.swp"), dir.join("src/sub/b")).unwrap();
+    assert_eq!(changed(&dir), ["/sub/b"]);
+}
+
+#[test]
+fn files_rewritten_in_place_wait_for_their_directory_to_change() {
+    let dir = TempDir::new("changed-in-place");
+    old_tree(&dir);
+    assert_eq!(changed(&dir), Vec::<String>::new());
+
+    // Rewriting a file in place leaves the modification time of its directory as it was.
+    fs::write(dir.join("src/sub/b"), "rewritten").unwrap();
+    assert_eq!(changed(&dir), Vec::<String>::new());
+
+    // Once something else changes the directory, it's read again and the rewrite shows.
+    fs::remove_file(dir.join("src/sub/c")).unwrap();
+    assert_eq!(changed(&dir), ["/sub/b"]);
+}
+
+#[test]
+fn the_first_run_walks_everything() {
+    let dir = TempDir::new("changed-first");
+    old_tree(&dir);
+    // Rewritten in place before anything was cached.
+    fs::write(dir.join("src/a"), "rewritten").unwrap();
+    assert_eq!(changed(&dir), ["/a"]);
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use std::{path::PathBuf, process::Command};
+
+use common::{noise, ok, track, TempDir};
+
+/// Output of `program` run on `files`, which checksums the same way independently.
+fn coreutils(program: &str, args: &[&str], files: &[PathBuf]) -> Vec<u8> {
+    let output = Command::new(program).args(args).args(files).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+/// Files tracked under two paths added in reverse order, returned sorted by path.
+fn tree(dir: &TempDir) -> Vec<PathBuf> {
+    let files = vec![
+        dir.write("one/a", "a"),
+        dir.write("one/b\\x", "backslash"),
+        dir.write("one/empty", ""),
+        dir.write("one/sub/noise", noise(300_000)),
+        dir.write("two/c", "c"),
+    ];
+    ok(track(dir).arg("add").arg(dir.join("two")));
+    ok(track(dir).arg("add").arg(dir.join("one")));
+    files
+}
+
+#[test]
+fn checksums_match_coreutils_in_path_order() {
+    let dir = TempDir::new("checksum");
+    let files = tree(&dir);
+    for (hash, program) in [("sha256", "sha256sum"), ("sha512", "sha512sum"), ("md5", "md5sum")] {
+        let expected = coreutils(program, &[], &files);
+        assert_eq!(
+            ok(track(&dir).args(["checksum", "--hash", hash])).into_bytes(),
+            expected,
+            "{}",
+            hash
+        );
+        // Hashing in parallel keeps the order.
+        assert_eq!(
+            ok(track(&dir).args(["-j", "4", "checksum", "--hash", hash])).into_bytes(),
+            expected,
+            "{}",
+            hash
+        );
+    }
+    assert_eq!(
+        ok(track(&dir).arg("checksum")).into_bytes(),
+        coreutils("sha256sum", &[], &files)
+    );
+}
+
+#[test]
+fn blake3_checksums_are_known_digests() {
+    let dir = TempDir::new("checksum-blake3");
+    let empty = dir.write("src/empty", "");
+    let abc = dir.write("src/abc", "abc");
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    assert_eq!(
+        ok(track(&dir).args(["checksum", "--hash", "blake3"])),
+        format!(
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85  {}\n\
+             af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262  {}\n",
+            abc.display(),
+            empty.display()
+        )
+    );
+}
+
+#[test]
+fn null_separated_lines_match_sha256sum_z() {
+    let dir = TempDir::new("checksum-null");
+    let files = tree(&dir);
+    let output = track(&dir).args(["checksum", "-0"]).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, coreutils("sha256sum", &["-z"], &files));
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use std::{fs, path::Path};
+
+use common::{noise, ok, track, TempDir};
+
+/// Export to the store in 64K chunks, returning how many objects were new.
+fn export(dir: &TempDir, store: &Path, chunking: &str) -> usize {
+    let output =
+        ok(track(dir)
+            .args(["export", "cas"])
+            .arg(store)
+            .args(["--chunking", chunking, "--chunk-size", "64K"]));
+    output
+        .strip_prefix("Stored ")
+        .and_then(|rest| rest.split(' ').next())
+        .and_then(|new| new.parse().ok())
+        .unwrap_or_else(|| panic!("unexpected output {}", output))
+}
+
+/// Restore the latest export of the store and return the content of the big file.
+fn restored(dir: &TempDir, store: &Path) -> Vec<u8> {
+    let to = dir.join("restored");
+    let _ = fs::remove_dir_all(&to);
+    ok(track(dir)
+        .arg("restore")
+        .arg(store)
+        .arg("--to")
+        .arg(&to)
+        .arg("--verify"));
+    fs::read(to.join(dir.join("src/big").strip_prefix("/").unwrap())).unwrap()
+}
+
+#[test]
+fn fixed_chunks_store_the_changed_chunk_again() {
+    let dir = TempDir::new("chunking-fixed");
+    let mut content = noise(1024 * 1024);
+    let big = dir.write("src/big", &content);
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    let store = dir.join("store");
+    // 16 chunks and their list.
+    assert_eq!(export(&dir, &store, "fixed"), 17);
+
+    content[300_000..300_010].copy_from_slice(b"overwrite!");
+    fs::write(&big, &content).unwrap();
+    // The chunk with the change and the new list.
+    assert_eq!(export(&dir, &store, "fixed"), 2);
+    assert!(restored(&dir, &store) == content);
+}
+
+#[test]
+fn cdc_chunks_share_the_data_after_an_insertion() {
+    let dir = TempDir::new("chunking-cdc");
+    let mut content = noise(1024 * 1024);
+    let big = dir.write("src/big", &content);
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    let store = dir.join("store");
+    assert!(export(&dir, &store, "cdc") > 2);
+
+    content.splice(300_000..300_000, b"inserted".iter().copied());
+    fs::write(&big, &content).unwrap();
+    // The chunk with the insertion, maybe its neighbour if a cut moved, and the new list.
+    let new = export(&dir, &store, "cdc");
+    assert!((2..=3).contains(&new), "{} new objects", new);
+    assert!(restored(&dir, &store) == content);
+
+    // Fixed chunks after the insertion all moved.
+    let fixed_store = dir.join("fixed-store");
+    export(&dir, &fixed_store, "fixed");
+    content.splice(100..100, b"inserted".iter().copied());
+    fs::write(&big, &content).unwrap();
+    assert!(export(&dir, &fixed_store, "fixed") > 10);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::{exported_files, track, tracked_tree, TempDir};
+
+fn export(dir: &TempDir, extra: &[&str]) -> String {
+    let output = track(dir)
+        .args(["export", "dir"])
+        .arg(dir.join("dest"))
+        .args(extra)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn cleaning_lists_what_it_removes_and_warns_about_foreign_entries() {
+    let dir = TempDir::new("clean-preview");
+    tracked_tree(&dir, "src", &[("a", "hello")]);
+    let dest = dir.join("dest");
+    // Nothing to clean the first time.
+    assert_eq!(export(&dir, &[]), "");
+
+    dir.write("dest/.git/HEAD", "ref");
+    dir.write("dest/junk/x", "12345");
+    dir.write("dest/notes", "z");
+    let top = dir
+        .path()
+        .components()
+        .nth(1)
+        .unwrap()
+        .as_os_str()
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(
+        export(&dir, &[]),
+        format!(
+            "Cleaning {} removes 3 entries, 3 files, 11 B:\n\
+             \x20 junk/  1 file, 5 B  (not written by track)\n\
+             \x20 notes  1 file, 1 B  (not written by track)\n\
+             \x20 {}/  1 file, 5 B\n\
+             Warning: no previous export wrote junk, notes, check {} is the right destination\n",
+            dest.display(),
+            top,
+            dest.display()
+        )
+    );
+    // .git is left alone, the rest is replaced by the export.
+    assert!(dest.join(".git/HEAD").exists());
+    assert!(!dest.join("junk").exists() && !dest.join("notes").exists());
+    let files = exported_files(&dest);
+    assert_eq!(files.len(), 2, "{:?}", files);
+    assert!(files.iter().any(|file| file.ends_with("src/a")), "{:?}", files);
+
+    // Entries a previous export wrote are removed without a warning.
+    assert_eq!(
+        export(&dir, &[]),
+        format!(
+            "Cleaning {} removes 1 entry, 1 file, 5 B:\n  {}/  1 file, 5 B\n",
+            dest.display(),
+            top
+        )
+    );
+}
+
+#[test]
+fn yes_cleans_without_listing() {
+    let dir = TempDir::new("clean-preview-yes");
+    tracked_tree(&dir, "src", &[("a", "hello")]);
+    dir.write("dest/stray", "stray");
+    assert_eq!(export(&dir, &["--yes"]), "");
+    assert!(!dir.join("dest/stray").exists());
+    assert_eq!(exported_files(&dir.join("dest")).len(), 1);
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use std::fs;
+
+use common::{files_under, ok, track, tracked_tree, TempDir};
+
+/// A tracked tree where `f.txt` and `noext` are the names of several files.
+fn colliding_tree(dir: &TempDir) -> std::path::PathBuf {
+    tracked_tree(
+        dir,
+        "src",
+        &[
+            ("x/f.txt", "x"),
+            ("x/noext", "xn"),
+            ("y/f.txt", "y"),
+            ("y/noext", "yn"),
+            ("z/f.txt", "z"),
+        ],
+    )
+}
+
+#[test]
+fn numbers_are_appended_to_the_stem_of_colliding_names() {
+    let dir = TempDir::new("collect-number");
+    let src = colliding_tree(&dir);
+    let dest = dir.join("dest");
+    assert_eq!(
+        ok(track(&dir).arg("collect").arg(&dest)),
+        format!(
+            "Collision: {0}/y/f.txt collected as f-1.txt\nCollision: {0}/y/noext collected as noext-1\n\
+             Collision: {0}/z/f.txt collected as f-2.txt\nCollected 5 files, 3 renamed\n",
+            src.display()
+        )
+    );
+    assert_eq!(files_under(&dest), ["f-1.txt", "f-2.txt", "f.txt", "noext", "noext-1"]);
+    for (name, content) in [
+        ("f.txt", "x"),
+        ("f-1.txt", "y"),
+        ("f-2.txt", "z"),
+        ("noext", "xn"),
+        ("noext-1", "yn"),
+    ] {
+        assert_eq!(fs::read_to_string(dest.join(name)).unwrap(), content, "{}", name);
+    }
+
+    // Collecting again replaces the files instead of renaming more copies.
+    ok(track(&dir).arg("collect").arg(&dest));
+    assert_eq!(files_under(&dest).len(), 5);
+}
+
+#[test]
+fn hashes_of_the_path_are_stable_across_runs() {
+    let dir = TempDir::new("collect-hash");
+    colliding_tree(&dir);
+    let mut runs = Vec::new();
+    for dest in ["first", "second"] {
+        let dest = dir.join(dest);
+        ok(track(&dir).arg("collect").arg(&dest).args(["--on-collision", "hash"]));
+        runs.push(files_under(&dest));
+    }
+    assert_eq!(runs[0], runs[1]);
+    let files = &runs[0];
+    assert_eq!(files.len(), 5, "{:?}", files);
+    let renamed: Vec<_> = files
+        .iter()
+        .filter(|name| name.as_str() != "f.txt" && name.as_str() != "noext")
+        .collect();
+    assert_eq!(renamed.len(), 3, "{:?}", files);
+    for name in renamed {
+        let (stem, suffix) = name.split_once('-').unwrap();
+        let hash = suffix.strip_suffix(".txt").unwrap_or(suffix);
+        assert!(stem == "f" || stem == "noext", "{}", name);
+        assert!(
+            hash.len() == 8 && hash.bytes().all(|b| b.is_ascii_hexdigit()),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn dry_runs_list_the_plan_without_copying() {
+    let dir = TempDir::new("collect-dry-run");
+    let src = colliding_tree(&dir);
+    let dest = dir.join("dest");
+    assert_eq!(
+        ok(track(&dir).arg("collect").arg(&dest).arg("--dry-run")),
+        format!(
+            "{0}/x/f.txt -> {1}/f.txt\n{0}/x/noext -> {1}/noext\n{0}/y/f.txt -> {1}/f-1.txt\n\
+             {0}/y/noext -> {1}/noext-1\n{0}/z/f.txt -> {1}/f-2.txt\nWould collect 5 files, 3 renamed\n",
+            src.display(),
+            dest.display()
+        )
+    );
+    assert!(!dest.exists());
+}
@@ -0,0 +1,184 @@
+//! Helpers running the track binary against a throwaway database and tree.
+#![allow(dead_code)]
+
+use std::{
+    ffi::CString,
+    fs,
+    io::Write,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A directory under the system temporary directory, removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        TempDir::new_in(&std::env::temp_dir(), name)
+    }
+
+    /// A directory under `parent`, for tests needing another filesystem than the temporary directory.
+    pub fn new_in(parent: &Path, name: &str) -> TempDir {
+        let path = parent.join(format!(
+            "track-test-{}-{}-{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+
+    /// Write `content` to `name` under the directory, creating its parents.
+    pub fn write(&self, name: &str, content: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // Restores and cas stores leave read-only files, which don't prevent removing them.
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The track binary using the database and config directory of `dir`, isolated from the environment.
+pub fn track(dir: &TempDir) -> Command {
+    let mut command = track_without_db(dir);
+    command.arg("--db").arg(dir.join("track.db"));
+    command
+}
+
+/// The track binary like `track`, left to find its database in the config directory of `dir`.
+pub fn track_without_db(dir: &TempDir) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_track"));
+    command
+        .env_remove("TRACK_DB")
+        .env_remove("TRACK_SKIP_DIRS")
+        .env("XDG_CONFIG_HOME", dir.join("config"));
+    command
+}
+
+/// Run `command`, failing the test unless it succeeds, and return its stdout.
+pub fn ok(command: &mut Command) -> String {
+    let output = command.output().unwrap();
+    assert!(
+        output.status.success(),
+        "{:?} failed with {}\nstdout: {}\nstderr: {}",
+        command,
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Run `command`, failing the test if it succeeds, and return its output.
+pub fn fails(command: &mut Command) -> Output {
+    let output = command.output().unwrap();
+    assert!(
+        !output.status.success(),
+        "{:?} succeeded\nstdout: {}",
+        command,
+        String::from_utf8_lossy(&output.stdout)
+    );
+    output
+}
+
+/// Run `command` with `stdin` written to its standard input, and return its output.
+pub fn with_stdin(command: &mut Command, stdin: impl AsRef<[u8]>) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_ref()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Bytes of a linear congruential generator, which deflate can't shrink and no two chunks share.
+pub fn noise(len: usize) -> Vec<u8> {
+    let mut state: u32 = 1;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+/// Write `files`, names and contents, under the directory `root` of `dir` and track it, returning its path.
+pub fn tracked_tree(dir: &TempDir, root: impl AsRef<Path>, files: &[(impl AsRef<Path>, impl AsRef<[u8]>)]) -> PathBuf {
+    let root = dir.join(root);
+    fs::create_dir_all(&root).unwrap();
+    for (name, content) in files {
+        let path = root.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+    }
+    ok(track(dir).arg("add").arg(&root));
+    root
+}
+
+/// Replace the regular file `path` with a fifo nothing ever writes to, keeping the modification time
+/// of its directory so the scan cache still lists it as the regular file it was.
+pub fn replace_with_fifo(path: &Path) {
+    let parent = path.parent().unwrap();
+    let mtime = filetime::FileTime::from_last_modification_time(&fs::metadata(parent).unwrap());
+    fs::remove_file(path).unwrap();
+    let fifo = CString::new(path.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+    filetime::set_file_mtime(parent, mtime).unwrap();
+}
+
+/// Relative paths of the files under `dir`, sorted.
+pub fn files_under(dir: &Path) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(current) = stack.pop() {
+        for entry in fs::read_dir(&current).unwrap() {
+            let entry = entry.unwrap();
+            let path = entry.path();
+            if entry.file_type().unwrap().is_dir() {
+                stack.push(path);
+            } else {
+                files.push(path.strip_prefix(dir).unwrap().to_string_lossy().into_owned());
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Relative paths of the files a dir export wrote under `dest`, sorted, without its marker file.
+pub fn exported_files(dest: &Path) -> Vec<String> {
+    let mut files = files_under(dest);
+    files.retain(|file| file != ".track-export");
+    files
+}
+
+/// Names of the entries of the tar.gz `archive`, in the order they were written.
+pub fn tar_names(archive: &Path) -> Vec<String> {
+    tar::Archive::new(flate2::read::GzDecoder::new(fs::File::open(archive).unwrap()))
+        .entries()
+        .unwrap()
+        .map(|entry| entry.unwrap().path().unwrap().to_string_lossy().into_owned())
+        .collect()
+}
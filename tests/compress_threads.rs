@@ -0,0 +1,47 @@
+mod common;
+
+use std::fs::{self, File};
+
+use common::{files_under, noise, ok, track, TempDir};
+use flate2::read::MultiGzDecoder;
+
+/// Export a tar archive compressed on `threads` threads and extract it, returning where.
+fn export_and_extract(dir: &TempDir, threads: &str) -> std::path::PathBuf {
+    let archive = dir.join(format!("out-{}.tar.gz", threads));
+    ok(track(dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .args(["--compress-threads", threads]));
+    let extracted = dir.join(format!("extracted-{}", threads));
+    // Parallel compression writes one gzip member per block.
+    tar::Archive::new(MultiGzDecoder::new(File::open(&archive).unwrap()))
+        .unpack(&extracted)
+        .unwrap();
+    extracted
+}
+
+#[test]
+fn parallel_compression_extracts_like_single_threaded() {
+    let dir = TempDir::new("compress-threads");
+    dir.write("src/empty", "");
+    dir.write("src/text", "some text\n".repeat(200_000));
+    dir.write("src/sub/noise", noise(3 * 1024 * 1024 + 17));
+    dir.write("src/sub/small", "small");
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+
+    let single = export_and_extract(&dir, "1");
+    let files = files_under(&single);
+    assert_eq!(files.len(), 4, "{:?}", files);
+    for threads in ["2", "4"] {
+        let parallel = export_and_extract(&dir, threads);
+        assert_eq!(files_under(&parallel), files);
+        for file in &files {
+            assert!(
+                fs::read(single.join(file)).unwrap() == fs::read(parallel.join(file)).unwrap(),
+                "{} differs with {} threads",
+                file,
+                threads
+            );
+        }
+    }
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use common::{ok, track, TempDir};
+
+/// The `"name":{"value":...,"source":...}` field of a setting in config show --json.
+fn setting(name: &str, value: &str, source: &str) -> String {
+    format!(r#""{}":{{"value":{},"source":"{}"}}"#, name, value, source)
+}
+
+#[test]
+fn config_show_reports_the_symlink_and_compression_settings() {
+    let dir = TempDir::new("config-show");
+    let output = ok(track(&dir).args(["config", "show", "--json"]));
+    assert!(
+        output.contains(&setting("symlinked dirs", r#""skip""#, "default")),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains(&setting("compression", r#""gzip level 6""#, "default")),
+        "{}",
+        output
+    );
+
+    let output = ok(track(&dir).args([
+        "config",
+        "show",
+        "--json",
+        "--symlinked-dirs",
+        "record",
+        "--compress-threads",
+        "4",
+    ]));
+    assert!(
+        output.contains(&setting("symlinked dirs", r#""record""#, "command line")),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains(&setting(
+            "compression",
+            r#""gzip level 6 on 4 threads""#,
+            "command line"
+        )),
+        "{}",
+        output
+    );
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use std::{fs::File, io::Read, path::Path};
+
+use common::{fails, ok, track, tracked_tree, TempDir};
+use filetime::FileTime;
+use flate2::read::GzDecoder;
+
+/// Name, kind, mode, modification time and content or link target of every entry of a tar.gz archive.
+fn entries(archive: &Path) -> Vec<(String, char, u32, u64, Vec<u8>)> {
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(archive).unwrap()));
+    archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().display().to_string();
+            let header = entry.header().clone();
+            let (kind, data) = match header.entry_type() {
+                tar::EntryType::Link => ('h', entry.link_name_bytes().unwrap().into_owned()),
+                tar::EntryType::Symlink => ('l', entry.link_name_bytes().unwrap().into_owned()),
+                _ => {
+                    let mut content = Vec::new();
+                    entry.read_to_end(&mut content).unwrap();
+                    ('f', content)
+                }
+            };
+            (
+                name,
+                kind,
+                header.mode().unwrap() & 0o7777,
+                header.mtime().unwrap(),
+                data,
+            )
+        })
+        .collect()
+}
+
+/// Convert `archive` to zip, then back to tar.gz, returning the final archive.
+fn round_trip(dir: &TempDir, archive: &Path) -> std::path::PathBuf {
+    let zip = archive.with_extension("zip");
+    let back = dir.join("back.tar.gz");
+    ok(track(dir).arg("convert").arg(archive).arg(&zip));
+    ok(track(dir).arg("convert").arg(&zip).arg(&back));
+    back
+}
+
+fn tree(dir: &TempDir) -> std::path::PathBuf {
+    let src = tracked_tree(
+        dir,
+        "src",
+        &[("a", "same"), ("dup", "same"), ("sub/run", "#!/bin/sh\n")],
+    );
+    std::fs::set_permissions(src.join("sub/run"), std::os::unix::fs::PermissionsExt::from_mode(0o750)).unwrap();
+    for (i, file) in ["a", "dup", "sub/run"].iter().enumerate() {
+        filetime::set_file_mtime(src.join(file), FileTime::from_unix_time(1_500_000_000 + i as i64, 0)).unwrap();
+    }
+    src
+}
+
+#[test]
+fn files_keep_their_names_modes_and_mtimes() {
+    let dir = TempDir::new("convert");
+    tree(&dir);
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir).args(["export", "tar"]).arg(&archive));
+    let original = entries(&archive);
+    assert_eq!(original.len(), 3);
+    assert!(original
+        .iter()
+        .any(|(name, _, mode, _, _)| name.ends_with("sub/run") && *mode == 0o750));
+    assert_eq!(entries(&round_trip(&dir, &archive)), original);
+}
+
+#[test]
+fn symlinks_survive_and_hard_links_become_copies() {
+    let dir = TempDir::new("convert-links");
+    let src = tree(&dir);
+    std::os::unix::fs::symlink("sub", src.join("link")).unwrap();
+
+    let archive = dir.join("links.tar.gz");
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .args(["--symlinked-dirs", "record"]));
+    let original = entries(&archive);
+    assert!(original
+        .iter()
+        .any(|(name, kind, _, _, target)| name.ends_with("src/link") && *kind == 'l' && target == b"sub"));
+    assert_eq!(entries(&round_trip(&dir, &archive)), original);
+
+    // Zip has no hard links, the content is copied instead.
+    let archive = dir.join("dedupe.tar.gz");
+    ok(track(&dir).args(["export", "tar"]).arg(&archive).arg("--dedupe"));
+    let deduped = entries(&archive);
+    assert!(deduped
+        .iter()
+        .any(|(name, kind, _, _, _)| name.ends_with("src/dup") && *kind == 'h'));
+    let copied: Vec<_> = deduped
+        .into_iter()
+        .map(|(name, kind, mode, mtime, data)| match kind {
+            'h' => (name, 'f', mode, mtime, b"same".to_vec()),
+            _ => (name, kind, mode, mtime, data),
+        })
+        .collect();
+    assert_eq!(entries(&round_trip(&dir, &archive)), copied);
+}
+
+#[test]
+fn destinations_need_a_known_format() {
+    let dir = TempDir::new("convert-errors");
+    tree(&dir);
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir).args(["export", "tar"]).arg(&archive));
+    for (dest, message) in [
+        ("out.rar", "can't tell the format of"),
+        ("out.tar.gz", "can't be converted into itself"),
+    ] {
+        let output = fails(track(&dir).arg("convert").arg(&archive).arg(dir.join(dest)));
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains(message), "{}", dest);
+    }
+    let text = dir.write("notes.txt", "not an archive");
+    let output = fails(track(&dir).arg("convert").arg(&text).arg(dir.join("out.zip")));
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not a tar.gz or zip archive"));
+}
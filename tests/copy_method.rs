@@ -0,0 +1,92 @@
+mod common;
+
+use std::{
+    fs,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::Path,
+};
+
+use common::{exported_files, noise, ok, track, tracked_tree, TempDir};
+
+const METHODS: [&str; 4] = ["auto", "buffered", "copy_file_range", "sendfile"];
+
+/// Track files larger than the copy buffer, empty and executable, returning their directory.
+fn large_tree(dir: &TempDir) -> std::path::PathBuf {
+    let src = tracked_tree(
+        dir,
+        "src",
+        &[
+            ("large", noise(3 * 1024 * 1024 + 17)),
+            ("empty", Vec::new()),
+            ("run.sh", b"#!/bin/sh\necho hi\n".to_vec()),
+        ],
+    );
+    fs::set_permissions(src.join("run.sh"), fs::Permissions::from_mode(0o750)).unwrap();
+    src
+}
+
+fn assert_copied(src: &Path, dest: &Path, method: &str) {
+    let exported = dest.join(src.strip_prefix("/").unwrap());
+    assert_eq!(exported_files(dest).len(), 3, "{}", method);
+    for name in ["large", "empty", "run.sh"] {
+        let (original, copy) = (src.join(name), exported.join(name));
+        assert!(
+            fs::read(&original).unwrap() == fs::read(&copy).unwrap(),
+            "{} differs with {}",
+            name,
+            method
+        );
+        assert_eq!(
+            fs::metadata(&original).unwrap().mode() & 0o7777,
+            fs::metadata(&copy).unwrap().mode() & 0o7777,
+            "{} with {}",
+            name,
+            method
+        );
+    }
+}
+
+#[test]
+fn every_method_copies_the_contents_intact() {
+    let dir = TempDir::new("copy-method");
+    let src = large_tree(&dir);
+    for method in METHODS {
+        let dest = dir.join(format!("dest-{}", method));
+        ok(track(&dir)
+            .args(["export", "dir"])
+            .arg(&dest)
+            .args(["--reflink", "never", "--copy-method", method]));
+        assert_copied(&src, &dest, method);
+    }
+}
+
+#[test]
+fn auto_falls_back_to_other_copies_across_filesystems() {
+    let dir = TempDir::new("copy-method-fallback");
+    let src = large_tree(&dir);
+    let shm = Path::new("/dev/shm");
+    if !shm.is_dir() || fs::metadata(shm).unwrap().dev() == fs::metadata(dir.path()).unwrap().dev() {
+        eprintln!("Skipping, no other filesystem to copy to");
+        return;
+    }
+    let dest_dir = TempDir::new_in(shm, "copy-method-fallback-dest");
+    for method in METHODS {
+        let dest = dest_dir.join(method);
+        let output = track(&dir)
+            .args(["export", "dir"])
+            .arg(&dest)
+            .args(["--reflink", "never", "--copy-method", method])
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // Depending on the kernel, copy_file_range refuses to copy across filesystems, which only
+        // the fallback of auto works around.
+        if method == "copy_file_range" && !output.status.success() {
+            assert_eq!(output.status.code(), Some(4), "{}", stderr);
+            assert!(stderr.contains("Invalid cross-device link"), "{}", stderr);
+            continue;
+        }
+        assert!(output.status.success(), "{}: {}", method, stderr);
+        assert_copied(&src, &dest, method);
+    }
+}
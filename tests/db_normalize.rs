@@ -0,0 +1,88 @@
+mod common;
+
+use std::fs;
+
+use common::{ok, track, TempDir};
+use rusqlite::Connection;
+
+/// Rows as older versions or hand edits may have stored them: with `.`, `..`, trailing slashes,
+/// duplicates and a path nested under another.
+fn unnormalized(name: &str) -> (TempDir, String) {
+    let dir = TempDir::new(name);
+    fs::create_dir_all(dir.join("src/sub")).unwrap();
+    fs::create_dir_all(dir.join("other")).unwrap();
+    let base = dir.path().display().to_string();
+    let conn = Connection::open(dir.join("track.db")).unwrap();
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    for path in ["./src", "src/", "src/sub", "x/../other/"] {
+        conn.execute(
+            "INSERT INTO paths (path) VALUES (?)",
+            [format!("{}/{}", base, path).into_bytes()],
+        )
+        .unwrap();
+    }
+    conn.execute(
+        "INSERT INTO tags (path, tag) VALUES (?, 'work')",
+        [format!("{}/src/", base).into_bytes()],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO excludes (path, pattern) VALUES (?, '*.log')",
+        [format!("{}/./src", base).into_bytes()],
+    )
+    .unwrap();
+    (dir, base)
+}
+
+#[test]
+fn duplicate_and_nested_paths_are_collapsed() {
+    let (dir, base) = unnormalized("db-normalize");
+    let changes = [
+        format!("normalize {}/./src to {}/src", base, base),
+        format!("merge {}/src/ into {}/src, the same path", base, base),
+        format!("normalize {}/x/../other/ to {}/other", base, base),
+        format!("remove {}/src/sub, nested under {}/src", base, base),
+    ];
+    let db = fs::read(dir.join("track.db")).unwrap();
+    let planned: String = changes.iter().map(|change| format!("Would {}\n", change)).collect();
+    assert_eq!(
+        ok(track(&dir).args(["db-normalize", "--dry-run"])),
+        planned + "Would make 4 changes\n"
+    );
+    assert_eq!(fs::read(dir.join("track.db")).unwrap(), db);
+
+    let done: String = ["Normalized", "Merged", "Normalized", "Removed"]
+        .iter()
+        .zip(&changes)
+        .map(|(verb, change)| format!("{}{}\n", verb, &change[change.find(' ').unwrap()..]))
+        .collect();
+    assert_eq!(ok(track(&dir).arg("db-normalize")), done + "Made 4 changes\n");
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}/other\n{}/src\n", base, base));
+    assert_eq!(
+        ok(track(&dir).arg("db-normalize")),
+        "The database is normalized already\n"
+    );
+
+    // The tags and excludes of the merged rows are kept.
+    let conn = Connection::open(dir.join("track.db")).unwrap();
+    let src = format!("{}/src", base).into_bytes();
+    let tag: String = conn
+        .query_row("SELECT tag FROM tags WHERE path = ?", [&src], |row| row.get(0))
+        .unwrap();
+    assert_eq!(tag, "work");
+    let pattern: String = conn
+        .query_row("SELECT pattern FROM excludes WHERE path = ?", [&src], |row| row.get(0))
+        .unwrap();
+    assert_eq!(pattern, "*.log");
+}
+
+#[test]
+fn removed_nested_paths_can_be_undone() {
+    let (dir, base) = unnormalized("db-normalize-undo");
+    ok(track(&dir).arg("db-normalize"));
+    ok(track(&dir).arg("undo"));
+    assert_eq!(
+        ok(track(&dir).arg("ls")),
+        format!("{}/other\n{}/src\n{}/src/sub\n", base, base, base)
+    );
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use std::process::Command;
+
+use common::{ok, track_without_db, TempDir};
+
+/// The track binary without a config directory, left to find its database from `--db` or `TRACK_DB`.
+fn track_without_config(dir: &TempDir) -> Command {
+    let mut command = track_without_db(dir);
+    command.env_remove("XDG_CONFIG_HOME").env_remove("HOME");
+    command
+}
+
+#[test]
+fn the_environment_chooses_the_database() {
+    let dir = TempDir::new("db-env");
+    let src = dir.write("src/a", "a").parent().unwrap().to_path_buf();
+    let db = dir.join("env.db");
+    ok(track_without_config(&dir).env("TRACK_DB", &db).arg("add").arg(&src));
+    assert!(db.exists());
+    assert_eq!(
+        ok(track_without_config(&dir).env("TRACK_DB", &db).arg("ls")),
+        format!("{}\n", src.display())
+    );
+    assert!(
+        ok(track_without_config(&dir).env("TRACK_DB", &db).args(["config", "show"]))
+            .contains(&format!("{} (environment)", db.display()))
+    );
+}
+
+#[test]
+fn the_command_line_chooses_the_database() {
+    let dir = TempDir::new("db-flag");
+    let src = dir.write("src/a", "a").parent().unwrap().to_path_buf();
+    let db = dir.join("flag.db");
+    ok(track_without_config(&dir).arg("--db").arg(&db).arg("add").arg(&src));
+    assert!(db.exists());
+    assert_eq!(
+        ok(track_without_config(&dir).arg("--db").arg(&db).arg("ls")),
+        format!("{}\n", src.display())
+    );
+}
+
+#[test]
+fn the_command_line_beats_the_environment() {
+    let dir = TempDir::new("db-both");
+    let flag = dir.join("flag.db");
+    let env = dir.join("env.db");
+    let src = dir.write("src/a", "a").parent().unwrap().to_path_buf();
+    ok(track_without_config(&dir)
+        .env("TRACK_DB", &env)
+        .arg("--db")
+        .arg(&flag)
+        .arg("add")
+        .arg(&src));
+    assert!(flag.exists());
+    assert!(!env.exists());
+    assert!(ok(track_without_config(&dir)
+        .env("TRACK_DB", &env)
+        .arg("--db")
+        .arg(&flag)
+        .args(["config", "show"]))
+    .contains(&format!("{} (command line)", flag.display())));
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use std::fs::{self, File};
+
+use common::{files_under, noise, ok, track, tracked_tree, TempDir};
+use flate2::read::GzDecoder;
+
+/// A tracked tree where `a` and `sub/copy-of-a` have the same content.
+fn duplicated_tree(dir: &TempDir) -> std::path::PathBuf {
+    let data = noise(200_000);
+    let mut other = data.clone();
+    other[0] ^= 1;
+    tracked_tree(dir, "src", &[("a", &data), ("sub/copy-of-a", &data), ("other", &other)])
+}
+
+/// Extract the tar.gz `archive` under `dir`, returning where its tracked tree `src` is.
+fn extract(archive: &std::path::Path, dir: &std::path::Path, src: &std::path::Path) -> std::path::PathBuf {
+    tar::Archive::new(GzDecoder::new(File::open(archive).unwrap()))
+        .unpack(dir)
+        .unwrap();
+    dir.join(src.strip_prefix("/").unwrap())
+}
+
+#[test]
+fn tar_archives_store_identical_files_once() {
+    let dir = TempDir::new("dedupe-tar");
+    let src = duplicated_tree(&dir);
+    let (plain, deduped) = (dir.join("plain.tar.gz"), dir.join("deduped.tar.gz"));
+    ok(track(&dir).args(["export", "tar"]).arg(&plain));
+    ok(track(&dir).args(["export", "tar"]).arg(&deduped).arg("--dedupe"));
+
+    // The copy is a hard link to the first file with its content.
+    let mut links = Vec::new();
+    for entry in tar::Archive::new(GzDecoder::new(File::open(&deduped).unwrap()))
+        .entries()
+        .unwrap()
+    {
+        let entry = entry.unwrap();
+        if entry.header().entry_type().is_hard_link() {
+            let name = entry.path().unwrap().into_owned();
+            links.push((name, entry.link_name().unwrap().unwrap().into_owned()));
+        }
+    }
+    let stored = src.strip_prefix("/").unwrap();
+    assert_eq!(links, [(stored.join("sub/copy-of-a"), stored.join("a"))]);
+    let (plain_size, deduped_size) = (
+        fs::metadata(&plain).unwrap().len(),
+        fs::metadata(&deduped).unwrap().len(),
+    );
+    assert!(
+        deduped_size < plain_size * 3 / 4,
+        "{} is not smaller than {}",
+        deduped_size,
+        plain_size
+    );
+
+    let extracted = extract(&deduped, &dir.join("extracted"), &src);
+    assert_eq!(files_under(&extracted), ["a", "other", "sub/copy-of-a"]);
+    for name in ["a", "other", "sub/copy-of-a"] {
+        assert!(
+            fs::read(extracted.join(name)).unwrap() == fs::read(src.join(name)).unwrap(),
+            "{} differs",
+            name
+        );
+    }
+}
+
+#[test]
+fn zip_archives_reuse_the_content_of_identical_files() {
+    let dir = TempDir::new("dedupe-zip");
+    let src = duplicated_tree(&dir);
+    let (plain, deduped) = (dir.join("plain.zip"), dir.join("deduped.zip"));
+    ok(track(&dir).args(["export", "zip"]).arg(&plain));
+    ok(track(&dir).args(["export", "zip"]).arg(&deduped).arg("--dedupe"));
+    // The compressed content is copied as it is, which zip can't share between entries.
+    assert_eq!(
+        fs::metadata(&plain).unwrap().len(),
+        fs::metadata(&deduped).unwrap().len()
+    );
+
+    // Converting reads every entry back, checking its CRC.
+    let converted = dir.join("converted.tar.gz");
+    ok(track(&dir).arg("convert").arg(&deduped).arg(&converted));
+    let extracted = extract(&converted, &dir.join("extracted"), &src);
+    assert_eq!(files_under(&extracted), ["a", "other", "sub/copy-of-a"]);
+    for name in ["a", "other", "sub/copy-of-a"] {
+        assert!(
+            fs::read(extracted.join(name)).unwrap() == fs::read(src.join(name)).unwrap(),
+            "{} differs",
+            name
+        );
+    }
+}
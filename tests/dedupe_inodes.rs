@@ -0,0 +1,60 @@
+mod common;
+
+use std::fs;
+
+use common::{ok, tar_names, track, TempDir};
+
+/// A file hard linked at three paths under two tracked paths, next to an unrelated file.
+fn hardlinked_tree(dir: &TempDir) {
+    let a = dir.write("src/a", "a");
+    fs::create_dir_all(dir.join("src/b")).unwrap();
+    fs::hard_link(&a, dir.join("src/b/hard")).unwrap();
+    fs::create_dir(dir.join("other")).unwrap();
+    fs::hard_link(&a, dir.join("other/c")).unwrap();
+    dir.write("src/d", "d");
+    ok(track(dir).arg("add").arg(dir.join("src")).arg(dir.join("other")));
+}
+
+#[test]
+fn hard_links_are_matched_once_with_dedupe_inodes() {
+    let dir = TempDir::new("dedupe-inodes");
+    hardlinked_tree(&dir);
+    assert_eq!(ok(track(&dir).arg("matched")).lines().count(), 4);
+
+    // The first path found is kept, other is scanned before src.
+    assert_eq!(
+        ok(track(&dir).args(["matched", "--dedupe-inodes"])),
+        format!("{}\n{}\n", dir.join("other/c").display(), dir.join("src/d").display())
+    );
+    assert_eq!(
+        ok(track(&dir).args(["matched", "--dedupe-inodes", "--show-excluded"])),
+        format!(
+            "{}: same file as one matched before, left out by --dedupe-inodes\n\
+             {}: same file as one matched before, left out by --dedupe-inodes\n",
+            dir.join("src/a").display(),
+            dir.join("src/b/hard").display()
+        )
+    );
+
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir).args(["export", "tar"]).arg(&archive).arg("--dedupe-inodes"));
+    let names = tar_names(&archive);
+    assert_eq!(names.len(), 2, "{:?}", names);
+    assert!(
+        names[0].ends_with("other/c") && names[1].ends_with("src/d"),
+        "{:?}",
+        names
+    );
+}
+
+#[test]
+fn files_under_nested_tracked_paths_are_matched_once_with_dedupe_inodes() {
+    let dir = TempDir::new("dedupe-inodes-nested");
+    dir.write("src/sub/a", "a");
+    ok(track(&dir).arg("add").arg(dir.join("src")).arg(dir.join("src/sub")));
+    assert_eq!(ok(track(&dir).arg("matched")).lines().count(), 2);
+    assert_eq!(
+        ok(track(&dir).args(["matched", "--dedupe-inodes"])),
+        format!("{}\n", dir.join("src/sub/a").display())
+    );
+}
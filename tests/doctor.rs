@@ -0,0 +1,114 @@
+mod common;
+
+use std::fs;
+
+use common::{fails, ok, track, TempDir};
+
+/// Checks printed by doctor, without the database and journal mode ones, which don't depend on the test.
+fn checks(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter(|line| !line.contains("  database: ") && !line.contains("  journal mode: "))
+        .map(str::to_owned)
+        .collect()
+}
+
+#[test]
+fn a_healthy_database_passes() {
+    let dir = TempDir::new("doctor-healthy");
+    let (a, b, config) = (dir.join("a"), dir.join("b"), dir.join("config"));
+    for path in [&a, &b, &config] {
+        fs::create_dir(path).unwrap();
+    }
+    ok(track(&dir).arg("add").arg(&a).arg(&b));
+
+    let output = ok(track(&dir).arg("doctor"));
+    assert!(
+        output.starts_with("pass   database: opened, schema version "),
+        "{}",
+        output
+    );
+    assert_eq!(
+        checks(&output),
+        [
+            format!("pass   config dir: {} is writable", config.display()),
+            format!("pass   root: {} is readable", a.display()),
+            format!("pass   root: {} is readable", b.display()),
+        ]
+    );
+}
+
+#[test]
+fn an_unhealthy_database_fails_until_fixed() {
+    let dir = TempDir::new("doctor-unhealthy");
+    let (a, sub, missing, config) = (
+        dir.join("a"),
+        dir.join("a/sub"),
+        dir.join("missing"),
+        dir.join("config"),
+    );
+    fs::create_dir_all(&sub).unwrap();
+    ok(track(&dir).arg("add").arg(&a).arg(&sub));
+    ok(track(&dir).arg("add").arg(&missing).arg("--force"));
+
+    let output = fails(track(&dir).arg("doctor"));
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        checks(&String::from_utf8_lossy(&output.stdout)),
+        [
+            format!("fail   config dir: {} is missing", config.display()),
+            format!("pass   root: {} is readable", a.display()),
+            format!("pass   root: {} is readable", sub.display()),
+            format!("fail   root: {} is missing", missing.display()),
+            format!(
+                "warn   nesting: {} is under {}, which already matches it",
+                sub.display(),
+                a.display()
+            ),
+        ]
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("2 of 7 checks failed"));
+    // Nothing was changed.
+    assert_eq!(
+        ok(track(&dir).arg("ls")),
+        format!("{}\n{}\n{}\n", a.display(), sub.display(), missing.display())
+    );
+
+    let output = fails(track(&dir).args(["doctor", "--json"]));
+    let json = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        json.starts_with(r#"{"version":1,"command":"doctor","data":["#),
+        "{}",
+        json
+    );
+    assert!(
+        json.contains(&format!(
+            r#"{{"check":"root","status":"fail","message":"{} is missing"}}"#,
+            missing.display()
+        )),
+        "{}",
+        json
+    );
+
+    fs::create_dir(&config).unwrap();
+    let output = ok(track(&dir).args(["doctor", "--fix"]));
+    assert_eq!(
+        checks(&output)[3..],
+        [
+            format!("fixed  root: {} is missing, removed", missing.display()),
+            format!("fixed  nesting: {} is under {}, removed", sub.display(), a.display()),
+        ]
+    );
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n", a.display()));
+    assert_eq!(
+        checks(&ok(track(&dir).arg("doctor"))),
+        [
+            format!("pass   config dir: {} is writable", config.display()),
+            format!("pass   root: {} is readable", a.display()),
+        ]
+    );
+
+    // The fix can be undone.
+    ok(track(&dir).arg("undo"));
+    assert_eq!(ok(track(&dir).arg("ls")).lines().count(), 3);
+}
@@ -0,0 +1,155 @@
+mod common;
+
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    path::Path,
+};
+
+use common::{fails, ok, track, tracked_tree, TempDir};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+/// Copy the tar.gz `archive` to `tampered` with the content `from` swapped for `to` in place, so the
+/// headers and pax records are left as they are.
+fn tamper(archive: &Path, tampered: &Path, from: &[u8], to: &[u8]) {
+    assert_eq!(from.len(), to.len());
+    let mut raw = Vec::new();
+    GzDecoder::new(File::open(archive).unwrap())
+        .read_to_end(&mut raw)
+        .unwrap();
+    let at = raw.windows(from.len()).position(|window| window == from).unwrap();
+    raw[at..at + from.len()].copy_from_slice(to);
+    let mut output = GzEncoder::new(File::create(tampered).unwrap(), Compression::default());
+    output.write_all(&raw).unwrap();
+    output.finish().unwrap();
+}
+
+/// The pax records of each entry of a tar.gz archive whose key starts with track.
+fn track_records(archive: &Path) -> Vec<(String, Vec<(String, String)>)> {
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(archive).unwrap()));
+    archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().display().to_string();
+            let records = match entry.pax_extensions().unwrap() {
+                Some(extensions) => extensions
+                    .map(|extension| extension.unwrap())
+                    .filter(|extension| extension.key().unwrap().starts_with("track."))
+                    .map(|extension| {
+                        (
+                            extension.key().unwrap().to_string(),
+                            extension.value().unwrap().to_string(),
+                        )
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            (name, records)
+        })
+        .collect()
+}
+
+fn restore(dir: &TempDir, archive: &Path) -> std::process::Output {
+    let to = dir.join("restored");
+    let _ = fs::remove_dir_all(&to);
+    track(dir)
+        .arg("restore")
+        .arg(archive)
+        .arg("--to")
+        .arg(&to)
+        .arg("--verify-embedded")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn each_file_carries_its_checksum() {
+    let dir = TempDir::new("embed-hashes");
+    tracked_tree(&dir, "src", &[("a", "a"), ("b", "b")]);
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir).args(["export", "tar"]).arg(&archive).arg("--embed-hashes"));
+    let records: Vec<_> = track_records(&archive)
+        .into_iter()
+        .map(|(_, records)| records)
+        .collect();
+    assert_eq!(
+        records,
+        [
+            [(
+                "track.sha256".to_string(),
+                "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb".to_string()
+            )],
+            [(
+                "track.sha256".to_string(),
+                "3e23e8160039594a33894f6564e1b1348bbd7a0088d42c4acb73eeaed59c009d".to_string()
+            )],
+        ]
+    );
+
+    // The record is named after the algorithm.
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .args(["--embed-hashes", "--hash", "md5"]));
+    assert_eq!(
+        track_records(&archive)[0].1,
+        [("track.md5".to_string(), "0cc175b9c0f1b6a831c399e269772661".to_string())]
+    );
+    let output = restore(&dir, &archive);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn restored_files_are_checked_against_their_embedded_hashes() {
+    let dir = TempDir::new("embed-hashes-verify");
+    let src = tracked_tree(&dir, "src", &[("a", "original"), ("b", "other")]);
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir).args(["export", "tar"]).arg(&archive).arg("--embed-hashes"));
+    let output = restore(&dir, &archive);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Verified 2 restored files against their embedded hashes"));
+
+    let tampered = dir.join("tampered.tar.gz");
+    tamper(&archive, &tampered, b"original", b"TAMPERED");
+    let output = restore(&dir, &tampered);
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let restored = dir.join("restored").join(src.strip_prefix("/").unwrap());
+    assert!(
+        stderr.contains(&format!("{}: FAILED", restored.join("a").display())),
+        "{}",
+        stderr
+    );
+    assert!(
+        !stderr.contains(&format!("{}: FAILED", restored.join("b").display())),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("1 of 2 restored files don't match their embedded hashes"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn archives_need_embedded_hashes_to_verify_them() {
+    let dir = TempDir::new("embed-hashes-missing");
+    tracked_tree(&dir, "src", &[("a", "a")]);
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir).args(["export", "tar"]).arg(&archive));
+    let output = restore(&dir, &archive);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("has no embedded hashes, export with --embed-hashes"));
+
+    let output = fails(
+        track(&dir)
+            .args(["export", "zip"])
+            .arg(dir.join("out.zip"))
+            .arg("--embed-hashes"),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--embed-hashes only applies to tar exports"));
+}
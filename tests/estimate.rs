@@ -0,0 +1,81 @@
+mod common;
+
+use std::fs;
+
+use common::{fails, noise, ok, track, TempDir};
+
+/// Size in bytes of a size printed like `1.9 MiB`.
+fn parse_size(size: &str) -> f64 {
+    let (value, unit) = size.split_once(' ').unwrap();
+    let power = ["B", "KiB", "MiB", "GiB"].iter().position(|&u| u == unit).unwrap();
+    value.parse::<f64>().unwrap() * 1024f64.powi(power as i32)
+}
+
+/// Estimate the size of a `kind` export from `sample` files, then export it for real and return both sizes.
+fn estimated_and_real(dir: &TempDir, kind: &str, sample: &str) -> (f64, f64) {
+    let output = ok(track(dir).args(["estimate", kind, "--sample", sample]));
+    let line = output
+        .lines()
+        .find_map(|line| line.strip_prefix("Estimated archive size: "))
+        .unwrap();
+    let estimated = parse_size(line.split(" (").next().unwrap());
+    let archive = dir.join(format!("out.{}", kind));
+    ok(track(dir).args(["export", kind]).arg(&archive));
+    (estimated, fs::metadata(&archive).unwrap().len() as f64)
+}
+
+fn assert_close(dir: &TempDir, kind: &str, sample: &str) {
+    let (estimated, real) = estimated_and_real(dir, kind, sample);
+    assert!(
+        (estimated - real).abs() <= real * 0.1,
+        "{} export of {} bytes estimated at {} from {} files",
+        kind,
+        real,
+        estimated,
+        sample
+    );
+}
+
+#[test]
+fn compressible_files_are_estimated_close_to_the_export() {
+    let dir = TempDir::new("estimate-text");
+    for i in 0..20 {
+        let text: String = (0..2000)
+            .map(|line| format!("line {} of file {}, some words to compress\n", line, i))
+            .collect();
+        dir.write(&format!("src/f{}", i), text);
+    }
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    for kind in ["tar", "zip"] {
+        for sample in ["64", "4"] {
+            assert_close(&dir, kind, sample);
+        }
+    }
+    // The text compresses to a small part of its size.
+    let (estimated, _) = estimated_and_real(&dir, "tar", "64");
+    assert!(estimated < 20.0 * 2000.0 * 40.0 / 5.0, "{}", estimated);
+}
+
+#[test]
+fn incompressible_files_are_estimated_close_to_the_export() {
+    let dir = TempDir::new("estimate-noise");
+    for i in 0..20 {
+        dir.write(&format!("src/f{}", i), noise(100_000 + i));
+    }
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    for kind in ["tar", "zip"] {
+        for sample in ["64", "4"] {
+            assert_close(&dir, kind, sample);
+        }
+    }
+    let output = ok(track(&dir).args(["estimate", "tar"]));
+    assert!(output.contains("(100.0% of the files)"), "{}", output);
+}
+
+#[test]
+fn only_archives_are_estimated() {
+    let dir = TempDir::new("estimate-dir");
+    let output = fails(track(&dir).args(["estimate", "dir"]));
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("estimate only applies to tar and zip exports"));
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use common::{exported_files, ok, track, tracked_tree, with_stdin, TempDir};
+
+fn matched(output: std::process::Output) -> String {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn listed_files_are_left_out_on_top_of_the_patterns() {
+    let dir = TempDir::new("exclude-stdin");
+    let src = tracked_tree(&dir, "src", &[("a", "a"), ("b.tmp", "b"), ("c", "c"), ("sub/d", "d")]);
+    // Relative paths are resolved against the current directory.
+    let list = format!("src/a\n{}\n", src.join("sub/d").display());
+    let output = with_stdin(
+        track(&dir)
+            .args(["matched", "--exclude-stdin", "--exclude", "*.tmp"])
+            .current_dir(dir.path()),
+        list,
+    );
+    assert_eq!(matched(output), format!("{}\n", src.join("c").display()));
+
+    // Only files listed exactly are left out, not the ones under a listed directory.
+    let output = with_stdin(
+        track(&dir).args(["matched", "--exclude-stdin"]),
+        format!("{}\n", src.join("sub").display()),
+    );
+    assert_eq!(matched(output).lines().count(), 4);
+}
+
+#[test]
+fn nul_separated_lists_can_name_line_breaks() {
+    let dir = TempDir::new("exclude-stdin-nul");
+    let src = tracked_tree(&dir, "src", &[("new\nline", "n"), ("new", "a"), ("c", "c")]);
+    let dest = dir.join("dest");
+    let list = format!("{}\0{}", src.join("new\nline").display(), src.join("c").display());
+    let output = with_stdin(
+        track(&dir).args(["export", "dir"]).arg(&dest).arg("--exclude-stdin"),
+        list,
+    );
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let files = exported_files(&dest);
+    assert_eq!(files.len(), 1, "{:?}", files);
+    assert!(files[0].ends_with("src/new"), "{:?}", files);
+}
+
+#[test]
+fn stdin_cant_list_both_paths_to_add_and_to_exclude() {
+    let dir = TempDir::new("exclude-stdin-add");
+    let src = dir.write("src/a", "a");
+    let output = with_stdin(
+        track(&dir)
+            .args(["add", "-", "export", "tar"])
+            .arg(dir.join("out.tar.gz"))
+            .arg("--exclude-stdin"),
+        format!("{}\n", src.display()),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("add - and --exclude-stdin can't both read stdin"));
+    // Nothing was added.
+    assert_eq!(ok(track(&dir).arg("ls")), "");
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use std::{fs, io::Read, os::unix::io::AsRawFd, process::Stdio, thread, time::Duration};
+
+use common::{fails, ok, track, TempDir};
+
+/// Exit code of `track` failing with `args`, after tracking a directory with one file.
+fn exit_code(dir: &TempDir, args: &[&str]) -> i32 {
+    fails(track(dir).args(args)).status.code().expect("track was killed")
+}
+
+fn tracked(name: &str) -> TempDir {
+    let dir = TempDir::new(name);
+    dir.write("src/a", "a");
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    dir
+}
+
+#[test]
+fn invalid_usage_exits_with_2() {
+    let dir = tracked("exit-usage");
+    assert_eq!(exit_code(&dir, &["no-such-command"]), 2);
+    assert_eq!(exit_code(&dir, &["ls", "--no-such-flag"]), 2);
+    let dest = dir.join("dest");
+    assert_eq!(
+        exit_code(&dir, &["export", "dir", dest.to_str().unwrap(), "--tag", "none"]),
+        2
+    );
+}
+
+#[test]
+fn database_errors_exit_with_3() {
+    let dir = TempDir::new("exit-database");
+    dir.write(
+        "track.db",
+        "this is not a sqlite database, but long enough to have a header",
+    );
+    assert_eq!(exit_code(&dir, &["ls"]), 3);
+
+    let dir = tracked("exit-locked");
+    let lock = fs::File::create(dir.join("track.lock")).unwrap();
+    assert_eq!(unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX) }, 0);
+    let other = dir.join("other");
+    fs::create_dir(&other).unwrap();
+    assert_eq!(exit_code(&dir, &["add", other.to_str().unwrap()]), 3);
+}
+
+#[test]
+fn failed_export_exits_with_4() {
+    let dir = tracked("exit-partial");
+    let dest = dir.join("dest");
+    ok(track(&dir).args(["export", "dir"]).arg(&dest));
+    // A directory in the way of an exported file makes the copy fail in the middle of the export.
+    let exported = dest.join(dir.join("src/a").strip_prefix("/").unwrap());
+    fs::remove_file(&exported).unwrap();
+    fs::create_dir(&exported).unwrap();
+    assert_eq!(
+        exit_code(&dir, &["export", "dir", dest.to_str().unwrap(), "--resume"]),
+        4
+    );
+}
+
+#[test]
+fn checksum_mismatch_exits_with_5() {
+    let dir = tracked("exit-mismatch");
+    let archive = dir.join("out.tar");
+    ok(track(&dir).args(["export", "tar"]).arg(&archive));
+    let name = dir.join("src/a");
+    let manifest = dir.write(
+        "manifest",
+        format!("0000  {}\n", name.strip_prefix("/").unwrap().display()),
+    );
+    let to = dir.join("restored");
+    let code = exit_code(
+        &dir,
+        &[
+            "restore",
+            archive.to_str().unwrap(),
+            "--to",
+            to.to_str().unwrap(),
+            "--verify",
+            "--manifest",
+            manifest.to_str().unwrap(),
+        ],
+    );
+    assert_eq!(code, 5);
+}
+
+#[test]
+fn interrupted_export_exits_with_130() {
+    let dir = TempDir::new("exit-interrupted");
+    // Incompressible, so the compressed archive fills the pipe.
+    let mut state = 1u32;
+    let noise: Vec<u8> = (0..4 << 20)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect();
+    dir.write("src/big", noise);
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    let mut child = track(&dir)
+        .args(["export", "tar", "-"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    // The export blocks once the pipe is full, the signal then arrives in the middle of it.
+    let mut stdout = child.stdout.take().unwrap();
+    let mut start = [0; 512];
+    stdout.read_exact(&mut start).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(unsafe { libc::kill(child.id() as i32, libc::SIGTERM) }, 0);
+    thread::sleep(Duration::from_millis(200));
+    drop(stdout);
+    assert_eq!(child.wait().unwrap().code(), Some(130));
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use std::process::Command;
+
+use common::{exported_files, fails, ok, track, TempDir};
+
+/// The track binary like `track`, with its home directory under `dir`.
+fn track_at_home(dir: &TempDir) -> Command {
+    let mut command = track(dir);
+    command.env("HOME", dir.join("home"));
+    command
+}
+
+#[test]
+fn added_paths_expand_the_home_directory_and_variables() {
+    let dir = TempDir::new("expand-add");
+    dir.write("home/docs/a", "a");
+    dir.write("home/notes/b", "b");
+    dir.write("home/music$/c", "c");
+    ok(track_at_home(&dir)
+        .arg("add")
+        // A $ not followed by a name is kept.
+        .args(["~/docs", "${HOME}/notes", "$HOME/music$"]));
+    assert_eq!(
+        ok(track(&dir).arg("ls")),
+        format!(
+            "{}\n{}\n{}\n",
+            dir.join("home/docs").display(),
+            dir.join("home/music$").display(),
+            dir.join("home/notes").display()
+        )
+    );
+
+    // ~user is the home directory of that user.
+    let output = ok(track_at_home(&dir).args(["add", "--dry-run", "--force", "~root/track-missing"]));
+    assert!(output.starts_with("/root/track-missing: added"), "{}", output);
+}
+
+#[test]
+fn export_destinations_expand_the_home_directory_and_variables() {
+    let dir = TempDir::new("expand-export");
+    dir.write("src/a", "a");
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+
+    ok(track_at_home(&dir).args(["export", "dir", "~/backup"]));
+    assert_eq!(exported_files(&dir.join("home/backup")).len(), 1);
+    ok(track_at_home(&dir)
+        .args(["export", "tar", "${HOME}/$TRACK_TEST_NAME.tar.gz"])
+        .env("TRACK_TEST_NAME", "out"));
+    assert!(dir.join("home/out.tar.gz").is_file());
+    // Nothing was written to a literal ~ directory.
+    assert!(!dir.join("~").exists());
+    assert!(!std::path::Path::new("~").exists());
+}
+
+#[test]
+fn unknown_users_and_unset_variables_are_usage_errors() {
+    let dir = TempDir::new("expand-errors");
+    let output = fails(
+        track_at_home(&dir)
+            .args(["export", "tar", "$TRACK_TEST_UNSET/out.tar.gz"])
+            .env_remove("TRACK_TEST_UNSET"),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("environment variable TRACK_TEST_UNSET is not set"));
+
+    let output = fails(track_at_home(&dir).args(["add", "~track-no-such-user/x"]));
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown user track-no-such-user"));
+    assert_eq!(ok(track(&dir).arg("ls")), "");
+}
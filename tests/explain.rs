@@ -0,0 +1,176 @@
+mod common;
+
+use std::{ffi::CString, os::unix::ffi::OsStrExt, path::PathBuf};
+
+use common::{exported_files, ok, track, TempDir};
+
+/// A tracked src with a file to export and files each left out by one filter: the stored exclude `*.log`,
+/// the `--exclude *.tmp` of `explain`, .git and the file type.
+fn tree(name: &str) -> (TempDir, PathBuf) {
+    let dir = TempDir::new(name);
+    for file in ["src/keep", "src/x.log", "src/.git/HEAD", "src/sub/t.tmp"] {
+        dir.write(file, "x");
+    }
+    let src = dir.join("src");
+    let fifo = CString::new(src.join("fifo").as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+    ok(track(&dir).arg("add").arg(&src).args(["--exclude", "*.log"]));
+    (dir, src)
+}
+
+fn explain(dir: &TempDir, file: impl Into<PathBuf>) -> String {
+    ok(track(dir).arg("explain").arg(file.into()).args(["--exclude", "*.tmp"]))
+}
+
+#[test]
+fn included_files_pass_every_filter() {
+    let (dir, src) = tree("explain-included");
+    assert_eq!(
+        explain(&dir, src.join("keep")),
+        format!(
+            "{}/keep
+  under tracked path {}
+    regular file: included
+    skipped directories, .git and --skip-dir: included
+    system directories: included
+    track database: included
+    --exclude-stdin: not used
+    pattern *.tmp: included
+    exclude *.log of the tracked path: included
+    --skip-git-lfs: not used
+    exported
+  would be exported
+",
+            src.display(),
+            src.display()
+        )
+    );
+}
+
+#[test]
+fn excluded_files_name_the_filter_leaving_them_out() {
+    let (dir, src) = tree("explain-excluded");
+    for (file, decision, reason) in [
+        (
+            "x.log",
+            "exclude *.log of the tracked path: excluded, it matches",
+            "matches an exclude of the tracked path",
+        ),
+        (
+            "sub/t.tmp",
+            "pattern *.tmp: excluded, it matches",
+            "matches an --exclude or --skip-caches pattern",
+        ),
+        (
+            ".git/HEAD",
+            "skipped directories, .git and --skip-dir: excluded, it is inside .git, a skipped directory",
+            "inside a skipped directory",
+        ),
+        (
+            "fifo",
+            "regular file: excluded, it is not a regular file",
+            "not a regular file",
+        ),
+        (
+            "missing",
+            "regular file: excluded, it does not exist",
+            "file does not exist",
+        ),
+    ] {
+        let output = explain(&dir, src.join(file));
+        let lines: Vec<_> = output.lines().collect();
+        // The other filters include the file.
+        let excluded: Vec<_> = lines.iter().filter(|line| line.contains(": excluded, ")).collect();
+        assert_eq!(excluded, [&format!("    {}", decision)], "{}", output);
+        assert_eq!(
+            lines[lines.len() - 2..],
+            [
+                format!("    not exported: {}", reason),
+                "  would not be exported".to_string()
+            ],
+            "{}",
+            output
+        );
+    }
+
+    // Exports agree.
+    let dest = dir.join("dest");
+    ok(track(&dir)
+        .args(["export", "dir"])
+        .arg(&dest)
+        .args(["--exclude", "*.tmp"]));
+    let name = src.strip_prefix("/").unwrap().join("keep");
+    assert_eq!(exported_files(&dest), [name.display().to_string()]);
+}
+
+#[test]
+fn untracked_files_are_under_no_tracked_path() {
+    let (dir, _) = tree("explain-untracked");
+    let file = dir.write("other", "x");
+    assert_eq!(
+        explain(&dir, &file),
+        format!(
+            "{}\n  not under any tracked path\n  would not be exported\n",
+            file.display()
+        )
+    );
+}
+
+#[test]
+fn database_files_are_left_out_of_tracked_paths_holding_them() {
+    let dir = TempDir::new("explain-db");
+    ok(track(&dir).arg("add").arg(dir.path()));
+    let output = ok(track(&dir).arg("explain").arg(dir.join("track.db")));
+    assert!(
+        output.contains(
+            "    track database: excluded, it is a track database file, left out unless --include-db is used\n"
+        ),
+        "{}",
+        output
+    );
+    assert!(
+        output.ends_with("    not exported: track database file\n  would not be exported\n"),
+        "{}",
+        output
+    );
+    let output = ok(track(&dir).arg("explain").arg(dir.join("track.db")).arg("--include-db"));
+    assert!(output.contains("    track database: not used\n"), "{}", output);
+    assert!(output.ends_with("    exported\n  would be exported\n"), "{}", output);
+}
+
+#[test]
+fn json_has_each_decision() {
+    let (dir, src) = tree("explain-json");
+    let output = ok(track(&dir).arg("explain").arg(src.join("x.log")).arg("--json"));
+    let decision = |filter: &str, used: bool, reason: Option<&str>| {
+        format!(
+            r#"{{"filter":"{}","used":{},"excluded":{},"reason":{}}}"#,
+            filter,
+            used,
+            reason.is_some(),
+            reason.map_or("null".to_string(), |reason| format!(r#""{}""#, reason))
+        )
+    };
+    let decisions = [
+        decision("regular file", true, None),
+        decision("skipped directories, .git and --skip-dir", true, None),
+        decision("system directories", true, None),
+        decision("track database", true, None),
+        decision("--exclude-stdin", false, None),
+        decision("exclude *.log of the tracked path", true, Some("it matches")),
+        decision("--skip-git-lfs", false, None),
+    ];
+    assert_eq!(
+        output,
+        format!(
+            concat!(
+                r#"{{"version":1,"command":"explain","data":{{"path":"{}","roots":[{{"path":"{}","filters":[{}],"#,
+                r#""exported":false,"reason":"matches an exclude of the tracked path"}}],"exported":false}}}}"#,
+                "\n"
+            ),
+            src.join("x.log").display(),
+            src.display(),
+            decisions.join(",")
+        )
+    );
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use common::{exported_files, ok, track, TempDir};
+use filetime::FileTime;
+
+/// Set the modification time of `path` an hour ago, so it's older than any export of the test.
+fn age(path: &std::path::Path) {
+    let past = SystemTime::now() - Duration::from_secs(3600);
+    filetime::set_file_mtime(path, FileTime::from_system_time(past)).unwrap();
+}
+
+#[test]
+fn changed_dir_export_keeps_the_unchanged_files() {
+    let dir = TempDir::new("changed-dir");
+    for name in ["a", "b", "c", "sub/d", "sub/e", "sub/f"] {
+        age(&dir.write(&format!("src/{}", name), name));
+    }
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    let dest = dir.join("dest");
+    ok(track(&dir).args(["export", "dir"]).arg(&dest).arg("--changed"));
+    assert_eq!(exported_files(&dest).len(), 6);
+
+    dir.write("src/g", "g");
+    ok(track(&dir).args(["export", "dir"]).arg(&dest).arg("--changed"));
+    let files = exported_files(&dest);
+    assert_eq!(files.len(), 7, "{:?}", files);
+    assert!(files.iter().any(|f| f.ends_with("src/a")));
+}
+
+#[test]
+fn changed_bagit_export_is_refused() {
+    let dir = TempDir::new("changed-bagit");
+    dir.write("src/a", "a");
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    let output = common::fails(
+        track(&dir)
+            .args(["export", "bagit"])
+            .arg(dir.join("bag"))
+            .arg("--changed"),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!dir.join("bag").join("data").exists());
+}
+
+#[test]
+fn export_only_locks_the_database_to_record_it() {
+    use std::os::unix::io::AsRawFd;
+
+    let dir = TempDir::new("export-lock");
+    dir.write("src/a", "a");
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    let lock = std::fs::File::create(dir.join("track.lock")).unwrap();
+    assert_eq!(unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX) }, 0);
+
+    let archive = dir.join("out.tar.gz");
+    let mut child = track(&dir)
+        .args(["--wait", "export", "tar"])
+        .arg(&archive)
+        .spawn()
+        .unwrap();
+    // The archive is written while another command holds the lock, recording the export waits for it.
+    let started = std::time::Instant::now();
+    while !archive.exists() {
+        assert!(
+            started.elapsed() < Duration::from_secs(30),
+            "the export waited for the lock"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(child.try_wait().unwrap().is_none());
+    drop(lock);
+    assert!(child.wait().unwrap().success());
+}
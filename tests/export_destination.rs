@@ -0,0 +1,103 @@
+mod common;
+
+use std::fs;
+
+use common::{exported_files, fails, ok, tar_names, track, tracked_tree, TempDir};
+
+fn usage_error(output: std::process::Output) -> String {
+    assert_eq!(output.status.code(), Some(2));
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn directory_exports_refuse_files() {
+    let dir = TempDir::new("destination-file");
+    tracked_tree(&dir, "src", &[("a", "a")]);
+    let file = dir.write("out", "keep");
+    for kind in ["dir", "bagit"] {
+        let stderr = usage_error(fails(track(&dir).args(["export", kind]).arg(&file)));
+        let message = format!(
+            "{} is a file, a {} export needs a directory to write to",
+            file.display(),
+            kind
+        );
+        assert!(stderr.contains(&message), "{}", stderr);
+    }
+    let stderr = usage_error(fails(
+        track(&dir)
+            .args(["export", "tar"])
+            .arg(dir.join("b.tar.gz"))
+            .arg("--also")
+            .arg(format!("dir:{}", file.display())),
+    ));
+    assert!(
+        stderr.contains(&format!("{} is a file, a dir export", file.display())),
+        "{}",
+        stderr
+    );
+    // Nothing was written, the archive given with the file included.
+    assert_eq!(fs::read_to_string(&file).unwrap(), "keep");
+    assert!(!dir.join("b.tar.gz").exists());
+}
+
+#[test]
+fn missing_directories_are_created() {
+    let dir = TempDir::new("destination-missing");
+    let src = tracked_tree(&dir, "src", &[("a", "a")]);
+    let dest = dir.join("new/nested/dest");
+    ok(track(&dir).args(["export", "dir"]).arg(&dest));
+    let name = src.strip_prefix("/").unwrap().join("a");
+    assert_eq!(exported_files(&dest), [name.display().to_string()]);
+}
+
+#[test]
+fn archive_exports_refuse_directories() {
+    let dir = TempDir::new("destination-dir");
+    tracked_tree(&dir, "src", &[("a", "a")]);
+    let dest = dir.join("dest");
+    dir.write("dest/other", "other");
+    for (kind, name) in [
+        ("tar", "backup.tar.gz"),
+        ("zip", "backup.zip"),
+        ("script", "restore.sh"),
+    ] {
+        let stderr = usage_error(fails(track(&dir).args(["export", kind]).arg(&dest)));
+        let message = format!(
+            "{} is a directory, a {} export writes a single file, give a file name like {}",
+            dest.display(),
+            kind,
+            dest.join(name).display()
+        );
+        assert!(stderr.contains(&message), "{}", stderr);
+    }
+    let listed: Vec<_> = fs::read_dir(&dest)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(listed, ["other"]);
+}
+
+#[test]
+fn per_root_archives_need_a_directory() {
+    let dir = TempDir::new("destination-per-root");
+    let src = tracked_tree(&dir, "src", &[("a", "a")]);
+    let file = dir.write("out", "keep");
+    let stderr = usage_error(fails(track(&dir).args(["export", "tar"]).arg(&file).arg("--per-root")));
+    let message = format!(
+        "{} is a file, a tar export with --per-root needs a directory to write to",
+        file.display()
+    );
+    assert!(stderr.contains(&message), "{}", stderr);
+
+    // An existing directory is fine there.
+    let dest = dir.join("archives");
+    fs::create_dir(&dest).unwrap();
+    ok(track(&dir).args(["export", "tar"]).arg(&dest).arg("--per-root"));
+    let archives: Vec<_> = fs::read_dir(&dest)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(archives.len(), 1, "{:?}", archives);
+    let name = src.strip_prefix("/").unwrap().join("a");
+    assert_eq!(tar_names(&archives[0]), [name.display().to_string()]);
+}
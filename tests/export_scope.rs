@@ -0,0 +1,130 @@
+mod common;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use common::{exported_files, fails, ok, tar_names, track, TempDir};
+use filetime::FileTime;
+use rusqlite::{Connection, OptionalExtension};
+
+/// Tracked paths a, b tagged work and c tagged work and docs, with one file each.
+fn roots(name: &str) -> TempDir {
+    let dir = TempDir::new(name);
+    for (root, tags) in [("a", &[][..]), ("b", &["work"][..]), ("c", &["work", "docs"][..])] {
+        dir.write(&format!("{}/f", root), root);
+        let mut add = track(&dir);
+        add.arg("add").arg(dir.join(root));
+        for tag in tags {
+            add.args(["--tag", tag]);
+        }
+        ok(&mut add);
+    }
+    dir
+}
+
+/// Tracked paths the files of an export are under, sorted.
+fn exported_roots(names: Vec<String>) -> Vec<String> {
+    let mut roots: Vec<_> = names
+        .iter()
+        .map(|name| name.trim_end_matches("/f").rsplit('/').next().unwrap().to_string())
+        .collect();
+    roots.sort();
+    roots
+}
+
+fn last_export_at(dir: &TempDir) -> Option<u64> {
+    let conn = Connection::open(dir.join("track.db")).unwrap();
+    conn.query_row("SELECT value FROM state WHERE key = 'last_export_at'", [], |row| {
+        row.get(0)
+    })
+    .optional()
+    .unwrap()
+}
+
+#[test]
+fn roots_and_tags_select_the_paths_exported() {
+    let dir = roots("export-scope");
+    let archive = dir.join("out.tar.gz");
+    let export = |args: &[&str]| {
+        ok(track(&dir)
+            .current_dir(dir.path())
+            .args(["export", "tar"])
+            .arg(&archive)
+            .args(args));
+        exported_roots(tar_names(&archive))
+    };
+    assert_eq!(export(&[]), ["a", "b", "c"]);
+    // Relative roots are resolved against the current directory.
+    assert_eq!(export(&["--root", "a"]), ["a"]);
+    assert_eq!(export(&["--root", "a", "--root", "c"]), ["a", "c"]);
+    assert_eq!(export(&["--tag", "work"]), ["b", "c"]);
+    assert_eq!(export(&["--tag", "docs"]), ["c"]);
+    // Either selects a path, each is exported once.
+    assert_eq!(export(&["--root", "a", "--tag", "docs", "--root", "c"]), ["a", "c"]);
+
+    let dest = dir.join("dest");
+    ok(track(&dir).args(["export", "dir"]).arg(&dest).args(["--tag", "docs"]));
+    assert_eq!(exported_roots(exported_files(&dest)), ["c"]);
+}
+
+#[test]
+fn untracked_roots_and_unknown_tags_are_refused() {
+    let dir = roots("export-scope-errors");
+    let archive = dir.join("out.tar.gz");
+    for (args, message) in [
+        (
+            vec!["--root".to_string(), dir.join("elsewhere").display().to_string()],
+            "is not a tracked path",
+        ),
+        // A directory under a tracked path isn't one.
+        (
+            vec!["--root".to_string(), dir.join("a/sub").display().to_string()],
+            "is not a tracked path",
+        ),
+        (
+            vec!["--tag".to_string(), "none".to_string()],
+            "no tracked path is tagged none",
+        ),
+    ] {
+        let output = fails(track(&dir).args(["export", "tar"]).arg(&archive).args(&args));
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains(message), "{:?}", args);
+    }
+    assert!(!archive.exists());
+}
+
+#[test]
+fn scoped_exports_dont_record_the_last_export() {
+    let dir = roots("export-scope-changed");
+    let earlier = SystemTime::now() - Duration::from_secs(100);
+    for root in ["a", "b", "c"] {
+        filetime::set_file_mtime(dir.join(root).join("f"), FileTime::from_system_time(earlier)).unwrap();
+    }
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir).args(["export", "tar"]).arg(&archive));
+    assert!(last_export_at(&dir).is_some());
+    // Move the last export back to after the files were modified, so the next one recorded is told apart.
+    let recorded = earlier.duration_since(UNIX_EPOCH).unwrap().as_secs() + 50;
+    Connection::open(dir.join("track.db"))
+        .unwrap()
+        .execute("UPDATE state SET value = ? WHERE key = 'last_export_at'", [recorded])
+        .unwrap();
+
+    // b changes, then only a is exported.
+    let later = SystemTime::now() + Duration::from_secs(10);
+    filetime::set_file_mtime(dir.join("b/f"), FileTime::from_system_time(later)).unwrap();
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .arg("--root")
+        .arg(dir.join("a")));
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .args(["--tag", "work"]));
+    assert_eq!(last_export_at(&dir), Some(recorded));
+
+    // The change to b is still exported by the next --changed export of every path.
+    ok(track(&dir).args(["export", "tar"]).arg(&archive).arg("--changed"));
+    assert_eq!(exported_roots(tar_names(&archive)), ["b"]);
+    assert!(last_export_at(&dir).unwrap() > recorded);
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use common::{ok, track, tracked_tree, TempDir};
+
+/// Run chattr with `flags` on `path`, returning whether it's supported here.
+fn chattr(flags: &str, path: &Path) -> bool {
+    Command::new("chattr")
+        .arg(flags)
+        .arg(path)
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Files made immutable or append only, which can't be removed along with the temporary directory
+/// until they're unprotected again.
+struct Protected(Vec<PathBuf>);
+
+impl Drop for Protected {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            chattr("-ia", path);
+        }
+    }
+}
+
+#[test]
+fn protected_files_are_detected_and_recorded() {
+    let dir = TempDir::new("file-flags");
+    let src = tracked_tree(&dir, "src", &[("app", "app"), ("imm", "imm"), ("plain", "plain")]);
+    let mut protected = Protected(Vec::new());
+    if !chattr("+a", &src.join("app")) {
+        eprintln!("Skipping, chattr is not supported here");
+        return;
+    }
+    protected.0.push(src.join("app"));
+    assert!(chattr("+i", &src.join("imm")));
+    protected.0.push(src.join("imm"));
+    assert!(chattr("+d", &src.join("plain")));
+
+    // Copies don't carry the flags, which exports warn about.
+    let output = track(&dir)
+        .args(["export", "dir"])
+        .arg(dir.join("dest"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!(
+            "Warning: {} is append only, its copy isn't\nWarning: {} is immutable, its copy isn't\n",
+            src.join("app").display(),
+            src.join("imm").display()
+        )
+    );
+
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir).args(["export", "tar"]).arg(&archive).arg("--file-flags"));
+    let to = dir.join("restored");
+    let restored = to.join(src.strip_prefix("/").unwrap());
+    let output = track(&dir)
+        .arg("restore")
+        .arg(&archive)
+        .arg("--to")
+        .arg(&to)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!(
+            "{app} had the chattr flags a when exported, chattr +a sets them again\n\
+             {imm} had the chattr flags i when exported, chattr +i sets them again\n\
+             {plain} had the chattr flags d when exported, chattr +d sets them again\n",
+            app = restored.join("app").display(),
+            imm = restored.join("imm").display(),
+            plain = restored.join("plain").display(),
+        )
+    );
+
+    // Restoring again leaves alone the restored files protected since.
+    assert!(chattr("+i", &restored.join("imm")));
+    protected.0.push(restored.join("imm"));
+    let output = track(&dir)
+        .arg("restore")
+        .arg(&archive)
+        .arg("--to")
+        .arg(&to)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!(
+        "Skipping {} which is immutable and can't be overwritten",
+        restored.join("imm").display()
+    )));
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use common::{exported_files, fails, ok, replace_with_fifo, tar_names, track, tracked_tree, TempDir};
+
+#[test]
+fn files_which_dont_open_in_time_are_skipped_with_a_warning() {
+    let dir = TempDir::new("file-timeout");
+    let src = tracked_tree(&dir, "src", &[("a", "a"), ("b", "b")]);
+    // The first export fills the scan cache.
+    ok(track(&dir).args(["export", "dir"]).arg(dir.join("first")));
+    replace_with_fifo(&src.join("b"));
+    let name = src.strip_prefix("/").unwrap().display().to_string();
+    let warning = format!("Skipping {} which didn't answer within 200ms", src.join("b").display());
+
+    for (kind, dest) in [("dir", "dest"), ("tar", "out.tar.gz"), ("zip", "out.zip")] {
+        let dest = dir.join(dest);
+        let output = track(&dir)
+            .args(["export", kind])
+            .arg(&dest)
+            .args(["--file-timeout", "200ms"])
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{}: {}", kind, stderr);
+        assert!(stderr.contains(&warning), "{}: {}", kind, stderr);
+        let names = match kind {
+            "dir" => exported_files(&dest),
+            "tar" => tar_names(&dest),
+            _ => {
+                let converted = dir.join("converted.tar.gz");
+                ok(track(&dir).arg("convert").arg(&dest).arg(&converted));
+                tar_names(&converted)
+            }
+        };
+        assert_eq!(names, [format!("{}/a", name)], "{}", kind);
+    }
+}
+
+#[test]
+fn file_timeout_refuses_the_options_reading_files_outside_of_it() {
+    let dir = TempDir::new("file-timeout-manifest");
+    tracked_tree(&dir, "src", &[("a", "a")]);
+    let output = fails(
+        track(&dir)
+            .args(["export", "tar"])
+            .arg(dir.join("out.tar.gz"))
+            .args(["--file-timeout", "1s", "--manifest"])
+            .arg(dir.join("manifest")),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--file-timeout doesn't apply with --manifest"));
+    assert!(!dir.join("out.tar.gz").exists());
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::{noise, ok, track, tracked_tree, TempDir};
+
+const POINTER: &str = "version https://git-lfs.github.com/spec/v1\noid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393\nsize 4096\n";
+
+#[test]
+fn skip_git_lfs_leaves_out_materialized_files_only() {
+    let dir = TempDir::new("git-lfs");
+    let attributes = "*.bin filter=lfs diff=lfs merge=lfs -text\nassets/** filter=lfs\nassets/*.txt -filter\n";
+    let content = noise(4096);
+    let src = tracked_tree(
+        &dir,
+        "src",
+        &[
+            ("repo/.git/HEAD", "ref: refs/heads/main\n".as_bytes()),
+            ("repo/.gitattributes", attributes.as_bytes()),
+            ("repo/big.bin", &content),
+            ("repo/pointer.bin", POINTER.as_bytes()),
+            ("repo/code.rs", b"fn main() {}\n"),
+            ("repo/assets/texture.png", &content),
+            ("repo/assets/readme.txt", b"textures\n"),
+            // A submodule follows its own attributes, which don't use LFS.
+            ("repo/sub/.git", b"gitdir: ../.git/modules/sub\n"),
+            ("repo/sub/big.bin", &content),
+            // Files outside of any repository aren't affected.
+            ("outside/big.bin", &content),
+        ],
+    );
+    let matched = |args: &[&str]| -> Vec<String> {
+        ok(track(&dir).arg("matched").args(args))
+            .lines()
+            .map(|line| line.strip_prefix(&format!("{}/", src.display())).unwrap().to_string())
+            .collect()
+    };
+
+    // .git directories are skipped either way.
+    assert_eq!(matched(&[]).len(), 9, "{:?}", matched(&[]));
+    assert_eq!(
+        matched(&["--skip-git-lfs"]),
+        [
+            "outside/big.bin",
+            "repo/.gitattributes",
+            "repo/assets/readme.txt",
+            "repo/code.rs",
+            "repo/pointer.bin",
+            "repo/sub/.git",
+            "repo/sub/big.bin",
+        ]
+    );
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::{ok, track, tracked_tree, TempDir};
+
+/// Names of the files matched with `args`, relative to the tracked tree `src`.
+fn matched(dir: &TempDir, src: &std::path::Path, args: &[&str]) -> Vec<String> {
+    ok(track(dir).arg("matched").args(args))
+        .lines()
+        .map(|line| line.strip_prefix(&format!("{}/", src.display())).unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn patterns_are_case_sensitive_by_default() {
+    let dir = TempDir::new("case-sensitive-excludes");
+    let src = tracked_tree(&dir, "src", &[("photo.jpg", ""), ("PHOTO.JPG", ""), ("b.txt", "")]);
+    assert_eq!(matched(&dir, &src, &["--exclude", "*.JPG"]), ["b.txt", "photo.jpg"]);
+    assert_eq!(
+        matched(&dir, &src, &["--exclude", "[a-c].TXT"]),
+        ["PHOTO.JPG", "b.txt", "photo.jpg"]
+    );
+}
+
+#[test]
+fn ignore_case_matches_patterns_regardless_of_case() {
+    let dir = TempDir::new("ignore-case-excludes");
+    let src = tracked_tree(&dir, "src", &[("photo.jpg", ""), ("PHOTO.JPG", ""), ("b.txt", "")]);
+    assert_eq!(matched(&dir, &src, &["--exclude", "*.JPG", "--ignore-case"]), ["b.txt"]);
+    // Letters of classes fold too.
+    assert_eq!(
+        matched(&dir, &src, &["--exclude", "[a-c].TXT", "--ignore-case"]),
+        ["PHOTO.JPG", "photo.jpg"]
+    );
+}
+
+#[test]
+fn ignore_case_applies_to_the_excludes_of_tracked_paths() {
+    let dir = TempDir::new("ignore-case-root-excludes");
+    dir.write("src/Build/main.o", "");
+    dir.write("src/main.c", "");
+    let src = dir.join("src");
+    ok(track(&dir).arg("add").arg(&src).args(["--exclude", "build/*.o"]));
+    assert_eq!(matched(&dir, &src, &[]), ["Build/main.o", "main.c"]);
+    assert_eq!(matched(&dir, &src, &["--ignore-case"]), ["main.c"]);
+}
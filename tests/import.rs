@@ -0,0 +1,119 @@
+mod common;
+
+use common::{exported_files, fails, ok, tar_names, track, track_without_db, TempDir};
+
+#[test]
+fn import_merges_the_paths_of_another_database() {
+    let dir = TempDir::new("import");
+    let (a, b, c) = (dir.join("a"), dir.join("b"), dir.join("c"));
+    dir.write("a/notes", "notes");
+    dir.write("a/debug.log", "log");
+    dir.write("b/debug.log", "log");
+    dir.write("c/c", "c");
+    let other = dir.join("other.db");
+    ok(track_without_db(&dir)
+        .arg("--db")
+        .arg(&other)
+        .arg("add")
+        .arg(&a)
+        .arg(&b)
+        .args(["--tag", "theirs", "--exclude", "*.log"]));
+    ok(track(&dir).arg("add").arg(&b).args(["--tag", "mine"]));
+    ok(track(&dir).arg("add").arg(&c));
+
+    assert_eq!(
+        ok(track(&dir).arg("import").arg(&other).arg("--dry-run")),
+        format!(
+            "Would import {}\nWould import 1 paths, 1 already tracked\n",
+            a.display()
+        )
+    );
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n{}\n", b.display(), c.display()));
+
+    assert_eq!(
+        ok(track(&dir).arg("import").arg(&other)),
+        "Imported 1 paths, 1 already tracked\n"
+    );
+    assert_eq!(
+        ok(track(&dir).arg("ls")),
+        format!("{}\n{}\n{}\n", a.display(), b.display(), c.display())
+    );
+    // The imported path comes with its tags and excludes.
+    let dest = dir.join("dest");
+    ok(track(&dir).args(["export", "dir"]).arg(&dest).args(["--tag", "theirs"]));
+    let files = exported_files(&dest);
+    assert_eq!(files.len(), 1, "{:?}", files);
+    assert!(files[0].ends_with("a/notes"), "{:?}", files);
+    // The path tracked already keeps its own.
+    let dest = dir.join("dest-mine");
+    ok(track(&dir).args(["export", "dir"]).arg(&dest).args(["--tag", "mine"]));
+    let files = exported_files(&dest);
+    assert_eq!(files.len(), 1, "{:?}", files);
+    assert!(files[0].ends_with("b/debug.log"), "{:?}", files);
+
+    // Importing again finds everything tracked already.
+    assert_eq!(
+        ok(track(&dir).arg("import").arg(&other)),
+        "Imported 0 paths, 2 already tracked\n"
+    );
+}
+
+#[test]
+fn exports_with_the_embedded_database_are_imported_back() {
+    let dir = TempDir::new("import-archive");
+    let (a, b) = (dir.join("a"), dir.join("b"));
+    dir.write("a/notes", "notes");
+    dir.write("a/debug.log", "log");
+    dir.write("b/b", "b");
+    ok(track(&dir)
+        .arg("add")
+        .arg(&a)
+        .args(["--tag", "work", "--exclude", "*.log"]));
+    ok(track(&dir).arg("add").arg(&b));
+    let tar = dir.join("out.tar.gz");
+    let zip = dir.join("out.zip");
+    ok(track(&dir).args(["export", "tar"]).arg(&tar).arg("--embed-db"));
+    ok(track(&dir).args(["export", "zip"]).arg(&zip).arg("--embed-db"));
+    assert!(tar_names(&tar).contains(&".track/paths.db".to_string()));
+
+    for archive in [&tar, &zip] {
+        let fresh = dir.join("fresh.db");
+        let _ = std::fs::remove_file(&fresh);
+        let fresh_track = || {
+            let mut command = track_without_db(&dir);
+            command.arg("--db").arg(&fresh);
+            command
+        };
+        assert_eq!(
+            ok(fresh_track().arg("import").arg("--from-archive").arg(archive)),
+            "Imported 2 paths, 0 already tracked\n"
+        );
+        assert_eq!(
+            ok(fresh_track().arg("ls")),
+            format!("{}\n{}\n", a.display(), b.display())
+        );
+        // The tags and excludes come back too.
+        let dest = dir.join("dest");
+        let _ = std::fs::remove_dir_all(&dest);
+        ok(fresh_track().args(["export", "dir"]).arg(&dest).args(["--tag", "work"]));
+        let files = exported_files(&dest);
+        assert_eq!(files.len(), 1, "{:?}", files);
+        assert!(files[0].ends_with("a/notes"), "{:?}", files);
+    }
+}
+
+#[test]
+fn archives_without_an_embedded_database_are_refused() {
+    let dir = TempDir::new("import-archive-missing");
+    dir.write("a/a", "a");
+    ok(track(&dir).arg("add").arg(dir.join("a")));
+    let tar = dir.join("out.tar.gz");
+    ok(track(&dir).args(["export", "tar"]).arg(&tar));
+
+    let output = fails(track(&dir).arg("import").arg("--from-archive").arg(&tar));
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!(
+        "{} has no embedded database, export with --embed-db to add one",
+        tar.display()
+    )));
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use common::{exported_files, ok, track, TempDir};
+
+#[test]
+fn the_database_is_only_matched_with_include_db() {
+    let dir = TempDir::new("include-db");
+    let file = dir.write("a", "a");
+    let output = track(&dir).arg("add").arg(dir.path()).output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!(
+        "Warning: {} contains the track database, which is left out unless --include-db is used",
+        dir.path().display()
+    )));
+
+    assert_eq!(ok(track(&dir).arg("matched")), format!("{}\n", file.display()));
+    let mut matched: Vec<_> = ok(track(&dir).arg("matched").arg("--include-db"))
+        .lines()
+        .map(String::from)
+        .collect();
+    matched.sort();
+    assert_eq!(
+        matched,
+        [file, dir.join("track.db"), dir.join("track.lock")].map(|path| path.display().to_string())
+    );
+
+    // Exports leave it out the same way.
+    let dest = dir.join("dest");
+    ok(track(&dir).args(["export", "dir"]).arg(&dest));
+    let files = exported_files(&dest);
+    assert_eq!(files.len(), 1, "{:?}", files);
+    assert!(files[0].ends_with("/a"), "{:?}", files);
+}
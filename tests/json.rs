@@ -0,0 +1,80 @@
+mod common;
+
+use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+use common::{ok, track, tracked_tree, TempDir};
+use filetime::FileTime;
+
+/// Check that `output` is a single JSON document in the envelope of `command`.
+fn assert_envelope(output: &str, command: &str) {
+    let prefix = format!(r#"{{"version":1,"command":"{}","data":"#, command);
+    assert!(output.starts_with(&prefix), "{} printed {}", command, output);
+    assert!(output.ends_with("}\n"), "{} printed {}", command, output);
+    assert_eq!(output.lines().count(), 1, "{} printed {}", command, output);
+}
+
+#[test]
+fn json_documents_have_an_envelope() {
+    let dir = TempDir::new("json-envelope");
+    let file = dir.write("src/a", "a");
+    let file = file.to_str().unwrap();
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+
+    for (args, command) in [
+        (&["stats", "--format", "json"][..], "stats"),
+        (&["top", "--format", "json"], "top"),
+        (&["recent", "--json"], "recent"),
+        (&["which", file, "--json"], "which"),
+        (&["explain", file, "--json"], "explain"),
+        (&["config", "show", "--json"], "config show"),
+        (
+            &["config", "show", "--list-presets", "--json"],
+            "config show --list-presets",
+        ),
+        (&["verify-db", "--json"], "verify-db"),
+    ] {
+        assert_envelope(&ok(track(&dir).args(args)), command);
+    }
+    // Doctor fails when a check does, its document is printed either way.
+    let output = track(&dir).args(["doctor", "--json"]).output().unwrap();
+    assert_envelope(&String::from_utf8_lossy(&output.stdout), "doctor");
+}
+
+#[test]
+fn streamed_json_stays_one_bare_object_per_line() {
+    let dir = TempDir::new("json-streamed");
+    dir.write("src/a", "a");
+    dir.write("src/b", "b");
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    let output = ok(track(&dir).args(["matched", "--format", "ndjson"]));
+    assert_eq!(output.lines().count(), 2, "{}", output);
+    for line in output.lines() {
+        assert!(line.starts_with('{') && !line.contains(r#""version":"#), "{}", line);
+    }
+}
+
+#[test]
+fn matched_records_describe_each_file() {
+    let dir = TempDir::new("json-matched");
+    let name = OsStr::from_bytes(b"n\xff");
+    let src = tracked_tree(&dir, "src", &[(OsStr::new("a"), &b"a"[..]), (name, b"bb")]);
+    for file in [src.join("a"), src.join(name)] {
+        filetime::set_file_mtime(&file, FileTime::from_unix_time(1_600_000_000, 0)).unwrap();
+    }
+    let bytes: Vec<_> = src
+        .join(name)
+        .as_os_str()
+        .as_bytes()
+        .iter()
+        .map(u8::to_string)
+        .collect();
+    assert_eq!(
+        ok(track(&dir).args(["matched", "--format", "ndjson"])),
+        format!(
+            "{{\"path\":\"{0}/a\",\"root\":\"{0}\",\"size\":1,\"mtime\":1600000000}}\n\
+             {{\"path\":{{\"bytes\":[{1}]}},\"root\":\"{0}\",\"size\":2,\"mtime\":1600000000}}\n",
+            src.display(),
+            bytes.join(",")
+        )
+    );
+}
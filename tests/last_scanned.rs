@@ -0,0 +1,80 @@
+mod common;
+
+use std::{
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use common::{ok, track, TempDir};
+use rusqlite::Connection;
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// The last_scanned_at and last_match_count columns of the tracked path `path`.
+fn columns(dir: &TempDir, path: &Path) -> (Option<u64>, Option<u64>) {
+    let conn = Connection::open(dir.join("track.db")).unwrap();
+    conn.query_row(
+        "SELECT last_scanned_at, last_match_count FROM paths WHERE path = ?",
+        [path.as_os_str().as_bytes()],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .unwrap()
+}
+
+#[test]
+fn scans_record_when_and_how_many_files_each_path_matched() {
+    let dir = TempDir::new("last-scanned");
+    dir.write("src/a", "a");
+    dir.write("src/b", "b");
+    dir.write("one/x", "x");
+    let (src, one) = (dir.join("src"), dir.join("one"));
+    ok(track(&dir).arg("add").arg(&src).arg(&one));
+    assert_eq!(columns(&dir, &src), (None, None));
+    assert_eq!(
+        ok(track(&dir).args(["ls", "--long"])),
+        format!("{}  never scanned\n{}  never scanned\n", one.display(), src.display())
+    );
+
+    let before = now();
+    ok(track(&dir).arg("matched"));
+    let (scanned_at, count) = columns(&dir, &src);
+    assert!((before..=now()).contains(&scanned_at.unwrap()), "{:?}", scanned_at);
+    assert_eq!(count, Some(2));
+    assert_eq!(columns(&dir, &one).1, Some(1));
+    let long = ok(track(&dir).args(["ls", "--long"]));
+    let lines: Vec<_> = long.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", long);
+    assert!(
+        lines[0].starts_with(&format!("{}  scanned ", one.display())),
+        "{}",
+        long
+    );
+    assert!(lines[0].ends_with("Z  1 file"), "{}", long);
+    assert!(
+        lines[1].starts_with(&format!("{}  scanned ", src.display())),
+        "{}",
+        long
+    );
+    assert!(lines[1].ends_with("Z  2 files"), "{}", long);
+
+    // The counts follow the filters of the last scan, exports included.
+    ok(track(&dir).args(["matched", "--exclude", "a"]));
+    assert_eq!(columns(&dir, &src).1, Some(1));
+    ok(track(&dir).args(["export", "tar"]).arg(dir.join("out.tar.gz")));
+    assert_eq!(columns(&dir, &src).1, Some(2));
+    assert_eq!(columns(&dir, &one).1, Some(1));
+}
+
+#[test]
+fn listing_the_paths_isnt_a_scan() {
+    let dir = TempDir::new("last-scanned-ls");
+    dir.write("src/a", "a");
+    let src = dir.join("src");
+    ok(track(&dir).arg("add").arg(&src));
+    ok(track(&dir).arg("ls"));
+    ok(track(&dir).args(["ls", "--long"]));
+    assert_eq!(columns(&dir, &src), (None, None));
+}
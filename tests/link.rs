@@ -0,0 +1,48 @@
+mod common;
+
+use std::fs;
+
+use common::{exported_files, fails, ok, track, tracked_tree, TempDir};
+
+#[test]
+fn symlink_exports_link_to_the_tracked_files() {
+    let dir = TempDir::new("link");
+    let src = tracked_tree(&dir, "src", &[("a", "a"), ("sub/b", "b")]);
+    let dest = dir.join("dest");
+    ok(track(&dir)
+        .args(["export", "dir"])
+        .arg(&dest)
+        .args(["--link", "symlink"]));
+
+    let copies = dest.join(src.strip_prefix("/").unwrap());
+    assert_eq!(exported_files(&dest).len(), 2);
+    for name in ["a", "sub/b"] {
+        let link = copies.join(name);
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_link(&link).unwrap(), src.join(name));
+    }
+    // Changes to the tracked files show through the links.
+    fs::write(src.join("a"), "changed").unwrap();
+    assert_eq!(fs::read_to_string(copies.join("a")).unwrap(), "changed");
+
+    // Copying over the links later leaves the tracked files alone.
+    ok(track(&dir).args(["export", "dir"]).arg(&dest).arg("--resume"));
+    assert!(fs::symlink_metadata(copies.join("a")).unwrap().is_file());
+    assert_eq!(fs::read_to_string(src.join("a")).unwrap(), "changed");
+}
+
+#[test]
+fn symlinks_only_apply_to_dir_exports() {
+    let dir = TempDir::new("link-tar");
+    tracked_tree(&dir, "src", &[("a", "a")]);
+    let archive = dir.join("out.tar.gz");
+    let output = fails(
+        track(&dir)
+            .args(["export", "tar"])
+            .arg(&archive)
+            .args(["--link", "symlink"]),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--link symlink only applies to dir exports"));
+    assert!(!archive.exists());
+}
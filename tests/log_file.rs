@@ -0,0 +1,90 @@
+mod common;
+
+use std::fs;
+
+use common::{ok, track, tracked_tree, TempDir};
+
+#[test]
+fn text_records_follow_the_export() {
+    let dir = TempDir::new("log-text");
+    let src = tracked_tree(&dir, "src", &[("a", "a"), ("b", "b")]);
+    let archive = dir.join("out.tar.gz");
+    let log = dir.join("track.log");
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .arg("--log-file")
+        .arg(&log));
+
+    let records: Vec<_> = fs::read_to_string(&log)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            // Each record starts with its UTC timestamp.
+            let (time, record) = line.split_once(' ').unwrap();
+            assert!(time.len() == 20 && time.ends_with('Z'), "{}", line);
+            record.to_string()
+        })
+        .collect();
+    assert_eq!(
+        records,
+        [
+            "info start command=\"export\"".to_string(),
+            "info scan-start roots=1".to_string(),
+            format!("info scan-root root=\"{}\" files=2", src.display()),
+            "info scan-end files=2".to_string(),
+            format!("info export-done files=2 dest=\"{}\"", archive.display()),
+            "info done".to_string(),
+        ]
+    );
+
+    // Later runs append to the same file.
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .arg("--log-file")
+        .arg(&log));
+    assert_eq!(fs::read_to_string(&log).unwrap().lines().count(), 12);
+}
+
+#[test]
+fn json_records_are_one_object_per_line() {
+    let dir = TempDir::new("log-json");
+    let src = tracked_tree(&dir, "src", &[("a", "a"), ("b", "b")]);
+    let archive = dir.join("out.tar.gz");
+    let log = dir.join("track.log");
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .arg("--log-file")
+        .arg(&log)
+        .args(["--log-format", "json"]));
+
+    let records: Vec<_> = fs::read_to_string(&log)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let rest = line.strip_prefix(r#"{"time":""#).unwrap();
+            let (time, record) = rest.split_once(r#"","#).unwrap();
+            assert!(time.len() == 20 && time.ends_with('Z'), "{}", line);
+            record.to_string()
+        })
+        .collect();
+    assert_eq!(
+        records,
+        [
+            r#""level":"info","event":"start","command":"export"}"#.to_string(),
+            r#""level":"info","event":"scan-start","roots":1}"#.to_string(),
+            format!(
+                r#""level":"info","event":"scan-root","root":"{}","files":2}}"#,
+                src.display()
+            ),
+            r#""level":"info","event":"scan-end","files":2}"#.to_string(),
+            format!(
+                r#""level":"info","event":"export-done","files":2,"dest":"{}"}}"#,
+                archive.display()
+            ),
+            r#""level":"info","event":"done"}"#.to_string(),
+        ]
+    );
+}
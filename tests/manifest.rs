@@ -0,0 +1,173 @@
+mod common;
+
+use std::{collections::HashMap, fs, io::Read, process::Command};
+
+use common::{fails, noise, ok, track, tracked_tree, with_stdin, TempDir};
+use flate2::read::GzDecoder;
+
+/// Contents of the entries of the tar.gz `archive` by name.
+fn tar_contents(archive: &std::path::Path) -> HashMap<String, Vec<u8>> {
+    let mut contents = HashMap::new();
+    for entry in tar::Archive::new(GzDecoder::new(fs::File::open(archive).unwrap()))
+        .entries()
+        .unwrap()
+    {
+        let mut entry = entry.unwrap();
+        let name = entry.path().unwrap().to_string_lossy().into_owned();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).unwrap();
+        contents.insert(name, content);
+    }
+    contents
+}
+
+#[test]
+fn manifests_list_the_archived_files_with_their_checksums() {
+    let dir = TempDir::new("manifest");
+    let src = tracked_tree(
+        &dir,
+        "src",
+        &[
+            ("small", b"small".to_vec()),
+            ("empty", Vec::new()),
+            ("sub/large", noise(3 * 256 * 1024 + 17)),
+        ],
+    );
+    // Checksums of the tracked files, by path.
+    let checksums: HashMap<String, String> = ok(track(&dir).arg("checksum"))
+        .lines()
+        .map(|line| {
+            let (hash, path) = line.split_once("  ").unwrap();
+            (path.to_string(), hash.to_string())
+        })
+        .collect();
+    assert_eq!(checksums.len(), 3);
+
+    // The files are hashed as they are compressed with several jobs, after they are archived with one.
+    let mut manifests = Vec::new();
+    for jobs in ["1", "4"] {
+        let archive = dir.join(format!("out-{}.tar.gz", jobs));
+        let manifest = dir.join(format!("manifest-{}", jobs));
+        ok(track(&dir)
+            .args(["--jobs", jobs, "export", "tar"])
+            .arg(&archive)
+            .arg("--manifest")
+            .arg(&manifest));
+        let manifest = fs::read_to_string(&manifest).unwrap();
+        let contents = tar_contents(&archive);
+        assert_eq!(manifest.lines().count(), contents.len(), "{}", manifest);
+        for line in manifest.lines() {
+            let (hash, name) = line.split_once("  ").unwrap();
+            let source = format!("/{}", name);
+            assert_eq!(checksums[&source], hash, "{}", name);
+            assert!(contents[name] == fs::read(&source).unwrap(), "{} differs", name);
+        }
+        manifests.push(manifest);
+    }
+    assert_eq!(manifests[0], manifests[1]);
+    assert!(
+        fs::read(dir.join("out-1.tar.gz")).unwrap() == fs::read(dir.join("out-4.tar.gz")).unwrap(),
+        "the archives differ"
+    );
+    assert!(manifests[0].contains(&format!("{}/sub/large", src.strip_prefix("/").unwrap().display())));
+
+    // The embedded manifest is the same.
+    let archive = dir.join("embedded.tar.gz");
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .arg("--embed-manifest"));
+    let embedded = String::from_utf8(tar_contents(&archive).remove(".track/manifest").unwrap()).unwrap();
+    let mut lines: Vec<_> = embedded.lines().collect();
+    let mut expected: Vec<_> = manifests[0].lines().collect();
+    lines.sort();
+    expected.sort();
+    assert_eq!(lines, expected);
+}
+
+/// Export a tar archive with a manifest of `paths`, returning the manifest.
+fn manifest_of(dir: &TempDir, paths: &str) -> String {
+    let manifest = dir.join("manifest");
+    ok(track(dir)
+        .args(["export", "tar"])
+        .arg(dir.join("out.tar.gz"))
+        .arg("--manifest")
+        .arg(&manifest)
+        .args(["--manifest-paths", paths]));
+    fs::read_to_string(&manifest).unwrap()
+}
+
+/// Check `manifest` with sha256sum from `cwd`.
+fn assert_checks(manifest: &str, cwd: &std::path::Path) {
+    let output = with_stdin(
+        Command::new("sha256sum").args(["-c", "--strict", "-"]).current_dir(cwd),
+        manifest,
+    );
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn manifest_paths_choose_the_names_checked() {
+    let dir = TempDir::new("manifest-paths");
+    let src = tracked_tree(&dir, "src", &[("a", "a"), ("sub/b\\x", "b")]);
+    let hash_a = "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb";
+    let hash_b = "3e23e8160039594a33894f6564e1b1348bbd7a0088d42c4acb73eeaed59c009d";
+    let lines = |a: &std::path::Path, b: &std::path::Path| {
+        // Backslashes are escaped like sha256sum does.
+        format!(
+            "{}  {}\n\\{}  {}\n",
+            hash_a,
+            a.display(),
+            hash_b,
+            b.display().to_string().replace('\\', "\\\\")
+        )
+    };
+
+    let manifest = manifest_of(&dir, "archive");
+    let name = src.strip_prefix("/").unwrap();
+    assert_eq!(manifest, lines(&name.join("a"), &name.join("sub/b\\x")));
+    let extracted = dir.join("extracted");
+    tar::Archive::new(GzDecoder::new(fs::File::open(dir.join("out.tar.gz")).unwrap()))
+        .unpack(&extracted)
+        .unwrap();
+    assert_checks(&manifest, &extracted);
+
+    let manifest = manifest_of(&dir, "absolute");
+    assert_eq!(manifest, lines(&src.join("a"), &src.join("sub/b\\x")));
+    assert_checks(&manifest, std::path::Path::new("/"));
+
+    let manifest = manifest_of(&dir, &format!("relative-to:{}", dir.path().display()));
+    assert_eq!(
+        manifest,
+        lines(std::path::Path::new("src/a"), std::path::Path::new("src/sub/b\\x"))
+    );
+    assert_checks(&manifest, dir.path());
+}
+
+#[test]
+fn manifest_paths_outside_the_directory_are_refused_before_exporting() {
+    let dir = TempDir::new("manifest-paths-outside");
+    tracked_tree(&dir, "src", &[("a", "a")]);
+    let output = fails(
+        track(&dir)
+            .args(["export", "tar"])
+            .arg(dir.join("out.tar.gz"))
+            .arg("--manifest")
+            .arg(dir.join("manifest"))
+            .arg("--manifest-paths")
+            .arg(format!("relative-to:{}", dir.join("elsewhere").display())),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("it can't be written in the manifest relative to it"));
+    assert!(!dir.join("out.tar.gz").exists());
+    assert!(!dir.join("manifest").exists());
+
+    let output = fails(
+        track(&dir)
+            .args(["export", "tar"])
+            .arg(dir.join("out.tar.gz"))
+            .args(["--manifest-paths", "relative"]),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown manifest paths mode relative"));
+}
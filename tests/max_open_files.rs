@@ -0,0 +1,74 @@
+mod common;
+
+use std::{os::unix::process::CommandExt, process::Command};
+
+use common::{exported_files, fails, ok, tar_names, track, TempDir};
+
+/// Lower the soft limit of open files of `command` to `limit`.
+fn with_open_files_limit(command: &mut Command, limit: libc::rlim_t) -> &mut Command {
+    unsafe {
+        command.pre_exec(move || {
+            let mut rlimit = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            if libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlimit) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            rlimit.rlim_cur = limit.min(rlimit.rlim_max);
+            if libc::setrlimit(libc::RLIMIT_NOFILE, &rlimit) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        })
+    }
+}
+
+fn many_files(name: &str) -> TempDir {
+    let dir = TempDir::new(name);
+    for i in 0..200 {
+        dir.write(&format!("src/f{}", i), format!("file {}", i));
+    }
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    dir
+}
+
+#[test]
+fn many_jobs_stay_within_a_low_limit() {
+    let dir = many_files("max-open-files");
+    let dest = dir.join("dest");
+    ok(with_open_files_limit(
+        track(&dir)
+            .args(["-j", "16", "--max-open-files", "4", "export", "dir"])
+            .arg(&dest),
+        32,
+    ));
+    assert_eq!(exported_files(&dest).len(), 200);
+
+    // The default is half of the soft limit.
+    let archive = dir.join("out.tar.gz");
+    ok(with_open_files_limit(
+        track(&dir)
+            .args(["-j", "16", "export", "tar"])
+            .arg(&archive)
+            .arg("--manifest")
+            .arg(dir.join("out.sha256")),
+        32,
+    ));
+    assert_eq!(tar_names(&archive).len(), 200);
+    let shown = ok(with_open_files_limit(track(&dir).args(["config", "show"]), 32));
+    assert!(shown.contains("max open files     16 (default)\n"), "{}", shown);
+}
+
+#[test]
+fn zero_open_files_is_a_usage_error() {
+    let dir = many_files("max-open-files-zero");
+    let output = fails(
+        track(&dir)
+            .args(["--max-open-files", "0", "export", "dir"])
+            .arg(dir.join("dest")),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--max-open-files must be at least 1"));
+    assert!(!dir.join("dest").exists());
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use std::fs;
+
+use common::{fails, ok, track, tracked_tree, TempDir};
+
+#[test]
+fn exports_over_the_limit_write_nothing() {
+    let dir = TempDir::new("max-total-size");
+    tracked_tree(&dir, "src", &[("a", vec![0u8; 700]), ("b", vec![0u8; 700])]);
+    for (kind, dest) in [
+        ("tar", "out.tar.gz"),
+        ("zip", "out.zip"),
+        ("dir", "dest"),
+        ("bagit", "bag"),
+        ("cas", "store"),
+        ("script", "restore.sh"),
+    ] {
+        let dest = dir.join(dest);
+        let output = fails(
+            track(&dir)
+                .args(["export", kind])
+                .arg(&dest)
+                .args(["--max-total-size", "1K"]),
+        );
+        assert_eq!(output.status.code(), Some(2), "{}", kind);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("the files to export add up to more than --max-total-size 1.0 KiB, nothing was exported"),
+            "{}: {}",
+            kind,
+            stderr
+        );
+        assert!(!dest.exists(), "{} export left {}", kind, dest.display());
+    }
+
+    let dest = dir.join("per-root");
+    let output = fails(
+        track(&dir)
+            .args(["export", "tar"])
+            .arg(&dest)
+            .args(["--per-root", "--max-total-size", "1K"]),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!dest.exists());
+
+    let output = fails(track(&dir).args(["export", "tar", "-", "--max-total-size", "1K"]));
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    // Nothing but the tracked tree and the database.
+    let mut names: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["src", "track.db", "track.lock"]);
+}
+
+#[test]
+fn exports_within_the_limit_are_written() {
+    let dir = TempDir::new("max-total-size-within");
+    tracked_tree(&dir, "src", &[("a", vec![0u8; 700]), ("b", vec![0u8; 700])]);
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .args(["--max-total-size", "1400"]));
+    assert!(archive.exists());
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use std::process::Output;
+
+use common::{exported_files, track, with_stdin, TempDir};
+
+/// Run `track --memory` with `args`, writing `stdin` to it.
+fn memory_run(dir: &TempDir, args: &[&str], stdin: &str) -> Output {
+    with_stdin(track(dir).arg("--memory").args(args), stdin)
+}
+
+#[test]
+fn memory_runs_add_paths_from_stdin_then_export_them() {
+    let dir = TempDir::new("memory");
+    dir.write("a/x", "x");
+    dir.write("b/y", "y");
+    dir.write("c/z", "z");
+    let dest = dir.join("dest");
+    let stdin = format!("{}\n{}\n", dir.join("a").display(), dir.join("b").display());
+    let c = dir.join("c");
+    let output = memory_run(
+        &dir,
+        &["add", c.to_str().unwrap(), "-", "export", "dir", dest.to_str().unwrap()],
+        &stdin,
+    );
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let files = exported_files(&dest);
+    assert_eq!(files.len(), 3, "{:?}", files);
+    for name in ["a/x", "b/y", "c/z"] {
+        assert!(files.iter().any(|file| file.ends_with(name)), "{:?}", files);
+    }
+    // Neither the database given with --db nor the config directory were touched.
+    assert!(!dir.join("track.db").exists());
+    assert!(!dir.join("config").exists());
+
+    // Nothing is left for the next run.
+    let output = memory_run(&dir, &["ls"], "");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn add_refuses_to_read_stdin_twice() {
+    let dir = TempDir::new("memory-stdin-twice");
+    let dest = dir.join("dest");
+    let output = memory_run(
+        &dir,
+        &["add", "-", "export", "dir", dest.to_str().unwrap(), "--exclude-stdin"],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(2));
+    let output = memory_run(
+        &dir,
+        &["add", "-", "--dry-run", "export", "dir", dest.to_str().unwrap()],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(2));
+}
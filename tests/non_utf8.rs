@@ -0,0 +1,92 @@
+mod common;
+
+use std::{
+    ffi::OsStr,
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use common::{ok, track, tracked_tree, TempDir};
+
+/// A tracked directory whose name isn't valid UTF-8, holding a file whose name isn't either and has
+/// a space and a quote.
+fn non_utf8_tree(dir: &TempDir) -> (PathBuf, PathBuf) {
+    let name = OsStr::from_bytes(b"f\xfe it's");
+    let src = tracked_tree(dir, OsStr::from_bytes(b"src\xff"), &[(name, "x")]);
+    let file = src.join(name);
+    (src, file)
+}
+
+/// Paths separated by NUL bytes.
+fn null_separated(paths: &[&Path]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for path in paths {
+        bytes.extend_from_slice(path.as_os_str().as_bytes());
+        bytes.push(0);
+    }
+    bytes
+}
+
+fn stdout_bytes(command: &mut Command) -> Vec<u8> {
+    let output = command.output().unwrap();
+    assert!(output.status.success(), "{:?} failed", command);
+    output.stdout
+}
+
+/// Check that each line of `escaped` is a shell word bash reads back as the matching path.
+fn assert_escaped(escaped: &str, paths: &[&Path]) {
+    let lines: Vec<&str> = escaped.lines().collect();
+    assert_eq!(lines.len(), paths.len(), "{}", escaped);
+    for (line, path) in lines.iter().zip(paths) {
+        assert!(line.is_ascii(), "{} isn't escaped", line);
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(format!("printf %s {}", line))
+            .output()
+            .unwrap();
+        assert_eq!(output.stdout, path.as_os_str().as_bytes(), "{}", line);
+    }
+}
+
+#[test]
+fn ls_keeps_the_bytes_of_paths() {
+    let dir = TempDir::new("non-utf8-ls");
+    let (src, _) = non_utf8_tree(&dir);
+    assert_eq!(
+        stdout_bytes(track(&dir).args(["ls", "--null"])),
+        null_separated(&[&src])
+    );
+    assert_escaped(&ok(track(&dir).args(["ls", "--escape"])), &[&src]);
+}
+
+#[test]
+fn matched_keeps_the_bytes_of_paths() {
+    let dir = TempDir::new("non-utf8-matched");
+    let (_, file) = non_utf8_tree(&dir);
+    assert_eq!(
+        stdout_bytes(track(&dir).args(["matched", "--null"])),
+        null_separated(&[&file])
+    );
+    assert_escaped(&ok(track(&dir).args(["matched", "--escape"])), &[&file]);
+}
+
+#[test]
+fn prune_keeps_the_bytes_of_paths() {
+    let dir = TempDir::new("non-utf8-prune");
+    let (src, file) = non_utf8_tree(&dir);
+    fs::remove_file(&file).unwrap();
+    fs::remove_dir(&src).unwrap();
+    assert_eq!(
+        stdout_bytes(track(&dir).args(["prune", "--null"])),
+        null_separated(&[&src])
+    );
+
+    let (src, file) = non_utf8_tree(&dir);
+    fs::remove_file(&file).unwrap();
+    fs::remove_dir(&src).unwrap();
+    let output = ok(track(&dir).args(["prune", "--escape"]));
+    let escaped = output.strip_prefix("Pruned ").expect("prune printed no path");
+    assert_escaped(escaped, &[&src]);
+}
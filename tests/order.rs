@@ -0,0 +1,64 @@
+mod common;
+
+use common::{fails, ok, tar_names, track, tracked_tree, TempDir};
+use filetime::FileTime;
+
+/// Entries of a `kind` export written in `order`, relative to `src`.
+fn exported_in_order(dir: &TempDir, src: &std::path::Path, kind: &str, order: &str) -> Vec<String> {
+    let archive = dir.join(format!("out.{}", kind));
+    ok(track(dir).args(["export", kind]).arg(&archive).args(["--order", order]));
+    let archive = if kind == "zip" {
+        let converted = dir.join("converted.tar.gz");
+        ok(track(dir).arg("convert").arg(&archive).arg(&converted));
+        converted
+    } else {
+        archive
+    };
+    let prefix = format!("{}/", src.strip_prefix("/").unwrap().display());
+    tar_names(&archive)
+        .into_iter()
+        .map(|name| name.strip_prefix(&prefix).unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn entries_are_written_in_the_requested_order() {
+    let dir = TempDir::new("order");
+    let src = tracked_tree(&dir, "src", &[("a/yy", "yyy"), ("b/zz", "z"), ("c", "cc"), ("d", "dd")]);
+    for (name, mtime) in [("a/yy", 100), ("b/zz", 300), ("c", 200), ("d", 200)] {
+        filetime::set_file_mtime(src.join(name), FileTime::from_unix_time(1_600_000_000 + mtime, 0)).unwrap();
+    }
+
+    for kind in ["tar", "zip"] {
+        for (order, expected) in [
+            ("walk", ["a/yy", "b/zz", "c", "d"]),
+            ("path", ["a/yy", "b/zz", "c", "d"]),
+            ("name", ["c", "d", "a/yy", "b/zz"]),
+            // Ties are broken by path.
+            ("size", ["b/zz", "c", "d", "a/yy"]),
+            ("mtime", ["a/yy", "c", "d", "b/zz"]),
+        ] {
+            assert_eq!(
+                exported_in_order(&dir, &src, kind, order),
+                expected,
+                "{} {}",
+                kind,
+                order
+            );
+        }
+    }
+}
+
+#[test]
+fn unknown_orders_are_refused() {
+    let dir = TempDir::new("order-unknown");
+    let output = fails(
+        track(&dir)
+            .args(["export", "tar"])
+            .arg(dir.join("out.tar.gz"))
+            .args(["--order", "random"]),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown order random"));
+    assert!(!dir.join("out.tar.gz").exists());
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use std::{
+    fs,
+    io::Write,
+    process::Stdio,
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{files_under, noise, ok, track, TempDir};
+
+fn terminate(pid: u32) {
+    assert_eq!(unsafe { libc::kill(pid as i32, libc::SIGTERM) }, 0);
+}
+
+#[test]
+fn interrupted_script_export_leaves_no_file() {
+    let dir = TempDir::new("partial-script");
+    dir.write("src/a", noise(32 << 20));
+    dir.write("src/b", "b");
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    let out = dir.join("out");
+    fs::create_dir(&out).unwrap();
+
+    let mut child = track(&dir)
+        .args(["export", "script"])
+        .arg(out.join("restore.sh"))
+        .arg("--embed")
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    // Interrupted while the first file is embedded, the export stops before the second one.
+    let started = Instant::now();
+    while files_under(&out).is_empty() {
+        assert!(started.elapsed() < Duration::from_secs(30), "the export didn't start");
+        thread::sleep(Duration::from_millis(5));
+    }
+    terminate(child.id());
+    assert_eq!(child.wait().unwrap().code(), Some(130));
+    assert_eq!(files_under(&out), Vec::<String>::new());
+}
+
+#[test]
+fn interrupted_convert_keeps_the_previous_destination() {
+    let dir = TempDir::new("partial-convert");
+    dir.write("src/a", noise(1 << 20));
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    let archive = dir.join("archive.tar.gz");
+    ok(track(&dir).args(["export", "tar"]).arg(&archive));
+    let content = fs::read(&archive).unwrap();
+    let out = dir.join("out");
+    let dest = dir.write("out/archive.zip", "previous archive");
+
+    let mut child = track(&dir)
+        .arg("convert")
+        .arg("-")
+        .arg(&dest)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    // Half of the archive is sent, convert then waits for the rest when the signal arrives.
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(&content[..content.len() / 2]).unwrap();
+    thread::sleep(Duration::from_millis(300));
+    terminate(child.id());
+    thread::sleep(Duration::from_millis(100));
+    drop(stdin);
+    assert_eq!(child.wait().unwrap().code(), Some(130));
+    assert_eq!(fs::read(&dest).unwrap(), b"previous archive");
+    assert_eq!(files_under(&out), ["archive.zip"]);
+}
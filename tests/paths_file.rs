@@ -0,0 +1,115 @@
+mod common;
+
+use common::{exported_files, fails, ok, track, track_without_db, TempDir};
+
+/// A paths file tracking `docs`, relative to the file, and `other` by its absolute path.
+fn paths_file(dir: &TempDir) -> std::path::PathBuf {
+    dir.write("tree/docs/report.txt", "report");
+    dir.write("tree/docs/draft.tmp", "draft");
+    dir.write("tree/other/notes.txt", "notes");
+    dir.write(
+        "tree/paths.toml",
+        format!(
+            r#"# Tracked paths, under version control.
+[[path]]
+path = "docs"
+tags = ["docs"]
+exclude = ["*.tmp"]
+
+[[path]]
+path = "{}"
+"#,
+            dir.join("tree/other").display()
+        ),
+    )
+}
+
+#[test]
+fn exports_read_the_paths_file_without_a_database() {
+    let dir = TempDir::new("paths-file");
+    let file = paths_file(&dir);
+    let ls = ok(track(&dir).arg("--paths-file").arg(&file).arg("ls"));
+    assert_eq!(
+        ls,
+        format!(
+            "{}\n{}\n",
+            dir.join("tree/docs").display(),
+            dir.join("tree/other").display()
+        )
+    );
+
+    let dest = dir.join("dest");
+    ok(track(&dir)
+        .arg("--paths-file")
+        .arg(&file)
+        .args(["export", "dir"])
+        .arg(&dest)
+        .args(["--tag", "docs"]));
+    let files = exported_files(&dest);
+    // Only the tagged path, without its excluded files.
+    assert_eq!(files.len(), 1, "{:?}", files);
+    assert!(files[0].ends_with("tree/docs/report.txt"), "{:?}", files);
+    assert!(!dir.join("track.db").exists());
+}
+
+#[test]
+fn paths_file_refuses_changes_and_invalid_files() {
+    let dir = TempDir::new("paths-file-invalid");
+    let file = paths_file(&dir);
+    let output = fails(
+        track(&dir)
+            .arg("--paths-file")
+            .arg(&file)
+            .arg("add")
+            .arg(dir.join("tree")),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("edit it instead"));
+
+    let invalid = dir.write("invalid.toml", "[[path]]\npath = \"a\"\ntag = [\"b\"]\n");
+    let output = fails(track(&dir).arg("--paths-file").arg(&invalid).arg("ls"));
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("line 3") && stderr.contains("unknown key tag"),
+        "{}",
+        stderr
+    );
+    assert!(!dir.join("track.db").exists());
+}
+
+#[test]
+fn paths_files_dont_need_a_config_directory() {
+    let dir = TempDir::new("paths-file-no-config");
+    let file = paths_file(&dir);
+    let dest = dir.join("dest");
+    // Without a config directory there is nowhere to look for the persistent database.
+    ok(track_without_db(&dir)
+        .env_remove("XDG_CONFIG_HOME")
+        .env_remove("HOME")
+        .arg("--paths-file")
+        .arg(&file)
+        .args(["export", "dir"])
+        .arg(&dest));
+    assert_eq!(exported_files(&dest).len(), 2, "{:?}", exported_files(&dest));
+    assert!(!dir.join("config").exists());
+}
+
+#[test]
+fn changed_exports_are_refused_with_a_paths_file() {
+    let dir = TempDir::new("paths-file-changed");
+    let file = paths_file(&dir);
+    let dest = dir.join("dest");
+    // The export time would only be recorded in memory, every file would be exported each time.
+    let output = fails(
+        track(&dir)
+            .arg("--paths-file")
+            .arg(&file)
+            .args(["export", "dir"])
+            .arg(&dest)
+            .arg("--changed"),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--since-last"));
+    assert!(!dest.exists());
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use std::{ffi::OsStr, fs, os::unix::ffi::OsStrExt, path::PathBuf};
+
+use common::{fails, ok, track, TempDir};
+
+/// Tracked directories with a space, bytes which aren't UTF-8 and a line break in their names.
+fn awkward_paths(dir: &TempDir) -> Vec<PathBuf> {
+    let paths = vec![
+        dir.join("a b"),
+        dir.join(OsStr::from_bytes(b"caf\xe9")),
+        dir.join("new\nline"),
+    ];
+    for path in &paths {
+        fs::create_dir(path).unwrap();
+    }
+    ok(track(dir).arg("add").args(&paths));
+    paths
+}
+
+/// The raw bytes of `paths`, each followed by `terminator`.
+fn raw(paths: &[PathBuf], terminator: u8) -> Vec<u8> {
+    let mut expected = Vec::new();
+    for path in paths {
+        expected.extend_from_slice(path.as_os_str().as_bytes());
+        expected.push(terminator);
+    }
+    expected
+}
+
+#[test]
+fn paths_only_prints_the_raw_bytes_of_each_path() {
+    let dir = TempDir::new("paths-only");
+    let paths = awkward_paths(&dir);
+    // Even with colors forced, which the default output gets.
+    let output = track(&dir)
+        .args(["--color", "always", "ls", "--paths-only"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(
+        output.stdout == raw(&paths, b'\n'),
+        "{:?}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    let decorated = track(&dir).args(["--color", "always", "ls"]).output().unwrap();
+    assert!(decorated.stdout != output.stdout);
+
+    for null in ["--null", "-0"] {
+        let output = track(&dir).args(["ls", "--paths-only", null]).output().unwrap();
+        assert!(output.status.success());
+        assert!(
+            output.stdout == raw(&paths, b'\0'),
+            "{:?}",
+            String::from_utf8_lossy(&output.stdout)
+        );
+    }
+}
+
+#[test]
+fn paths_only_conflicts_with_other_formats() {
+    let dir = TempDir::new("paths-only-conflicts");
+    for other in ["--escape", "--long"] {
+        let output = fails(track(&dir).args(["ls", "--paths-only", other]));
+        assert_eq!(output.status.code(), Some(2), "{}", other);
+        assert!(
+            String::from_utf8_lossy(&output.stderr)
+                .contains(&format!("The argument '--paths-only' cannot be used with '{}'", other)),
+            "{}",
+            other
+        );
+    }
+}
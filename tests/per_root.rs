@@ -0,0 +1,123 @@
+mod common;
+
+use std::fs;
+
+use common::{fails, files_under, noise, ok, tar_names, track, TempDir};
+
+#[test]
+fn each_tracked_path_gets_its_own_archive() {
+    let dir = TempDir::new("per-root");
+    dir.write("a/x", "x");
+    dir.write("b/c/y", "y");
+    dir.write("b/c/z", "z");
+    ok(track(&dir).arg("add").arg(dir.join("a")).arg(dir.join("b/c")));
+    let out = dir.join("out");
+    ok(track(&dir).args(["export", "tar"]).arg(&out).arg("--per-root"));
+
+    let base = dir.path().to_str().unwrap().trim_start_matches('/').replace('/', "-");
+    let stored = dir.path().strip_prefix("/").unwrap().display().to_string();
+    assert_eq!(
+        files_under(&out),
+        [format!("{}-a.tar.gz", base), format!("{}-b-c.tar.gz", base)]
+    );
+    assert_eq!(
+        tar_names(&out.join(format!("{}-a.tar.gz", base))),
+        [format!("{}/a/x", stored)]
+    );
+    assert_eq!(
+        tar_names(&out.join(format!("{}-b-c.tar.gz", base))),
+        [format!("{}/b/c/y", stored), format!("{}/b/c/z", stored)]
+    );
+
+    let out = dir.join("out-zip");
+    ok(track(&dir).args(["export", "zip"]).arg(&out).arg("--per-root"));
+    assert_eq!(
+        files_under(&out),
+        [format!("{}-a.zip", base), format!("{}-b-c.zip", base)]
+    );
+}
+
+#[test]
+fn archive_names_taken_already_are_numbered() {
+    let dir = TempDir::new("per-root-names");
+    dir.write("x-y/a", "a");
+    dir.write("x/y/b", "b");
+    ok(track(&dir).arg("add").arg(dir.join("x-y")).arg(dir.join("x/y")));
+    let out = dir.join("out");
+    ok(track(&dir).args(["export", "tar"]).arg(&out).arg("--per-root"));
+
+    let base = dir.path().to_str().unwrap().trim_start_matches('/').replace('/', "-");
+    assert_eq!(
+        files_under(&out),
+        [format!("{}-x-y-2.tar.gz", base), format!("{}-x-y.tar.gz", base)]
+    );
+    let stored = dir.path().strip_prefix("/").unwrap().display().to_string();
+    // Tracked paths are taken in the order of their components, x/y comes before x-y.
+    assert_eq!(
+        tar_names(&out.join(format!("{}-x-y.tar.gz", base))),
+        [format!("{}/x/y/b", stored)]
+    );
+    assert_eq!(
+        tar_names(&out.join(format!("{}-x-y-2.tar.gz", base))),
+        [format!("{}/x-y/a", stored)]
+    );
+}
+
+#[test]
+fn parallel_roots_export_like_one_after_the_other() {
+    let dir = TempDir::new("per-root-parallel");
+    // The first roots are the largest, so the archives of the last ones are done first.
+    for i in 0..6 {
+        dir.write(&format!("r{}/big", i), noise((6 - i) * 256 * 1024));
+        dir.write(&format!("r{}/small", i), "small");
+    }
+    let mut add = track(&dir);
+    add.arg("add");
+    for i in 0..6 {
+        add.arg(dir.join(format!("r{}", i)));
+    }
+    ok(&mut add);
+
+    let export = |out: &str, extra: &[&str]| {
+        let out = dir.join(out);
+        let stdout = ok(track(&dir)
+            .args(["export", "tar"])
+            .arg(&out)
+            .arg("--per-root")
+            .arg("--manifest")
+            .arg(out.with_extension("sha256"))
+            .args(extra));
+        let archives: Vec<_> = files_under(&out)
+            .into_iter()
+            .map(|name| (tar_names(&out.join(&name)), name))
+            .collect();
+        let manifest = fs::read_to_string(out.with_extension("sha256")).unwrap();
+        (stdout.replace(out.to_str().unwrap(), "OUT"), archives, manifest)
+    };
+    let sequential = export("seq", &[]);
+    assert_eq!(sequential.0.lines().count(), 6, "{}", sequential.0);
+    assert_eq!(export("par", &["--parallel-roots", "4"]), sequential);
+}
+
+#[test]
+fn parallel_roots_need_per_root_and_one_root_at_a_time() {
+    let dir = TempDir::new("per-root-parallel-usage");
+    dir.write("a/x", "x");
+    ok(track(&dir).arg("add").arg(dir.join("a")));
+    let out = dir.join("out");
+    let output = fails(
+        track(&dir)
+            .args(["export", "tar"])
+            .arg(&out)
+            .args(["--parallel-roots", "2"]),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    let output = fails(
+        track(&dir)
+            .args(["export", "tar"])
+            .arg(&out)
+            .args(["--per-root", "--parallel-roots", "0"]),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--parallel-roots needs at least 1 root at a time"));
+}
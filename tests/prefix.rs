@@ -0,0 +1,65 @@
+mod common;
+
+use std::fs;
+
+use common::{files_under, ok, tar_names, track, tracked_tree, TempDir};
+
+#[test]
+fn restore_removes_the_prefix_of_the_export() {
+    let dir = TempDir::new("prefix");
+    let src = tracked_tree(&dir, "src", &[("a", "a"), ("sub/b", "b")]);
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .args(["--prefix", "backup/daily"]));
+    let stored = src.strip_prefix("/").unwrap();
+    let mut names = tar_names(&archive);
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            format!("backup/daily/{}/a", stored.display()),
+            format!("backup/daily/{}/sub/b", stored.display())
+        ]
+    );
+
+    let restored = dir.join("restored");
+    ok(track(&dir)
+        .arg("restore")
+        .arg(&archive)
+        .arg("--to")
+        .arg(&restored)
+        .args(["--prefix", "backup/daily"]));
+    let under = restored.join(stored);
+    assert_eq!(files_under(&restored).len(), 2);
+    assert_eq!(fs::read_to_string(under.join("a")).unwrap(), "a");
+    assert_eq!(fs::read_to_string(under.join("sub/b")).unwrap(), "b");
+
+    // Without it the prefix is restored like any other directory.
+    let kept = dir.join("kept");
+    ok(track(&dir).arg("restore").arg(&archive).arg("--to").arg(&kept));
+    assert_eq!(
+        fs::read_to_string(kept.join("backup/daily").join(stored).join("a")).unwrap(),
+        "a"
+    );
+}
+
+#[test]
+fn restore_skips_names_outside_of_the_prefix() {
+    let dir = TempDir::new("prefix-other");
+    tracked_tree(&dir, "src", &[("a", "a")]);
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .args(["--prefix", "backup"]));
+    let restored = dir.join("restored");
+    ok(track(&dir)
+        .arg("restore")
+        .arg(&archive)
+        .arg("--to")
+        .arg(&restored)
+        .args(["--prefix", "elsewhere"]));
+    assert!(!restored.exists() || files_under(&restored).is_empty());
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use common::{fails, ok, track, tracked_tree, TempDir};
+use filetime::FileTime;
+
+fn mode_and_mtime(path: &Path) -> (u32, FileTime) {
+    let meta = fs::metadata(path).unwrap();
+    (
+        meta.permissions().mode() & 0o7777,
+        FileTime::from_last_modification_time(&meta),
+    )
+}
+
+/// Give the directories of `src` distinct modes and old modification times.
+fn age_dirs(src: &Path) {
+    for (dir, mode, mtime) in [
+        (src.join("sub/deep"), 0o700, 1_000_000_000),
+        (src.join("sub"), 0o750, 1_100_000_000),
+        (src.to_path_buf(), 0o755, 1_200_000_000),
+    ] {
+        fs::set_permissions(&dir, fs::Permissions::from_mode(mode)).unwrap();
+        filetime::set_file_mtime(&dir, FileTime::from_unix_time(mtime, 0)).unwrap();
+    }
+}
+
+fn assert_mirrored(src: &Path, exported: &Path) {
+    for dir in ["", "sub", "sub/deep"] {
+        assert_eq!(
+            mode_and_mtime(&exported.join(dir)),
+            mode_and_mtime(&src.join(dir)),
+            "{}",
+            exported.join(dir).display()
+        );
+    }
+}
+
+#[test]
+fn dir_exports_copy_the_mode_and_mtime_of_directories() {
+    let dir = TempDir::new("preserve-dirs");
+    let src = tracked_tree(&dir, "src", &[("top", "top"), ("sub/f", "f"), ("sub/deep/g", "g")]);
+    age_dirs(&src);
+
+    let dest = dir.join("dest");
+    ok(track(&dir).args(["export", "dir"]).arg(&dest).arg("--preserve-dirs"));
+    let exported = dest.join(src.strip_prefix("/").unwrap());
+    assert_mirrored(&src, &exported);
+
+    // Without it, the directories are as they were created.
+    let plain = dir.join("plain");
+    ok(track(&dir).args(["export", "dir"]).arg(&plain));
+    let (_, mtime) = mode_and_mtime(&plain.join(src.strip_prefix("/").unwrap()).join("sub"));
+    assert_ne!(mtime, FileTime::from_unix_time(1_100_000_000, 0));
+}
+
+#[test]
+fn bagit_exports_copy_them_in_the_payload() {
+    let dir = TempDir::new("preserve-dirs-bagit");
+    let src = tracked_tree(&dir, "src", &[("sub/f", "f"), ("sub/deep/g", "g")]);
+    age_dirs(&src);
+
+    let dest = dir.join("bag");
+    ok(track(&dir).args(["export", "bagit"]).arg(&dest).arg("--preserve-dirs"));
+    assert_mirrored(&src, &dest.join("data").join(src.strip_prefix("/").unwrap()));
+}
+
+#[test]
+fn archives_are_refused() {
+    let dir = TempDir::new("preserve-dirs-tar");
+    tracked_tree(&dir, "src", &[("a", "a")]);
+    let archive = dir.join("out.tar.gz");
+    let output = fails(track(&dir).args(["export", "tar"]).arg(&archive).arg("--preserve-dirs"));
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--preserve-dirs only applies to dir and bagit exports"));
+    assert!(!archive.exists());
+}
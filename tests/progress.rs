@@ -0,0 +1,110 @@
+mod common;
+
+use common::{ok, replace_with_fifo, track, tracked_tree, TempDir};
+
+/// Stderr of an export of the tracked tree of `dir` with `args`.
+fn progress(dir: &TempDir, args: &[&str]) -> String {
+    let output = track(dir)
+        .args(["export", "tar"])
+        .arg(dir.join("out.tar.gz"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn json_progress_is_one_event_per_line() {
+    let dir = TempDir::new("progress-json");
+    let src = tracked_tree(&dir, "src", &[("a", "a"), ("b", "bb")]);
+    let stderr = progress(&dir, &["--progress-format", "json"]);
+    let lines: Vec<_> = stderr.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", stderr);
+
+    // Files are reported in the order they are written, counting up to the total.
+    let mut paths = Vec::new();
+    for (i, line) in lines[..2].iter().enumerate() {
+        let path = line
+            .strip_prefix(r#"{"event":"file","path":""#)
+            .and_then(|rest| rest.strip_suffix(&format!(r#"","done":{},"total":2}}"#, i + 1)))
+            .unwrap_or_else(|| panic!("{}", line));
+        paths.push(path.to_string());
+    }
+    paths.sort();
+    assert_eq!(
+        paths,
+        [src.join("a"), src.join("b")].map(|path| path.display().to_string())
+    );
+    assert_eq!(lines[2], r#"{"event":"done","files":2,"bytes":3}"#);
+}
+
+#[test]
+fn text_progress_rewrites_one_line() {
+    let dir = TempDir::new("progress-text");
+    tracked_tree(&dir, "src", &[("a", "a"), ("b", "bb")]);
+    assert_eq!(
+        progress(&dir, &["--progress-format", "text"]),
+        "\rExported 1/2 files\rExported 2/2 files\n"
+    );
+}
+
+#[test]
+fn progress_is_only_written_in_a_format_asked_for() {
+    let dir = TempDir::new("progress-none");
+    tracked_tree(&dir, "src", &[("a", "a")]);
+    // Stderr isn't a terminal.
+    assert_eq!(progress(&dir, &[]), "");
+}
+
+#[test]
+fn always_writes_plain_lines_when_stderr_isnt_a_terminal() {
+    let dir = TempDir::new("progress-always");
+    tracked_tree(&dir, "src", &[("a", "a"), ("b", "bb")]);
+    // The export takes less than a second, only the final line is written.
+    assert_eq!(progress(&dir, &["--progress", "always"]), "2/2 files, 3 B\n");
+    // A format asked for is still used.
+    assert_eq!(
+        progress(&dir, &["--progress", "always", "--progress-format", "text"]),
+        "\rExported 1/2 files\rExported 2/2 files\n"
+    );
+}
+
+#[test]
+fn periodic_lines_are_written_once_a_second_between_warnings() {
+    let dir = TempDir::new("progress-periodic");
+    let src = tracked_tree(&dir, "src", &[("a", "a"), ("b", "bb"), ("c", "ccc")]);
+    // The first export fills the scan cache, then a stalls the next one for more than a second.
+    ok(track(&dir).args(["export", "dir"]).arg(dir.join("first")));
+    replace_with_fifo(&src.join("a"));
+    let stderr = progress(
+        &dir,
+        &["--progress", "always", "--file-timeout", "1200ms", "--jobs", "1"],
+    );
+    assert_eq!(
+        stderr,
+        format!(
+            "Skipping {} which didn't answer within 1.2s\n1/3 files, 2 B\n2/3 files, 5 B\n",
+            src.join("a").display()
+        )
+    );
+}
+
+#[test]
+fn never_reports_nothing() {
+    let dir = TempDir::new("progress-never");
+    tracked_tree(&dir, "src", &[("a", "a")]);
+    for format in ["text", "json"] {
+        assert_eq!(
+            progress(&dir, &["--progress", "never", "--progress-format", format]),
+            ""
+        );
+    }
+    let output = track(&dir)
+        .args(["export", "tar"])
+        .arg(dir.join("out.tar.gz"))
+        .args(["--progress", "sometimes"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
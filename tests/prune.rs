@@ -0,0 +1,95 @@
+mod common;
+
+use std::fs;
+
+use common::{ok, track, tracked_tree, TempDir};
+
+#[test]
+fn deep_prune_removes_paths_without_files() {
+    let dir = TempDir::new("prune-deep");
+    let full = tracked_tree(&dir, "full", &[("a", "a")]);
+    let empty = dir.join("empty");
+    fs::create_dir_all(empty.join("sub/deeper")).unwrap();
+    ok(track(&dir).arg("add").arg(&empty));
+    // Filters don't make a path empty, its files only have to exist.
+    let logs = tracked_tree(&dir, "logs", &[("debug.log", "log")]);
+    ok(track(&dir).arg("add").arg(&logs).args(["--exclude", "*.log"]));
+
+    // Without --deep only missing paths are pruned.
+    assert_eq!(ok(track(&dir).arg("prune")), "");
+    assert_eq!(ok(track(&dir).arg("ls")).lines().count(), 3);
+
+    assert_eq!(
+        ok(track(&dir).args(["prune", "--deep"])),
+        format!("Pruned (empty) {}\n", empty.display())
+    );
+    assert_eq!(
+        ok(track(&dir).arg("ls")),
+        format!("{}\n{}\n", full.display(), logs.display())
+    );
+}
+
+#[test]
+fn paths_which_changed_kind_are_kept_with_a_warning() {
+    let dir = TempDir::new("prune-kinds");
+    let (was_dir, was_file) = (dir.join("was-dir"), dir.join("was-file"));
+    fs::create_dir(&was_dir).unwrap();
+    dir.write("was-file", "f");
+    ok(track(&dir).arg("add").arg(&was_dir).arg(&was_file));
+    fs::remove_dir(&was_dir).unwrap();
+    dir.write("was-dir", "f");
+    fs::remove_file(&was_file).unwrap();
+    fs::create_dir(&was_file).unwrap();
+
+    let output = track(&dir).arg("prune").output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!(
+            "Warning: {} was a directory and is now a file, keeping it\n\
+             Warning: {} was a file and is now a directory, keeping it\n",
+            was_dir.display(),
+            was_file.display()
+        )
+    );
+    // The new kinds are recorded, the next prune has nothing to say.
+    let output = track(&dir).arg("prune").output().unwrap();
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+    assert_eq!(ok(track(&dir).arg("ls")).lines().count(), 2);
+}
+
+#[test]
+fn missing_files_only_prunes_the_files_which_are_gone() {
+    let dir = TempDir::new("prune-missing-files");
+    let (kept, file, gone_dir, never) = (
+        dir.join("kept"),
+        dir.join("file"),
+        dir.join("unplugged"),
+        dir.join("never"),
+    );
+    dir.write("kept", "k");
+    dir.write("file", "f");
+    fs::create_dir(&gone_dir).unwrap();
+    ok(track(&dir).arg("add").arg(&kept).arg(&file).arg(&gone_dir));
+    // Paths added before they existed have no known kind.
+    ok(track(&dir).arg("add").arg(&never).arg("--force"));
+    fs::remove_file(&file).unwrap();
+    fs::remove_dir(&gone_dir).unwrap();
+
+    assert_eq!(
+        ok(track(&dir).args(["prune", "--missing-files"])),
+        format!("Pruned (missing file) {}\n", file.display())
+    );
+    assert_eq!(
+        ok(track(&dir).arg("ls")),
+        format!("{}\n{}\n{}\n", kept.display(), never.display(), gone_dir.display())
+    );
+
+    // Without it, every missing path is pruned.
+    assert_eq!(
+        ok(track(&dir).arg("prune")),
+        format!("Pruned {}\nPruned {}\n", never.display(), gone_dir.display())
+    );
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n", kept.display()));
+}
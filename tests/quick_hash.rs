@@ -0,0 +1,69 @@
+mod common;
+
+use std::{fs, path::Path};
+
+use common::{noise, ok, tar_names, track, tracked_tree, TempDir};
+use filetime::FileTime;
+
+const OLD: i64 = 1_600_000_000;
+
+/// Names of the files a tar export with `--changed` and `args` wrote, relative to `src`.
+fn changed(dir: &TempDir, src: &Path, args: &[&str]) -> Vec<String> {
+    let archive = dir.join("out.tar.gz");
+    ok(track(dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .arg("--changed")
+        .args(args));
+    let prefix = format!("{}/", src.strip_prefix("/").unwrap().display());
+    tar_names(&archive)
+        .into_iter()
+        .map(|name| name.strip_prefix(&prefix).unwrap().to_string())
+        .collect()
+}
+
+/// Flip the byte at `offset` of `path`, or append one past its end, keeping its modification time.
+fn rewrite(path: &Path, offset: usize) {
+    let mut content = fs::read(path).unwrap();
+    match content.get_mut(offset) {
+        Some(b) => *b ^= 0xff,
+        None => content.push(0),
+    }
+    fs::write(path, content).unwrap();
+    filetime::set_file_mtime(path, FileTime::from_unix_time(OLD, 0)).unwrap();
+}
+
+#[test]
+fn hashes_tell_changes_which_keep_the_modification_time() {
+    let dir = TempDir::new("quick-hash");
+    let size = 64 * 1024;
+    let names = ["head", "middle", "size", "tail", "untouched"];
+    let files: Vec<_> = names.iter().map(|&name| (name, noise(size))).collect();
+    let src = tracked_tree(&dir, "src", &files);
+    for name in names {
+        filetime::set_file_mtime(src.join(name), FileTime::from_unix_time(OLD, 0)).unwrap();
+    }
+
+    // Each check keeps signatures of its own, the first export with one has every file.
+    assert_eq!(changed(&dir, &src, &["--quick-hash=4"]), names);
+    assert_eq!(changed(&dir, &src, &["--full-hash"]), names);
+    assert!(changed(&dir, &src, &[]).is_empty());
+
+    rewrite(&src.join("head"), 10);
+    rewrite(&src.join("middle"), size / 2);
+    rewrite(&src.join("size"), size);
+    rewrite(&src.join("tail"), size - 10);
+    // The files still look older than the previous export by their modification time.
+    assert!(changed(&dir, &src, &[]).is_empty());
+    // The quick hash only reads the first and last 4 KiB.
+    assert_eq!(changed(&dir, &src, &["--quick-hash=4"]), ["head", "size", "tail"]);
+    assert_eq!(
+        changed(&dir, &src, &["--full-hash"]),
+        ["head", "middle", "size", "tail"]
+    );
+    // Nothing changed since.
+    assert!(changed(&dir, &src, &["--quick-hash=4"]).is_empty());
+    assert!(changed(&dir, &src, &["--full-hash"]).is_empty());
+    // Signatures of other edges never match.
+    assert_eq!(changed(&dir, &src, &["--quick-hash=40"]), names);
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use std::{os::unix::ffi::OsStrExt, path::PathBuf};
+
+use common::{ok, track, TempDir};
+use rusqlite::Connection;
+
+const NEW_YEAR_2020: u64 = 1577836800;
+const NEW_YEAR_2021: u64 = 1609459200;
+
+/// Track a path for each of `times` and give them these `added_at` times, unknown for None.
+fn seeded(name: &str, times: &[Option<u64>]) -> (TempDir, Vec<PathBuf>) {
+    let dir = TempDir::new(name);
+    let paths: Vec<_> = (0..times.len())
+        .map(|i| dir.write(&format!("p{}/f", i), "f").parent().unwrap().to_path_buf())
+        .collect();
+    ok(track(&dir).arg("add").args(&paths));
+    set(&dir, "added_at", &paths, times);
+    (dir, paths)
+}
+
+fn set(dir: &TempDir, column: &str, paths: &[PathBuf], times: &[Option<u64>]) {
+    let conn = Connection::open(dir.join("track.db")).unwrap();
+    for (path, time) in paths.iter().zip(times) {
+        conn.execute(
+            &format!("UPDATE paths SET {} = ? WHERE path = ?", column),
+            rusqlite::params![time, path.as_os_str().as_bytes()],
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn newest_paths_come_first() {
+    let (dir, paths) = seeded(
+        "recent",
+        &[
+            Some(NEW_YEAR_2020),
+            Some(NEW_YEAR_2021),
+            Some(NEW_YEAR_2021),
+            Some(NEW_YEAR_2021 + 61),
+            None,
+        ],
+    );
+    // Ties are sorted by path and paths added before added_at was recorded are left out.
+    let listed = [
+        format!("2021-01-01T00:01:01Z  {}\n", paths[3].display()),
+        format!("2021-01-01T00:00:00Z  {}\n", paths[1].display()),
+        format!("2021-01-01T00:00:00Z  {}\n", paths[2].display()),
+        format!("2020-01-01T00:00:00Z  {}\n", paths[0].display()),
+    ];
+    assert_eq!(ok(track(&dir).arg("recent")), listed.concat());
+    assert_eq!(ok(track(&dir).args(["recent", "--by", "added"])), listed.concat());
+    assert_eq!(ok(track(&dir).args(["recent", "-n", "2"])), listed[..2].concat());
+    assert_eq!(ok(track(&dir).args(["recent", "--limit", "0"])), "");
+}
+
+#[test]
+fn recent_lists_by_last_scan() {
+    let (dir, paths) = seeded("recent-scanned", &[Some(NEW_YEAR_2020); 4]);
+    // Paths never scanned are left out.
+    assert_eq!(ok(track(&dir).args(["recent", "--by", "scanned"])), "");
+    set(
+        &dir,
+        "last_scanned_at",
+        &paths,
+        &[
+            Some(NEW_YEAR_2021),
+            None,
+            Some(NEW_YEAR_2021 + 3600),
+            Some(NEW_YEAR_2020),
+        ],
+    );
+    assert_eq!(
+        ok(track(&dir).args(["recent", "--by", "scanned", "-n", "2"])),
+        format!(
+            "2021-01-01T01:00:00Z  {}\n2021-01-01T00:00:00Z  {}\n",
+            paths[2].display(),
+            paths[0].display()
+        )
+    );
+}
+
+#[test]
+fn json_has_both_times() {
+    let (dir, paths) = seeded("recent-json", &[Some(NEW_YEAR_2020), Some(NEW_YEAR_2021), None]);
+    set(&dir, "last_scanned_at", &paths, &[Some(NEW_YEAR_2021)]);
+    assert_eq!(
+        ok(track(&dir).args(["recent", "--json"])),
+        format!(
+            concat!(
+                r#"{{"version":1,"command":"recent","data":["#,
+                r#"{{"path":"{}","added_at":{},"scanned_at":null}},"#,
+                r#"{{"path":"{}","added_at":{},"scanned_at":{}}}]}}"#,
+                "\n"
+            ),
+            paths[1].display(),
+            NEW_YEAR_2021,
+            paths[0].display(),
+            NEW_YEAR_2020,
+            NEW_YEAR_2021
+        )
+    );
+}
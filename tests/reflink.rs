@@ -0,0 +1,74 @@
+mod common;
+
+use std::{
+    fs,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::Path,
+};
+
+use common::{fails, ok, track, tracked_tree, TempDir};
+
+/// Track a source tree with a regular file and an executable one, returning its directory.
+fn copied_tree(dir: &TempDir) -> std::path::PathBuf {
+    let data = vec![7u8; 300_000];
+    let src = tracked_tree(dir, "src", &[("data", &data[..]), ("run.sh", b"#!/bin/sh\necho hi\n")]);
+    fs::set_permissions(src.join("run.sh"), fs::Permissions::from_mode(0o750)).unwrap();
+    src
+}
+
+/// Check that `dest` holds copies of the files of `src` with their permissions.
+fn assert_copied(src: &Path, dest: &Path) {
+    let exported = dest.join(src.strip_prefix("/").unwrap());
+    for name in ["data", "run.sh"] {
+        let (original, copy) = (src.join(name), exported.join(name));
+        assert_eq!(fs::read(&original).unwrap(), fs::read(&copy).unwrap(), "{}", name);
+        let (original, copy) = (fs::metadata(&original).unwrap(), fs::symlink_metadata(&copy).unwrap());
+        assert!(copy.file_type().is_file(), "{} is not a regular file", name);
+        assert_eq!(original.mode() & 0o7777, copy.mode() & 0o7777, "{}", name);
+        assert_ne!(original.ino(), copy.ino(), "{} was hard linked", name);
+    }
+}
+
+#[test]
+fn reflink_never_copies_the_contents() {
+    let dir = TempDir::new("reflink-never");
+    let src = copied_tree(&dir);
+    for method in ["auto", "buffered", "copy_file_range", "sendfile"] {
+        let dest = dir.join(format!("dest-{}", method));
+        ok(track(&dir)
+            .args(["export", "dir"])
+            .arg(&dest)
+            .args(["--reflink", "never", "--copy-method", method]));
+        assert_copied(&src, &dest);
+    }
+}
+
+#[test]
+fn reflink_auto_falls_back_to_copies() {
+    let dir = TempDir::new("reflink-auto");
+    let src = copied_tree(&dir);
+    // Clones can't cross filesystems, which makes sure the fallback is taken when tmpfs is available.
+    let shm = Path::new("/dev/shm");
+    let other_fs = shm.is_dir() && fs::metadata(shm).unwrap().dev() != fs::metadata(dir.path()).unwrap().dev();
+    let dest_dir = if other_fs {
+        TempDir::new_in(shm, "reflink-auto-dest")
+    } else {
+        TempDir::new("reflink-auto-dest")
+    };
+
+    if other_fs {
+        let output = fails(
+            track(&dir)
+                .args(["export", "dir"])
+                .arg(dest_dir.join("always"))
+                .args(["--reflink", "always"]),
+        );
+        assert!(String::from_utf8_lossy(&output.stderr).contains("could not reflink"));
+    }
+    let dest = dest_dir.join("auto");
+    ok(track(&dir)
+        .args(["export", "dir"])
+        .arg(&dest)
+        .args(["--reflink", "auto"]));
+    assert_copied(&src, &dest);
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use common::{fails, ok, track, TempDir};
+
+#[test]
+fn relative_paths_follow_the_home_directory() {
+    let dir = TempDir::new("relative");
+    let (first, second) = (dir.join("first"), dir.join("second"));
+    dir.write("first/docs/a", "a");
+    dir.write("second/docs/b", "b");
+    ok(track(&dir)
+        .env("HOME", &first)
+        .arg("add")
+        .arg("--relative")
+        .arg(first.join("docs")));
+
+    assert_eq!(
+        ok(track(&dir).env("HOME", &first).arg("ls")),
+        format!("{}\n", first.join("docs").display())
+    );
+    // The same database used with another home directory tracks the docs there.
+    assert_eq!(
+        ok(track(&dir).env("HOME", &second).arg("ls")),
+        format!("{}\n", second.join("docs").display())
+    );
+    assert_eq!(
+        ok(track(&dir).env("HOME", &second).arg("matched")),
+        format!("{}\n", second.join("docs/b").display())
+    );
+}
+
+#[test]
+fn relative_paths_must_be_under_the_home_directory() {
+    let dir = TempDir::new("relative-outside");
+    let elsewhere = dir.write("elsewhere/a", "a").parent().unwrap().to_path_buf();
+    let output = fails(
+        track(&dir)
+            .env("HOME", dir.join("home"))
+            .arg("add")
+            .arg("--relative")
+            .arg(&elsewhere),
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains(&format!("{} is not under the home directory", elsewhere.display())));
+    assert_eq!(ok(track(&dir).arg("ls")), "");
+}
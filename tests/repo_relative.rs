@@ -0,0 +1,68 @@
+mod common;
+
+use common::{fails, ok, tar_names, track, tracked_tree, TempDir};
+
+#[test]
+fn files_of_repositories_are_stored_under_the_repository_name() {
+    let dir = TempDir::new("repo-relative");
+    let src = tracked_tree(
+        &dir,
+        "src",
+        &[
+            ("one/.git/HEAD", "ref: refs/heads/main\n"),
+            ("one/a", "a"),
+            ("one/sub/b", "b"),
+            // Worktrees and submodules have a .git file.
+            ("two/.git", "gitdir: ../.git/worktrees/two\n"),
+            ("two/c", "c"),
+            // Files belong to the nearest repository.
+            ("two/nested/.git/HEAD", "ref: refs/heads/main\n"),
+            ("two/nested/d", "d"),
+            ("loose", "loose"),
+        ],
+    );
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir).args(["export", "tar"]).arg(&archive).arg("--repo-relative"));
+    let mut names = tar_names(&archive);
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "nested/d".to_string(),
+            "one/a".to_string(),
+            "one/sub/b".to_string(),
+            format!("{}/loose", src.strip_prefix("/").unwrap().display()),
+            "two/.git".to_string(),
+            "two/c".to_string(),
+        ]
+    );
+
+    // Without it, every file keeps its full path.
+    ok(track(&dir).args(["export", "tar"]).arg(&archive));
+    let prefix = format!("{}/", src.strip_prefix("/").unwrap().display());
+    assert!(tar_names(&archive).iter().all(|name| name.starts_with(&prefix)));
+}
+
+#[test]
+fn repositories_with_the_same_name_are_refused() {
+    let dir = TempDir::new("repo-relative-clash");
+    let src = tracked_tree(
+        &dir,
+        "src",
+        &[
+            ("x/proj/.git/HEAD", ""),
+            ("x/proj/a", "a"),
+            ("y/proj/.git/HEAD", ""),
+            ("y/proj/b", "b"),
+        ],
+    );
+    let archive = dir.join("out.tar.gz");
+    let output = fails(track(&dir).args(["export", "tar"]).arg(&archive).arg("--repo-relative"));
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!(
+        "the repositories {} and {} would both be stored as proj",
+        src.join("x/proj").display(),
+        src.join("y/proj").display()
+    )));
+    assert!(!archive.exists());
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+use common::{ok, track, tracked_tree, TempDir};
+
+/// Output of the reporting `command` in `format`.
+fn report(dir: &TempDir, command: &str, format: &str) -> String {
+    ok(track(dir).args([command, "--format", format]))
+}
+
+#[test]
+fn stats_in_every_format() {
+    let dir = TempDir::new("report-stats");
+    let src = tracked_tree(&dir, "src", &[("a", "aaaa"), ("sub/b", "b"), ("c", "cc")]);
+    let src = src.display();
+    assert_eq!(report(&dir, "stats", "text"), format!("{}  3  7 B\n", src));
+    assert_eq!(
+        report(&dir, "stats", "json"),
+        format!(
+            r#"{{"version":1,"command":"stats","data":[{{"path":"{}","files":3,"size":7}}]}}"#,
+            src
+        ) + "\n"
+    );
+    assert_eq!(report(&dir, "stats", "csv"), format!("path,files,size\n{},3,7\n", src));
+}
+
+#[test]
+fn top_in_every_format() {
+    let dir = TempDir::new("report-top");
+    let src = tracked_tree(&dir, "src", &[("a", "aaaa"), ("sub/b", "b"), ("c,\"q", "cc")]);
+    let src = src.display();
+    assert_eq!(
+        report(&dir, "top", "text"),
+        format!("{0}/a      4 B\n{0}/c,\"q   2 B\n{0}/sub/b  1 B\n", src)
+    );
+    assert_eq!(
+        report(&dir, "top", "json"),
+        format!(
+            r#"{{"version":1,"command":"top","data":[{{"path":"{0}/a","size":4}},{{"path":"{0}/c,\"q","size":2}},{{"path":"{0}/sub/b","size":1}}]}}"#,
+            src
+        ) + "\n"
+    );
+    // Paths with commas or quotes are quoted, their quotes doubled.
+    assert_eq!(
+        report(&dir, "top", "csv"),
+        format!("path,size\n{0}/a,4\n\"{0}/c,\"\"q\",2\n{0}/sub/b,1\n", src)
+    );
+}
+
+#[test]
+fn json_keeps_the_bytes_of_paths() {
+    let dir = TempDir::new("report-bytes");
+    let name = OsStr::from_bytes(b"n\xff");
+    let src = tracked_tree(&dir, "src", &[(name, "z")]);
+    let bytes: Vec<_> = src
+        .join(name)
+        .as_os_str()
+        .as_bytes()
+        .iter()
+        .map(u8::to_string)
+        .collect();
+    assert_eq!(
+        report(&dir, "top", "json"),
+        format!(
+            r#"{{"version":1,"command":"top","data":[{{"path":{{"bytes":[{}]}},"size":1}}]}}"#,
+            bytes.join(",")
+        ) + "\n"
+    );
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use std::{fs, os::unix::fs::symlink};
+
+use common::{ok, track, TempDir};
+
+/// A tree at home/me, reachable as well through the symlink data/me, returning both paths.
+fn aliased(dir: &TempDir) -> (std::path::PathBuf, std::path::PathBuf) {
+    dir.write("home/me/notes", "notes");
+    fs::create_dir(dir.join("data")).unwrap();
+    symlink(dir.join("home/me"), dir.join("data/me")).unwrap();
+    let real = fs::canonicalize(dir.join("home/me")).unwrap();
+    (real, dir.join("data/me"))
+}
+
+fn add(dir: &TempDir, path: &std::path::Path, extra: &[&str]) -> String {
+    let output = track(dir).arg("add").arg(path).args(extra).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn resolved_paths_aliasing_a_tracked_path_are_skipped() {
+    let dir = TempDir::new("resolve");
+    let (real, alias) = aliased(&dir);
+    add(&dir, &alias, &[]);
+
+    let stderr = add(&dir, &real, &["--resolve"]);
+    assert!(
+        stderr.contains(&format!(
+            "Skipping {}, it resolves to the same path as the tracked {}",
+            real.display(),
+            alias.display()
+        )),
+        "{}",
+        stderr
+    );
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n", alias.display()));
+    assert_eq!(ok(track(&dir).arg("matched")).lines().count(), 1);
+
+    // --dry-run tells the same.
+    assert_eq!(
+        ok(track(&dir).arg("add").arg(&real).args(["--resolve", "--dry-run"])),
+        format!(
+            "{}: skipped (same as {})\nWould add 0 of 1 paths\n",
+            real.display(),
+            alias.display()
+        )
+    );
+}
+
+#[test]
+fn resolve_stores_the_real_path() {
+    let dir = TempDir::new("resolve-stored");
+    let (real, alias) = aliased(&dir);
+    add(&dir, &alias, &["--resolve"]);
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n", real.display()));
+
+    // Added again through the alias, it's the same path.
+    let stderr = add(&dir, &alias, &["--canonicalize"]);
+    assert!(!stderr.contains("Skipping"), "{}", stderr);
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n", real.display()));
+}
+
+#[test]
+fn aliases_are_kept_without_resolve() {
+    let dir = TempDir::new("resolve-default");
+    let (real, alias) = aliased(&dir);
+    add(&dir, &alias, &[]);
+    add(&dir, &real, &[]);
+    let mut listed: Vec<_> = ok(track(&dir).arg("ls")).lines().map(str::to_owned).collect();
+    listed.sort();
+    let mut expected = vec![alias.display().to_string(), real.display().to_string()];
+    expected.sort();
+    assert_eq!(listed, expected);
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::Path,
+};
+
+use common::{fails, ok, track, tracked_tree, TempDir};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+/// Copy the tar.gz `archive` to `tampered`, with the content of the file named `name` changed and
+/// its header, size included, left as it is.
+fn tamper(archive: &Path, tampered: &Path, name: &str) {
+    let mut input = tar::Archive::new(GzDecoder::new(File::open(archive).unwrap()));
+    let mut output = tar::Builder::new(GzEncoder::new(File::create(tampered).unwrap(), Compression::default()));
+    for entry in input.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let header = entry.header().clone();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).unwrap();
+        if entry.path().unwrap().ends_with(name) {
+            content.iter_mut().for_each(|b| *b ^= 0xff);
+        }
+        output.append(&header, &content[..]).unwrap();
+    }
+    output.into_inner().unwrap().finish().unwrap();
+}
+
+fn restore(dir: &TempDir, archive: &Path, args: &[&str]) -> std::process::Output {
+    let to = dir.join("restored");
+    let _ = fs::remove_dir_all(&to);
+    track(dir)
+        .arg("restore")
+        .arg(archive)
+        .arg("--to")
+        .arg(&to)
+        .arg("--verify")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn assert_tampered(output: &std::process::Output, dir: &TempDir, src: &Path) {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(5), "{}", stderr);
+    let restored = dir.join("restored").join(src.strip_prefix("/").unwrap());
+    assert!(
+        stderr.contains(&format!("{}: FAILED\n", restored.join("a").display())),
+        "{}",
+        stderr
+    );
+    assert!(
+        !stderr.contains(&format!("{}: FAILED", restored.join("sub/b").display())),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("1 of 2 restored files don't match the manifest"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn restore_verify_checks_the_embedded_manifest() {
+    let dir = TempDir::new("restore-verify");
+    let src = tracked_tree(&dir, "src", &[("a", "a content"), ("sub/b", "b content")]);
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .arg("--embed-manifest"));
+
+    let output = restore(&dir, &archive, &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Verified 2 restored files"));
+
+    let tampered = dir.join("tampered.tar.gz");
+    tamper(&archive, &tampered, "a");
+    assert_tampered(&restore(&dir, &tampered, &[]), &dir, &src);
+}
+
+#[test]
+fn restore_verify_checks_a_manifest_given_apart() {
+    let dir = TempDir::new("restore-verify-manifest");
+    let src = tracked_tree(&dir, "src", &[("a", "a content"), ("sub/b", "b content")]);
+    let archive = dir.join("out.tar.gz");
+    let manifest = dir.join("manifest");
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .arg("--manifest")
+        .arg(&manifest));
+    let manifest = manifest.to_str().unwrap();
+
+    // Without the manifest, there's nothing to verify against.
+    let output = restore(&dir, &archive, &[]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("has no embedded manifest"));
+
+    let output = restore(&dir, &archive, &["--manifest", manifest]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let tampered = dir.join("tampered.tar.gz");
+    tamper(&archive, &tampered, "a");
+    assert_tampered(&restore(&dir, &tampered, &["--manifest", manifest]), &dir, &src);
+
+    // A manifest of another algorithm matches nothing.
+    let output = fails(
+        track(&dir)
+            .arg("restore")
+            .arg(&archive)
+            .arg("--to")
+            .arg(dir.join("restored-md5"))
+            .args(["--verify", "--manifest", manifest, "--hash", "md5"]),
+    );
+    assert_eq!(output.status.code(), Some(5));
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use std::fs;
+
+use common::{exported_files, ok, track, tracked_tree, TempDir};
+use filetime::FileTime;
+
+/// Replace the content of the export `copy` of `src` without changing its size or modification time,
+/// so it's only overwritten if it's copied again.
+fn mark(src: &std::path::Path, copy: &std::path::Path) {
+    let mtime = FileTime::from_last_modification_time(&fs::metadata(src).unwrap());
+    fs::write(copy, "X".repeat(fs::metadata(src).unwrap().len() as usize)).unwrap();
+    filetime::set_file_mtime(copy, mtime).unwrap();
+}
+
+#[test]
+fn resume_copies_only_the_missing_files() {
+    let dir = TempDir::new("resume");
+    let src = tracked_tree(&dir, "src", &[("a", "aa"), ("b", "bb"), ("sub/c", "cc")]);
+    let dest = dir.join("dest");
+    ok(track(&dir).args(["export", "dir"]).arg(&dest));
+
+    // An interrupted export is missing some files, the ones it copied are complete.
+    let copies = dest.join(src.strip_prefix("/").unwrap());
+    mark(&src.join("a"), &copies.join("a"));
+    fs::remove_file(copies.join("b")).unwrap();
+    fs::remove_dir_all(copies.join("sub")).unwrap();
+    ok(track(&dir).args(["export", "dir"]).arg(&dest).arg("--resume"));
+
+    assert_eq!(exported_files(&dest).len(), 3);
+    assert_eq!(fs::read_to_string(copies.join("a")).unwrap(), "XX");
+    assert_eq!(fs::read_to_string(copies.join("b")).unwrap(), "bb");
+    assert_eq!(fs::read_to_string(copies.join("sub/c")).unwrap(), "cc");
+
+    // A file changed since is copied again.
+    fs::write(src.join("a"), "new").unwrap();
+    ok(track(&dir).args(["export", "dir"]).arg(&dest).arg("--resume"));
+    assert_eq!(fs::read_to_string(copies.join("a")).unwrap(), "new");
+}
+
+#[test]
+fn resume_keeps_what_a_destination_already_holds() {
+    let dir = TempDir::new("resume-populated");
+    let src = tracked_tree(&dir, "src", &[("a", "aa"), ("b", "bb")]);
+    let dest = dir.join("dest");
+    let copies = dest.join(src.strip_prefix("/").unwrap());
+    fs::create_dir_all(&copies).unwrap();
+    mark(&src.join("a"), &copies.join("a"));
+    fs::write(dest.join("mine"), "mine").unwrap();
+    ok(track(&dir).args(["export", "dir"]).arg(&dest).arg("--resume"));
+
+    assert_eq!(fs::read_to_string(copies.join("a")).unwrap(), "XX");
+    assert_eq!(fs::read_to_string(copies.join("b")).unwrap(), "bb");
+    assert_eq!(fs::read_to_string(dest.join("mine")).unwrap(), "mine");
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use std::{path::Path, process::Stdio};
+
+use common::{fails, ok, tar_names, track, TempDir};
+
+/// Track directories holding a file each, under `dir`.
+fn track_dirs(dir: &TempDir, names: &[&str]) {
+    let mut add = track(dir);
+    add.arg("add");
+    for name in names {
+        dir.write(&format!("{}/f", name), "f");
+        add.arg(dir.join(name));
+    }
+    ok(&mut add);
+}
+
+/// Tracked directories tagged `tag`, relative to `dir`, or none when the tag is unknown.
+fn tagged(dir: &TempDir, tag: &str) -> Vec<String> {
+    let archive = dir.join("tagged.tar.gz");
+    let output = track(dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .args(["--tag", tag])
+        .output()
+        .unwrap();
+    if !output.status.success() {
+        assert!(String::from_utf8_lossy(&output.stderr).contains(&format!("no tracked path is tagged {}", tag)));
+        return Vec::new();
+    }
+    let prefix = format!("{}/", dir.path().strip_prefix("/").unwrap().display());
+    tar_names(&archive)
+        .iter()
+        .map(|name| {
+            let name = Path::new(name.strip_prefix(&prefix).unwrap());
+            name.parent().unwrap().display().to_string()
+        })
+        .collect()
+}
+
+#[test]
+fn tag_and_untag_select_the_paths_by_pattern() {
+    let dir = TempDir::new("retag");
+    track_dirs(&dir, &["proj-a", "proj-b", "other", "work/x"]);
+
+    assert_eq!(
+        ok(track(&dir).args(["tag", "--glob", "proj-*", "work", "mine"])),
+        format!(
+            "Tagged {}\nTagged {}\n",
+            dir.join("proj-a").display(),
+            dir.join("proj-b").display()
+        )
+    );
+    assert_eq!(tagged(&dir, "work"), ["proj-a", "proj-b"]);
+    assert_eq!(tagged(&dir, "mine"), ["proj-a", "proj-b"]);
+    // Paths having the tags already are left as they are.
+    let output = track(&dir).args(["tag", "--glob", "proj-*", "work"]).output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Tagged 0 of 2 matching paths\n"
+    );
+    // Patterns with a slash match the end of the path.
+    ok(track(&dir).args(["tag", "--glob", "work/*", "work"]));
+    assert_eq!(tagged(&dir, "work"), ["proj-a", "proj-b", "work/x"]);
+
+    assert_eq!(
+        ok(track(&dir).args(["untag", "--glob", "proj-b", "work", "mine"])),
+        format!("Untagged {}\n", dir.join("proj-b").display())
+    );
+    assert_eq!(tagged(&dir, "work"), ["proj-a", "work/x"]);
+    assert_eq!(tagged(&dir, "mine"), ["proj-a"]);
+
+    let output = fails(track(&dir).args(["untag", "--glob", "nope-*", "work"]));
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no tracked path matches nope-*"));
+}
+
+#[test]
+fn many_paths_are_only_changed_once_confirmed() {
+    let dir = TempDir::new("retag-many");
+    let names: Vec<_> = (0..11).map(|i| format!("p{:02}", i)).collect();
+    track_dirs(&dir, &names.iter().map(String::as_str).collect::<Vec<_>>());
+
+    let output = fails(track(&dir).args(["tag", "--glob", "p*", "bulk"]).stdin(Stdio::null()));
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("p* matches 11 tracked paths, use --yes to change them without a terminal"));
+    assert!(tagged(&dir, "bulk").is_empty());
+
+    let output = ok(track(&dir).args(["tag", "--glob", "p*", "bulk", "--yes"]));
+    assert_eq!(output.lines().count(), 11);
+    assert_eq!(tagged(&dir, "bulk"), names);
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::{ok, track, TempDir};
+
+fn cached_dirs(dir: &TempDir) -> i64 {
+    let db = rusqlite::Connection::open(dir.join("track.db")).unwrap();
+    db.query_row("SELECT COUNT(*) FROM scanned_dirs", [], |row| row.get(0))
+        .unwrap()
+}
+
+#[test]
+fn cached_scans_match_cold_scans() {
+    let dir = TempDir::new("scan-cache");
+    // Files and directories whose names interleave, in several directories.
+    for name in [
+        "a",
+        "b/x",
+        "b/y/z",
+        "b.txt",
+        "c",
+        "B",
+        "a.d/w",
+        "b2/v",
+        "node_modules/m",
+    ] {
+        dir.write(&format!("src/{}", name), name);
+    }
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    let cold = || ok(track(&dir).args(["matched", "--no-scan-cache", "--skip-dir", "node_modules"]));
+    let cached = || ok(track(&dir).args(["matched", "--skip-dir", "node_modules"]));
+
+    // Matched only reads the cache, it doesn't fill it.
+    assert_eq!(cached(), cold());
+    assert_eq!(cached_dirs(&dir), 0);
+
+    let dest = dir.join("dest");
+    ok(track(&dir)
+        .args(["export", "dir"])
+        .arg(&dest)
+        .args(["--skip-dir", "node_modules"]));
+    assert!(cached_dirs(&dir) > 0);
+    assert_eq!(cached(), cold());
+
+    // A directory changed since it was cached is read again, the others come from the cache.
+    dir.write("src/b/y/new", "new");
+    dir.write("src/b/a", "a");
+    assert_eq!(cached(), cold());
+    assert!(cached().contains("/src/b/y/new\n"));
+}
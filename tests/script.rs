@@ -0,0 +1,79 @@
+mod common;
+
+use std::{fs, os::unix::fs::PermissionsExt, path::Path, process::Command};
+
+use common::{files_under, ok, track, tracked_tree, TempDir};
+
+/// Names a generated script must quote, whose expansion would run commands or split words.
+const NAMES: &[&str] = &[
+    "it's",
+    "$(touch pwned)",
+    "`touch pwned`",
+    "a\"b",
+    "back\\slash",
+    "two  spaces",
+    "new\nline",
+    "tab\there",
+    "-dash",
+];
+
+/// Track a tree of files with tricky names and sizes covering every base64 padding.
+fn tricky_tree(dir: &TempDir) -> std::path::PathBuf {
+    let mut files = Vec::new();
+    for (i, name) in NAMES.iter().enumerate() {
+        files.push((name.to_string(), "x".repeat(i)));
+        files.push((format!("{}.d/inner", name), name.to_string()));
+    }
+    let src = tracked_tree(dir, "src", &files);
+    fs::set_permissions(src.join("it's"), fs::Permissions::from_mode(0o751)).unwrap();
+    src
+}
+
+fn sh(dir: &TempDir, args: &[&Path]) -> std::process::Output {
+    Command::new("sh").args(args).current_dir(dir.path()).output().unwrap()
+}
+
+#[test]
+fn embedded_script_is_valid_and_restores_every_file() {
+    let dir = TempDir::new("script-embed");
+    let src = tricky_tree(&dir);
+    let script = dir.join("restore.sh");
+    ok(track(&dir).args(["export", "script"]).arg(&script).arg("--embed"));
+
+    let output = Command::new("sh").arg("-n").arg(&script).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let dest = dir.join("dest");
+    fs::create_dir(&dest).unwrap();
+    let output = sh(&dir, &[&script, &dest]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let restored = dest.join(src.strip_prefix("/").unwrap());
+    assert_eq!(files_under(&restored), files_under(&src));
+    for file in files_under(&src) {
+        let (original, copy) = (src.join(&file), restored.join(&file));
+        assert_eq!(fs::read(&original).unwrap(), fs::read(&copy).unwrap(), "{}", file);
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(mode(&original), mode(&copy), "{}", file);
+    }
+    assert!(!dir.join("pwned").exists());
+    assert!(!dir.join("src/pwned").exists());
+}
+
+#[test]
+fn listing_script_is_valid_and_reports_missing_files() {
+    let dir = TempDir::new("script-list");
+    tricky_tree(&dir);
+    let script = dir.join("check.sh");
+    ok(track(&dir).args(["export", "script"]).arg(&script));
+
+    let output = Command::new("sh").arg("-n").arg(&script).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let dest = dir.join("dest");
+    fs::create_dir(&dest).unwrap();
+    let output = sh(&dir, &[&script, &dest]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("missing ").count(), 2 * NAMES.len(), "{}", stderr);
+    assert!(!dir.join("pwned").exists());
+}
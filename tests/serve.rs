@@ -0,0 +1,148 @@
+mod common;
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    process::{Child, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{ok, track, TempDir};
+
+/// Server running in the background, killed when dropped.
+struct Server {
+    child: Child,
+    socket: PathBuf,
+}
+
+impl Server {
+    fn start(dir: &TempDir) -> Server {
+        let socket = dir.join("track.sock");
+        let child = track(dir)
+            .arg("serve")
+            .arg("--socket")
+            .arg(&socket)
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        while !socket.exists() {
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "the server isn't listening"
+            );
+            thread::sleep(Duration::from_millis(20));
+        }
+        Server { child, socket }
+    }
+
+    fn connect(&self) -> Client {
+        let stream = UnixStream::connect(&self.socket).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+        Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    /// Send a request line and return the response line.
+    fn call(&mut self, request: &str) -> String {
+        self.send(request);
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        line.trim_end().to_owned()
+    }
+
+    fn send(&mut self, request: &str) {
+        writeln!(self.writer, "{}", request).unwrap();
+    }
+}
+
+#[test]
+fn serve_adds_then_lists_paths() {
+    let dir = TempDir::new("serve");
+    dir.write("src/a", "a");
+    let src = dir.join("src");
+    let src = src.to_str().unwrap();
+    let server = Server::start(&dir);
+    let mut client = server.connect();
+
+    let add = format!(
+        r#"{{"jsonrpc":"2.0","id":1,"method":"add","params":{{"paths":["{}"]}}}}"#,
+        src
+    );
+    assert_eq!(
+        client.call(&add),
+        format!(
+            r#"{{"jsonrpc":"2.0","id":1,"result":{{"added":["{}"],"already_tracked":[],"failed":[]}}}}"#,
+            src
+        )
+    );
+    // Notifications, without an id, aren't answered.
+    client.send(r#"{"jsonrpc":"2.0","method":"ls"}"#);
+    assert_eq!(
+        client.call(r#"{"jsonrpc":"2.0","id":"ls","method":"ls"}"#),
+        format!(r#"{{"jsonrpc":"2.0","id":"ls","result":["{}"]}}"#, src)
+    );
+    assert_eq!(
+        client.call(&add.replace(r#""id":1"#, r#""id":2"#)),
+        format!(
+            r#"{{"jsonrpc":"2.0","id":2,"result":{{"added":[],"already_tracked":["{}"],"failed":[]}}}}"#,
+            src
+        )
+    );
+    // Paths added through the server are in the database the commands read.
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n", src));
+}
+
+#[test]
+fn serve_answers_bad_requests_with_errors() {
+    let dir = TempDir::new("serve-errors");
+    let server = Server::start(&dir);
+    let mut client = server.connect();
+    assert_eq!(
+        client.call(r#"{"jsonrpc":"2.0","id":1,"method":"add","params":{"paths":["relative"]}}"#),
+        r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32602,"message":"relative isn't absolute"}}"#
+    );
+    assert_eq!(
+        client.call(r#"{"jsonrpc":"2.0","id":2,"method":"nope"}"#),
+        r#"{"jsonrpc":"2.0","id":2,"error":{"code":-32601,"message":"unknown method nope"}}"#
+    );
+    assert!(client
+        .call("not json")
+        .starts_with(r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32700,"#));
+    // The session goes on after errors.
+    assert_eq!(
+        client.call(r#"{"jsonrpc":"2.0","id":3,"method":"ls"}"#),
+        r#"{"jsonrpc":"2.0","id":3,"result":[]}"#
+    );
+}
+
+#[test]
+fn serve_removes_its_socket_on_sigterm() {
+    let dir = TempDir::new("serve-sigterm");
+    let mut server = Server::start(&dir);
+    drop(server.connect());
+    assert_eq!(
+        unsafe { libc::kill(server.child.id() as libc::pid_t, libc::SIGTERM) },
+        0
+    );
+    assert!(server.child.wait().unwrap().success());
+    assert!(!server.socket.exists());
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+use common::{fails, ok, track, TempDir};
+
+#[test]
+fn each_file_left_out_is_listed_with_the_reason() {
+    let dir = TempDir::new("show-excluded");
+    for file in [
+        "src/keep",
+        "src/a.tmp",
+        "src/.git/HEAD",
+        "src/node_modules/x",
+        "src/sub/b.log",
+    ] {
+        dir.write(file, "x");
+    }
+    std::os::unix::fs::symlink("keep", dir.join("src/link")).unwrap();
+    let fifo = CString::new(dir.join("src/fifo").as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+    // The database is under the tracked path.
+    ok(track(&dir).arg("add").arg(dir.path()).args(["--exclude", "*.log"]));
+
+    let output = ok(track(&dir).args([
+        "matched",
+        "--show-excluded",
+        "--exclude",
+        "*.tmp",
+        "--skip-dir",
+        "node_modules",
+    ]));
+    let src = dir.join("src");
+    assert_eq!(
+        output,
+        [
+            format!("{}: skipped directory", src.join(".git").display()),
+            format!(
+                "{}: matches an --exclude or --skip-caches pattern",
+                src.join("a.tmp").display()
+            ),
+            format!("{}: not a regular file", src.join("fifo").display()),
+            format!(
+                "{}: symlink, only followed or recorded with --symlinked-dirs when it points to a directory",
+                src.join("link").display()
+            ),
+            format!("{}: skipped directory", src.join("node_modules").display()),
+            format!(
+                "{}: matches an exclude of the tracked path",
+                src.join("sub/b.log").display()
+            ),
+            format!("{}: track database file", dir.join("track.db").display()),
+            format!("{}: track database file", dir.join("track.lock").display()),
+        ]
+        .map(|line| line + "\n")
+        .concat()
+    );
+
+    // The matched files themselves aren't listed.
+    assert!(!output.contains("keep"), "{}", output);
+    assert_eq!(
+        ok(track(&dir).args(["matched", "--exclude", "*.tmp", "--skip-dir", "node_modules"])),
+        format!("{}\n", src.join("keep").display())
+    );
+}
+
+#[test]
+fn ndjson_records_have_the_root_and_the_reason() {
+    let dir = TempDir::new("show-excluded-ndjson");
+    dir.write("src/a.tmp", "x");
+    dir.write("src/b", "x");
+    let src = dir.join("src");
+    ok(track(&dir).arg("add").arg(&src).args(["--exclude", "*.tmp"]));
+    assert_eq!(
+        ok(track(&dir).args(["matched", "--show-excluded", "--format", "ndjson"])),
+        format!(
+            "{{\"path\":\"{}\",\"root\":\"{}\",\"reason\":\"matches an exclude of the tracked path\"}}\n",
+            src.join("a.tmp").display(),
+            src.display()
+        )
+    );
+
+    let output = fails(track(&dir).args(["matched", "--show-excluded", "-0"]));
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("it can't use -0"));
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use common::{fails, ok, tar_names, track, tracked_tree, TempDir};
+use filetime::FileTime;
+
+/// Set the modification time of `path`, in seconds since the epoch.
+fn set_mtime(path: &std::path::Path, secs: i64) {
+    filetime::set_file_mtime(path, FileTime::from_unix_time(secs, 0)).unwrap();
+}
+
+/// Names of the files exported with `--since-last snapshots`, relative to the tracked tree `src`.
+fn exported_since_last(dir: &TempDir, src: &std::path::Path) -> Vec<String> {
+    let archive = dir.join("out.tar.gz");
+    ok(track(dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .arg("--since-last")
+        .arg(dir.join("snapshots")));
+    let prefix = format!("{}/", src.strip_prefix("/").unwrap().display());
+    tar_names(&archive)
+        .into_iter()
+        .map(|name| name.strip_prefix(&prefix).unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn only_files_modified_since_the_newest_snapshot_are_exported() {
+    let dir = TempDir::new("since-last");
+    let src = tracked_tree(&dir, "src", &[("old", "old"), ("between", "between"), ("new", "new")]);
+    set_mtime(&src.join("old"), 1_000);
+    set_mtime(&src.join("between"), 2_000);
+    set_mtime(&src.join("new"), 3_000);
+
+    // Without any snapshot yet everything is exported.
+    assert_eq!(exported_since_last(&dir, &src), ["between", "new", "old"]);
+
+    set_mtime(&dir.write("snapshots/track-20200101T000000Z.tar.gz", ""), 1_500);
+    assert_eq!(exported_since_last(&dir, &src), ["between", "new"]);
+    set_mtime(&dir.write("snapshots/track-20200102T000000Z.tar.gz", ""), 2_500);
+    // Other files of the directory don't count as snapshots.
+    set_mtime(&dir.write("snapshots/notes.txt", ""), 5_000);
+    set_mtime(&dir.write("snapshots/track-notes.txt", ""), 5_000);
+    assert_eq!(exported_since_last(&dir, &src), ["new"]);
+    // A file modified at the time of the snapshot may not be in it.
+    set_mtime(&src.join("between"), 2_500);
+    assert_eq!(exported_since_last(&dir, &src), ["between", "new"]);
+}
+
+#[test]
+fn since_last_conflicts_with_changed() {
+    let dir = TempDir::new("since-last-changed");
+    tracked_tree(&dir, "src", &[("a", "a")]);
+    let output = fails(
+        track(&dir)
+            .args(["export", "tar"])
+            .arg(dir.join("out.tar.gz"))
+            .arg("--since-last")
+            .arg(dir.join("snapshots"))
+            .arg("--changed"),
+    );
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::{ok, track, tracked_tree, TempDir};
+
+#[test]
+fn skip_caches_leaves_out_regenerable_files() {
+    let dir = TempDir::new("skip-caches");
+    let src = tracked_tree(
+        &dir,
+        "src",
+        &[
+            ("py/__pycache__/mod.cpython-311.pyc", ""),
+            ("py/mod.pyc", ""),
+            ("py/.mypy_cache/3.11/mod.json", ""),
+            ("py/mod.py", ""),
+            ("rust/target/debug/app", ""),
+            ("rust/src/main.rs", ""),
+            ("js/node_modules/left-pad/index.js", ""),
+            ("js/index.js", ""),
+            ("home/.cargo/registry/cache/crate.crate", ""),
+            ("home/.cargo/config.toml", ""),
+            ("c/main.o", ""),
+            ("java/Main.class", ""),
+            // Names which only look like caches are kept.
+            ("docs/target.md", ""),
+            ("docs/node_modules.txt", ""),
+            ("docs/notes.o.txt", ""),
+        ],
+    );
+    let matched = |args: &[&str]| -> Vec<String> {
+        ok(track(&dir).arg("matched").args(args))
+            .lines()
+            .map(|line| line.strip_prefix(&format!("{}/", src.display())).unwrap().to_string())
+            .collect()
+    };
+
+    assert_eq!(matched(&[]).len(), 15);
+    assert_eq!(
+        matched(&["--skip-caches"]),
+        [
+            "docs/node_modules.txt",
+            "docs/notes.o.txt",
+            "docs/target.md",
+            "home/.cargo/config.toml",
+            "js/index.js",
+            "py/mod.py",
+            "rust/src/main.rs",
+        ]
+    );
+}
+
+#[test]
+fn presets_are_listed() {
+    let dir = TempDir::new("skip-caches-list");
+    assert_eq!(
+        ok(track(&dir).args(["config", "show", "--list-presets"])),
+        "caches: **/__pycache__/** *.pyc **/.mypy_cache/** **/target/** **/node_modules/** **/.cargo/registry/** *.o *.class\n"
+    );
+}
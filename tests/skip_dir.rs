@@ -0,0 +1,76 @@
+mod common;
+
+use std::os::unix::fs::symlink;
+
+use common::{ok, track, tracked_tree, TempDir};
+
+/// Matched files of `dir`, relative to the tracked directory `src`.
+fn matched(dir: &TempDir, src: &std::path::Path, args: &[&str]) -> Vec<String> {
+    ok(track(dir).arg("matched").args(args))
+        .lines()
+        .map(|line| line.strip_prefix(src.to_str().unwrap()).unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn skipped_directories_are_left_out_wherever_they_are() {
+    let dir = TempDir::new("skip-dir");
+    let src = tracked_tree(
+        &dir,
+        "src",
+        &[
+            ("node_modules/x/f", "f"),
+            ("sub/node_modules/g", "g"),
+            ("sub/keep/h", "h"),
+            ("node_modules.txt", "txt"),
+            (".git/config", "git"),
+        ],
+    );
+    let mut all = matched(&dir, &src, &[]);
+    all.sort();
+    assert_eq!(
+        all,
+        [
+            "/node_modules.txt",
+            "/node_modules/x/f",
+            "/sub/keep/h",
+            "/sub/node_modules/g"
+        ]
+    );
+
+    // Only directories are skipped, by their whole name.
+    let mut files = matched(&dir, &src, &["--skip-dir", "node_modules"]);
+    files.sort();
+    assert_eq!(files, ["/node_modules.txt", "/sub/keep/h"]);
+    assert_eq!(
+        matched(&dir, &src, &["--skip-dir", "node_modules", "--skip-dir", "keep"]),
+        ["/node_modules.txt"]
+    );
+    let mut env = track(&dir);
+    env.env("TRACK_SKIP_DIRS", "node_modules,keep").arg("matched");
+    assert_eq!(ok(&mut env), format!("{}/node_modules.txt\n", src.display()));
+}
+
+#[test]
+fn skipped_directories_arent_scanned() {
+    let dir = TempDir::new("skip-dir-scan");
+    let src = tracked_tree(&dir, "src", &[("node_modules/f", "f"), ("a", "a")]);
+    // Following the loop warns, once the directory is scanned.
+    symlink(".", src.join("node_modules/loop")).unwrap();
+    let output = track(&dir)
+        .args(["matched", "--symlinked-dirs", "follow"])
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("loops back to a parent directory"));
+
+    let output = track(&dir)
+        .args(["matched", "--symlinked-dirs", "follow", "--skip-dir", "node_modules"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{}/a\n", src.display())
+    );
+}
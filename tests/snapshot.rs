@@ -0,0 +1,61 @@
+mod common;
+
+use std::fs;
+
+use common::{ok, track, TempDir};
+
+fn names(dir: &std::path::Path) -> Vec<String> {
+    let mut names: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn snapshots_are_renamed_into_place_once() {
+    let dir = TempDir::new("snapshot");
+    dir.write("src/a", "a");
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    let snapshots = dir.join("snapshots");
+    let output = ok(track(&dir).arg("snapshot").arg(&snapshots));
+
+    let names = names(&snapshots);
+    assert_eq!(names.len(), 1, "{:?}", names);
+    assert!(
+        names[0].starts_with("track-") && names[0].ends_with("Z.tar.gz"),
+        "{:?}",
+        names
+    );
+    assert_eq!(
+        output,
+        format!("Exported 1 files to {}\n", snapshots.join(&names[0]).display())
+    );
+}
+
+#[test]
+fn snapshots_retain_the_newest_of_each_period() {
+    let dir = TempDir::new("snapshot-retain");
+    dir.write("src/a", "a");
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    let snapshots = dir.join("snapshots");
+    for name in [
+        "track-20200101T100000Z.tar.gz",
+        "track-20200101T200000Z.tar.gz",
+        "track-20200102T100000Z.tar.gz",
+        "notes.txt",
+    ] {
+        dir.write(&format!("snapshots/{}", name), "");
+    }
+    ok(track(&dir)
+        .arg("snapshot")
+        .arg(&snapshots)
+        .args(["--retain", "daily:2"]));
+
+    // Today's snapshot and the newest of the last day before, other files are left alone.
+    let names = names(&snapshots);
+    assert_eq!(names.len(), 3, "{:?}", names);
+    assert_eq!(names[0], "notes.txt");
+    assert_eq!(names[1], "track-20200102T100000Z.tar.gz");
+}
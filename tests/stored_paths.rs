@@ -0,0 +1,54 @@
+mod common;
+
+use std::fs;
+
+use common::{ok, track, TempDir};
+
+/// Last line track printed on stderr, the summary of tag and untag.
+fn summary(command: &mut std::process::Command) -> String {
+    let output = command.output().unwrap();
+    assert!(output.status.success(), "{:?} failed", command);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    stderr.lines().last().unwrap_or_default().to_string()
+}
+
+#[test]
+fn paths_relative_to_home_are_found_by_their_absolute_path() {
+    let dir = TempDir::new("stored-relative");
+    let home = dir.join("home");
+    let docs = home.join("docs");
+    fs::create_dir_all(&docs).unwrap();
+    let track = || {
+        let mut command = track(&dir);
+        command.env("HOME", &home);
+        command
+    };
+    ok(track().args(["add", "--relative"]).arg(&docs));
+    assert_eq!(
+        summary(track().args(["tag", "--glob", "docs", "work"])),
+        "Tagged 1 of 1 matching paths"
+    );
+    ok(track().arg("rm").arg(&docs));
+    assert_eq!(ok(track().arg("ls")), "");
+}
+
+#[test]
+fn many_paths_are_tagged_at_once() {
+    let dir = TempDir::new("stored-many");
+    let mut add = track(&dir);
+    add.arg("add");
+    for i in 0..300 {
+        let path = dir.join(format!("p{}", i));
+        fs::create_dir(&path).unwrap();
+        add.arg(path);
+    }
+    ok(&mut add);
+    assert_eq!(
+        summary(track(&dir).args(["tag", "--glob", "p*", "--yes", "bulk"])),
+        "Tagged 300 of 300 matching paths"
+    );
+    assert_eq!(
+        summary(track(&dir).args(["untag", "--glob", "p1*", "--yes", "bulk"])),
+        "Untagged 111 of 111 matching paths"
+    );
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::{exported_files, fails, ok, tar_names, track, tracked_tree, TempDir};
+
+/// Components of the exported name of the tracked directory `src`, which a file name follows.
+fn depth(src: &std::path::Path) -> usize {
+    src.strip_prefix("/").unwrap().components().count()
+}
+
+#[test]
+fn leading_components_are_stripped_from_names() {
+    let dir = TempDir::new("strip");
+    let src = tracked_tree(&dir, "src", &[("a", "a"), ("sub/b", "b")]);
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .args(["--strip-components", &(depth(&src) - 1).to_string()]));
+    let mut names = tar_names(&archive);
+    names.sort();
+    assert_eq!(names, ["src/a", "src/sub/b"]);
+
+    let dest = dir.join("dest");
+    ok(track(&dir)
+        .args(["export", "dir"])
+        .arg(&dest)
+        .args(["--strip-components", &depth(&src).to_string()]));
+    assert_eq!(exported_files(&dest), ["a", "sub/b"]);
+}
+
+#[test]
+fn paths_too_short_to_strip_fail_by_default() {
+    let dir = TempDir::new("strip-error");
+    let src = tracked_tree(&dir, "src", &[("a", "a"), ("sub/b", "b")]);
+    let archive = dir.join("out.tar.gz");
+    let output = fails(
+        track(&dir)
+            .args(["export", "tar"])
+            .arg(&archive)
+            .args(["--strip-components", &(depth(&src) + 1).to_string()]),
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "cannot strip {} components from {}",
+            depth(&src) + 1,
+            src.join("a").display()
+        )),
+        "{}",
+        stderr
+    );
+    assert!(!archive.exists());
+}
+
+#[test]
+fn paths_too_short_to_strip_are_skipped_if_asked() {
+    let dir = TempDir::new("strip-skip");
+    let src = tracked_tree(&dir, "src", &[("a", "a"), ("sub/b", "b")]);
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .args(["--strip-components", &(depth(&src) + 1).to_string()])
+        .args(["--strip-mode", "skip"]));
+    assert_eq!(tar_names(&archive), ["b"]);
+
+    // Stripping past every path leaves an empty archive.
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .args(["--strip-components", "100", "--strip-mode", "skip"]));
+    assert!(tar_names(&archive).is_empty());
+}
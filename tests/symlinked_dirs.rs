@@ -0,0 +1,97 @@
+mod common;
+
+use std::{fs::File, os::unix::fs::symlink, path::PathBuf};
+
+use common::{ok, track, tracked_tree, TempDir};
+use flate2::read::GzDecoder;
+
+/// A tracked tree with a file, a symlink to a directory beside it, one to a directory outside of the
+/// tracked path and one looping back to the tracked path.
+fn symlinked_tree(dir: &TempDir) -> PathBuf {
+    dir.write("ext/y", "y");
+    let src = tracked_tree(dir, "src", &[("real/x", "x")]);
+    symlink("real", src.join("link")).unwrap();
+    symlink("../ext", src.join("out")).unwrap();
+    symlink(".", src.join("loop")).unwrap();
+    src
+}
+
+/// Paths matched with a --symlinked-dirs mode, relative to the tracked path and sorted.
+fn matched(dir: &TempDir, src: &std::path::Path, mode: &str) -> Vec<String> {
+    let output = ok(track(dir).args(["matched", "--symlinked-dirs", mode]));
+    let mut paths: Vec<_> = output
+        .lines()
+        .map(|line| line.strip_prefix(src.to_str().unwrap()).unwrap().to_string())
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn config_show(dir: &TempDir, mode: &str) -> String {
+    ok(track(dir).args(["config", "show", "--json", "--symlinked-dirs", mode]))
+}
+
+#[test]
+fn skip_leaves_out_symlinked_dirs() {
+    let dir = TempDir::new("symlinked-skip");
+    let src = symlinked_tree(&dir);
+    assert_eq!(matched(&dir, &src, "skip"), ["/real/x"]);
+    assert!(config_show(&dir, "skip").contains(r#""symlinked dirs":{"value":"skip","source":"command line"}"#));
+}
+
+#[test]
+fn follow_scans_symlinked_dirs_but_loops() {
+    let dir = TempDir::new("symlinked-follow");
+    let src = symlinked_tree(&dir);
+    let output = track(&dir)
+        .args(["matched", "--symlinked-dirs", "follow"])
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("loops back to a parent directory"));
+    assert_eq!(matched(&dir, &src, "follow"), ["/link/x", "/out/y", "/real/x"]);
+    assert!(config_show(&dir, "follow").contains(r#""symlinked dirs":{"value":"follow","source":"command line"}"#));
+}
+
+#[test]
+fn record_archives_the_symlinks_themselves() {
+    let dir = TempDir::new("symlinked-record");
+    let src = symlinked_tree(&dir);
+    // Only exports match the recorded symlinks.
+    assert_eq!(matched(&dir, &src, "record"), ["/real/x"]);
+    assert!(config_show(&dir, "record").contains(r#""symlinked dirs":{"value":"record","source":"command line"}"#));
+
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .args(["--symlinked-dirs", "record"]));
+    let prefix = src.strip_prefix("/").unwrap().to_path_buf();
+    let mut entries = Vec::new();
+    for entry in tar::Archive::new(GzDecoder::new(File::open(&archive).unwrap()))
+        .entries()
+        .unwrap()
+    {
+        let entry = entry.unwrap();
+        let name = entry
+            .path()
+            .unwrap()
+            .strip_prefix(&prefix)
+            .unwrap()
+            .display()
+            .to_string();
+        let target = entry.link_name().unwrap().map(|target| target.display().to_string());
+        entries.push((name, target));
+    }
+    entries.sort();
+    let expected = [
+        ("link", Some("real")),
+        ("loop", Some(".")),
+        ("out", Some("../ext")),
+        ("real/x", None),
+    ];
+    let expected: Vec<_> = expected
+        .iter()
+        .map(|(name, target)| (name.to_string(), target.map(str::to_string)))
+        .collect();
+    assert_eq!(entries, expected);
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use std::process::Stdio;
+
+use common::{fails, ok, track, TempDir};
+
+#[test]
+fn exclude_top_saves_the_largest_files_as_excludes() {
+    let dir = TempDir::new("trim");
+    let (src, sub) = (dir.join("src"), dir.join("src/sub"));
+    // Names with pattern characters are excluded literally.
+    let big = dir.write("src/sub/big[1].bin", vec![0; 5000]);
+    let mid = dir.write("src/mid", vec![0; 3000]);
+    dir.write("src/small", vec![0; 1000]);
+    dir.write("src/sub/big1.bin", "x");
+    ok(track(&dir).arg("add").arg(&src).arg(&sub));
+
+    // The file under both tracked paths counts once and is excluded from both.
+    assert_eq!(
+        ok(track(&dir).args(["trim", "--exclude-top", "2"])),
+        format!(
+            "Excluded {big} from {src}\nExcluded {big} from {sub}\nExcluded {mid} from {src}\n",
+            big = big.display(),
+            mid = mid.display(),
+            src = src.display(),
+            sub = sub.display(),
+        )
+    );
+    let matched = ok(track(&dir).arg("matched"));
+    assert!(!matched.contains(big.to_str().unwrap()), "{}", matched);
+    assert!(!matched.contains(mid.to_str().unwrap()), "{}", matched);
+    assert!(
+        matched.contains(dir.join("src/sub/big1.bin").to_str().unwrap()),
+        "{}",
+        matched
+    );
+    // The next trim goes on with the largest files left.
+    let output = ok(track(&dir).args(["trim", "--exclude-top", "1"]));
+    assert_eq!(
+        output,
+        format!("Excluded {} from {}\n", dir.join("src/small").display(), src.display())
+    );
+}
+
+#[test]
+fn choosing_needs_a_terminal() {
+    let dir = TempDir::new("trim-no-terminal");
+    dir.write("src/a", "a");
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    let output = fails(track(&dir).arg("trim").stdin(Stdio::null()));
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("use --exclude-top N without a terminal"));
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::{fails, ok, tar_names, track, tracked_tree, TempDir};
+
+fn names(dir: &TempDir, extra: &[&str]) -> Vec<String> {
+    let archive = dir.join("out.tar.gz");
+    ok(track(dir)
+        .args(["export", "tar"])
+        .arg(&archive)
+        .arg("--trim-common-prefix")
+        .args(extra));
+    let mut names = tar_names(&archive);
+    names.sort();
+    names
+}
+
+#[test]
+fn the_deepest_directory_shared_by_every_file_is_removed() {
+    let dir = TempDir::new("trim-common-prefix");
+    tracked_tree(&dir, "src/a", &[("x", "x"), ("sub/y", "y")]);
+    assert_eq!(names(&dir, &[]), ["sub/y", "x"]);
+
+    // The prefix shrinks as the files exported spread out.
+    tracked_tree(&dir, "src/b", &[("z", "z")]);
+    assert_eq!(names(&dir, &[]), ["a/sub/y", "a/x", "b/z"]);
+    assert_eq!(
+        names(&dir, &["--prefix", "backup"]),
+        ["backup/a/sub/y", "backup/a/x", "backup/b/z"]
+    );
+}
+
+#[test]
+fn a_single_file_keeps_its_name() {
+    let dir = TempDir::new("trim-common-prefix-file");
+    let src = dir.write("src/only", "only");
+    ok(track(&dir).arg("add").arg(&src));
+    assert_eq!(names(&dir, &[]), ["only"]);
+}
+
+#[test]
+fn it_conflicts_with_strip_components() {
+    let dir = TempDir::new("trim-common-prefix-strip");
+    tracked_tree(&dir, "src", &[("a", "a")]);
+    let output = fails(track(&dir).args(["export", "tar"]).arg(dir.join("out.tar.gz")).args([
+        "--trim-common-prefix",
+        "--strip-components",
+        "1",
+    ]));
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::{exported_files, fails, ok, track, tracked_tree, TempDir};
+
+#[test]
+fn undo_tracks_removed_paths_again_with_their_tags_and_excludes() {
+    let dir = TempDir::new("undo");
+    let work = dir.join("work");
+    dir.write("work/notes", "notes");
+    dir.write("work/debug.log", "log");
+    ok(track(&dir)
+        .arg("add")
+        .arg(&work)
+        .args(["--tag", "job", "--exclude", "*.log"]));
+    let other = tracked_tree(&dir, "other", &[("x", "x")]);
+    ok(track(&dir).arg("rm").arg(&work));
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n", other.display()));
+
+    let output = ok(track(&dir).arg("undo"));
+    assert_eq!(output, format!("Restored {}\n", work.display()));
+    assert_eq!(
+        ok(track(&dir).arg("ls")),
+        format!("{}\n{}\n", other.display(), work.display())
+    );
+    // The tag still selects the path and the exclude still leaves out the log.
+    let dest = dir.join("dest");
+    ok(track(&dir).args(["export", "dir"]).arg(&dest).args(["--tag", "job"]));
+    let files = exported_files(&dest);
+    assert_eq!(files.len(), 1, "{:?}", files);
+    assert!(files[0].ends_with("work/notes"), "{:?}", files);
+
+    // Each removal is undone once.
+    let output = fails(track(&dir).arg("undo"));
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("nothing to undo"));
+}
+
+#[test]
+fn only_the_last_removals_can_be_undone() {
+    let dir = TempDir::new("undo-history");
+    let mut paths = Vec::new();
+    for i in 0..11 {
+        paths.push(tracked_tree(&dir, format!("p{}", i), &[("f", "f")]));
+    }
+    for path in &paths {
+        ok(track(&dir).arg("rm").arg(path));
+    }
+
+    for path in paths[1..].iter().rev() {
+        assert_eq!(ok(track(&dir).arg("undo")), format!("Restored {}\n", path.display()));
+    }
+    // The oldest removal fell out of the history.
+    assert_eq!(fails(track(&dir).arg("undo")).status.code(), Some(2));
+    let listed = ok(track(&dir).arg("ls"));
+    assert_eq!(listed.lines().count(), 10, "{}", listed);
+    assert!(
+        !listed.lines().any(|line| line == paths[0].to_str().unwrap()),
+        "{}",
+        listed
+    );
+}
+
+#[test]
+fn paths_tracked_again_are_left_as_they_are() {
+    let dir = TempDir::new("undo-tracked-again");
+    let src = tracked_tree(&dir, "src", &[("a", "a")]);
+    ok(track(&dir).arg("add").arg(&src).args(["--tag", "old"]));
+    ok(track(&dir).arg("rm").arg(&src));
+    ok(track(&dir).arg("add").arg(&src));
+
+    let output = track(&dir).arg("undo").output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("{} is tracked again already, left as it is", src.display())),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("Undid rm, 0 paths tracked again"), "{}", stderr);
+    assert_eq!(ok(track(&dir).arg("ls")), format!("{}\n", src.display()));
+    // The path keeps the tags it was tracked again with, none.
+    let output = fails(
+        track(&dir)
+            .args(["export", "dir"])
+            .arg(dir.join("dest"))
+            .args(["--tag", "old"]),
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no tracked path is tagged old"));
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use std::{
+    fs::OpenOptions,
+    io::{Seek, SeekFrom, Write},
+};
+
+use common::{fails, ok, track, TempDir};
+use rusqlite::Connection;
+
+/// Track 50 tagged paths, enough for the index of the paths to fill a page.
+fn populated(name: &str) -> TempDir {
+    let dir = TempDir::new(name);
+    let mut command = track(&dir);
+    command.args(["add", "--tag", "t"]);
+    for i in 0..50 {
+        command.arg(dir.write(&format!("src/p{}", i), "p"));
+    }
+    ok(&mut command);
+    dir
+}
+
+#[test]
+fn healthy_databases_pass() {
+    let dir = populated("verify-db");
+    assert_eq!(
+        ok(track(&dir).arg("verify-db")),
+        "pass   integrity: ok\n\
+         pass   foreign keys: every reference resolves\n\
+         pass   enforcement: foreign keys are enforced\n"
+    );
+    assert_eq!(
+        ok(track(&dir).args(["verify-db", "--json"])),
+        concat!(
+            r#"{"version":1,"command":"verify-db","data":["#,
+            r#"{"check":"integrity","status":"pass","message":"ok"},"#,
+            r#"{"check":"foreign keys","status":"pass","message":"every reference resolves"},"#,
+            r#"{"check":"enforcement","status":"pass","message":"foreign keys are enforced"}]}"#,
+            "\n"
+        )
+    );
+}
+
+#[test]
+fn damaged_pages_fail() {
+    let dir = populated("verify-db-damaged");
+    let db = dir.join("track.db");
+    let (page_size, root): (u64, u64) = {
+        let conn = Connection::open(&db).unwrap();
+        (
+            conn.query_row("PRAGMA page_size", [], |row| row.get(0)).unwrap(),
+            conn.query_row(
+                "SELECT rootpage FROM sqlite_master WHERE name = 'idx_paths_path'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap(),
+        )
+    };
+    // Cells fill pages from their end, zero the second half of the index's page.
+    let mut file = OpenOptions::new().write(true).open(&db).unwrap();
+    file.seek(SeekFrom::Start((root - 1) * page_size + page_size / 2))
+        .unwrap();
+    file.write_all(&vec![0; page_size as usize / 2]).unwrap();
+    drop(file);
+
+    let output = fails(track(&dir).arg("verify-db"));
+    assert_eq!(output.status.code(), Some(3));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("fail   integrity: row 1 missing from index idx_paths_path"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("pass   enforcement"), "{}", stdout);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("problems found in the database"));
+}
+
+#[test]
+fn broken_references_fail() {
+    let dir = populated("verify-db-references");
+    let conn = Connection::open(dir.join("track.db")).unwrap();
+    // Bundled SQLite enforces foreign keys by default, like track itself.
+    conn.execute_batch("PRAGMA foreign_keys = OFF").unwrap();
+    conn.execute("INSERT INTO tags (path, tag) VALUES (x'2f676f6e65', 'stale')", [])
+        .unwrap();
+    drop(conn);
+
+    let output = fails(track(&dir).arg("verify-db"));
+    assert_eq!(output.status.code(), Some(3));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("pass   integrity: ok"), "{}", stdout);
+    assert!(
+        stdout.contains("fail   foreign keys: row 51 of tags references a missing row of paths"),
+        "{}",
+        stdout
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("1 problems found in the database"));
+}
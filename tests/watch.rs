@@ -0,0 +1,101 @@
+mod common;
+
+use std::{
+    io::{BufRead, BufReader},
+    os::unix::io::AsRawFd,
+    process::{Child, Stdio},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use common::{exported_files, ok, track, TempDir};
+use filetime::FileTime;
+
+/// Watch running in the background, killed when dropped.
+struct Watch {
+    child: Child,
+    lines: Receiver<String>,
+}
+
+impl Watch {
+    /// Wait for the next line printed by the watch.
+    fn next_line(&self) -> String {
+        self.lines
+            .recv_timeout(Duration::from_secs(30))
+            .expect("watch printed nothing")
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn watch(dir: &TempDir, args: &[&str]) -> Watch {
+    let mut child = track(dir)
+        .args(["watch", "--interval", "100ms", "--debounce", "100ms"])
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let (sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if sender.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+    Watch { child, lines }
+}
+
+#[test]
+fn changed_watch_keeps_the_files_of_previous_cycles() {
+    let dir = TempDir::new("watch-changed");
+    let past = FileTime::from_system_time(SystemTime::now() - Duration::from_secs(3600));
+    for name in ["a", "b", "sub/c"] {
+        filetime::set_file_mtime(dir.write(&format!("src/{}", name), name), past).unwrap();
+    }
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    let dest = dir.join("dest");
+    let dest_arg = dest.to_str().unwrap();
+
+    let watch = watch(&dir, &["export", "dir", dest_arg, "--changed"]);
+    assert_eq!(watch.next_line(), "Exported 3 files");
+    // --changed compares modification times in seconds with the start of the last export.
+    thread::sleep(Duration::from_millis(1100));
+    dir.write("src/d", "d");
+    assert_eq!(watch.next_line(), "Exported 1 files after 1 changes");
+    drop(watch);
+
+    let files = exported_files(&dest);
+    assert_eq!(files.len(), 4, "{:?}", files);
+    for name in ["a", "b", "sub/c", "d"] {
+        assert!(
+            files.iter().any(|file| file.ends_with(&format!("src/{}", name))),
+            "{:?}",
+            files
+        );
+    }
+}
+
+#[test]
+fn watch_retries_while_the_database_is_locked() {
+    let dir = TempDir::new("watch-locked");
+    dir.write("src/a", "a");
+    ok(track(&dir).arg("add").arg(dir.join("src")));
+    // Held like another track command would, the watch must not stop on it.
+    let lock = std::fs::File::create(dir.join("track.lock")).unwrap();
+    assert_eq!(unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX) }, 0);
+
+    let dest = dir.join("dest");
+    let watch = watch(&dir, &["export", "dir", dest.to_str().unwrap()]);
+    thread::sleep(Duration::from_millis(500));
+    drop(lock);
+    assert_eq!(watch.next_line(), "Exported 1 files");
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{ok, track, TempDir};
+
+#[test]
+fn every_tracked_path_including_a_file_is_listed() {
+    let dir = TempDir::new("which-nested");
+    let outer = dir.join("outer");
+    let inner = outer.join("inner");
+    let log = dir.write("outer/inner/debug.log", "log");
+    ok(track(&dir).arg("add").arg(&outer));
+    ok(track(&dir).arg("add").arg(&inner).args(["--exclude", "*.log"]));
+
+    // Exported as long as one of the tracked paths keeps it.
+    assert_eq!(
+        ok(track(&dir).arg("which").arg(&log)),
+        format!(
+            "{}\n  under tracked path {}, exported\n  under tracked path {}, not exported: matches an exclude \
+             of the tracked path\n  would be exported\n",
+            log.display(),
+            outer.display(),
+            inner.display()
+        )
+    );
+    assert_eq!(
+        ok(track(&dir).arg("which").arg(&log).arg("--json")),
+        format!(
+            r#"{{"version":1,"command":"which","data":{{"path":"{}","roots":[{{"path":"{}","exported":true,"reason":null}},{{"path":"{}","exported":false,"reason":"matches an exclude of the tracked path"}}],"exported":true}}}}"#,
+            log.display(),
+            outer.display(),
+            inner.display()
+        ) + "\n"
+    );
+
+    ok(track(&dir).arg("add").arg(&outer).args(["--exclude", "*.log"]));
+    assert!(ok(track(&dir).arg("which").arg(&log)).ends_with("  would not be exported\n"));
+}
+
+#[test]
+fn files_under_no_tracked_path_are_not_exported() {
+    let dir = TempDir::new("which-untracked");
+    let src = dir.join("src");
+    dir.write("src/a", "a");
+    let loose = dir.write("loose", "loose");
+    ok(track(&dir).arg("add").arg(&src));
+    assert_eq!(
+        ok(track(&dir).arg("which").arg(&loose)),
+        format!(
+            "{}\n  not under any tracked path\n  would not be exported\n",
+            loose.display()
+        )
+    );
+    assert_eq!(
+        ok(track(&dir).arg("which").arg(&loose).arg("--json")),
+        format!(
+            r#"{{"version":1,"command":"which","data":{{"path":"{}","roots":[],"exported":false}}}}"#,
+            loose.display()
+        ) + "\n"
+    );
+}
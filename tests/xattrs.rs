@@ -0,0 +1,62 @@
+mod common;
+
+use std::path::Path;
+
+use common::{ok, track, tracked_tree, TempDir};
+
+/// Set a user xattr on `path`, false when the filesystem doesn't support them.
+fn set_xattr(path: &Path) -> bool {
+    match xattr::set(path, "user.track-test", b"some value") {
+        Ok(()) => true,
+        Err(err) if err.raw_os_error() == Some(libc::ENOTSUP) => false,
+        Err(err) => panic!("could not set an xattr on {}: {}", path.display(), err),
+    }
+}
+
+#[test]
+fn xattrs_survive_a_tar_export_and_restore() {
+    let dir = TempDir::new("xattrs-tar");
+    let src = tracked_tree(&dir, "src", &[("a", "a")]);
+    if !set_xattr(&src.join("a")) {
+        eprintln!("skipped, {} doesn't support user xattrs", dir.path().display());
+        return;
+    }
+    let archive = dir.join("out.tar.gz");
+    ok(track(&dir).args(["export", "tar"]).arg(&archive).arg("--xattrs"));
+
+    let restored = dir.join("restored");
+    ok(track(&dir)
+        .arg("restore")
+        .arg(&archive)
+        .arg("--to")
+        .arg(&restored)
+        .arg("--xattrs"));
+    let file = restored.join(src.strip_prefix("/").unwrap()).join("a");
+    assert_eq!(
+        xattr::get(&file, "user.track-test").unwrap().as_deref(),
+        Some(&b"some value"[..])
+    );
+
+    // They are only restored when asked for.
+    let plain = dir.join("plain");
+    ok(track(&dir).arg("restore").arg(&archive).arg("--to").arg(&plain));
+    let file = plain.join(src.strip_prefix("/").unwrap()).join("a");
+    assert_eq!(xattr::get(&file, "user.track-test").unwrap(), None);
+}
+
+#[test]
+fn xattrs_are_copied_by_dir_exports() {
+    let dir = TempDir::new("xattrs-dir");
+    let src = tracked_tree(&dir, "src", &[("a", "a")]);
+    if !set_xattr(&src.join("a")) {
+        eprintln!("skipped, {} doesn't support user xattrs", dir.path().display());
+        return;
+    }
+    let dest = dir.join("dest");
+    ok(track(&dir).args(["export", "dir"]).arg(&dest).arg("--xattrs"));
+    let file = dest.join(src.strip_prefix("/").unwrap()).join("a");
+    assert_eq!(
+        xattr::get(&file, "user.track-test").unwrap().as_deref(),
+        Some(&b"some value"[..])
+    );
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use std::fs;
+
+use common::{fails, files_under, ok, track, TempDir};
+
+/// Number of entries of a zip archive, from its end of central directory record.
+fn zip_entries(zip: &[u8]) -> u16 {
+    let end = zip.len() - 22;
+    assert_eq!(
+        &zip[end..end + 4],
+        b"PK\x05\x06",
+        "the archive doesn't end with its directory"
+    );
+    u16::from_le_bytes([zip[end + 10], zip[end + 11]])
+}
+
+#[test]
+fn zip64_never_keeps_the_destination_past_the_entry_limit() {
+    let dir = TempDir::new("zip64-never");
+    let src = dir.join("src");
+    fs::create_dir(&src).unwrap();
+    for i in 0..65535 {
+        fs::write(src.join(i.to_string()), "").unwrap();
+    }
+    ok(track(&dir).arg("add").arg(&src));
+    let archive = dir.write("out/archive.zip", "previous archive");
+
+    // The 65535th entry is only refused when writing the central directory, after every file.
+    let output = fails(
+        track(&dir)
+            .args(["export", "zip"])
+            .arg(&archive)
+            .args(["--zip64", "never"]),
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("can't be archived with --zip64 never"));
+    assert_eq!(fs::read(&archive).unwrap(), b"previous archive");
+    assert_eq!(files_under(&dir.join("out")), ["archive.zip"]);
+
+    fs::remove_file(src.join("0")).unwrap();
+    ok(track(&dir)
+        .args(["export", "zip"])
+        .arg(&archive)
+        .args(["--zip64", "never"]));
+    assert_eq!(zip_entries(&fs::read(&archive).unwrap()), 65534);
+    assert_eq!(files_under(&dir.join("out")), ["archive.zip"]);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::Read,
+    os::unix::fs::{symlink, PermissionsExt},
+    process::Stdio,
+};
+
+use common::{noise, track, TempDir};
+use flate2::read::GzDecoder;
+
+#[test]
+fn zips_streamed_to_stdout_read_back() {
+    let dir = TempDir::new("zip-stream");
+    let src = dir.join("src");
+    let files: [(&str, Vec<u8>); 4] = [
+        ("empty", Vec::new()),
+        ("text", b"hello\n".repeat(10_000)),
+        ("sub/noise", noise(300_000)),
+        ("sub/run.sh", b"#!/bin/sh\necho hi\n".to_vec()),
+    ];
+    for (name, content) in &files {
+        dir.write(&format!("src/{}", name), content);
+    }
+    fs::set_permissions(src.join("sub/run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+    symlink("sub", src.join("link")).unwrap();
+    common::ok(track(&dir).arg("add").arg(&src));
+
+    // Stdout is a pipe, which the zip writer can't seek back into.
+    let output = track(&dir)
+        .args(["export", "zip", "-", "--symlinked-dirs", "record"])
+        .stderr(Stdio::inherit())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "export zip - failed with {}", output.status);
+    let zip = dir.join("out.zip");
+    fs::write(&zip, &output.stdout).unwrap();
+
+    // Converting reads every entry back, checking its CRC.
+    let archive = dir.join("out.tar.gz");
+    common::ok(track(&dir).arg("convert").arg(&zip).arg(&archive));
+
+    let prefix = src.strip_prefix("/").unwrap().to_path_buf();
+    let mut found = BTreeMap::new();
+    let mut link = None;
+    for entry in tar::Archive::new(GzDecoder::new(File::open(&archive).unwrap()))
+        .entries()
+        .unwrap()
+    {
+        let mut entry = entry.unwrap();
+        let name = entry.path().unwrap().strip_prefix(&prefix).unwrap().to_path_buf();
+        let name = name.to_str().unwrap().to_owned();
+        if entry.header().entry_type().is_symlink() {
+            link = Some((name, entry.link_name().unwrap().unwrap().into_owned()));
+            continue;
+        }
+        if entry.header().entry_type().is_dir() {
+            continue;
+        }
+        let mode = entry.header().mode().unwrap() & 0o777;
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).unwrap();
+        found.insert(name, (content, mode));
+    }
+
+    assert_eq!(found.len(), files.len(), "{:?}", found.keys().collect::<Vec<_>>());
+    for (name, content) in &files {
+        let (found_content, _) = &found[*name];
+        assert!(found_content == content, "{} differs", name);
+    }
+    assert_eq!(found["sub/run.sh"].1, 0o755);
+    assert_eq!(link, Some(("link".to_owned(), "sub".into())));
+}